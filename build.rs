@@ -5,31 +5,6 @@ fn report_build_profile() {
     );
 }
 
-fn report_enabled_features() {
-    let mut enabled_features: Vec<&str> = Vec::new();
-
-    if cfg!(feature = "cuda") {
-        enabled_features.push("cuda");
-    }
-
-    if cfg!(feature = "cudann") {
-        enabled_features.push("cudann");
-    }
-
-    if cfg!(feature = "nccl") {
-        enabled_features.push("nccl");
-    }
-
-    if enabled_features.is_empty() {
-        enabled_features.push("none");
-    }
-
-    println!(
-        "cargo:rustc-env=BUILD_FEATURES={}",
-        enabled_features.join(",")
-    );
-}
-
 fn report_repository_version() {
     let version = match std::env::var("CI_BUILD_REF") {
         Ok(val) if !val.is_empty() => val,
@@ -61,6 +36,5 @@ fn main() {
     println!("cargo:rerun-if-changed=migrations");
 
     report_build_profile();
-    report_enabled_features();
     report_repository_version();
 }
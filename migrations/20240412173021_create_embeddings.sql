@@ -0,0 +1,11 @@
+CREATE TABLE embeddings (
+  id BLOB NOT NULL PRIMARY KEY DEFAULT (randomblob(16)),
+
+  model TEXT NOT NULL,
+  content TEXT NOT NULL,
+  vector BLOB NOT NULL,
+
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_embeddings_on_model ON embeddings(model);
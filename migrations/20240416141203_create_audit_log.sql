@@ -0,0 +1,20 @@
+CREATE TABLE audit_log (
+  id BLOB NOT NULL PRIMARY KEY DEFAULT (randomblob(16)),
+
+  -- Failed logins may not have a known user, entries are kept even if the user is later removed
+  user_id BLOB
+    REFERENCES users(id)
+    ON DELETE SET NULL,
+
+  event TEXT NOT NULL,
+  outcome TEXT NOT NULL,
+
+  client_ip TEXT,
+  user_agent TEXT,
+  details TEXT,
+
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_audit_log_on_user_id ON audit_log(user_id);
+CREATE INDEX idx_audit_log_on_created_at ON audit_log(created_at);
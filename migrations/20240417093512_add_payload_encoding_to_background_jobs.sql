@@ -0,0 +1,2 @@
+-- Payloads were previously always JSON, existing rows keep that encoding
+ALTER TABLE background_jobs ADD COLUMN payload_encoding TEXT NOT NULL DEFAULT 'json';
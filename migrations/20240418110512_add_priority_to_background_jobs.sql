@@ -0,0 +1,5 @@
+-- Higher priority jobs are claimed first within a queue, existing jobs keep the default
+ALTER TABLE background_jobs ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX idx_background_jobs_on_claim_order
+  ON background_jobs(queue_name, state, priority DESC, attempt_run_at);
@@ -0,0 +1,9 @@
+-- Held by whichever worker is running a job that may only run once at a time anywhere. Leases
+-- that have expired are treated as released so a worker that died can't hold a lock forever.
+CREATE TABLE job_locks (
+  name TEXT NOT NULL PRIMARY KEY,
+  holder TEXT NOT NULL,
+
+  acquired_at TIMESTAMP NOT NULL,
+  expires_at TIMESTAMP NOT NULL
+);
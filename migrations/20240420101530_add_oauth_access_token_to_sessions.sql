@@ -0,0 +1,3 @@
+-- Kept so the grant can be revoked with the provider when the session is logged out. This is only
+-- populated for providers that support revoking tokens.
+ALTER TABLE sessions ADD COLUMN oauth_access_token TEXT;
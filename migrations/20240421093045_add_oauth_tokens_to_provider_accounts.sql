@@ -0,0 +1,11 @@
+-- The most recent tokens issued for each linked account. Accounts with a refresh token have their
+-- access token renewed in the background before it expires, when the provider refuses to renew it
+-- the account is flagged until the user logs in with the provider again.
+ALTER TABLE oauth_provider_accounts ADD COLUMN access_token TEXT;
+ALTER TABLE oauth_provider_accounts ADD COLUMN access_token_expires_at TIMESTAMP;
+ALTER TABLE oauth_provider_accounts ADD COLUMN refresh_token TEXT;
+ALTER TABLE oauth_provider_accounts ADD COLUMN needs_reauthentication BOOLEAN NOT NULL DEFAULT false;
+
+CREATE INDEX idx_oauth_provider_accounts_on_access_token_expires_at
+  ON oauth_provider_accounts(access_token_expires_at)
+  WHERE refresh_token IS NOT NULL AND needs_reauthentication = false;
@@ -0,0 +1,3 @@
+-- Keys stop being accepted once their revocation time has passed. Rotating a key sets this a short
+-- while in the future so requests already signed with it can finish.
+ALTER TABLE api_keys ADD COLUMN revoked_at TIMESTAMP;
@@ -0,0 +1,6 @@
+-- Logins that haven't completed yet are capped per address so the table can't be flooded by
+-- repeatedly starting logins that never finish.
+ALTER TABLE oauth_state ADD COLUMN client_ip TEXT;
+
+CREATE INDEX idx_oauth_state_on_client_ip_created_at
+  ON oauth_state(client_ip, created_at);
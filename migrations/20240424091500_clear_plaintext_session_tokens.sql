@@ -0,0 +1,4 @@
+-- Provider access tokens are now encrypted before they're stored. The ones written before that
+-- can't be told apart from encrypted ones, they're dropped and only mean those sessions won't have
+-- their grant revoked at logout.
+UPDATE sessions SET oauth_access_token = NULL;
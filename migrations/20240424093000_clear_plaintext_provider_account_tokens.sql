@@ -0,0 +1,6 @@
+-- Provider tokens are now encrypted before they're stored, the plaintext ones written before that
+-- are dropped. Affected accounts get new tokens the next time their user logs in.
+UPDATE oauth_provider_accounts
+  SET access_token = NULL,
+      refresh_token = NULL,
+      access_token_expires_at = NULL;
@@ -0,0 +1,6 @@
+-- Claimed jobs are leased to the worker running them. An active job whose lease has expired was
+-- abandoned by a worker that went away mid run and can be claimed again.
+ALTER TABLE background_jobs ADD COLUMN lease_expires_at TIMESTAMP;
+
+-- Jobs that were already running when this was added never finished, they're up for grabs
+UPDATE background_jobs SET lease_expires_at = CURRENT_TIMESTAMP WHERE state = 'active';
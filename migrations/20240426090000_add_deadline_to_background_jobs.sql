@@ -0,0 +1,3 @@
+-- Jobs enqueued on behalf of a request that is waiting on the result carry the request's deadline,
+-- nothing is waiting on them anymore once it has passed.
+ALTER TABLE background_jobs ADD COLUMN deadline TIMESTAMP;
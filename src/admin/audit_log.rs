@@ -0,0 +1,55 @@
+use axum::extract::{Query, State};
+use axum::response::{IntoResponse, Response};
+use http::StatusCode;
+use serde::Deserialize;
+
+use crate::api::{ApiError, ApiJson};
+use crate::database::custom_types::UserId;
+use crate::database::models::{AuditEvent, AuditEventError};
+use crate::database::Database;
+use crate::extractors::AdminIdentity;
+
+const DEFAULT_AUDIT_EVENT_LIMIT: u32 = 100;
+
+const MAX_AUDIT_EVENT_LIMIT: u32 = 1_000;
+
+pub async fn handler(
+    _admin: AdminIdentity,
+    State(database): State<Database>,
+    Query(params): Query<AuditLogParameters>,
+) -> Result<Response, AuditLogError> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_AUDIT_EVENT_LIMIT)
+        .min(MAX_AUDIT_EVENT_LIMIT);
+
+    let mut conn = database
+        .acquire()
+        .await
+        .map_err(AuditLogError::DatabaseConnection)?;
+    let events = AuditEvent::recent(&mut conn, params.user_id, limit).await?;
+
+    Ok((StatusCode::OK, ApiJson(events)).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct AuditLogParameters {
+    limit: Option<u32>,
+    user_id: Option<UserId>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditLogError {
+    #[error("issue with database connection: {0}")]
+    DatabaseConnection(sqlx::Error),
+
+    #[error("failed to query audit events: {0}")]
+    QueryFailed(#[from] AuditEventError),
+}
+
+impl IntoResponse for AuditLogError {
+    fn into_response(self) -> Response {
+        tracing::error!("{self}");
+        ApiError::internal().into_response()
+    }
+}
@@ -0,0 +1,130 @@
+use axum::extract::{Path, Query, State};
+use axum::response::{IntoResponse, Response};
+use http::StatusCode;
+use serde::Deserialize;
+
+use crate::api::{ApiError, ApiJson};
+use crate::app::State as AppState;
+use crate::background_jobs::{JobStore, JobStoreError};
+use crate::database::custom_types::{BackgroundJobId, BackgroundJobState};
+use crate::database::models::{BackgroundJob, BackgroundJobError};
+use crate::database::Database;
+use crate::extractors::AdminIdentity;
+
+const DEFAULT_REQUEUE_LIMIT: usize = 100;
+
+const MAX_REQUEUE_LIMIT: usize = 1_000;
+
+/// Makes a scheduled job eligible to run immediately instead of waiting for its next attempt.
+/// Idle workers poll for ready jobs frequently enough that no separate wake up is needed for the
+/// job to be picked up.
+pub async fn run_now_handler(
+    admin: AdminIdentity,
+    State(database): State<Database>,
+    Path(job_id): Path<BackgroundJobId>,
+) -> Result<Response, RunNowError> {
+    let mut transaction = database.begin().await.map_err(RunNowError::Transaction)?;
+
+    match BackgroundJob::state_of(&mut transaction, job_id).await? {
+        Some(BackgroundJobState::Scheduled) => (),
+        Some(state) => return Err(RunNowError::NotScheduled(state)),
+        None => return Err(RunNowError::NotFound),
+    }
+
+    if !BackgroundJob::run_now(&mut transaction, job_id).await? {
+        return Err(RunNowError::NotScheduled(BackgroundJobState::Active));
+    }
+
+    transaction
+        .commit()
+        .await
+        .map_err(RunNowError::Transaction)?;
+
+    tracing::info!(admin = ?admin.user_id(), job = ?job_id, "job scheduled to run immediately");
+
+    let msg = serde_json::json!({"id": job_id, "state": BackgroundJobState::Scheduled.to_string()});
+    Ok((StatusCode::OK, ApiJson(msg)).into_response())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RunNowError {
+    #[error("background job query failed: {0}")]
+    BackgroundJob(#[from] BackgroundJobError),
+
+    #[error("no background job exists with the requested ID")]
+    NotFound,
+
+    #[error("background job is {0} and can't be run now")]
+    NotScheduled(BackgroundJobState),
+
+    #[error("an error occurred with a transaction operation: {0}")]
+    Transaction(sqlx::Error),
+}
+
+impl IntoResponse for RunNowError {
+    fn into_response(self) -> Response {
+        match self {
+            RunNowError::NotFound => {
+                ApiError::new(StatusCode::NOT_FOUND, "job_not_found", self.to_string())
+                    .into_response()
+            }
+            RunNowError::NotScheduled(_) => {
+                ApiError::new(StatusCode::CONFLICT, "job_not_scheduled", self.to_string())
+                    .into_response()
+            }
+            _ => {
+                tracing::error!("{self}");
+                ApiError::internal().into_response()
+            }
+        }
+    }
+}
+
+/// Gives the dead jobs with the provided name a fresh set of attempts, for use once whatever was
+/// killing them has been fixed. Only a limited batch is requeued per request so a bad fix can't
+/// flood the queue, the count in the response shows whether there are more left to go. The jobs
+/// are runnable immediately and picked up by idle workers on their next poll.
+pub async fn requeue_dead_handler(
+    admin: AdminIdentity,
+    State(state): State<AppState>,
+    Query(params): Query<RequeueDeadParameters>,
+) -> Result<Response, RequeueDeadError> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_REQUEUE_LIMIT)
+        .min(MAX_REQUEUE_LIMIT);
+
+    let requeued = state
+        .basic_task_store()
+        .requeue_dead_by_name(&params.name, limit)
+        .await?;
+
+    tracing::info!(
+        admin = ?admin.user_id(),
+        job_name = params.name,
+        requeued,
+        "dead jobs requeued"
+    );
+
+    let msg = serde_json::json!({"name": params.name, "requeued": requeued});
+    Ok((StatusCode::OK, ApiJson(msg)).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct RequeueDeadParameters {
+    name: String,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequeueDeadError {
+    #[error("failed to requeue dead jobs: {0}")]
+    Store(#[from] JobStoreError),
+}
+
+impl IntoResponse for RequeueDeadError {
+    fn into_response(self) -> Response {
+        tracing::error!("{self}");
+        ApiError::internal().into_response()
+    }
+}
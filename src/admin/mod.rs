@@ -0,0 +1,15 @@
+use axum::routing::{get, post};
+use axum::Router;
+
+mod audit_log;
+mod jobs;
+
+use crate::app::State;
+
+pub fn router(state: State) -> Router<State> {
+    Router::new()
+        .route("/audit-log", get(audit_log::handler))
+        .route("/jobs/requeue-dead", post(jobs::requeue_dead_handler))
+        .route("/jobs/:id/run-now", post(jobs::run_now_handler))
+        .with_state(state)
+}
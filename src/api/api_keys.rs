@@ -0,0 +1,110 @@
+use axum::extract::{Path, State};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::StatusCode;
+use jwt_simple::prelude::*;
+use time::OffsetDateTime;
+
+use crate::api::ApiError;
+use crate::app::State as AppState;
+use crate::database::custom_types::{AuditEventType, Fingerprint};
+use crate::database::models::{ApiKey, AuditEventError, CreateAuditEvent};
+use crate::extractors::{Requestor, SessionIdentity};
+
+/// Replaces one of the user's API keys with a newly generated key pair. The private key is only
+/// ever returned in this response, the old key keeps working for the configured grace period so
+/// requests already signed with it can finish. This is authenticated by the session cookie, the
+/// router's CSRF protection is what keeps other sites from rotating keys on the user's behalf.
+pub async fn rotate_handler(
+    session: SessionIdentity,
+    State(state): State<AppState>,
+    requestor: Requestor,
+    Path(fingerprint): Path<String>,
+) -> Result<Response, ApiKeyRotationError> {
+    let fingerprint =
+        Fingerprint::from_key_id(&fingerprint).map_err(|_| ApiKeyRotationError::NotFound)?;
+
+    let database = state.database();
+    let mut tx = database
+        .begin()
+        .await
+        .map_err(ApiKeyRotationError::DatabaseFailure)?;
+
+    let api_key = ApiKey::from_fingerprint(&mut tx, &fingerprint)
+        .await
+        .map_err(ApiKeyRotationError::DatabaseFailure)?
+        .filter(|key| key.user_id() == session.user_id())
+        .ok_or(ApiKeyRotationError::NotFound)?;
+
+    let key_pair = ES384KeyPair::generate();
+    let public_key = key_pair.public_key();
+    let new_fingerprint = Fingerprint::from_public_key(&public_key);
+    let private_key = key_pair
+        .to_pem()
+        .map_err(ApiKeyRotationError::KeyEncodingFailed)?;
+
+    let revoke_at = OffsetDateTime::now_utc() + state.api_key_rotation_grace();
+    api_key
+        .rotate(&mut tx, &public_key.to_bytes(), &new_fingerprint, revoke_at)
+        .await
+        .map_err(ApiKeyRotationError::DatabaseFailure)?
+        .ok_or(ApiKeyRotationError::AlreadyRotated)?;
+
+    CreateAuditEvent::success(AuditEventType::ApiKeyRotated, &requestor)
+        .with_user(session.user_id())
+        .with_details(format!("{fingerprint} replaced by {new_fingerprint}"))
+        .save(&mut tx)
+        .await?;
+
+    tx.commit()
+        .await
+        .map_err(ApiKeyRotationError::DatabaseFailure)?;
+
+    let msg = serde_json::json!({
+        "fingerprint": new_fingerprint.to_string(),
+        "private_key": private_key,
+        "previous_key_revoked_at": revoke_at.unix_timestamp(),
+    });
+    Ok((StatusCode::CREATED, Json(msg)).into_response())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiKeyRotationError {
+    #[error("API key has already been rotated")]
+    AlreadyRotated,
+
+    #[error("failed to record the rotation in the audit log: {0}")]
+    AuditFailed(#[from] AuditEventError),
+
+    #[error("database failure while rotating API key: {0}")]
+    DatabaseFailure(sqlx::Error),
+
+    #[error("unable to encode the generated API key: {0}")]
+    KeyEncodingFailed(jwt_simple::Error),
+
+    #[error("no active API key with that fingerprint")]
+    NotFound,
+}
+
+impl IntoResponse for ApiKeyRotationError {
+    fn into_response(self) -> Response {
+        match self {
+            ApiKeyRotationError::AlreadyRotated => ApiError::new(
+                StatusCode::CONFLICT,
+                "already_rotated",
+                "the API key has already been replaced",
+            )
+            .into_response(),
+            ApiKeyRotationError::NotFound => ApiError::new(
+                StatusCode::NOT_FOUND,
+                "not_found",
+                "no active API key with that fingerprint",
+            )
+            .into_response(),
+            _ => {
+                tracing::error!("{self}");
+                ApiError::internal().into_response()
+            }
+        }
+    }
+}
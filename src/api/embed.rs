@@ -0,0 +1,73 @@
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::api::{ApiError, ApiJson, MODEL_LOADING_RETRY_SECS};
+use crate::extractors::SessionIdentity;
+use crate::http_server::ServerTimings;
+use crate::llm::hugging_face::EMBEDDING_MODEL;
+use crate::llm::{Embedder, EmbedderError, ModelStatus};
+
+pub async fn handler(
+    _session: SessionIdentity,
+    State(embedder): State<Embedder>,
+    timings: ServerTimings,
+    ApiJson(request): ApiJson<EmbedRequest>,
+) -> Result<Response, EmbedError> {
+    match embedder.status() {
+        ModelStatus::Ready => (),
+        ModelStatus::Loading => return Err(EmbedError::ModelLoading),
+        ModelStatus::NotLoaded | ModelStatus::Failed => {
+            // Nothing is currently trying to load the model, kick that off so a retry has a
+            // chance of succeeding.
+            embedder.warm_up();
+            return Err(EmbedError::ModelLoading);
+        }
+    }
+
+    let embeddings = timings
+        .measure("inference", embedder.embed_batch(&request.texts))
+        .await?;
+
+    let response = EmbedResponse {
+        model: EMBEDDING_MODEL,
+        embeddings,
+    };
+
+    Ok((StatusCode::OK, ApiJson(response)).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct EmbedRequest {
+    texts: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct EmbedResponse {
+    model: &'static str,
+    embeddings: Vec<Vec<f32>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmbedError {
+    #[error("failed to generate embeddings: {0}")]
+    EmbeddingFailed(#[from] EmbedderError),
+
+    #[error("the embedding model is not yet available")]
+    ModelLoading,
+}
+
+impl IntoResponse for EmbedError {
+    fn into_response(self) -> Response {
+        match self {
+            EmbedError::ModelLoading => {
+                ApiError::model_loading(MODEL_LOADING_RETRY_SECS).into_response()
+            }
+            _ => {
+                tracing::error!("{self}");
+                ApiError::internal().into_response()
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use axum::extract::rejection::JsonRejection;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::{header, HeaderValue, StatusCode};
+use serde::Serialize;
+
+use crate::http_server::REQUEST_MAX_SIZE;
+
+/// The common error body returned by all API endpoints. Clients are expected to program against
+/// the `code` value, the `message` is purely informational and may change at any time.
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+
+    #[serde(skip)]
+    retry_after: Option<u64>,
+
+    code: &'static str,
+    message: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_bytes: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn internal() -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "backend service experienced an issue servicing the request",
+        )
+    }
+
+    pub fn invalid_json(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_json", message)
+    }
+
+    pub fn method_not_allowed() -> Self {
+        Self::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method_not_allowed",
+            "the request method is not supported by this endpoint",
+        )
+    }
+
+    pub fn model_loading(retry_after: u64) -> Self {
+        let mut err = Self::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "model_loading",
+            "the model is still being loaded, try again shortly",
+        );
+        err.retry_after = Some(retry_after);
+        err
+    }
+
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            retry_after: None,
+            code,
+            message: message.into(),
+            max_bytes: None,
+            request_id: None,
+        }
+    }
+
+    pub fn payload_too_large(max_bytes: usize) -> Self {
+        let mut err = Self::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "payload_too_large",
+            format!("request body exceeded the maximum allowed size of {max_bytes} bytes"),
+        );
+        err.max_bytes = Some(max_bytes);
+        err
+    }
+
+    /// Identifies the request in the logs, for errors where the details have been withheld from
+    /// the client and someone will need to go looking for them.
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    pub fn unsupported_media_type(message: impl Into<String>) -> Self {
+        Self::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+            message,
+        )
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection.status() {
+            StatusCode::PAYLOAD_TOO_LARGE => ApiError::payload_too_large(REQUEST_MAX_SIZE),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => {
+                ApiError::unsupported_media_type(rejection.body_text())
+            }
+            _ => ApiError::invalid_json(rejection.body_text()),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, Json(&self)).into_response();
+
+        if let Some(retry_after) = self.retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_payload_too_large_body() {
+        let response = ApiError::payload_too_large(1_024).into_response();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body["code"], "payload_too_large");
+        assert_eq!(body["max_bytes"], 1_024);
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_header() {
+        let response = ApiError::model_loading(10).into_response();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "10");
+    }
+}
@@ -0,0 +1,16 @@
+use axum::extract::FromRequest;
+use axum::response::{IntoResponse, Response};
+
+use crate::api::ApiError;
+
+/// A drop-in replacement for [`axum::Json`] that reports any rejections (malformed bodies, oversized
+/// payloads, etc) using the standard [`ApiError`] body.
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct ApiJson<T>(pub T);
+
+impl<T: serde::Serialize> IntoResponse for ApiJson<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
@@ -0,0 +1,41 @@
+use axum::extract::DefaultBodyLimit;
+use axum::routing::post;
+use axum::Router;
+
+mod api_keys;
+mod embed;
+mod error;
+mod json;
+mod upload;
+
+pub use error::ApiError;
+pub use json::ApiJson;
+
+use crate::app::State;
+use crate::http_server::{with_request_timeout, RequestTimeouts};
+
+/// The number of seconds clients should wait before retrying a request that was rejected because
+/// a model was still being loaded.
+const MODEL_LOADING_RETRY_SECS: u64 = 10;
+
+pub fn router(state: State, timeouts: RequestTimeouts) -> Router<State> {
+    let max_upload_size = state.max_upload_size();
+
+    let standard = Router::new().route("/keys/:fingerprint/rotate", post(api_keys::rotate_handler));
+
+    // Embedding large batches and receiving uploads are expected to take a while
+    let long_running = Router::new()
+        .route("/embed", post(embed::handler))
+        .route(
+            "/uploads",
+            post(upload::handler).layer(DefaultBodyLimit::max(max_upload_size)),
+        )
+        .route(
+            "/uploads/multipart",
+            post(upload::multipart_handler).layer(DefaultBodyLimit::disable()),
+        );
+
+    with_request_timeout(standard, timeouts.standard())
+        .merge(with_request_timeout(long_running, timeouts.long_running()))
+        .with_state(state)
+}
@@ -0,0 +1,271 @@
+use axum::body::Bytes;
+use axum::extract::multipart::{MultipartError, MultipartRejection};
+use axum::extract::rejection::BytesRejection;
+use axum::extract::{Multipart, State};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::{header, HeaderMap, StatusCode};
+use uuid::Uuid;
+
+use crate::api::ApiError;
+use crate::app::{AppStateError, State as AppState, UploadRef, UploadStore, UploadStoreError};
+use crate::extractors::SessionIdentity;
+
+/// Used when the client doesn't say what it is uploading.
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The multipart field expected to hold the uploaded file.
+const FILE_FIELD: &str = "file";
+
+pub async fn handler(
+    session: SessionIdentity,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Response, UploadError> {
+    let body = read_upload(body, state.max_upload_size()).map_err(UploadError::InvalidBody)?;
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .unwrap_or(DEFAULT_CONTENT_TYPE);
+
+    let upload_id = Uuid::new_v4();
+    let key = format!("{}/{upload_id}", session.user_id());
+
+    let upload_store = state.upload_store()?;
+    let upload_ref = upload_store.put(&key, body, content_type).await?;
+
+    Ok(created(upload_id, &upload_ref))
+}
+
+/// Accepts the file from a `multipart/form-data` body. The route opts out of the request body
+/// limit, the store's object size limit is enforced while the file is read instead.
+pub async fn multipart_handler(
+    session: SessionIdentity,
+    State(state): State<AppState>,
+    multipart: Result<Multipart, MultipartRejection>,
+) -> Result<Response, UploadError> {
+    let multipart = multipart.map_err(|rejection| {
+        UploadError::InvalidBody(ApiError::new(
+            rejection.status(),
+            "invalid_body",
+            rejection.body_text(),
+        ))
+    })?;
+
+    let upload_id = Uuid::new_v4();
+    let key = format!("{}/{upload_id}", session.user_id());
+
+    let upload_store = state.upload_store()?;
+    let upload_ref = store_multipart(&upload_store, &key, multipart).await?;
+
+    Ok(created(upload_id, &upload_ref))
+}
+
+fn created(upload_id: Uuid, upload_ref: &UploadRef) -> Response {
+    let msg = serde_json::json!({
+        "id": upload_id,
+        "content_type": upload_ref.content_type,
+        "size": upload_ref.size,
+    });
+
+    (StatusCode::CREATED, Json(msg)).into_response()
+}
+
+fn invalid_multipart(err: MultipartError) -> UploadError {
+    UploadError::InvalidBody(ApiError::new(err.status(), "invalid_body", err.body_text()))
+}
+
+/// Uploads are allowed to exceed the global request size limit up to their own limit, this turns
+/// the rejection of anything beyond that into the same structured error the rest of the API uses.
+fn read_upload(body: Result<Bytes, BytesRejection>, max_bytes: usize) -> Result<Bytes, ApiError> {
+    body.map_err(|rejection| match rejection.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => ApiError::payload_too_large(max_bytes),
+        status => ApiError::new(status, "invalid_body", rejection.body_text()),
+    })
+}
+
+/// Reads the file field into memory, refusing it as soon as it grows past the store's object size
+/// limit. Any other fields are skipped.
+async fn store_multipart(
+    upload_store: &UploadStore,
+    key: &str,
+    mut multipart: Multipart,
+) -> Result<UploadRef, UploadError> {
+    let limits = upload_store.limits();
+
+    while let Some(mut field) = multipart.next_field().await.map_err(invalid_multipart)? {
+        if field.name() != Some(FILE_FIELD) {
+            continue;
+        }
+
+        let content_type = field
+            .content_type()
+            .unwrap_or(DEFAULT_CONTENT_TYPE)
+            .to_string();
+        if !limits.allows(&content_type) {
+            return Err(UploadStoreError::ContentTypeNotAllowed(content_type).into());
+        }
+
+        let mut contents = Vec::new();
+        while let Some(chunk) = field.chunk().await.map_err(invalid_multipart)? {
+            if contents.len() + chunk.len() > limits.max_object_size() {
+                return Err(UploadStoreError::TooLarge(limits.max_object_size()).into());
+            }
+
+            contents.extend_from_slice(&chunk);
+        }
+
+        return Ok(upload_store
+            .put(key, contents.into(), &content_type)
+            .await?);
+    }
+
+    Err(UploadError::InvalidBody(ApiError::new(
+        StatusCode::BAD_REQUEST,
+        "missing_file",
+        format!("the multipart body did not include a '{FILE_FIELD}' field"),
+    )))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UploadError {
+    #[error("upload body was rejected")]
+    InvalidBody(ApiError),
+
+    #[error("failed to store upload: {0}")]
+    Store(#[from] UploadStoreError),
+
+    #[error("upload store was not available: {0}")]
+    StoreUnavailable(#[from] AppStateError),
+}
+
+impl IntoResponse for UploadError {
+    fn into_response(self) -> Response {
+        match self {
+            UploadError::InvalidBody(err) => err.into_response(),
+            UploadError::Store(UploadStoreError::TooLarge(max_bytes)) => {
+                ApiError::payload_too_large(max_bytes).into_response()
+            }
+            UploadError::Store(err @ UploadStoreError::ContentTypeNotAllowed(_)) => {
+                ApiError::unsupported_media_type(err.to_string()).into_response()
+            }
+            _ => {
+                tracing::error!("{self}");
+                ApiError::internal().into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::extract::DefaultBodyLimit;
+    use axum::routing::post;
+    use axum::Router;
+    use http::Request;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::app::{UploadLimits, UploadLocation};
+
+    const TEST_LIMIT: usize = 1_024;
+
+    async fn upload_response(size: usize) -> Response {
+        let router = Router::new()
+            .route(
+                "/",
+                post(|body: Result<Bytes, BytesRejection>| async move {
+                    read_upload(body, TEST_LIMIT).map(|_| StatusCode::CREATED)
+                }),
+            )
+            .layer(DefaultBodyLimit::max(TEST_LIMIT));
+
+        let request = Request::post("/")
+            .body(Body::from(vec![0u8; size]))
+            .unwrap();
+        router.oneshot(request).await.unwrap()
+    }
+
+    async fn multipart_response(
+        upload_store: UploadStore,
+        content_type: &str,
+        contents: &str,
+    ) -> Response {
+        let router = Router::new()
+            .route(
+                "/",
+                post(|multipart: Multipart| async move {
+                    store_multipart(&upload_store, "user/upload", multipart)
+                        .await
+                        .map(|upload_ref| created(Uuid::nil(), &upload_ref))
+                }),
+            )
+            .layer(DefaultBodyLimit::disable());
+
+        let body = format!(
+            "--boundary\r\n\
+             Content-Disposition: form-data; name=\"note\"\r\n\r\n\
+             ignored\r\n\
+             --boundary\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"upload.txt\"\r\n\
+             Content-Type: {content_type}\r\n\r\n\
+             {contents}\r\n\
+             --boundary--\r\n"
+        );
+        let request = Request::post("/")
+            .header(
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=boundary",
+            )
+            .body(Body::from(body))
+            .unwrap();
+        router.oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_multipart_upload_stored() {
+        let directory = std::env::temp_dir().join(format!("uploads-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let location = UploadLocation::Local(directory.clone());
+        let upload_store = UploadStore::open(&location)
+            .unwrap()
+            .with_limits(UploadLimits::new(TEST_LIMIT, &["text/plain"]));
+
+        let response = multipart_response(upload_store.clone(), "text/plain", "contents").await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let written = std::fs::read_to_string(directory.join("user/upload")).unwrap();
+        assert_eq!(written, "contents");
+
+        let response = multipart_response(upload_store.clone(), "text/html", "<p>").await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let oversized = "a".repeat(TEST_LIMIT + 1);
+        let response = multipart_response(upload_store, "text/plain", &oversized).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_upload_at_limit_accepted() {
+        let response = upload_response(TEST_LIMIT).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_upload_over_limit_rejected() {
+        let response = upload_response(TEST_LIMIT + 1).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body["code"], "payload_too_large");
+        assert_eq!(body["max_bytes"], TEST_LIMIT);
+    }
+}
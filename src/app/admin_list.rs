@@ -0,0 +1,48 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+/// The email addresses of users allowed to access the administrative endpoints. Emails are
+/// compared case-insensitively, matching how they are stored on users.
+#[derive(Clone, Default)]
+pub struct AdminList {
+    emails: Arc<BTreeSet<String>>,
+}
+
+impl AdminList {
+    pub fn contains(&self, email: &str) -> bool {
+        self.emails.contains(&email.trim().to_lowercase())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.emails.is_empty()
+    }
+
+    pub fn new(emails: &[impl AsRef<str>]) -> Self {
+        let emails = emails
+            .iter()
+            .map(|e| e.as_ref().trim().to_lowercase())
+            .filter(|e| !e.is_empty())
+            .collect();
+
+        Self {
+            emails: Arc::new(emails),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emails_match_case_insensitively() {
+        let admins = AdminList::new(&["Ops@Example.com", " ", ""]);
+
+        assert!(admins.contains("ops@example.com"));
+        assert!(admins.contains("OPS@EXAMPLE.COM"));
+        assert!(!admins.contains("dev@example.com"));
+        assert!(!admins.contains(""));
+
+        assert!(AdminList::new(&[""]).is_empty());
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+/// The hostnames the service is willing to answer for. Anything derived from the `Host` of a
+/// request (such as the OAuth redirect URL) is only trustworthy once the host has been checked
+/// against this list. Entries are either an exact hostname, `*.example.com` to match any subdomain
+/// of `example.com`, or a lone `*` that matches everything which should only be used in
+/// development.
+#[derive(Clone, Default)]
+pub struct AllowedHosts {
+    patterns: Arc<Vec<String>>,
+}
+
+impl AllowedHosts {
+    /// Checks the value of a `Host` header against the allowed hosts, any port present is
+    /// ignored.
+    pub fn is_allowed(&self, host: &str) -> bool {
+        let hostname = normalize(strip_port(host));
+        if hostname.is_empty() {
+            return false;
+        }
+
+        self.patterns.iter().any(|pattern| {
+            if pattern == "*" {
+                return true;
+            }
+
+            match pattern.strip_prefix("*.") {
+                Some(domain) => hostname
+                    .strip_suffix(domain)
+                    .map(|prefix| prefix.len() > 1 && prefix.ends_with('.'))
+                    .unwrap_or(false),
+                None => *pattern == hostname,
+            }
+        })
+    }
+
+    pub fn new(hosts: &[impl AsRef<str>]) -> Self {
+        let patterns = hosts
+            .iter()
+            .map(|h| {
+                normalize(
+                    h.as_ref()
+                        .trim()
+                        .trim_start_matches('[')
+                        .trim_end_matches(']'),
+                )
+            })
+            .filter(|h| !h.is_empty())
+            .collect();
+
+        Self {
+            patterns: Arc::new(patterns),
+        }
+    }
+}
+
+/// Hostnames are case insensitive and may be written with a trailing dot.
+fn normalize(hostname: &str) -> String {
+    hostname.trim_end_matches('.').to_lowercase()
+}
+
+/// Removes the port from a `Host` value, IPv6 addresses are always wrapped in brackets when they
+/// appear there which are removed as well.
+fn strip_port(host: &str) -> &str {
+    if let Some(bracketed) = host.strip_prefix('[') {
+        return bracketed.split(']').next().unwrap_or_default();
+    }
+
+    host.split(':').next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hosts_matched_against_patterns() {
+        let allowed = AllowedHosts::new(&["Example.com", "*.apps.example.com", "[::1]", " "]);
+
+        assert!(allowed.is_allowed("example.com"));
+        assert!(allowed.is_allowed("EXAMPLE.com:3000"));
+        assert!(allowed.is_allowed("example.com."));
+        assert!(allowed.is_allowed("one.apps.example.com"));
+        assert!(allowed.is_allowed("[::1]:3001"));
+
+        assert!(!allowed.is_allowed("apps.example.com"));
+        assert!(!allowed.is_allowed("evilapps.example.com"));
+        assert!(!allowed.is_allowed("example.com.attacker.net"));
+        assert!(!allowed.is_allowed("attacker.net"));
+        assert!(!allowed.is_allowed(""));
+        assert!(!allowed.is_allowed(":3000"));
+
+        let anything = AllowedHosts::new(&["*"]);
+        assert!(anything.is_allowed("attacker.net"));
+
+        assert!(!AllowedHosts::new(&[""]).is_allowed("localhost"));
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+/// Fragments of user agents that identify well known crawlers, link previewers, and scripted
+/// clients. These are matched case-insensitively anywhere within the user agent.
+pub const DEFAULT_BOT_PATTERNS: &[&str] = &[
+    "bot",
+    "crawl",
+    "spider",
+    "slurp",
+    "facebookexternalhit",
+    "embedly",
+    "preview",
+    "headless",
+    "lighthouse",
+    "curl/",
+    "wget/",
+    "python-requests",
+    "python-urllib",
+    "go-http-client",
+    "okhttp",
+    "java/",
+    "libwww-perl",
+];
+
+/// Classifies requests as coming from automated clients based on their user agent.
+#[derive(Clone)]
+pub struct BotClassifier {
+    patterns: Arc<Vec<String>>,
+}
+
+impl BotClassifier {
+    /// Clients that don't identify themselves at all are treated as bots, every mainstream browser
+    /// sends a user agent.
+    pub fn is_bot(&self, user_agent: Option<&str>) -> bool {
+        let user_agent = match user_agent {
+            Some(ua) if !ua.trim().is_empty() => ua.to_lowercase(),
+            _ => return true,
+        };
+
+        self.patterns
+            .iter()
+            .any(|pattern| user_agent.contains(pattern.as_str()))
+    }
+
+    pub fn new(patterns: &[impl AsRef<str>]) -> Self {
+        let patterns = patterns
+            .iter()
+            .map(|p| p.as_ref().trim().to_lowercase())
+            .filter(|p| !p.is_empty())
+            .collect();
+
+        Self {
+            patterns: Arc::new(patterns),
+        }
+    }
+}
+
+impl Default for BotClassifier {
+    fn default() -> Self {
+        Self::new(DEFAULT_BOT_PATTERNS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_classification() {
+        let classifier = BotClassifier::default();
+
+        assert!(classifier.is_bot(Some(
+            "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)"
+        )));
+        assert!(classifier.is_bot(Some("curl/8.4.0")));
+        assert!(classifier.is_bot(None));
+        assert!(classifier.is_bot(Some("  ")));
+
+        assert!(!classifier.is_bot(Some(
+            "Mozilla/5.0 (X11; Linux x86_64; rv:124.0) Gecko/20100101 Firefox/124.0"
+        )));
+    }
+
+    #[test]
+    fn test_custom_patterns() {
+        let classifier = BotClassifier::new(&["InternalMonitor"]);
+
+        assert!(classifier.is_bot(Some("internalmonitor/1.0")));
+        assert!(!classifier.is_bot(Some("curl/8.4.0")));
+    }
+}
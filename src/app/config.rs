@@ -1,32 +1,223 @@
 use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use pico_args::Arguments;
 use tracing::Level;
 use url::Url;
 
-use crate::app::Version;
+use crate::app::{
+    LogFormat, LogFormatError, UploadLocation, UploadLocationError, Version, DEFAULT_BOT_PATTERNS,
+};
+use crate::auth::{
+    LoginAnomalySensitivity, LoginAnomalySensitivityError, SignupMode, SignupModeError,
+};
+use crate::background_jobs::impls::EmbedJob;
+use crate::background_jobs::{JobLike, QueueConfigs, QueueConfigsError};
+use crate::http_server::{RateLimits, RateLimitsError, RequestTimeouts};
+use crate::llm::{ModelDevice, ModelDeviceError};
+use crate::{ShutdownReason, ShutdownTimeouts};
 
-#[derive(Debug)]
+/// Uploads are allowed to be considerably larger than the global request limit, this is used when
+/// nothing else has been configured.
+const DEFAULT_MAX_UPLOAD_SIZE: usize = 16 * 1_024 * 1_024;
+
+/// Uploads are buffered in memory before being written out so this can't be raised arbitrarily.
+const MAX_UPLOAD_SIZE_CEILING: usize = 1_024 * 1_024 * 1_024;
+
+/// Hosts the service answers for when none have been configured, enough for local development.
+const DEFAULT_ALLOWED_HOSTS: &[&str] = &["localhost", "127.0.0.1", "::1"];
+
+/// Matches the size of tokio's blocking thread pool when it isn't configured.
+const DEFAULT_MAX_BLOCKING_THREADS: usize = 512;
+
+/// The most requests handled at once across all clients before new ones are turned away.
+const DEFAULT_CONCURRENCY_LIMIT: usize = 1_024;
+
+/// Responses smaller than this many bytes aren't worth the CPU time spent compressing them.
+const DEFAULT_COMPRESSION_MIN_SIZE: u16 = 1_024;
+
+/// How long a rotated API key keeps working in seconds, so requests already signed with it can
+/// finish.
+const DEFAULT_API_KEY_ROTATION_GRACE: u64 = 5 * 60;
+
+/// How far API clients' clocks may drift from ours in seconds before their tokens are rejected.
+const DEFAULT_API_TOKEN_CLOCK_SKEW: u64 = 15;
+
+/// The oldest an API token may be in seconds, regardless of when it claims to expire.
+const DEFAULT_API_TOKEN_MAX_AGE: u64 = 15 * 60;
+
+/// Failed logins aimed at a single account before it is temporarily locked.
+const DEFAULT_LOGIN_LOCKOUT_ATTEMPTS: u32 = 5;
+
+/// How far back failed logins are counted towards locking an account, in seconds.
+const DEFAULT_LOGIN_LOCKOUT_WINDOW: u64 = 15 * 60;
+
+/// Logins a single address may have started but not yet finished before it is refused more.
+const DEFAULT_OAUTH_STATE_LIMIT: u32 = 10;
+
+/// Where the database, service key, and uploads live when neither a data directory nor their
+/// individual paths have been configured.
+const DEFAULT_DATA_DIR: &str = "./data";
+
+/// What the service has been asked to do once its configuration is loaded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Command {
+    /// Run the service normally
+    #[default]
+    Serve,
+
+    /// Validate the runtime environment and exit without serving anything
+    Check,
+}
+
+#[derive(Clone, Debug)]
 pub struct Config {
+    command: Command,
+
+    internal_listen_addr: Option<SocketAddr>,
     listen_addr: SocketAddr,
+    log_format: LogFormat,
     log_level: Level,
 
+    admin_emails: Vec<String>,
+    allowed_hosts: Vec<String>,
+    background_run_retention: u32,
+    queue_configs: QueueConfigs,
+    bot_patterns: Vec<String>,
+
+    data_dir: Option<PathBuf>,
     database_url: Url,
+    database_connect_attempts: u8,
     smtp_url: Option<Url>,
 
+    compression_min_size: u16,
+    event_compression: bool,
+
+    api_key_rotation_grace: Duration,
+    api_token_clock_skew: Duration,
+    api_token_max_age: Duration,
+    github_client_id: Option<String>,
+    github_client_secret: Option<String>,
     google_client_id: String,
     google_client_secret: String,
+    login_anomaly_sensitivity: LoginAnomalySensitivity,
+    login_lockout_attempts: u32,
+    login_lockout_window: Duration,
+    oauth_retries: u8,
+    oauth_state_limit: u32,
+    secret_access_log_level: Level,
+    signup_mode: SignupMode,
+    allowed_email_domains: Vec<String>,
+
+    embed_job_timeout: Duration,
+    max_upload_size: usize,
+    model_device: ModelDevice,
+    model_device_fallback: bool,
+    ready_requires_model: bool,
 
+    server_timing: bool,
     service_key_path: PathBuf,
-    upload_directory: PathBuf,
+    session_cookie_host_prefix: bool,
+    upload_content_types: Vec<String>,
+    upload_location: UploadLocation,
+
+    concurrency_limit: usize,
+    rate_limits: RateLimits,
+    request_timeouts: RequestTimeouts,
+    user_concurrency_limit: usize,
+
+    max_blocking_threads: usize,
+    shutdown_timeouts: ShutdownTimeouts,
+    worker_threads: usize,
 }
 
 impl Config {
+    /// Emails of the users allowed to access the administrative endpoints.
+    pub fn admin_emails(&self) -> &[String] {
+        &self.admin_emails
+    }
+
+    /// Email domains new accounts may be created for when signups are restricted by domain.
+    pub fn allowed_email_domains(&self) -> &[String] {
+        &self.allowed_email_domains
+    }
+
+    /// The hostnames requests are allowed to be addressed to.
+    pub fn allowed_hosts(&self) -> &[String] {
+        &self.allowed_hosts
+    }
+
+    /// The number of finished runs kept for each background job, older runs are pruned.
+    /// How long an API key keeps working after it has been rotated.
+    pub fn api_key_rotation_grace(&self) -> Duration {
+        self.api_key_rotation_grace
+    }
+
+    /// How far an API client's clock may be from ours before its tokens are rejected.
+    pub fn api_token_clock_skew(&self) -> Duration {
+        self.api_token_clock_skew
+    }
+
+    /// The oldest an API token is accepted, no matter how far away its expiration is.
+    pub fn api_token_max_age(&self) -> Duration {
+        self.api_token_max_age
+    }
+
+    pub fn background_run_retention(&self) -> u32 {
+        self.background_run_retention
+    }
+
+    /// The number of workers to run for each background job queue.
+    pub fn queue_configs(&self) -> &QueueConfigs {
+        &self.queue_configs
+    }
+
+    /// User agent fragments used to identify automated clients.
+    pub fn bot_patterns(&self) -> &[String] {
+        &self.bot_patterns
+    }
+
+    /// The most requests a listener handles at once, requests beyond this are rejected as
+    /// overloaded rather than queued.
+    pub fn concurrency_limit(&self) -> usize {
+        self.concurrency_limit
+    }
+
+    /// The smallest response body, in bytes, that gets compressed for clients that support it.
+    pub fn compression_min_size(&self) -> u16 {
+        self.compression_min_size
+    }
+
+    pub fn command(&self) -> Command {
+        self.command
+    }
+
+    /// The root the default database, service key, and upload paths were placed under, when one
+    /// was configured.
+    pub fn data_dir(&self) -> Option<PathBuf> {
+        self.data_dir.clone()
+    }
+
+    /// How many times the initial connection to the database is attempted before giving up.
+    pub fn database_connect_attempts(&self) -> u8 {
+        self.database_connect_attempts
+    }
+
     pub fn database_url(&self) -> Url {
         self.database_url.clone()
     }
 
+    /// Whether clients of the event websocket are allowed to negotiate compressed messages.
+    pub fn embed_job_timeout(&self) -> Duration {
+        self.embed_job_timeout
+    }
+
+    pub fn event_compression(&self) -> bool {
+        self.event_compression
+    }
+
     pub fn from_env_and_args() -> Result<Self, ConfigError> {
         if dotenvy::dotenv().is_err() {
             tracing::warn!("no dotfile environment config files detected");
@@ -44,15 +235,46 @@ impl Config {
             std::process::exit(0);
         }
 
+        let command = match cli_args.subcommand()?.as_deref() {
+            None | Some("serve") => Command::Serve,
+            Some("check") => Command::Check,
+            Some(other) => return Err(ConfigError::UnknownCommand(other.to_string())),
+        };
+
+        let data_dir: Option<PathBuf> = match cli_args.opt_value_from_str("--data-dir")? {
+            Some(dd) => Some(dd),
+            None => match std::env::var("DATA_DIR") {
+                Ok(dd) if !dd.is_empty() => Some(PathBuf::from(dd)),
+                _ => None,
+            },
+        };
+        let data_root = data_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR));
+
         let database_str = match cli_args.opt_value_from_str("--db-url")? {
             Some(du) => du,
             None => match std::env::var("DATABASE_URL") {
                 Ok(du) if !du.is_empty() => du,
-                _ => "sqlite://./data/service.db".to_string(),
+                _ => format!("sqlite://{}", data_root.join("service.db").display()),
             },
         };
         let database_url = Url::parse(&database_str).map_err(ConfigError::InvalidDatabaseUrl)?;
 
+        let database_connect_attempts =
+            match cli_args.opt_value_from_str("--db-connect-attempts")? {
+                Some(dca) => dca,
+                None => match std::env::var("DATABASE_CONNECT_ATTEMPTS") {
+                    Ok(dca) if !dca.is_empty() => dca
+                        .parse()
+                        .map_err(ConfigError::InvalidDatabaseConnectAttempts)?,
+                    _ => 5,
+                },
+            };
+        if database_connect_attempts == 0 {
+            return Err(ConfigError::NoDatabaseConnectAttempts);
+        }
+
         let smtp_str = match cli_args.opt_value_from_str("--smtp-url")? {
             Some(du) => Some(du),
             None => match std::env::var("SMTP_URL") {
@@ -69,7 +291,7 @@ impl Config {
             Some(path) => path,
             None => match std::env::var("SERVICE_KEY") {
                 Ok(sk) if !sk.is_empty() => sk,
-                _ => "./data/service-key.pem".to_string(),
+                _ => data_root.join("service-key.pem").display().to_string(),
             },
         };
         let service_key_path = PathBuf::from(service_key_str);
@@ -78,10 +300,12 @@ impl Config {
             Some(path) => path,
             None => match std::env::var("UPLOAD_DIR") {
                 Ok(ud) if !ud.is_empty() => ud,
-                _ => "./data/uploads".to_string(),
+                _ => data_root.join("uploads").display().to_string(),
             },
         };
-        let upload_directory = PathBuf::from(upload_dir_str);
+        let upload_location = upload_dir_str
+            .parse()
+            .map_err(ConfigError::InvalidUploadLocation)?;
 
         let google_client_id = match std::env::var("GOOGLE_OAUTH_CLIENT_ID") {
             Ok(cid) if !cid.is_empty() => cid,
@@ -92,6 +316,44 @@ impl Config {
             _ => return Err(ConfigError::MissingGoogleClientSecret),
         };
 
+        // GitHub logins are optional, but only when neither half of the credential is present
+        let github_client_id = std::env::var("GITHUB_OAUTH_CLIENT_ID")
+            .ok()
+            .filter(|cid| !cid.is_empty());
+        let github_client_secret = std::env::var("GITHUB_OAUTH_CLIENT_SECRET")
+            .ok()
+            .filter(|cs| !cs.is_empty());
+        if github_client_id.is_some() != github_client_secret.is_some() {
+            return Err(ConfigError::IncompleteGithubCredentials);
+        }
+
+        let model_device_str = match cli_args.opt_value_from_str("--model-device")? {
+            Some(md) => md,
+            None => match std::env::var("MODEL_DEVICE") {
+                Ok(md) if !md.is_empty() => md,
+                _ => "cpu".to_string(),
+            },
+        };
+        let model_device: ModelDevice = model_device_str
+            .parse()
+            .map_err(ConfigError::InvalidModelDevice)?;
+
+        let model_device_fallback = cli_args.contains("--model-device-fallback")
+            || matches!(
+                std::env::var("MODEL_DEVICE_FALLBACK").as_deref(),
+                Ok("1") | Ok("true")
+            );
+
+        let embed_job_timeout = match cli_args.opt_value_from_str("--embed-job-timeout")? {
+            Some(ejt) => Duration::from_secs(ejt),
+            None => match std::env::var("EMBED_JOB_TIMEOUT") {
+                Ok(ejt) if !ejt.is_empty() => {
+                    Duration::from_secs(ejt.parse().map_err(ConfigError::InvalidEmbedJobTimeout)?)
+                }
+                _ => EmbedJob::EXECUTION_TIMEOUT,
+            },
+        };
+
         let listen_str = match cli_args.opt_value_from_str("--listen")? {
             Some(l) => l,
             None => match std::env::var("LISTEN_ADDR") {
@@ -101,25 +363,439 @@ impl Config {
         };
         let listen_addr: SocketAddr = listen_str.parse().map_err(ConfigError::InvalidListenAddr)?;
 
+        let internal_listen_str =
+            match cli_args.opt_value_from_str::<_, String>("--internal-listen")? {
+                Some(il) => Some(il),
+                None => match std::env::var("INTERNAL_LISTEN_ADDR") {
+                    Ok(il) if !il.is_empty() => Some(il),
+                    _ => None,
+                },
+            };
+        let internal_listen_addr = internal_listen_str
+            .map(|il| il.parse())
+            .transpose()
+            .map_err(ConfigError::InvalidInternalListenAddr)?;
+
+        let concurrency_limit = match cli_args.opt_value_from_str("--concurrency-limit")? {
+            Some(cl) => cl,
+            None => match std::env::var("CONCURRENCY_LIMIT") {
+                Ok(cl) if !cl.is_empty() => {
+                    cl.parse().map_err(ConfigError::InvalidConcurrencyLimit)?
+                }
+                _ => NonZeroUsize::new(DEFAULT_CONCURRENCY_LIMIT).expect("non-zero"),
+            },
+        };
+
+        let default_request_timeouts = RequestTimeouts::default();
+        let request_timeout = match cli_args.opt_value_from_str("--request-timeout")? {
+            Some(rt) => Duration::from_secs(rt),
+            None => match std::env::var("REQUEST_TIMEOUT") {
+                Ok(rt) if !rt.is_empty() => {
+                    Duration::from_secs(rt.parse().map_err(ConfigError::InvalidRequestTimeout)?)
+                }
+                _ => default_request_timeouts.standard(),
+            },
+        };
+        let long_request_timeout = match cli_args.opt_value_from_str("--long-request-timeout")? {
+            Some(lrt) => Duration::from_secs(lrt),
+            None => match std::env::var("LONG_REQUEST_TIMEOUT") {
+                Ok(lrt) if !lrt.is_empty() => Duration::from_secs(
+                    lrt.parse()
+                        .map_err(ConfigError::InvalidLongRequestTimeout)?,
+                ),
+                _ => default_request_timeouts.long_running(),
+            },
+        };
+        let request_timeouts = RequestTimeouts::new(request_timeout, long_request_timeout);
+
+        let rate_limits = match cli_args.opt_value_from_str::<_, String>("--rate-limits")? {
+            Some(rl) => Some(rl),
+            None => match std::env::var("RATE_LIMITS") {
+                Ok(rl) if !rl.is_empty() => Some(rl),
+                _ => None,
+            },
+        };
+        let rate_limits = match rate_limits {
+            Some(rl) => rl.parse().map_err(ConfigError::InvalidRateLimits)?,
+            None => RateLimits::default(),
+        };
+
+        let user_concurrency_limit =
+            match cli_args.opt_value_from_str("--user-concurrency-limit")? {
+                Some(ucl) => ucl,
+                None => match std::env::var("USER_CONCURRENCY_LIMIT") {
+                    Ok(ucl) if !ucl.is_empty() => ucl
+                        .parse()
+                        .map_err(ConfigError::InvalidUserConcurrencyLimit)?,
+                    _ => 16,
+                },
+            };
+
+        let admin_emails = match cli_args.opt_value_from_str("--admin-emails")? {
+            Some(ae) => Some(ae),
+            None => match std::env::var("ADMIN_EMAILS") {
+                Ok(ae) if !ae.is_empty() => Some(ae),
+                _ => None,
+            },
+        };
+        let admin_emails = match admin_emails {
+            Some(ae) => ae.split(',').map(|e| e.trim().to_string()).collect(),
+            None => Vec::new(),
+        };
+
+        let allowed_hosts_str = match cli_args.opt_value_from_str("--allowed-hosts")? {
+            Some(ah) => Some(ah),
+            None => match std::env::var("ALLOWED_HOSTS") {
+                Ok(ah) if !ah.is_empty() => Some(ah),
+                _ => None,
+            },
+        };
+        let allowed_hosts = match allowed_hosts_str {
+            Some(ah) => ah.split(',').map(|h| h.trim().to_string()).collect(),
+            None => DEFAULT_ALLOWED_HOSTS
+                .iter()
+                .map(|h| h.to_string())
+                .collect(),
+        };
+
+        let background_run_retention =
+            match cli_args.opt_value_from_str("--background-run-retention")? {
+                Some(brr) => brr,
+                None => match std::env::var("BACKGROUND_RUN_RETENTION") {
+                    Ok(brr) if !brr.is_empty() => brr
+                        .parse()
+                        .map_err(ConfigError::InvalidBackgroundRunRetention)?,
+                    _ => 10,
+                },
+            };
+
+        let queue_configs = match cli_args.opt_value_from_str::<_, String>("--queue-workers")? {
+            Some(qw) => Some(qw),
+            None => match std::env::var("QUEUE_WORKERS") {
+                Ok(qw) if !qw.is_empty() => Some(qw),
+                _ => None,
+            },
+        };
+        let queue_configs = match queue_configs {
+            Some(qw) => qw.parse().map_err(ConfigError::InvalidQueueWorkers)?,
+            None => QueueConfigs::default(),
+        };
+
+        let bot_patterns_str = match cli_args.opt_value_from_str("--bot-patterns")? {
+            Some(bp) => Some(bp),
+            None => match std::env::var("BOT_USER_AGENT_PATTERNS") {
+                Ok(bp) if !bp.is_empty() => Some(bp),
+                _ => None,
+            },
+        };
+        let bot_patterns = match bot_patterns_str {
+            Some(bp) => bp.split(',').map(|p| p.trim().to_string()).collect(),
+            None => DEFAULT_BOT_PATTERNS.iter().map(|p| p.to_string()).collect(),
+        };
+
+        let api_key_rotation_grace =
+            match cli_args.opt_value_from_str("--api-key-rotation-grace")? {
+                Some(akrg) => akrg,
+                None => match std::env::var("API_KEY_ROTATION_GRACE") {
+                    Ok(akrg) if !akrg.is_empty() => akrg
+                        .parse()
+                        .map_err(ConfigError::InvalidApiKeyRotationGrace)?,
+                    _ => DEFAULT_API_KEY_ROTATION_GRACE,
+                },
+            };
+        let api_key_rotation_grace = Duration::from_secs(api_key_rotation_grace);
+
+        let api_token_clock_skew = match cli_args.opt_value_from_str("--api-token-clock-skew")? {
+            Some(atcs) => atcs,
+            None => match std::env::var("API_TOKEN_CLOCK_SKEW") {
+                Ok(atcs) if !atcs.is_empty() => atcs
+                    .parse()
+                    .map_err(ConfigError::InvalidApiTokenClockSkew)?,
+                _ => DEFAULT_API_TOKEN_CLOCK_SKEW,
+            },
+        };
+        let api_token_clock_skew = Duration::from_secs(api_token_clock_skew);
+
+        let api_token_max_age = match cli_args.opt_value_from_str("--api-token-max-age")? {
+            Some(atma) => atma,
+            None => match std::env::var("API_TOKEN_MAX_AGE") {
+                Ok(atma) if !atma.is_empty() => {
+                    atma.parse().map_err(ConfigError::InvalidApiTokenMaxAge)?
+                }
+                _ => DEFAULT_API_TOKEN_MAX_AGE,
+            },
+        };
+        let api_token_max_age = Duration::from_secs(api_token_max_age);
+
+        let login_anomaly_sensitivity =
+            match cli_args.opt_value_from_str::<_, String>("--login-anomaly-sensitivity")? {
+                Some(las) => Some(las),
+                None => match std::env::var("LOGIN_ANOMALY_SENSITIVITY") {
+                    Ok(las) if !las.is_empty() => Some(las),
+                    _ => None,
+                },
+            };
+        let login_anomaly_sensitivity = match login_anomaly_sensitivity {
+            Some(las) => las
+                .parse()
+                .map_err(ConfigError::InvalidLoginAnomalySensitivity)?,
+            None => LoginAnomalySensitivity::default(),
+        };
+
+        let login_lockout_attempts =
+            match cli_args.opt_value_from_str("--login-lockout-attempts")? {
+                Some(lla) => lla,
+                None => match std::env::var("LOGIN_LOCKOUT_ATTEMPTS") {
+                    Ok(lla) if !lla.is_empty() => lla
+                        .parse()
+                        .map_err(ConfigError::InvalidLoginLockoutAttempts)?,
+                    _ => DEFAULT_LOGIN_LOCKOUT_ATTEMPTS,
+                },
+            };
+
+        let login_lockout_window = match cli_args.opt_value_from_str("--login-lockout-window")? {
+            Some(llw) => llw,
+            None => match std::env::var("LOGIN_LOCKOUT_WINDOW") {
+                Ok(llw) if !llw.is_empty() => llw
+                    .parse()
+                    .map_err(ConfigError::InvalidLoginLockoutWindow)?,
+                _ => DEFAULT_LOGIN_LOCKOUT_WINDOW,
+            },
+        };
+        let login_lockout_window = Duration::from_secs(login_lockout_window);
+
+        let oauth_retries = match cli_args.opt_value_from_str("--oauth-retries")? {
+            Some(or) => or,
+            None => match std::env::var("OAUTH_RETRIES") {
+                Ok(or) if !or.is_empty() => or.parse().map_err(ConfigError::InvalidOAuthRetries)?,
+                _ => 2,
+            },
+        };
+
+        let oauth_state_limit = match cli_args.opt_value_from_str("--oauth-state-limit")? {
+            Some(osl) => osl,
+            None => match std::env::var("OAUTH_STATE_LIMIT") {
+                Ok(osl) if !osl.is_empty() => {
+                    osl.parse().map_err(ConfigError::InvalidOAuthStateLimit)?
+                }
+                _ => DEFAULT_OAUTH_STATE_LIMIT,
+            },
+        };
+
+        let secret_access_log_level =
+            match cli_args.opt_value_from_str("--secret-access-log-level")? {
+                Some(sall) => sall,
+                None => match std::env::var("SECRET_ACCESS_LOG_LEVEL") {
+                    Ok(sall) if !sall.is_empty() => sall
+                        .parse()
+                        .map_err(ConfigError::InvalidSecretAccessLogLevel)?,
+                    _ => Level::DEBUG,
+                },
+            };
+
+        let signup_mode = match cli_args.opt_value_from_str::<_, String>("--signup-mode")? {
+            Some(sm) => Some(sm),
+            None => match std::env::var("SIGNUP_MODE") {
+                Ok(sm) if !sm.is_empty() => Some(sm),
+                _ => None,
+            },
+        };
+        let signup_mode = match signup_mode {
+            Some(sm) => sm.parse().map_err(ConfigError::InvalidSignupMode)?,
+            None => SignupMode::default(),
+        };
+
+        let allowed_email_domains = match cli_args.opt_value_from_str("--allowed-email-domains")? {
+            Some(aed) => Some(aed),
+            None => match std::env::var("ALLOWED_EMAIL_DOMAINS") {
+                Ok(aed) if !aed.is_empty() => Some(aed),
+                _ => None,
+            },
+        };
+        let allowed_email_domains: Vec<String> = match allowed_email_domains {
+            Some(aed) => aed
+                .split(',')
+                .map(|d| d.trim().to_string())
+                .filter(|d| !d.is_empty())
+                .collect(),
+            None => Vec::new(),
+        };
+        if signup_mode == SignupMode::Domain && allowed_email_domains.is_empty() {
+            return Err(ConfigError::MissingAllowedEmailDomains);
+        }
+
+        let max_upload_size = match cli_args.opt_value_from_str("--max-upload-size")? {
+            Some(mus) => mus,
+            None => match std::env::var("MAX_UPLOAD_SIZE") {
+                Ok(mus) if !mus.is_empty() => {
+                    mus.parse().map_err(ConfigError::InvalidMaxUploadSize)?
+                }
+                _ => DEFAULT_MAX_UPLOAD_SIZE,
+            },
+        };
+        if max_upload_size > MAX_UPLOAD_SIZE_CEILING {
+            return Err(ConfigError::MaxUploadSizeTooLarge(max_upload_size));
+        }
+
+        let upload_content_types = match cli_args.opt_value_from_str("--upload-content-types")? {
+            Some(uct) => Some(uct),
+            None => match std::env::var("UPLOAD_CONTENT_TYPES") {
+                Ok(uct) if !uct.is_empty() => Some(uct),
+                _ => None,
+            },
+        };
+        let upload_content_types: Vec<String> = match upload_content_types {
+            Some(uct) => uct
+                .split(',')
+                .map(|ct| ct.trim().to_string())
+                .filter(|ct| !ct.is_empty())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let ready_requires_model = cli_args.contains("--ready-requires-model")
+            || matches!(
+                std::env::var("READY_REQUIRES_MODEL").as_deref(),
+                Ok("1") | Ok("true")
+            );
+
+        let compression_min_size = match cli_args.opt_value_from_str("--compression-min-size")? {
+            Some(cms) => cms,
+            None => match std::env::var("COMPRESSION_MIN_SIZE") {
+                Ok(cms) if !cms.is_empty() => cms
+                    .parse()
+                    .map_err(ConfigError::InvalidCompressionMinSize)?,
+                _ => DEFAULT_COMPRESSION_MIN_SIZE,
+            },
+        };
+
+        let event_compression = cli_args.contains("--event-compression")
+            || matches!(
+                std::env::var("EVENT_COMPRESSION").as_deref(),
+                Ok("1") | Ok("true")
+            );
+
+        let server_timing = cli_args.contains("--server-timing")
+            || matches!(
+                std::env::var("SERVER_TIMING").as_deref(),
+                Ok("1") | Ok("true")
+            );
+
+        let session_cookie_host_prefix = cli_args.contains("--session-cookie-host-prefix")
+            || matches!(
+                std::env::var("SESSION_COOKIE_HOST_PREFIX").as_deref(),
+                Ok("1") | Ok("true")
+            );
+
+        let worker_threads = match cli_args.opt_value_from_str("--worker-threads")? {
+            Some(wt) => wt,
+            None => match std::env::var("TOKIO_WORKER_THREADS") {
+                Ok(wt) if !wt.is_empty() => {
+                    wt.parse().map_err(ConfigError::InvalidWorkerThreads)?
+                }
+                // Same as tokio's own default of one worker per core
+                _ => std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN),
+            },
+        };
+
+        let max_blocking_threads = match cli_args.opt_value_from_str("--max-blocking-threads")? {
+            Some(mbt) => mbt,
+            None => match std::env::var("MAX_BLOCKING_THREADS") {
+                Ok(mbt) if !mbt.is_empty() => mbt
+                    .parse()
+                    .map_err(ConfigError::InvalidMaxBlockingThreads)?,
+                _ => NonZeroUsize::new(DEFAULT_MAX_BLOCKING_THREADS).expect("non-zero"),
+            },
+        };
+
+        let shutdown_timeouts = shutdown_timeouts_from_args(&mut cli_args)?;
+
+        let log_format = match cli_args.opt_value_from_str::<_, String>("--log-format")? {
+            Some(lf) => Some(lf),
+            None => match std::env::var("LOG_FORMAT") {
+                Ok(lf) if !lf.is_empty() => Some(lf),
+                _ => None,
+            },
+        };
+        let log_format = match log_format {
+            Some(lf) => lf.parse().map_err(ConfigError::InvalidLogFormat)?,
+            None => LogFormat::default(),
+        };
+
         let log_level = cli_args
             .opt_value_from_str("--log-level")?
             .unwrap_or(Level::INFO);
 
         Ok(Config {
+            command,
+
+            internal_listen_addr,
             listen_addr,
+            log_format,
             log_level,
 
+            admin_emails,
+            allowed_hosts,
+            background_run_retention,
+            queue_configs,
+            bot_patterns,
+
+            data_dir,
             database_url,
+            database_connect_attempts,
             smtp_url,
 
+            compression_min_size,
+            event_compression,
+
+            api_key_rotation_grace,
+            api_token_clock_skew,
+            api_token_max_age,
+            github_client_id,
+            github_client_secret,
             google_client_id,
             google_client_secret,
+            login_anomaly_sensitivity,
+            login_lockout_attempts,
+            login_lockout_window,
+            oauth_retries,
+            oauth_state_limit,
+            secret_access_log_level,
+            signup_mode,
+            allowed_email_domains,
+
+            embed_job_timeout,
+            max_upload_size,
+            model_device,
+            model_device_fallback,
+            ready_requires_model,
 
+            server_timing,
             service_key_path,
-            upload_directory,
+            session_cookie_host_prefix,
+            upload_content_types,
+            upload_location,
+
+            concurrency_limit: concurrency_limit.get(),
+            rate_limits,
+            request_timeouts,
+            user_concurrency_limit,
+
+            max_blocking_threads: max_blocking_threads.get(),
+            shutdown_timeouts,
+            worker_threads: worker_threads.get(),
         })
     }
 
+    /// The client ID for GitHub logins, which are only offered when this is set.
+    pub fn github_client_id(&self) -> Option<&str> {
+        self.github_client_id.as_deref()
+    }
+
+    pub fn github_client_secret(&self) -> Option<&str> {
+        self.github_client_secret.as_deref()
+    }
+
     pub fn google_client_id(&self) -> &str {
         self.google_client_id.as_str()
     }
@@ -128,24 +804,143 @@ impl Config {
         self.google_client_secret.as_str()
     }
 
+    /// A separate address to serve the administrative and detailed status endpoints on. When not
+    /// set everything is served from the main listening address.
+    pub fn internal_listen_addr(&self) -> Option<&SocketAddr> {
+        self.internal_listen_addr.as_ref()
+    }
+
     pub fn listen_addr(&self) -> &SocketAddr {
         &self.listen_addr
     }
 
+    /// How different a login's address needs to be from the user's recent sessions before it is
+    /// reported as suspicious.
+    pub fn login_anomaly_sensitivity(&self) -> LoginAnomalySensitivity {
+        self.login_anomaly_sensitivity
+    }
+
+    /// Failed logins aimed at an account within the lockout window before further logins to it
+    /// are refused, zero disables lockouts.
+    pub fn login_lockout_attempts(&self) -> u32 {
+        self.login_lockout_attempts
+    }
+
+    /// How far back failed logins are counted towards locking an account.
+    pub fn login_lockout_window(&self) -> Duration {
+        self.login_lockout_window
+    }
+
+    /// How many more times the requests made to a login provider while completing a login are
+    /// attempted after failing with a network error.
+    pub fn oauth_retries(&self) -> u8 {
+        self.oauth_retries
+    }
+
+    /// Logins a single address may have in progress at once before new ones are refused.
+    pub fn oauth_state_limit(&self) -> u32 {
+        self.oauth_state_limit
+    }
+
+    /// Whether log lines are written for people (compact) or log aggregators (json).
+    pub fn log_format(&self) -> LogFormat {
+        self.log_format
+    }
+
     pub fn log_level(&self) -> Level {
         self.log_level
     }
 
+    /// The most threads the async runtime will start for blocking work such as database calls and
+    /// model inference.
+    pub fn max_blocking_threads(&self) -> usize {
+        self.max_blocking_threads
+    }
+
+    /// The largest request body accepted by the upload route in bytes.
+    pub fn max_upload_size(&self) -> usize {
+        self.max_upload_size
+    }
+
+    pub fn model_device(&self) -> ModelDevice {
+        self.model_device
+    }
+
+    /// Whether models should fall back to running on the CPU when the configured device isn't
+    /// available instead of failing to start.
+    pub fn model_device_fallback(&self) -> bool {
+        self.model_device_fallback
+    }
+
+    /// How quickly each client may make requests to each group of routes.
+    pub fn rate_limits(&self) -> &RateLimits {
+        &self.rate_limits
+    }
+
+    /// How long requests may take before they are cut off.
+    pub fn request_timeouts(&self) -> RequestTimeouts {
+        self.request_timeouts
+    }
+
+    /// Whether the readiness check should fail until the embedding model has been loaded.
+    pub fn ready_requires_model(&self) -> bool {
+        self.ready_requires_model
+    }
+
+    /// Whether responses should include a `Server-Timing` header describing where time was spent
+    /// handling the request.
+    pub fn server_timing(&self) -> bool {
+        self.server_timing
+    }
+
+    /// The level used to record each time a secret such as the service signing key is read.
+    pub fn secret_access_log_level(&self) -> Level {
+        self.secret_access_log_level
+    }
+
     pub fn service_key_path(&self) -> PathBuf {
         self.service_key_path.clone()
     }
 
+    /// Whether clients connecting over HTTPS should receive a `__Host-` prefixed session cookie.
+    pub fn session_cookie_host_prefix(&self) -> bool {
+        self.session_cookie_host_prefix
+    }
+
+    /// Who is allowed to create an account by logging in for the first time.
+    pub fn signup_mode(&self) -> SignupMode {
+        self.signup_mode
+    }
+
+    /// How long the service keeps serving after being asked to stop and how long each part of it
+    /// then has to finish up.
+    pub fn shutdown_timeouts(&self) -> ShutdownTimeouts {
+        self.shutdown_timeouts
+    }
+
     pub fn smtp_url(&self) -> Option<Url> {
         self.smtp_url.clone()
     }
 
-    pub fn upload_directory(&self) -> PathBuf {
-        self.upload_directory.clone()
+    /// The content types uploads are allowed to have, any type is accepted when this is empty.
+    pub fn upload_content_types(&self) -> &[String] {
+        &self.upload_content_types
+    }
+
+    /// Where uploaded client data is stored, either a local directory or a remote object store.
+    pub fn upload_location(&self) -> UploadLocation {
+        self.upload_location.clone()
+    }
+
+    /// The maximum number of requests a single user (or anonymous client) can have in flight at
+    /// once.
+    pub fn user_concurrency_limit(&self) -> usize {
+        self.user_concurrency_limit
+    }
+
+    /// The number of threads the async runtime uses to drive tasks.
+    pub fn worker_threads(&self) -> usize {
+        self.worker_threads
     }
 }
 
@@ -157,46 +952,399 @@ pub enum ConfigError {
     #[error("unable to read environment details: {0}")]
     EnvironmentUnavailable(dotenvy::Error),
 
+    #[error("both a github auth client ID and secret need to be provided to enable github logins")]
+    IncompleteGithubCredentials,
+
+    #[error("invalid API key rotation grace period: {0}")]
+    InvalidApiKeyRotationGrace(std::num::ParseIntError),
+
+    #[error("invalid API token clock skew: {0}")]
+    InvalidApiTokenClockSkew(std::num::ParseIntError),
+
+    #[error("invalid API token maximum age: {0}")]
+    InvalidApiTokenMaxAge(std::num::ParseIntError),
+
+    #[error("invalid background run retention: {0}")]
+    InvalidBackgroundRunRetention(std::num::ParseIntError),
+
+    #[error("invalid compression minimum size: {0}")]
+    InvalidCompressionMinSize(std::num::ParseIntError),
+
+    #[error("invalid concurrency limit: {0}")]
+    InvalidConcurrencyLimit(std::num::ParseIntError),
+
+    #[error("invalid queue worker declaration: {0}")]
+    InvalidQueueWorkers(QueueConfigsError),
+
+    #[error("invalid rate limit declaration: {0}")]
+    InvalidRateLimits(RateLimitsError),
+
+    #[error("invalid database connection attempt count: {0}")]
+    InvalidDatabaseConnectAttempts(std::num::ParseIntError),
+
     #[error("invalid database URL: {0}")]
     InvalidDatabaseUrl(url::ParseError),
 
+    #[error("invalid embedding job timeout: {0}")]
+    InvalidEmbedJobTimeout(std::num::ParseIntError),
+
+    #[error("invalid shutdown grace period: {0}")]
+    InvalidGracePeriod(std::num::ParseIntError),
+
     #[error("invalid mail server URL: {0}")]
     InvalidSmtpUrl(url::ParseError),
 
+    #[error("invalid internal listening address: {0}")]
+    InvalidInternalListenAddr(std::net::AddrParseError),
+
     #[error("invalid listening address: {0}")]
     InvalidListenAddr(std::net::AddrParseError),
 
+    #[error("invalid log format: {0}")]
+    InvalidLogFormat(LogFormatError),
+
+    #[error("invalid long running request timeout: {0}")]
+    InvalidLongRequestTimeout(std::num::ParseIntError),
+
+    #[error("invalid login anomaly sensitivity: {0}")]
+    InvalidLoginAnomalySensitivity(LoginAnomalySensitivityError),
+
+    #[error("invalid login lockout attempt count: {0}")]
+    InvalidLoginLockoutAttempts(std::num::ParseIntError),
+
+    #[error("invalid login lockout window: {0}")]
+    InvalidLoginLockoutWindow(std::num::ParseIntError),
+
+    #[error("invalid maximum blocking thread count: {0}")]
+    InvalidMaxBlockingThreads(std::num::ParseIntError),
+
+    #[error("invalid maximum upload size: {0}")]
+    InvalidMaxUploadSize(std::num::ParseIntError),
+
+    #[error("invalid model device: {0}")]
+    InvalidModelDevice(ModelDeviceError),
+
+    #[error("invalid OAuth retry count: {0}")]
+    InvalidOAuthRetries(std::num::ParseIntError),
+
+    #[error("invalid OAuth state limit: {0}")]
+    InvalidOAuthStateLimit(std::num::ParseIntError),
+
+    #[error("invalid request timeout: {0}")]
+    InvalidRequestTimeout(std::num::ParseIntError),
+
+    #[error("invalid secret access log level: {0}")]
+    InvalidSecretAccessLogLevel(tracing::metadata::ParseLevelError),
+
+    #[error("invalid shutdown timeout: {0}")]
+    InvalidShutdownTimeout(std::num::ParseIntError),
+
+    #[error("invalid signup mode: {0}")]
+    InvalidSignupMode(SignupModeError),
+
+    #[error("invalid upload location: {0}")]
+    InvalidUploadLocation(UploadLocationError),
+
+    #[error("invalid per-user concurrency limit: {0}")]
+    InvalidUserConcurrencyLimit(std::num::ParseIntError),
+
+    #[error("invalid runtime worker thread count: {0}")]
+    InvalidWorkerThreads(std::num::ParseIntError),
+
+    #[error("invalid worker shutdown timeout: {0}")]
+    InvalidWorkerShutdownTimeout(std::num::ParseIntError),
+
+    #[error(
+        "maximum upload size of {0} bytes exceeds the limit of {MAX_UPLOAD_SIZE_CEILING} bytes"
+    )]
+    MaxUploadSizeTooLarge(usize),
+
+    #[error("at least one allowed email domain needs to be provided when signups are restricted by domain")]
+    MissingAllowedEmailDomains,
+
     #[error("a google auth client ID needs to be provided")]
     MissingGoogleClientId,
 
     #[error("a google auth client secret needs to be provided")]
     MissingGoogleClientSecret,
+
+    #[error("at least one database connection attempt needs to be allowed")]
+    NoDatabaseConnectAttempts,
+
+    #[error("unknown command '{0}', expected 'serve' or 'check'")]
+    UnknownCommand(String),
+
+    #[error("the shutdown timeout needs to be at least one second for work to drain")]
+    ZeroShutdownTimeout,
+}
+
+/// The shutdown timing options are all whole seconds and default to whatever the service used
+/// before they were configurable.
+fn shutdown_timeouts_from_args(cli_args: &mut Arguments) -> Result<ShutdownTimeouts, ConfigError> {
+    let defaults = ShutdownTimeouts::default();
+
+    let grace_period = match cli_args.opt_value_from_str("--grace-period")? {
+        Some(gp) => Duration::from_secs(gp),
+        None => match std::env::var("GRACE_PERIOD") {
+            Ok(gp) if !gp.is_empty() => {
+                Duration::from_secs(gp.parse().map_err(ConfigError::InvalidGracePeriod)?)
+            }
+            _ => defaults.grace_period(ShutdownReason::Terminate),
+        },
+    };
+
+    let shutdown_timeout = match cli_args.opt_value_from_str("--shutdown-timeout")? {
+        Some(st) => Duration::from_secs(st),
+        None => match std::env::var("SHUTDOWN_TIMEOUT") {
+            Ok(st) if !st.is_empty() => {
+                Duration::from_secs(st.parse().map_err(ConfigError::InvalidShutdownTimeout)?)
+            }
+            _ => defaults.drain_timeout(ShutdownReason::Terminate),
+        },
+    };
+
+    // Going over the termination budget is only warned about once logging is up, deployments may
+    // have given us longer. No time at all to drain is never what anyone wants.
+    if shutdown_timeout.is_zero() {
+        return Err(ConfigError::ZeroShutdownTimeout);
+    }
+
+    let worker_shutdown_timeout = match cli_args.opt_value_from_str("--worker-shutdown-timeout")? {
+        Some(wst) => Duration::from_secs(wst),
+        None => match std::env::var("WORKER_SHUTDOWN_TIMEOUT") {
+            Ok(wst) if !wst.is_empty() => Duration::from_secs(
+                wst.parse()
+                    .map_err(ConfigError::InvalidWorkerShutdownTimeout)?,
+            ),
+            _ => defaults.worker_shutdown_timeout(),
+        },
+    };
+
+    Ok(ShutdownTimeouts::new(
+        grace_period,
+        shutdown_timeout,
+        worker_shutdown_timeout,
+    ))
 }
 
 fn print_help() {
+    println!("Usage: web-app-template [serve|check] [options]\n");
+    println!("  Commands:");
+    println!("    serve                         Run the service (default)");
+    println!("    check                         Validate the config, database, service key,");
+    println!("                                  directories, and model availability then exit");
+    println!("                                  non-zero if anything isn't usable\n");
     println!("Service may be configured using the environment or CLI flags\n");
     println!("  Available options:");
     println!("    -h, --help                    Print this notice and exit");
     println!("    -v, --version                 Display the version of this compiled version");
     println!("                                  and exit\n");
+    println!("    --log-format, LOG_FORMAT      How log lines are written, either compact for");
+    println!("                                  local development or json for log aggregation");
+    println!("                                  (default compact)");
     println!(
         "    --listen, LISTEN_ADDR         Specify the address to bind to (default [::]:3000)"
     );
+    println!("    --internal-listen, INTERNAL_LISTEN_ADDR");
+    println!("                                  Serve the admin and detailed status endpoints");
+    println!("                                  on this separate address instead, leaving only");
+    println!("                                  the liveness check on the main listener");
+    println!("    --compression-min-size, COMPRESSION_MIN_SIZE");
+    println!("                                  Smallest response in bytes compressed for");
+    println!("                                  clients that accept it (default 1024)");
+    println!("    --event-compression, EVENT_COMPRESSION");
+    println!("                                  Allow event websocket clients to negotiate gzip");
+    println!("                                  compression of large messages");
+    println!("    --server-timing, SERVER_TIMING");
+    println!("                                  Include a Server-Timing header in responses for");
+    println!("                                  debugging, should not be enabled in production");
+    println!("    --data-dir, DATA_DIR          Directory holding the database, service key, and");
+    println!("                                  uploads unless their paths are set individually,");
+    println!("                                  created at startup if missing (default ./data)");
     println!("    --service-key, SERVICE_KEY    Path to the p384 private key used for signatures");
-    println!("    --upload-dir, UPLOAD_DIR      Path used to store uploaded client data\n");
+    println!("    --session-cookie-host-prefix, SESSION_COOKIE_HOST_PREFIX");
+    println!("                                  Name the session cookie with a __Host- prefix");
+    println!("                                  for HTTPS clients, existing sessions will need");
+    println!("                                  to log in again when this is changed");
+    println!("    --upload-dir, UPLOAD_DIR      Path used to store uploaded client data, or a");
+    println!("                                  file:// or s3://bucket/prefix URL");
+    println!("    --max-upload-size, MAX_UPLOAD_SIZE");
+    println!("                                  Largest accepted upload in bytes (default 16MiB,");
+    println!("                                  may not exceed 1GiB)");
+    println!("    --upload-content-types, UPLOAD_CONTENT_TYPES");
+    println!("                                  Comma separated content types uploads may have,");
+    println!("                                  any type is accepted when empty (default empty)\n");
     println!("    --db-url, DATABASE_URL        Configure the url and settings of the sqlite");
-    println!("                                  database (default in ./data/service.db)");
+    println!("                                  database (default service.db in the data dir)");
+    println!("    --db-connect-attempts, DATABASE_CONNECT_ATTEMPTS");
+    println!("                                  Times to try connecting to and migrating the");
+    println!("                                  database at startup before failing (default 5)");
+    println!("    --concurrency-limit, CONCURRENCY_LIMIT");
+    println!("                                  Maximum number of in-flight requests across all");
+    println!("                                  clients, more are rejected (default 1024)");
+    println!("    --request-timeout, REQUEST_TIMEOUT");
+    println!("                                  Seconds a request may take before it is cut");
+    println!("                                  off (default 5)");
+    println!("    --long-request-timeout, LONG_REQUEST_TIMEOUT");
+    println!("                                  Seconds uploads and embedding requests may take");
+    println!("                                  before they are cut off (default 300)");
+    println!("    --rate-limits, RATE_LIMITS    Comma separated request rates allowed for each");
+    println!("                                  client per route group (api, auth, events,");
+    println!("                                  pages) such as 'auth=20/60' for 20 requests");
+    println!("                                  every 60 seconds, unlisted groups keep defaults");
+    println!("    --user-concurrency-limit, USER_CONCURRENCY_LIMIT");
+    println!("                                  Maximum number of in-flight requests allowed for");
+    println!("                                  a single user or client (default 16)");
+    println!("    --model-device, MODEL_DEVICE  Compute device used to run models, one of cpu,");
+    println!("                                  cuda[:N], or metal[:N] (default cpu)");
+    println!("    --model-device-fallback, MODEL_DEVICE_FALLBACK");
+    println!("                                  Run models on the CPU with a warning if the");
+    println!("                                  requested device isn't available instead of");
+    println!("                                  failing to start");
+    println!("    --ready-requires-model, READY_REQUIRES_MODEL");
+    println!("                                  Report the service as not ready until the");
+    println!("                                  embedding model has finished loading");
+    println!("    --embed-job-timeout, EMBED_JOB_TIMEOUT");
+    println!("                                  Seconds a background embedding job may run");
+    println!("                                  before it is cancelled (default 600)\n");
+    println!("    --worker-threads, TOKIO_WORKER_THREADS");
+    println!("                                  Number of threads driving async tasks (default");
+    println!("                                  one per CPU core)");
+    println!("    --max-blocking-threads, MAX_BLOCKING_THREADS");
+    println!("                                  Most threads started for blocking database and");
+    println!("                                  model work (default 512)\n");
+    println!("    --grace-period, GRACE_PERIOD  Seconds to keep serving requests after SIGTERM");
+    println!("                                  while load balancers stop routing to us");
+    println!("                                  (default 10)");
+    println!("    --shutdown-timeout, SHUTDOWN_TIMEOUT");
+    println!("                                  Seconds in-progress work has to finish once");
+    println!("                                  shutdown begins before exiting (default 15)");
+    println!("    --worker-shutdown-timeout, WORKER_SHUTDOWN_TIMEOUT");
+    println!("                                  Extra seconds background workers have to stop");
+    println!("                                  beyond their job drain timeout (default 5)\n");
+    println!("    --admin-emails, ADMIN_EMAILS  Comma separated emails of the users allowed to");
+    println!("                                  access the admin endpoints (default none)");
+    println!("    --allowed-hosts, ALLOWED_HOSTS");
+    println!("                                  Comma separated hostnames requests may be sent");
+    println!("                                  to, '*.example.com' matches subdomains and '*'");
+    println!("                                  anything (default localhost only)");
+    println!("    --background-run-retention, BACKGROUND_RUN_RETENTION");
+    println!("                                  Number of finished runs kept for each background");
+    println!("                                  job, older runs are pruned (default 10)");
+    println!("    --queue-workers, QUEUE_WORKERS");
+    println!("                                  Comma separated worker counts for background");
+    println!("                                  job queues such as 'default=4,embedding=1',");
+    println!("                                  unlisted queues get a single worker");
+    println!("    --bot-patterns, BOT_USER_AGENT_PATTERNS");
+    println!("                                  Comma separated user agent fragments that mark");
+    println!("                                  a client as a bot, replaces the built-in list\n");
+    println!("    --api-key-rotation-grace, API_KEY_ROTATION_GRACE");
+    println!("                                  Seconds a rotated API key keeps working so");
+    println!("                                  requests signed with it can finish (default 300)");
+    println!("    --api-token-clock-skew, API_TOKEN_CLOCK_SKEW");
+    println!("                                  Seconds an API client's clock may differ from");
+    println!("                                  ours before its tokens are rejected (default 15)");
+    println!("    --api-token-max-age, API_TOKEN_MAX_AGE");
+    println!("                                  Oldest accepted API token in seconds, regardless");
+    println!("                                  of its expiration (default 900)");
+    println!("    --login-anomaly-sensitivity, LOGIN_ANOMALY_SENSITIVITY");
+    println!("                                  Report logins from networks unlike the user's");
+    println!("                                  recent sessions, one of disabled, low, or high");
+    println!("                                  (default low)");
+    println!("    --login-lockout-attempts, LOGIN_LOCKOUT_ATTEMPTS");
+    println!("                                  Failed logins aimed at an account before logins");
+    println!("                                  to it are temporarily refused, 0 disables");
+    println!("                                  lockouts (default 5)");
+    println!("    --login-lockout-window, LOGIN_LOCKOUT_WINDOW");
+    println!("                                  Seconds failed logins count towards a lockout");
+    println!("                                  (default 900)");
+    println!("    --oauth-retries, OAUTH_RETRIES");
+    println!("                                  Times to retry requests to a login provider that");
+    println!("                                  fail with a network error (default 2)");
+    println!("    --oauth-state-limit, OAUTH_STATE_LIMIT");
+    println!("                                  Logins a single address may have started but");
+    println!("                                  not finished before more are refused (default 10)");
+    println!("    --secret-access-log-level, SECRET_ACCESS_LOG_LEVEL");
+    println!("                                  Level used to record which code read a secret,");
+    println!("                                  values are never logged (default debug)");
+    println!("    --signup-mode, SIGNUP_MODE    Who may create an account on their first login,");
+    println!("                                  one of open, invite (admins only), or domain");
+    println!("                                  (default open)");
+    println!("    --allowed-email-domains, ALLOWED_EMAIL_DOMAINS");
+    println!("                                  Comma separated email domains allowed to sign");
+    println!("                                  up, required when the signup mode is domain\n");
     println!("  Additional Environment Options:");
     println!("    GOOGLE_OAUTH_CLIENT_ID        The client ID associated with this app for");
     println!("                                  performing authentication using Google services.");
     println!("    GOOGLE_OAUTH_CLIENT_SECRET    The client secret paired with the client ID.");
+    println!("    GITHUB_OAUTH_CLIENT_ID        The client ID of a GitHub OAuth app, logging in");
+    println!("                                  with GitHub is only offered when this is set.");
+    println!("    GITHUB_OAUTH_CLIENT_SECRET    The client secret paired with the GitHub client");
+    println!("                                  ID, required when the ID is set.");
+    println!("    AWS_ACCESS_KEY_ID             Credentials used for s3:// upload locations,");
+    println!("    AWS_SECRET_ACCESS_KEY         instance credentials are used when unset.");
+    println!("    AWS_REGION                    Region of the upload bucket (default us-east-1).");
+    println!("    AWS_ENDPOINT                  Endpoint of an S3 compatible service other than");
+    println!("                                  AWS itself.");
+    println!("    AWS_ALLOW_HTTP                Set to true to reach that endpoint without TLS.");
 }
 
 fn print_version() {
     let version = Version::new();
 
     println!(
-        "Service version {} built in {} mode with features: {:?}",
+        "Service version {} built in {} mode with features: {}",
         version.version, version.build_profile, version.features
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use super::*;
+
+    fn args(values: &[&str]) -> Arguments {
+        Arguments::from_vec(values.iter().map(OsString::from).collect())
+    }
+
+    #[test]
+    fn test_shutdown_timeout_flags() {
+        let mut cli_args = args(&[
+            "--grace-period",
+            "2",
+            "--shutdown-timeout",
+            "45",
+            "--worker-shutdown-timeout",
+            "0",
+        ]);
+
+        let timeouts = shutdown_timeouts_from_args(&mut cli_args).unwrap();
+        assert_eq!(
+            timeouts.grace_period(ShutdownReason::Terminate),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            timeouts.drain_timeout(ShutdownReason::Terminate),
+            Duration::from_secs(45)
+        );
+        assert_eq!(timeouts.worker_shutdown_timeout(), Duration::ZERO);
+        assert!(cli_args.finish().is_empty());
+    }
+
+    #[test]
+    fn test_shutdown_timeouts_reject_invalid_values() {
+        let mut cli_args = args(&["--shutdown-timeout", "soon"]);
+
+        let result = shutdown_timeouts_from_args(&mut cli_args);
+        assert!(matches!(result, Err(ConfigError::ArgumentReadError(_))));
+    }
+
+    #[test]
+    fn test_shutdown_timeouts_reject_zero_drain() {
+        let mut cli_args = args(&["--shutdown-timeout", "0"]);
+
+        let result = shutdown_timeouts_from_args(&mut cli_args);
+        assert!(matches!(result, Err(ConfigError::ZeroShutdownTimeout)));
+    }
+}
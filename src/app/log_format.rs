@@ -0,0 +1,140 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// How log lines are written out. The compact format is meant for people reading a terminal while
+/// JSON is meant for log aggregators that index the individual fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Compact,
+
+    /// One JSON object per line, including the fields of every span the event happened within
+    Json,
+}
+
+impl LogFormat {
+    /// Builds the formatting layer writing to `writer`. Filtering is left to the caller.
+    pub fn layer<S, W>(self, writer: W) -> Box<dyn Layer<S> + Send + Sync + 'static>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+    {
+        match self {
+            LogFormat::Compact => tracing_subscriber::fmt::layer()
+                .compact()
+                .with_writer(writer)
+                .boxed(),
+            LogFormat::Json => tracing_subscriber::fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
+                .with_writer(writer)
+                .boxed(),
+        }
+    }
+}
+
+impl Display for LogFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            LogFormat::Compact => "compact",
+            LogFormat::Json => "json",
+        };
+
+        f.write_str(msg)
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = LogFormatError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        match val.trim().to_lowercase().as_str() {
+            "compact" => Ok(LogFormat::Compact),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(LogFormatError::Unknown(val.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LogFormatError {
+    #[error("unknown log format '{0}', expected one of compact or json")]
+    Unknown(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct CapturedLines(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for CapturedLines {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'w> MakeWriter<'w> for CapturedLines {
+        type Writer = Self;
+
+        fn make_writer(&'w self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_json_lines_include_request_span_fields() {
+        let captured = CapturedLines::default();
+        let subscriber =
+            tracing_subscriber::registry().with(LogFormat::Json.layer(captured.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "http_request",
+                request_id = "01HVZ7Q2",
+                method = "GET",
+                uri = "/api/v1/status",
+            );
+            let _entered = span.enter();
+            tracing::info!(status = 200, "finished processing request");
+        });
+
+        let output = captured.0.lock().unwrap().clone();
+        let line = String::from_utf8(output).unwrap();
+        let entry: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+
+        assert_eq!(entry["level"], "INFO");
+        assert_eq!(entry["fields"]["message"], "finished processing request");
+        assert_eq!(entry["fields"]["status"], 200);
+
+        let span = &entry["span"];
+        assert_eq!(span["name"], "http_request");
+        assert_eq!(span["request_id"], "01HVZ7Q2");
+        assert_eq!(span["method"], "GET");
+        assert_eq!(span["uri"], "/api/v1/status");
+    }
+
+    #[test]
+    fn test_parsing() {
+        assert_eq!("JSON".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!(" compact".parse::<LogFormat>().unwrap(), LogFormat::Compact);
+        assert!("pretty".parse::<LogFormat>().is_err());
+    }
+}
@@ -1,13 +1,32 @@
+mod admin_list;
+mod allowed_hosts;
+mod bot_classifier;
 mod config;
+mod log_format;
 mod secrets;
+mod self_check;
 mod service_verification_key;
+mod start_time;
 mod state;
 mod upload_store;
 mod version;
 
-pub use config::{Config, ConfigError};
-pub use secrets::{ProviderCredential, Secrets, ServiceSigningKey};
+pub use admin_list::AdminList;
+pub use allowed_hosts::AllowedHosts;
+pub use bot_classifier::{BotClassifier, DEFAULT_BOT_PATTERNS};
+pub use config::{Command, Config, ConfigError};
+pub use log_format::{LogFormat, LogFormatError};
+pub use secrets::{
+    record_secret_access, set_secret_access_level, ProviderCredential, SecretKind, Secrets,
+    ServiceSigningKey, TokenCipher, TokenCipherError,
+};
+pub use self_check::{self_check, SelfCheckReport};
 pub use service_verification_key::ServiceVerificationKey;
-pub use state::{AppState, AppState as State, AppStateSetupError as StateSetupError};
-pub use upload_store::UploadStore;
-pub use version::Version;
+pub use start_time::StartTime;
+pub use state::{
+    AppState, AppState as State, AppStateError, AppStateSetupError as StateSetupError,
+};
+pub use upload_store::{
+    UploadLimits, UploadLocation, UploadLocationError, UploadRef, UploadStore, UploadStoreError,
+};
+pub use version::{Feature, FeatureSet, Version};
@@ -7,18 +7,25 @@ use axum::extract::{FromRef, FromRequestParts};
 use http::request::Parts;
 
 mod provider_credential;
+mod secret_access;
 mod service_signing_key;
+mod token_cipher;
 
 pub use provider_credential::ProviderCredential;
+pub use secret_access::{record_secret_access, set_secret_access_level, SecretKind};
 pub use service_signing_key::ServiceSigningKey;
+pub use token_cipher::{TokenCipher, TokenCipherError};
 
 use crate::app::State;
 use crate::database::custom_types::LoginProvider;
 
+/// Holds the long lived secrets of the service. The accessors hand out the wrappers around each
+/// secret, access to the secret material itself is recorded by the wrappers when it is read.
 #[derive(Clone)]
 pub struct Secrets {
     provider_credentials: Arc<BTreeMap<LoginProvider, ProviderCredential>>,
     service_signing_key: ServiceSigningKey,
+    token_cipher: TokenCipher,
 }
 
 impl Secrets {
@@ -26,9 +33,12 @@ impl Secrets {
         credentials: BTreeMap<LoginProvider, ProviderCredential>,
         service_signing_key: ServiceSigningKey,
     ) -> Self {
+        let token_cipher = TokenCipher::derive(&service_signing_key);
+
         Self {
             provider_credentials: Arc::new(credentials),
             service_signing_key,
+            token_cipher,
         }
     }
 
@@ -39,6 +49,11 @@ impl Secrets {
     pub fn service_signing_key(&self) -> ServiceSigningKey {
         self.service_signing_key.clone()
     }
+
+    /// Encrypts and decrypts the provider tokens kept in the database.
+    pub fn token_cipher(&self) -> TokenCipher {
+        self.token_cipher.clone()
+    }
 }
 
 #[async_trait]
@@ -2,6 +2,8 @@ use std::sync::Arc;
 
 use oauth2::{ClientId, ClientSecret};
 
+use crate::app::{record_secret_access, SecretKind};
+
 #[derive(Clone)]
 pub struct ProviderCredential {
     id: Arc<str>,
@@ -20,7 +22,9 @@ impl ProviderCredential {
         }
     }
 
+    #[track_caller]
     pub fn secret(&self) -> ClientSecret {
+        record_secret_access(SecretKind::ProviderClientSecret);
         ClientSecret::new(self.secret.to_string())
     }
 }
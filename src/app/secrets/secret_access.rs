@@ -0,0 +1,67 @@
+use std::fmt::{self, Display, Formatter};
+use std::panic::Location;
+use std::sync::OnceLock;
+
+use tracing::Level;
+
+/// The level secret accesses are recorded at until [`set_secret_access_level`] is called.
+const DEFAULT_SECRET_ACCESS_LEVEL: Level = Level::DEBUG;
+
+static SECRET_ACCESS_LEVEL: OnceLock<Level> = OnceLock::new();
+
+/// The secrets whose use is recorded. Only the kind of secret is ever logged, never its value.
+#[derive(Clone, Copy, Debug)]
+pub enum SecretKind {
+    OAuthCsrfToken,
+    OAuthPkceVerifier,
+    ProviderClientSecret,
+    ProviderToken,
+    ServiceSigningKey,
+}
+
+impl Display for SecretKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SecretKind::OAuthCsrfToken => "oauth_csrf_token",
+            SecretKind::OAuthPkceVerifier => "oauth_pkce_verifier",
+            SecretKind::ProviderClientSecret => "provider_client_secret",
+            SecretKind::ProviderToken => "provider_token",
+            SecretKind::ServiceSigningKey => "service_signing_key",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Records that a secret was read along with the location in the code that asked for it. Events
+/// are emitted under the `secret_access` target so they can be filtered independently of the rest
+/// of the logs.
+#[track_caller]
+pub fn record_secret_access(secret: SecretKind) {
+    let caller = Location::caller();
+    let level = SECRET_ACCESS_LEVEL
+        .get()
+        .copied()
+        .unwrap_or(DEFAULT_SECRET_ACCESS_LEVEL);
+
+    // The level of a tracing event has to be known at compile time
+    macro_rules! access_event {
+        ($level:expr) => {
+            tracing::event!(target: "secret_access", $level, %secret, %caller, "secret accessed")
+        };
+    }
+
+    match level {
+        Level::TRACE => access_event!(Level::TRACE),
+        Level::DEBUG => access_event!(Level::DEBUG),
+        Level::INFO => access_event!(Level::INFO),
+        Level::WARN => access_event!(Level::WARN),
+        Level::ERROR => access_event!(Level::ERROR),
+    }
+}
+
+/// Sets the level secret accesses are recorded at. This is expected to be called once during
+/// startup, later calls have no effect.
+pub fn set_secret_access_level(level: Level) {
+    let _ = SECRET_ACCESS_LEVEL.set(level);
+}
@@ -3,7 +3,7 @@ use std::sync::Arc;
 
 use jwt_simple::prelude::*;
 
-use crate::app::ServiceVerificationKey;
+use crate::app::{record_secret_access, SecretKind, ServiceVerificationKey};
 
 #[derive(Clone)]
 pub struct ServiceSigningKey(Arc<ES384KeyPair>);
@@ -24,7 +24,9 @@ impl ServiceSigningKey {
 impl Deref for ServiceSigningKey {
     type Target = Arc<ES384KeyPair>;
 
+    #[track_caller]
     fn deref(&self) -> &Self::Target {
+        record_secret_access(SecretKind::ServiceSigningKey);
         &self.0
     }
 }
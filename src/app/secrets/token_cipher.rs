@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as B64;
+use base64::Engine;
+
+use crate::app::{record_secret_access, SecretKind, ServiceSigningKey};
+use crate::database::custom_types::SealedToken;
+
+/// AES-GCM nonces are 96 bits, each sealed token starts with the one it was encrypted with.
+const NONCE_LENGTH: usize = 12;
+
+/// Encrypts the tokens login providers issue us before they are written to the database, a copy
+/// of the database alone isn't enough to act on behalf of our users. The key is derived from the
+/// service key, tokens sealed before the service key is replaced can no longer be opened.
+#[derive(Clone)]
+pub struct TokenCipher(Arc<Aes256Gcm>);
+
+impl TokenCipher {
+    pub fn derive(service_key: &ServiceSigningKey) -> Self {
+        let key_material = hmac_sha512::HMAC::mac(b"provider-token", service_key.to_bytes());
+        let key = Key::<Aes256Gcm>::from_slice(&key_material[..32]);
+
+        Self(Arc::new(Aes256Gcm::new(key)))
+    }
+
+    #[track_caller]
+    pub fn open(&self, sealed: &SealedToken) -> Result<String, TokenCipherError> {
+        record_secret_access(SecretKind::ProviderToken);
+
+        let sealed = B64
+            .decode(sealed.as_str())
+            .map_err(|_| TokenCipherError::Malformed)?;
+        if sealed.len() < NONCE_LENGTH {
+            return Err(TokenCipherError::Malformed);
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+        let token = self
+            .0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| TokenCipherError::Unreadable)?;
+
+        String::from_utf8(token).map_err(|_| TokenCipherError::Malformed)
+    }
+
+    pub fn seal(&self, token: &str) -> SealedToken {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, token.as_bytes())
+            .expect("tokens to be well within the AES-GCM message limit");
+
+        let sealed = [nonce.as_slice(), ciphertext.as_slice()].concat();
+        SealedToken::from(B64.encode(sealed))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TokenCipherError {
+    #[error("sealed token wasn't in the expected format")]
+    Malformed,
+
+    #[error("sealed token was altered or sealed with a different service key")]
+    Unreadable,
+}
+
+#[cfg(test)]
+mod tests {
+    use jwt_simple::prelude::ES384KeyPair;
+
+    use super::*;
+
+    #[test]
+    fn test_sealed_tokens_only_open_with_the_same_key() {
+        let cipher = TokenCipher::derive(&ServiceSigningKey::new(ES384KeyPair::generate()));
+
+        let sealed = cipher.seal("provider-access-token");
+        assert!(!sealed.as_str().contains("provider-access-token"));
+        assert_ne!(sealed, cipher.seal("provider-access-token"));
+        assert_eq!(cipher.open(&sealed).unwrap(), "provider-access-token");
+
+        let other = TokenCipher::derive(&ServiceSigningKey::new(ES384KeyPair::generate()));
+        assert!(matches!(
+            other.open(&sealed),
+            Err(TokenCipherError::Unreadable)
+        ));
+
+        let truncated = SealedToken::from(sealed.as_str()[..8].to_string());
+        assert!(matches!(
+            cipher.open(&truncated),
+            Err(TokenCipherError::Malformed)
+        ));
+    }
+}
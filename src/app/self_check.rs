@@ -0,0 +1,156 @@
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+
+use crate::app::state::load_or_create_service_key;
+use crate::app::{Config, UploadLocation, UploadStore};
+use crate::database::{sqlite, Database};
+use crate::http_server::ASSET_DIRECTORY;
+use crate::llm::hugging_face::{self, EMBEDDING_MODEL};
+use crate::llm::ModelDevice;
+
+/// The outcome of each check made against the runtime environment, in the order they were run.
+/// Displaying the report gives one line per check suitable for printing to a terminal or a
+/// deployment log.
+pub struct SelfCheckReport {
+    checks: Vec<(&'static str, Result<String, String>)>,
+}
+
+impl SelfCheckReport {
+    /// Whether every check passed and the service should be able to start.
+    pub fn is_healthy(&self) -> bool {
+        self.checks.iter().all(|(_, outcome)| outcome.is_ok())
+    }
+
+    fn record<E: Display>(&mut self, name: &'static str, outcome: Result<String, E>) {
+        self.checks
+            .push((name, outcome.map_err(|err| err.to_string())));
+    }
+}
+
+impl Display for SelfCheckReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (name, outcome) in self.checks.iter() {
+            match outcome {
+                Ok(detail) => writeln!(f, "[ ok ] {name}: {detail}")?,
+                Err(err) => writeln!(f, "[FAIL] {name}: {err}")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Validates everything the service needs from its environment without starting any of it. This
+/// goes through the same setup the service does at startup, so it will run pending migrations and
+/// generate a service key if one doesn't exist yet.
+pub async fn self_check(config: &Config) -> SelfCheckReport {
+    // We were handed a config so it must have parsed
+    let mut report = SelfCheckReport {
+        checks: vec![("config", Ok("loaded".to_string()))],
+    };
+
+    report.record("database", check_database(config).await);
+
+    let key_path = config.service_key_path();
+    let key_existed = key_path.exists();
+    let key_check = load_or_create_service_key(&key_path).map(|_| {
+        let action = if key_existed { "loaded" } else { "created" };
+        format!("{action} {}", key_path.display())
+    });
+    report.record("service key", key_check);
+
+    report.record(
+        "asset directory",
+        check_directory(Path::new(ASSET_DIRECTORY), false),
+    );
+    report.record(
+        "upload store",
+        check_upload_store(&config.upload_location()).await,
+    );
+
+    let device_check = config
+        .model_device()
+        .select(config.model_device_fallback())
+        .map(|device| format!("running on {}", ModelDevice::from(&device)));
+    report.record("model device", device_check);
+
+    let model_check = hugging_face::check_safetensor_model_version(EMBEDDING_MODEL)
+        .await
+        .map(|version| format!("{EMBEDDING_MODEL} available at {}", version.version()));
+    report.record("embedding model", model_check);
+
+    report
+}
+
+async fn check_database(config: &Config) -> Result<String, String> {
+    let database = Database::connect(&config.database_url())
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let (applied, known) = sqlite::migration_status(&database)
+        .await
+        .map_err(|err| format!("unable to read migration status: {err}"))?;
+
+    Ok(format!(
+        "connected, {applied} of {known} migrations applied"
+    ))
+}
+
+async fn check_upload_store(location: &UploadLocation) -> Result<String, String> {
+    if let Some(directory) = location.local_directory() {
+        return check_directory(directory, true);
+    }
+
+    let store = UploadStore::open(location).map_err(|err| err.to_string())?;
+    store
+        .list_with_delimiter(None)
+        .await
+        .map_err(|err| format!("unable to list {location}: {err}"))?;
+
+    Ok(format!("listed {location}"))
+}
+
+/// Confirms the path is an existing directory, and when `writable` is set that files can be
+/// created and removed inside of it.
+fn check_directory(path: &Path, writable: bool) -> Result<String, String> {
+    if !path.is_dir() {
+        return Err(format!("{} is not an existing directory", path.display()));
+    }
+
+    if writable {
+        let probe = path.join(format!(".self-check-{}", uuid::Uuid::new_v4()));
+
+        std::fs::write(&probe, b"")
+            .and_then(|_| std::fs::remove_file(&probe))
+            .map_err(|err| format!("{} is not writable: {err}", path.display()))?;
+
+        return Ok(format!("{} is writable", path.display()));
+    }
+
+    Ok(format!("{} exists", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_checks() {
+        let root = std::env::temp_dir().join(format!("self-check-{}", uuid::Uuid::new_v4()));
+
+        assert!(check_directory(&root, false).is_err());
+
+        std::fs::create_dir_all(&root).unwrap();
+        assert!(check_directory(&root, false).is_ok());
+        assert!(check_directory(&root, true).is_ok());
+
+        // The probe file shouldn't be left behind
+        assert_eq!(std::fs::read_dir(&root).unwrap().count(), 0);
+
+        let file = root.join("not-a-directory");
+        std::fs::write(&file, b"").unwrap();
+        assert!(check_directory(&file, true).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
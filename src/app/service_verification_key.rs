@@ -3,10 +3,17 @@ use std::sync::Arc;
 
 use jwt_simple::prelude::*;
 
+use crate::database::custom_types::Fingerprint;
+
 #[derive(Clone)]
 pub struct ServiceVerificationKey(Arc<ES384PublicKey>);
 
 impl ServiceVerificationKey {
+    /// The key ID set on every token the service signs.
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::from_public_key(&self.0)
+    }
+
     pub fn new(key: ES384PublicKey) -> Self {
         Self(Arc::new(key))
     }
@@ -0,0 +1,28 @@
+use std::time::{Duration, Instant};
+
+use time::OffsetDateTime;
+
+/// When the process started serving. The wall clock time is what gets reported while the
+/// monotonic instant is used to measure uptime so changes to the system clock don't skew it.
+#[derive(Clone, Copy, Debug)]
+pub struct StartTime {
+    instant: Instant,
+    started_at: OffsetDateTime,
+}
+
+impl StartTime {
+    pub fn now() -> Self {
+        Self {
+            instant: Instant::now(),
+            started_at: OffsetDateTime::now_utc(),
+        }
+    }
+
+    pub fn started_at(&self) -> OffsetDateTime {
+        self.started_at
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.instant.elapsed()
+    }
+}
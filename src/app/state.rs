@@ -1,85 +1,330 @@
 use std::collections::BTreeMap;
-use std::fmt::Write;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
 use std::path::PathBuf;
+use std::time::Duration;
 
 use axum::extract::FromRef;
 use jwt_simple::prelude::*;
-use object_store::local::LocalFileSystem;
-use sha2::Digest;
 
 use crate::app::{
-    Config, ProviderCredential, Secrets, ServiceSigningKey, ServiceVerificationKey, UploadStore,
+    AdminList, AllowedHosts, BotClassifier, Config, ProviderCredential, Secrets, ServiceSigningKey,
+    ServiceVerificationKey, StartTime, UploadLimits, UploadLocation, UploadStore,
 };
-use crate::background_jobs::{BasicTaskContext, BasicTaskStore, EventTaskContext, EventTaskStore};
-use crate::database::custom_types::LoginProvider;
-use crate::database::{Database, DatabaseSetupError};
+use crate::auth::{LoginAnomalySensitivity, LoginLockout, SessionCookie, SignupPolicy};
+use crate::background_jobs::impls::{EmbedTaskContext, RefreshOAuthTokensContext};
+use crate::background_jobs::{
+    BasicTaskContext, BasicTaskStore, EventTaskContext, EventTaskStore, QueueConfigs,
+};
+use crate::database::custom_types::{Fingerprint, LoginProvider};
+use crate::database::{self, Database, DatabaseHealth, DatabaseSetupError};
 use crate::event_bus::EventBus;
+use crate::extractors::ApiTokenTiming;
+use crate::health_check::{Dependencies, ModelReadiness, ModelSourceHealth, ServiceReadiness};
+use crate::http_server::{CsrfKey, RateLimiter, UserConcurrencyLimiter};
+use crate::llm::{Embedder, ModelDeviceError};
+
+/// How many times a service key that fails to parse is read before giving up on it. This only
+/// comes up when another process is in the middle of writing out a key it just generated.
+const SERVICE_KEY_READ_ATTEMPTS: u8 = 10;
+
+const SERVICE_KEY_READ_DELAY: Duration = Duration::from_millis(50);
 
 #[derive(Clone)]
 pub struct AppState {
+    admin_list: AdminList,
+    allowed_hosts: AllowedHosts,
+    api_key_rotation_grace: Duration,
+    api_token_timing: ApiTokenTiming,
+    background_run_retention: u32,
+    bot_classifier: BotClassifier,
+    csrf_key: CsrfKey,
     database: Database,
+    database_health: DatabaseHealth,
+    dependencies: Dependencies,
+    embedder: Embedder,
     event_bus: EventBus,
+    login_anomaly_sensitivity: LoginAnomalySensitivity,
+    login_lockout: LoginLockout,
+    model_source_health: ModelSourceHealth,
+    oauth_retries: u8,
+    oauth_state_limit: u32,
+    queue_configs: QueueConfigs,
+    rate_limiter: RateLimiter,
+    ready_requires_model: bool,
     secrets: Secrets,
 
+    service_readiness: ServiceReadiness,
     service_verifier: ServiceVerificationKey,
-    upload_directory: PathBuf,
+    session_cookie: SessionCookie,
+    signup_policy: SignupPolicy,
+    start_time: StartTime,
+    upload_limits: UploadLimits,
+    upload_location: UploadLocation,
+    user_concurrency_limiter: UserConcurrencyLimiter,
 }
 
 impl AppState {
+    pub fn admin_list(&self) -> AdminList {
+        self.admin_list.clone()
+    }
+
+    pub fn allowed_hosts(&self) -> AllowedHosts {
+        self.allowed_hosts.clone()
+    }
+
+    /// How long a rotated API key keeps working.
+    pub fn api_key_rotation_grace(&self) -> Duration {
+        self.api_key_rotation_grace
+    }
+
+    pub fn api_token_timing(&self) -> ApiTokenTiming {
+        self.api_token_timing
+    }
+
+    pub fn bot_classifier(&self) -> BotClassifier {
+        self.bot_classifier.clone()
+    }
+
+    pub fn csrf_key(&self) -> CsrfKey {
+        self.csrf_key.clone()
+    }
+
     pub fn database(&self) -> Database {
         self.database.clone()
     }
 
+    pub fn database_health(&self) -> DatabaseHealth {
+        self.database_health.clone()
+    }
+
+    pub fn embedder(&self) -> Embedder {
+        self.embedder.clone()
+    }
+
     pub fn event_bus(&self) -> EventBus {
         self.event_bus.clone()
     }
 
+    pub fn login_anomaly_sensitivity(&self) -> LoginAnomalySensitivity {
+        self.login_anomaly_sensitivity
+    }
+
+    pub fn login_lockout(&self) -> LoginLockout {
+        self.login_lockout
+    }
+
+    pub(crate) fn model_source_health(&self) -> ModelSourceHealth {
+        self.model_source_health.clone()
+    }
+
+    /// The largest body accepted by the upload route, distinct from the global request limit.
+    pub fn max_upload_size(&self) -> usize {
+        self.upload_limits.max_object_size()
+    }
+
+    /// How many times requests to a login provider are retried after a network error.
+    pub fn oauth_retries(&self) -> u8 {
+        self.oauth_retries
+    }
+
+    /// Logins a single address may have in progress at once.
+    pub fn oauth_state_limit(&self) -> u32 {
+        self.oauth_state_limit
+    }
+
+    pub fn queue_configs(&self) -> QueueConfigs {
+        self.queue_configs.clone()
+    }
+
     pub async fn from_config(config: &Config) -> Result<Self, AppStateSetupError> {
-        let database = Database::connect(&config.database_url()).await?;
-        let event_bus = EventBus::new();
+        // A freshly mounted data volume won't have anything in it yet
+        if let Some(data_dir) = config.data_dir() {
+            let upload_location = config.upload_location();
+            let managed_dirs = [Some(data_dir.as_path()), upload_location.local_directory()];
+
+            for dir in managed_dirs
+                .into_iter()
+                .flatten()
+                .filter(|dir| dir.starts_with(&data_dir))
+            {
+                std::fs::create_dir_all(dir).map_err(AppStateSetupError::DataDirUnavailable)?;
+            }
+        }
+
+        let database = Database::connect_with_retry(
+            &config.database_url(),
+            config.database_connect_attempts(),
+            database::CONNECT_BACKOFF,
+        )
+        .await?;
+        let service_readiness = ServiceReadiness::new();
+        service_readiness.mark_database_ready();
+
+        let model_device = config
+            .model_device()
+            .select(config.model_device_fallback())
+            .map_err(AppStateSetupError::ModelDeviceUnavailable)?;
+        let embedder = Embedder::new(model_device);
+        let event_bus = EventBus::default();
 
         let service_key = load_or_create_service_key(&config.service_key_path())?;
         let service_verifier = service_key.verifier();
+        let csrf_key = CsrfKey::derive(&service_key);
 
         let mut credentials = BTreeMap::new();
         credentials.insert(
             LoginProvider::Google,
             ProviderCredential::new(config.google_client_id(), config.google_client_secret()),
         );
+        if let (Some(id), Some(secret)) = (config.github_client_id(), config.github_client_secret())
+        {
+            credentials.insert(LoginProvider::Github, ProviderCredential::new(id, secret));
+        }
         let secrets = Secrets::new(credentials, service_key);
 
+        let admin_list = AdminList::new(config.admin_emails());
+        let signup_policy = SignupPolicy::new(
+            config.signup_mode(),
+            config.allowed_email_domains(),
+            admin_list.clone(),
+        );
+
+        let model_source_health = ModelSourceHealth::default();
+        let dependencies = Dependencies::new(
+            database.clone(),
+            event_bus.clone(),
+            model_source_health.clone(),
+            config.smtp_url(),
+            config.upload_location(),
+        );
+
         Ok(Self {
+            admin_list,
+            allowed_hosts: AllowedHosts::new(config.allowed_hosts()),
+            api_key_rotation_grace: config.api_key_rotation_grace(),
+            api_token_timing: ApiTokenTiming::new(
+                config.api_token_clock_skew(),
+                config.api_token_max_age(),
+            ),
+            background_run_retention: config.background_run_retention(),
+            bot_classifier: BotClassifier::new(config.bot_patterns()),
+            csrf_key,
             database,
+            database_health: DatabaseHealth::new(),
+            dependencies,
+            embedder,
             event_bus,
+            login_anomaly_sensitivity: config.login_anomaly_sensitivity(),
+            login_lockout: LoginLockout::new(
+                config.login_lockout_attempts(),
+                config.login_lockout_window(),
+            ),
+            model_source_health,
+            oauth_retries: config.oauth_retries(),
+            oauth_state_limit: config.oauth_state_limit(),
+            queue_configs: config.queue_configs().clone(),
+            rate_limiter: RateLimiter::new(config.rate_limits().clone()),
+            ready_requires_model: config.ready_requires_model(),
             secrets,
+            service_readiness,
             service_verifier,
-            upload_directory: config.upload_directory(),
+            session_cookie: SessionCookie::new(config.session_cookie_host_prefix()),
+            signup_policy,
+            start_time: StartTime::now(),
+            upload_limits: UploadLimits::new(
+                config.max_upload_size(),
+                config.upload_content_types(),
+            ),
+            upload_location: config.upload_location(),
+            user_concurrency_limiter: UserConcurrencyLimiter::new(config.user_concurrency_limit()),
         })
     }
 
+    pub fn rate_limiter(&self) -> RateLimiter {
+        self.rate_limiter.clone()
+    }
+
     pub fn secrets(&self) -> Secrets {
         self.secrets.clone()
     }
 
+    pub fn service_readiness(&self) -> ServiceReadiness {
+        self.service_readiness.clone()
+    }
+
     pub fn service_verifier(&self) -> ServiceVerificationKey {
         self.service_verifier.clone()
     }
 
+    pub fn session_cookie(&self) -> SessionCookie {
+        self.session_cookie
+    }
+
+    pub fn signup_policy(&self) -> SignupPolicy {
+        self.signup_policy.clone()
+    }
+
+    /// When this instance of the service was started.
+    pub fn start_time(&self) -> StartTime {
+        self.start_time
+    }
+
     pub fn basic_task_store(&self) -> BasicTaskStore {
-        let context = BasicTaskContext::new(self.database());
+        let context = BasicTaskContext::new(self.database(), self.background_run_retention);
         BasicTaskStore::new(context)
     }
 
     pub fn event_task_store(&self) -> EventTaskStore {
         let context = EventTaskContext::new(self.database(), self.event_bus());
-        EventTaskStore::new(context)
+        EventTaskStore::new(context, self.basic_task_store())
+    }
+
+    pub fn user_concurrency_limiter(&self) -> UserConcurrencyLimiter {
+        self.user_concurrency_limiter.clone()
     }
 
     pub fn upload_store(&self) -> Result<UploadStore, AppStateError> {
-        let local_fs = LocalFileSystem::new_with_prefix(&self.upload_directory)
+        let store = UploadStore::open(&self.upload_location)
             .map_err(AppStateError::UploadStoreUnavailable)?;
 
-        Ok(UploadStore::new(local_fs))
+        Ok(store.with_limits(self.upload_limits.clone()))
+    }
+}
+
+impl FromRef<AppState> for AdminList {
+    fn from_ref(state: &AppState) -> Self {
+        state.admin_list()
+    }
+}
+
+impl FromRef<AppState> for AllowedHosts {
+    fn from_ref(state: &AppState) -> Self {
+        state.allowed_hosts()
+    }
+}
+
+impl FromRef<AppState> for ApiTokenTiming {
+    fn from_ref(state: &AppState) -> Self {
+        state.api_token_timing()
+    }
+}
+
+impl FromRef<AppState> for BasicTaskContext {
+    fn from_ref(state: &AppState) -> Self {
+        state.basic_task_store().context()
+    }
+}
+
+impl FromRef<AppState> for BotClassifier {
+    fn from_ref(state: &AppState) -> Self {
+        state.bot_classifier()
+    }
+}
+
+impl FromRef<AppState> for CsrfKey {
+    fn from_ref(state: &AppState) -> Self {
+        state.csrf_key()
     }
 }
 
@@ -89,18 +334,96 @@ impl FromRef<AppState> for Database {
     }
 }
 
+impl FromRef<AppState> for DatabaseHealth {
+    fn from_ref(state: &AppState) -> Self {
+        state.database_health()
+    }
+}
+
+impl FromRef<AppState> for Dependencies {
+    fn from_ref(state: &AppState) -> Self {
+        state.dependencies.clone()
+    }
+}
+
+impl FromRef<AppState> for EmbedTaskContext {
+    fn from_ref(state: &AppState) -> Self {
+        EmbedTaskContext::new(state.database(), state.embedder())
+    }
+}
+
+impl FromRef<AppState> for Embedder {
+    fn from_ref(state: &AppState) -> Self {
+        state.embedder()
+    }
+}
+
+impl FromRef<AppState> for EventBus {
+    fn from_ref(state: &AppState) -> Self {
+        state.event_bus()
+    }
+}
+
+impl FromRef<AppState> for EventTaskContext {
+    fn from_ref(state: &AppState) -> Self {
+        state.event_task_store().context()
+    }
+}
+
+impl FromRef<AppState> for ModelReadiness {
+    fn from_ref(state: &AppState) -> Self {
+        ModelReadiness::new(state.embedder(), state.ready_requires_model)
+    }
+}
+
+impl FromRef<AppState> for RefreshOAuthTokensContext {
+    fn from_ref(state: &AppState) -> Self {
+        RefreshOAuthTokensContext::new(state.database(), state.event_bus(), state.secrets())
+    }
+}
+
 impl FromRef<AppState> for Secrets {
     fn from_ref(state: &AppState) -> Self {
         state.secrets()
     }
 }
 
+impl FromRef<AppState> for ServiceReadiness {
+    fn from_ref(state: &AppState) -> Self {
+        state.service_readiness()
+    }
+}
+
 impl FromRef<AppState> for ServiceVerificationKey {
     fn from_ref(state: &AppState) -> Self {
         state.service_verifier()
     }
 }
 
+impl FromRef<AppState> for SessionCookie {
+    fn from_ref(state: &AppState) -> Self {
+        state.session_cookie()
+    }
+}
+
+impl FromRef<AppState> for StartTime {
+    fn from_ref(state: &AppState) -> Self {
+        state.start_time()
+    }
+}
+
+impl FromRef<AppState> for RateLimiter {
+    fn from_ref(state: &AppState) -> Self {
+        state.rate_limiter()
+    }
+}
+
+impl FromRef<AppState> for UserConcurrencyLimiter {
+    fn from_ref(state: &AppState) -> Self {
+        state.user_concurrency_limiter()
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum AppStateError {
     #[error("unable to get a handle on the upload store: {0}")]
@@ -112,12 +435,18 @@ pub enum AppStateSetupError {
     #[error("private service key could not be loaded: {0}")]
     InvalidServiceKey(jwt_simple::Error),
 
+    #[error("unable to create the data directory: {0}")]
+    DataDirUnavailable(std::io::Error),
+
     #[error("failed to setup the database: {0}")]
     DatabaseSetupError(#[from] DatabaseSetupError),
 
     #[error("failed to write fingerprint: {0}")]
     FingerprintWriteFailed(std::io::Error),
 
+    #[error("unable to use the configured model device: {0}")]
+    ModelDeviceUnavailable(ModelDeviceError),
+
     #[error("failed to write public key: {0}")]
     PublicKeyWriteFailed(std::io::Error),
 
@@ -128,49 +457,69 @@ pub enum AppStateSetupError {
     UnreadableServiceKey(std::io::Error),
 }
 
-fn fingerprint_key(keys: &ES384KeyPair) -> String {
-    let public_key = keys.key_pair().public_key();
-    let compressed_point = public_key.as_ref().to_encoded_point(true);
+/// Generates and writes out a new service key, returning `None` when a key already exists. The
+/// file is created exclusively so when several processes start at once only one of them writes a
+/// key and the rest load that one instead of replacing it.
+fn create_service_key(private_path: &PathBuf) -> Result<Option<ES384KeyPair>, AppStateSetupError> {
+    let mut key_file = match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(private_path)
+    {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => return Ok(None),
+        Err(err) => return Err(AppStateSetupError::ServiceKeyWriteFailed(err)),
+    };
 
-    let mut hasher = sha2::Sha256::new();
-    hasher.update(compressed_point);
-    let hashed_bytes = hasher.finalize();
+    let new_key = ES384KeyPair::generate();
+    let private_pem = new_key.to_pem().expect("fresh keys to export");
 
-    hashed_bytes.iter().fold(String::new(), |mut output, b| {
-        let _ = write!(output, "{b:02x}");
-        output
-    })
+    key_file
+        .write_all(private_pem.as_bytes())
+        .and_then(|_| key_file.sync_all())
+        .map_err(AppStateSetupError::ServiceKeyWriteFailed)?;
+
+    let public_spki = new_key
+        .public_key()
+        .to_pem()
+        .expect("fresh key to have public component");
+    let mut public_path = private_path.clone();
+    public_path.set_extension("public");
+    std::fs::write(public_path, public_spki).map_err(AppStateSetupError::PublicKeyWriteFailed)?;
+
+    Ok(Some(new_key))
 }
 
-fn load_or_create_service_key(
-    private_path: &PathBuf,
-) -> Result<ServiceSigningKey, AppStateSetupError> {
-    let mut session_key_raw = if private_path.exists() {
+/// Reads an existing service key. Another process may have only just created the file, the read
+/// is retried for a moment while it finishes writing the key out.
+fn read_service_key(private_path: &PathBuf) -> Result<ES384KeyPair, AppStateSetupError> {
+    let mut attempt = 1;
+
+    loop {
         let key_bytes =
             std::fs::read(private_path).map_err(AppStateSetupError::UnreadableServiceKey)?;
         let private_pem = String::from_utf8_lossy(&key_bytes);
 
-        ES384KeyPair::from_pem(&private_pem).map_err(AppStateSetupError::InvalidServiceKey)?
-    } else {
-        let new_key = ES384KeyPair::generate();
-        let private_pem = new_key.to_pem().expect("fresh keys to export");
-
-        std::fs::write(private_path, private_pem)
-            .map_err(AppStateSetupError::ServiceKeyWriteFailed)?;
-
-        let public_spki = new_key
-            .public_key()
-            .to_pem()
-            .expect("fresh key to have public component");
-        let mut public_path = private_path.clone();
-        public_path.set_extension("public");
-        std::fs::write(public_path, public_spki)
-            .map_err(AppStateSetupError::PublicKeyWriteFailed)?;
+        match ES384KeyPair::from_pem(&private_pem) {
+            Ok(key) => return Ok(key),
+            Err(_) if attempt < SERVICE_KEY_READ_ATTEMPTS => {
+                std::thread::sleep(SERVICE_KEY_READ_DELAY);
+                attempt += 1;
+            }
+            Err(err) => return Err(AppStateSetupError::InvalidServiceKey(err)),
+        }
+    }
+}
 
-        new_key
+pub(crate) fn load_or_create_service_key(
+    private_path: &PathBuf,
+) -> Result<ServiceSigningKey, AppStateSetupError> {
+    let mut session_key_raw = match create_service_key(private_path)? {
+        Some(new_key) => new_key,
+        None => read_service_key(private_path)?,
     };
 
-    let fingerprint = fingerprint_key(&session_key_raw);
+    let fingerprint = Fingerprint::from_public_key(&session_key_raw.public_key()).to_string();
     session_key_raw = session_key_raw.with_key_id(&fingerprint);
 
     let mut fingerprint_path = private_path.clone();
@@ -182,3 +531,64 @@ fn load_or_create_service_key(
 
     Ok(ServiceSigningKey::new(session_key_raw))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concurrent_key_creation_converges() {
+        let root = std::env::temp_dir().join(format!("service-key-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let key_path = root.join("service-key.pem");
+
+        let barrier = std::sync::Barrier::new(2);
+        let public_keys: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    scope.spawn(|| {
+                        barrier.wait();
+                        let key = load_or_create_service_key(&key_path).unwrap();
+                        key.public_key().to_pem().unwrap()
+                    })
+                })
+                .collect();
+
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert_eq!(public_keys[0], public_keys[1]);
+
+        let stored = std::fs::read_to_string(&key_path).unwrap();
+        let stored_key = ES384KeyPair::from_pem(&stored).unwrap();
+        assert_eq!(stored_key.public_key().to_pem().unwrap(), public_keys[0]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_signed_key_id_matches_verifier_fingerprint() {
+        let root = std::env::temp_dir().join(format!("service-key-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let key_path = root.join("service-key.pem");
+
+        let signing_key = load_or_create_service_key(&key_path).unwrap();
+        let verifier = signing_key.verifier();
+
+        let claims = Claims::create(jwt_simple::prelude::Duration::from_mins(5));
+        let token = signing_key.sign(claims).unwrap();
+
+        let metadata = Token::decode_metadata(&token).unwrap();
+        let key_id = metadata.key_id().unwrap();
+        assert_eq!(
+            Fingerprint::from_key_id(key_id).unwrap(),
+            verifier.fingerprint()
+        );
+
+        // A restart reads the same key back and keeps signing with the same key ID
+        let reloaded = load_or_create_service_key(&key_path).unwrap();
+        assert_eq!(reloaded.verifier().fingerprint(), verifier.fingerprint());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,19 +1,421 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
 
+use axum::body::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use object_store::aws::AmazonS3Builder;
 use object_store::local::LocalFileSystem;
+use object_store::path::Path as ObjectPath;
+use object_store::prefix::PrefixStore;
+use object_store::ObjectStore;
+use serde::Serialize;
+use url::Url;
 
-pub struct UploadStore(LocalFileSystem);
+/// Where uploaded client data is kept. Bare paths and `file://` URLs are stored on the local
+/// filesystem, `s3://bucket/prefix` URLs in an S3 compatible bucket under the optional prefix.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UploadLocation {
+    Local(PathBuf),
+
+    /// Credentials, region, and any custom endpoint are read from the standard `AWS_*` environment
+    /// variables.
+    S3(Url),
+}
+
+impl UploadLocation {
+    /// The directory uploads are written to when they are kept on the local filesystem.
+    pub fn local_directory(&self) -> Option<&Path> {
+        match self {
+            UploadLocation::Local(path) => Some(path),
+            UploadLocation::S3(_) => None,
+        }
+    }
+}
+
+impl Display for UploadLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadLocation::Local(path) => write!(f, "{}", path.display()),
+            UploadLocation::S3(url) => write!(f, "{url}"),
+        }
+    }
+}
+
+impl FromStr for UploadLocation {
+    type Err = UploadLocationError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        let url = match Url::parse(val) {
+            Ok(url) => url,
+            // Anything that isn't a URL is a path, relative ones included
+            Err(_) => return Ok(UploadLocation::Local(PathBuf::from(val))),
+        };
+
+        match url.scheme() {
+            "file" => url
+                .to_file_path()
+                .map(UploadLocation::Local)
+                .map_err(|_| UploadLocationError::InvalidFileUrl(val.to_string())),
+            "s3" => match url.host_str() {
+                Some(bucket) if !bucket.is_empty() => Ok(UploadLocation::S3(url)),
+                _ => Err(UploadLocationError::MissingBucket(val.to_string())),
+            },
+            // Windows drive letters parse as single character schemes
+            scheme if scheme.len() == 1 => Ok(UploadLocation::Local(PathBuf::from(val))),
+            scheme => Err(UploadLocationError::UnsupportedScheme(scheme.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UploadLocationError {
+    #[error("'{0}' isn't a usable local file URL")]
+    InvalidFileUrl(String),
+
+    #[error("'{0}' doesn't name an S3 bucket")]
+    MissingBucket(String),
+
+    #[error("unsupported upload storage scheme '{0}', expected a path, file://, or s3://")]
+    UnsupportedScheme(String),
+}
+
+/// Restrictions on what gets written through [`UploadStore::put`]. The default accepts objects
+/// of any size and content type.
+#[derive(Clone, Debug)]
+pub struct UploadLimits {
+    allowed_content_types: Arc<BTreeSet<String>>,
+    max_object_size: usize,
+}
+
+impl UploadLimits {
+    /// Content types are compared without their parameters and case-insensitively, an entry like
+    /// `image/*` allows every subtype. An empty list allows any content type.
+    pub fn allows(&self, content_type: &str) -> bool {
+        if self.allowed_content_types.is_empty() {
+            return true;
+        }
+
+        let essence = content_type_essence(content_type);
+        if self.allowed_content_types.contains(&essence) {
+            return true;
+        }
+
+        match essence.split_once('/') {
+            Some((top_level, _)) => self
+                .allowed_content_types
+                .contains(&format!("{top_level}/*")),
+            None => false,
+        }
+    }
+
+    pub fn max_object_size(&self) -> usize {
+        self.max_object_size
+    }
+
+    pub fn new(max_object_size: usize, allowed_content_types: &[impl AsRef<str>]) -> Self {
+        let allowed_content_types = allowed_content_types
+            .iter()
+            .map(|ct| content_type_essence(ct.as_ref()))
+            .filter(|ct| !ct.is_empty())
+            .collect();
+
+        Self {
+            allowed_content_types: Arc::new(allowed_content_types),
+            max_object_size,
+        }
+    }
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        Self::new(usize::MAX, &[] as &[&str])
+    }
+}
+
+/// A stored upload, as returned from [`UploadStore::put`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct UploadRef {
+    pub content_type: String,
+    pub key: String,
+    pub size: usize,
+}
+
+#[derive(Clone)]
+pub struct UploadStore {
+    inner: Arc<dyn ObjectStore>,
+    limits: UploadLimits,
+}
 
 impl UploadStore {
-    pub fn new(inner: LocalFileSystem) -> Self {
-        Self(inner)
+    /// Streams the contents of a previously stored upload.
+    pub async fn get(
+        &self,
+        key: &str,
+    ) -> Result<BoxStream<'static, Result<Bytes, UploadStoreError>>, UploadStoreError> {
+        let path = ObjectPath::parse(key).map_err(UploadStoreError::InvalidKey)?;
+
+        let result = self.inner.get(&path).await.map_err(|err| match err {
+            object_store::Error::NotFound { .. } => UploadStoreError::NotFound(key.to_string()),
+            err => UploadStoreError::StoreFailed(err),
+        })?;
+
+        Ok(result
+            .into_stream()
+            .map(|chunk| chunk.map_err(UploadStoreError::StoreFailed))
+            .boxed())
+    }
+
+    pub fn limits(&self) -> &UploadLimits {
+        &self.limits
+    }
+
+    pub fn new(inner: impl ObjectStore) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            limits: UploadLimits::default(),
+        }
+    }
+
+    /// Constructs the store backing the location. Local directories need to already exist,
+    /// nothing is contacted for remote stores until they are used.
+    pub fn open(location: &UploadLocation) -> Result<Self, object_store::Error> {
+        match location {
+            UploadLocation::Local(path) => Ok(Self::new(LocalFileSystem::new_with_prefix(path)?)),
+            UploadLocation::S3(url) => s3_store(url, AmazonS3Builder::from_env()),
+        }
+    }
+
+    /// Writes an upload under the key once it has been checked against the store's limits.
+    pub async fn put(
+        &self,
+        key: &str,
+        bytes: Bytes,
+        content_type: &str,
+    ) -> Result<UploadRef, UploadStoreError> {
+        let path = ObjectPath::parse(key).map_err(UploadStoreError::InvalidKey)?;
+
+        let size = bytes.len();
+        if size > self.limits.max_object_size {
+            return Err(UploadStoreError::TooLarge(self.limits.max_object_size));
+        }
+
+        if !self.limits.allows(content_type) {
+            return Err(UploadStoreError::ContentTypeNotAllowed(
+                content_type.to_string(),
+            ));
+        }
+
+        self.inner
+            .put(&path, bytes)
+            .await
+            .map_err(UploadStoreError::StoreFailed)?;
+
+        Ok(UploadRef {
+            content_type: content_type_essence(content_type),
+            key: path.to_string(),
+            size,
+        })
+    }
+
+    pub fn with_limits(mut self, limits: UploadLimits) -> Self {
+        self.limits = limits;
+        self
     }
 }
 
 impl Deref for UploadStore {
-    type Target = LocalFileSystem;
+    type Target = dyn ObjectStore;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        self.inner.as_ref()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UploadStoreError {
+    #[error("uploads with a content type of '{0}' aren't allowed")]
+    ContentTypeNotAllowed(String),
+
+    #[error("invalid upload key: {0}")]
+    InvalidKey(object_store::path::Error),
+
+    #[error("no upload is stored under '{0}'")]
+    NotFound(String),
+
+    #[error("failed to access the upload store: {0}")]
+    StoreFailed(object_store::Error),
+
+    #[error("upload exceeded the maximum object size of {0} bytes")]
+    TooLarge(usize),
+}
+
+/// The media type without any parameters, `text/plain; charset=utf-8` becomes `text/plain`.
+fn content_type_essence(content_type: &str) -> String {
+    let essence = content_type.split(';').next().unwrap_or_default();
+    essence.trim().to_lowercase()
+}
+
+fn s3_store(url: &Url, builder: AmazonS3Builder) -> Result<UploadStore, object_store::Error> {
+    let bucket_url = format!("s3://{}", url.host_str().unwrap_or_default());
+    let bucket = builder.with_url(bucket_url).build()?;
+
+    match url.path().trim_matches('/') {
+        "" => Ok(UploadStore::new(bucket)),
+        prefix => Ok(UploadStore::new(PrefixStore::new(bucket, prefix))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::routing::put;
+    use axum::Router;
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    fn temp_directory() -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("uploads-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_location_parsing() {
+        assert_eq!(
+            "./data/uploads".parse::<UploadLocation>().unwrap(),
+            UploadLocation::Local(PathBuf::from("./data/uploads"))
+        );
+        assert_eq!(
+            "file:///srv/uploads".parse::<UploadLocation>().unwrap(),
+            UploadLocation::Local(PathBuf::from("/srv/uploads"))
+        );
+
+        let location: UploadLocation = "s3://bucket/uploads".parse().unwrap();
+        assert!(location.local_directory().is_none());
+
+        assert!("s3:///uploads".parse::<UploadLocation>().is_err());
+        assert!("ftp://host/uploads".parse::<UploadLocation>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_url_opens_local_store() {
+        let directory = temp_directory();
+
+        let url = Url::from_directory_path(&directory).unwrap();
+        let store = UploadStore::open(&url.as_str().parse().unwrap()).unwrap();
+        store
+            .put("user/upload", "contents".into(), "text/plain")
+            .await
+            .unwrap();
+
+        let written = std::fs::read_to_string(directory.join("user/upload")).unwrap();
+        assert_eq!(written, "contents");
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_s3_url_writes_to_bucket() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let recorded = received.clone();
+        let router = Router::new().route(
+            "/*key",
+            put(move |uri: http::Uri| async move {
+                recorded.lock().unwrap().push(uri.path().to_string());
+                [(http::header::ETAG, "\"etag\"")]
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let builder = AmazonS3Builder::new()
+            .with_endpoint(endpoint)
+            .with_allow_http(true)
+            .with_region("us-east-1")
+            .with_access_key_id("test-key")
+            .with_secret_access_key("test-secret");
+        let url = Url::parse("s3://bucket/uploads").unwrap();
+
+        let store = s3_store(&url, builder).unwrap();
+        store
+            .put("user/upload", "contents".into(), "text/plain")
+            .await
+            .unwrap();
+
+        assert_eq!(*received.lock().unwrap(), ["/bucket/uploads/user/upload"]);
+    }
+
+    #[tokio::test]
+    async fn test_put_get_round_trip() {
+        let directory = temp_directory();
+        let store = UploadStore::open(&UploadLocation::Local(directory.clone())).unwrap();
+
+        let upload_ref = store
+            .put(
+                "user/upload",
+                "contents".into(),
+                "Text/Plain; charset=utf-8",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            upload_ref,
+            UploadRef {
+                content_type: "text/plain".to_string(),
+                key: "user/upload".to_string(),
+                size: 8,
+            }
+        );
+
+        let chunks: Vec<Bytes> = store
+            .get(&upload_ref.key)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(chunks.concat(), b"contents");
+
+        assert!(matches!(
+            store.get("user/missing").await,
+            Err(UploadStoreError::NotFound(_))
+        ));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_put_enforces_limits() {
+        let directory = temp_directory();
+        let store = UploadStore::open(&UploadLocation::Local(directory.clone()))
+            .unwrap()
+            .with_limits(UploadLimits::new(4, &["image/*", "text/plain"]));
+
+        assert!(store
+            .put("small", "text".into(), "text/plain")
+            .await
+            .is_ok());
+        assert!(store.put("image", "png".into(), "image/png").await.is_ok());
+
+        assert!(matches!(
+            store.put("large", "too large".into(), "text/plain").await,
+            Err(UploadStoreError::TooLarge(4))
+        ));
+        assert!(matches!(
+            store.put("html", "<p>".into(), "text/html").await,
+            Err(UploadStoreError::ContentTypeNotAllowed(_))
+        ));
+        assert!(!directory.join("large").exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
     }
 }
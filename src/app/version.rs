@@ -1,12 +1,100 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
+
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::Json;
-use serde::Serialize;
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+
+/// The optional cargo features the service can be compiled with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Feature {
+    Cuda,
+    Cudann,
+    Nccl,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 3] = [Feature::Cuda, Feature::Cudann, Feature::Nccl];
+
+    /// Whether this feature was enabled when the running binary was built.
+    pub fn is_compiled(&self) -> bool {
+        match self {
+            Feature::Cuda => cfg!(feature = "cuda"),
+            Feature::Cudann => cfg!(feature = "cudann"),
+            Feature::Nccl => cfg!(feature = "nccl"),
+        }
+    }
+
+    /// The name of the feature as it appears in the crate manifest.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Feature::Cuda => "cuda",
+            Feature::Cudann => "cudann",
+            Feature::Nccl => "nccl",
+        }
+    }
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A set of enabled features. This serializes as an object with every known feature mapped to
+/// whether it is enabled so tooling doesn't need to know which features might be missing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeatureSet(BTreeSet<Feature>);
+
+impl FeatureSet {
+    /// The features the running binary was built with.
+    pub fn compiled() -> Self {
+        Feature::ALL
+            .into_iter()
+            .filter(Feature::is_compiled)
+            .collect()
+    }
+
+    pub fn has(&self, feature: Feature) -> bool {
+        self.0.contains(&feature)
+    }
+}
+
+impl Display for FeatureSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("none");
+        }
+
+        let names: Vec<_> = self.0.iter().map(Feature::name).collect();
+        f.write_str(&names.join(", "))
+    }
+}
+
+impl FromIterator<Feature> for FeatureSet {
+    fn from_iter<I: IntoIterator<Item = Feature>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl Serialize for FeatureSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(Feature::ALL.len()))?;
+
+        for feature in Feature::ALL {
+            map.serialize_entry(feature.name(), &self.has(feature))?;
+        }
+
+        map.end()
+    }
+}
 
 #[derive(Serialize)]
 pub struct Version {
     pub build_profile: &'static str,
-    pub features: Vec<&'static str>,
+    pub features: FeatureSet,
     pub version: &'static str,
 }
 
@@ -20,7 +108,7 @@ impl Version {
     pub fn new() -> Self {
         Self {
             build_profile: env!("BUILD_PROFILE"),
-            features: env!("BUILD_FEATURES").split(',').collect::<Vec<_>>(),
+            features: FeatureSet::compiled(),
             version: env!("REPO_VERSION"),
         }
     }
@@ -31,3 +119,24 @@ impl IntoResponse for Version {
         (StatusCode::OK, Json(self)).into_response()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_features_serialize_as_flags() {
+        let features: FeatureSet = [Feature::Nccl, Feature::Cuda].into_iter().collect();
+        assert!(features.has(Feature::Cuda));
+        assert!(!features.has(Feature::Cudann));
+
+        let encoded = serde_json::to_value(&features).unwrap();
+        assert_eq!(
+            encoded,
+            serde_json::json!({"cuda": true, "cudann": false, "nccl": true})
+        );
+        assert_eq!(features.to_string(), "cuda, nccl");
+
+        assert_eq!(FeatureSet::default().to_string(), "none");
+    }
+}
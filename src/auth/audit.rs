@@ -0,0 +1,18 @@
+use crate::database::models::CreateAuditEvent;
+use crate::database::Database;
+
+/// Writes an entry to the audit log. Failing to record an event is logged but never prevents the
+/// action being audited from completing.
+pub(crate) async fn record(database: &Database, event: CreateAuditEvent) {
+    let mut conn = match database.acquire().await {
+        Ok(conn) => conn,
+        Err(err) => {
+            tracing::error!("failed to acquire database connection for audit event: {err}");
+            return;
+        }
+    };
+
+    if let Err(err) = event.save(&mut conn).await {
+        tracing::error!("failed to record audit event: {err}");
+    }
+}
@@ -8,10 +8,11 @@ use crate::app::State as AppState;
 use crate::auth::{OAuthClient, OAuthClientError};
 use crate::database::custom_types::LoginProvider;
 use crate::database::models::{CreateOAuthState, OAuthStateError};
-use crate::extractors::{ServerBase, SessionIdentity};
+use crate::extractors::{Requestor, ServerBase, SessionIdentity};
 
 pub async fn handler(
     session: Option<SessionIdentity>,
+    requestor: Requestor,
     State(state): State<AppState>,
     ServerBase(hostname): ServerBase,
     Path(provider): Path<LoginProvider>,
@@ -37,13 +38,17 @@ pub async fn handler(
     let database = state.database();
     CreateOAuthState::new(
         provider,
+        requestor.client_ip(),
         oauth_challenge.csrf_token,
         oauth_challenge.pkce_code_verifier,
         params.next_url,
     )
-    .save(&database)
+    .save(&database, state.oauth_state_limit())
     .await
-    .map_err(LoginError::UnableToStoreSession)?;
+    .map_err(|err| match err {
+        OAuthStateError::TooManyOutstanding => LoginError::TooManyOutstanding,
+        err => LoginError::UnableToStoreSession(err),
+    })?;
 
     Ok(Redirect::to(authorization_url.as_str()).into_response())
 }
@@ -56,12 +61,22 @@ pub enum LoginError {
     #[error("failed to configure OAuth client: {0}")]
     UnableToConfigureOAuth(OAuthClientError),
 
+    #[error("client has too many logins in progress")]
+    TooManyOutstanding,
+
     #[error("unable to create session in the database: {0}")]
     UnableToStoreSession(OAuthStateError),
 }
 
 impl IntoResponse for LoginError {
     fn into_response(self) -> Response {
+        if let LoginError::TooManyOutstanding = self {
+            tracing::warn!("refusing to start a login: {self}");
+            let err_msg =
+                serde_json::json!({"msg": "too many logins in progress, try again later"});
+            return (StatusCode::TOO_MANY_REQUESTS, Json(err_msg)).into_response();
+        }
+
         tracing::error!("encountered an issue starting the login process: {self}");
         let err_msg = serde_json::json!({"msg": "backend service experienced an issue servicing the request"});
         (StatusCode::INTERNAL_SERVER_ERROR, Json(err_msg)).into_response()
@@ -0,0 +1,135 @@
+use std::fmt::{self, Display, Formatter};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// The number of the user's most recent sessions a new login is compared against.
+pub const RECENT_SESSION_WINDOW: u32 = 10;
+
+/// How different the address of a new login needs to be from a user's recent sessions before it is
+/// considered suspicious. Addresses are compared by network rather than exactly so that ordinary
+/// address churn from an ISP doesn't constantly trip the check. There is no geographic lookup,
+/// a different network is the only signal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LoginAnomalySensitivity {
+    Disabled,
+
+    /// Flags logins from outside any IPv4 /16 or IPv6 /32 the user has recently logged in from
+    #[default]
+    Low,
+
+    /// Flags logins from outside any IPv4 /24 or IPv6 /48 the user has recently logged in from
+    High,
+}
+
+impl LoginAnomalySensitivity {
+    /// Whether a login from `client_ip` stands out from the addresses of the user's recent
+    /// sessions. Users without any previous sessions have nothing to compare against and are never
+    /// flagged.
+    pub fn is_anomalous(&self, client_ip: IpAddr, recent_ips: &[IpAddr]) -> bool {
+        if *self == LoginAnomalySensitivity::Disabled || recent_ips.is_empty() {
+            return false;
+        }
+
+        !recent_ips
+            .iter()
+            .any(|recent_ip| self.same_network(client_ip, *recent_ip))
+    }
+
+    fn same_network(&self, first: IpAddr, second: IpAddr) -> bool {
+        let (v4_prefix, v6_prefix) = match self {
+            LoginAnomalySensitivity::Disabled => return true,
+            LoginAnomalySensitivity::Low => (16, 32),
+            LoginAnomalySensitivity::High => (24, 48),
+        };
+
+        match (canonical(first), canonical(second)) {
+            (IpAddr::V4(first), IpAddr::V4(second)) => {
+                let mask = u32::MAX << (32 - v4_prefix);
+                u32::from(first) & mask == u32::from(second) & mask
+            }
+            (IpAddr::V6(first), IpAddr::V6(second)) => {
+                let mask = u128::MAX << (128 - v6_prefix);
+                u128::from(first) & mask == u128::from(second) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Clients reaching a dual stack listener over IPv4 show up as IPv4-mapped IPv6 addresses, these
+/// need to be compared as the IPv4 address they actually are.
+fn canonical(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(addr),
+        IpAddr::V4(_) => addr,
+    }
+}
+
+impl Display for LoginAnomalySensitivity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            LoginAnomalySensitivity::Disabled => "disabled",
+            LoginAnomalySensitivity::Low => "low",
+            LoginAnomalySensitivity::High => "high",
+        };
+
+        f.write_str(msg)
+    }
+}
+
+impl FromStr for LoginAnomalySensitivity {
+    type Err = LoginAnomalySensitivityError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        match val.trim().to_lowercase().as_str() {
+            "disabled" | "off" => Ok(LoginAnomalySensitivity::Disabled),
+            "low" => Ok(LoginAnomalySensitivity::Low),
+            "high" => Ok(LoginAnomalySensitivity::High),
+            _ => Err(LoginAnomalySensitivityError::Unknown(val.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoginAnomalySensitivityError {
+    #[error("unknown login anomaly sensitivity '{0}', expected one of disabled, low, or high")]
+    Unknown(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn test_sensitivity_levels() {
+        let recent = [ip("203.0.113.10"), ip("2001:db8:1:2::1")];
+
+        let low = LoginAnomalySensitivity::Low;
+        assert!(!low.is_anomalous(ip("203.0.200.1"), &recent));
+        assert!(!low.is_anomalous(ip("2001:db8:ffff::1"), &recent));
+        assert!(low.is_anomalous(ip("198.51.100.1"), &recent));
+
+        let high = LoginAnomalySensitivity::High;
+        assert!(!high.is_anomalous(ip("203.0.113.99"), &recent));
+        assert!(high.is_anomalous(ip("203.0.200.1"), &recent));
+        assert!(high.is_anomalous(ip("2001:db8:ffff::1"), &recent));
+
+        let disabled = LoginAnomalySensitivity::Disabled;
+        assert!(!disabled.is_anomalous(ip("198.51.100.1"), &recent));
+    }
+
+    #[test]
+    fn test_first_login_never_anomalous() {
+        assert!(!LoginAnomalySensitivity::High.is_anomalous(ip("198.51.100.1"), &[]));
+    }
+
+    #[test]
+    fn test_mapped_addresses_compare_as_ipv4() {
+        let recent = [ip("203.0.113.10")];
+        assert!(!LoginAnomalySensitivity::High.is_anomalous(ip("::ffff:203.0.113.20"), &recent));
+    }
+}
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use crate::database::custom_types::UserId;
+use crate::database::models::{AuditEvent, AuditEventError};
+use crate::database::DatabaseConnection;
+
+/// Temporarily refuses to complete logins for an account once enough failed logins aimed at it
+/// have been recorded in the audit log within the window. Authentication itself happens at the
+/// provider, this limits how often someone can keep trying to get into an account through the
+/// callback. A successful login clears the count.
+#[derive(Clone, Copy, Debug)]
+pub struct LoginLockout {
+    attempts: u32,
+    window: Duration,
+}
+
+impl LoginLockout {
+    /// Whether logins to the account are currently refused. Lockouts are disabled when no attempts
+    /// are allowed to be counted.
+    pub async fn is_locked(
+        &self,
+        conn: &mut DatabaseConnection,
+        user_id: UserId,
+    ) -> Result<bool, AuditEventError> {
+        if self.attempts == 0 {
+            return Ok(false);
+        }
+
+        let failures = AuditEvent::recent_login_failures(conn, user_id, self.window).await?;
+        Ok(failures >= i64::from(self.attempts))
+    }
+
+    pub fn new(attempts: u32, window: Duration) -> Self {
+        Self { attempts, window }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::FromRequestParts;
+
+    use super::*;
+    use crate::app::BotClassifier;
+    use crate::database::custom_types::AuditEventType;
+    use crate::database::models::{CreateAuditEvent, CreateUser};
+    use crate::extractors::Requestor;
+    use crate::tests::prelude::*;
+
+    async fn record_login(conn: &mut DatabaseConnection, user_id: UserId, succeeded: bool) {
+        let (mut parts, _) = http::Request::new(()).into_parts();
+        let classifier = BotClassifier::new(&[] as &[&str]);
+        let requestor = Requestor::from_request_parts(&mut parts, &classifier)
+            .await
+            .unwrap();
+
+        let event = if succeeded {
+            CreateAuditEvent::success(AuditEventType::Login, &requestor)
+        } else {
+            CreateAuditEvent::failure(AuditEventType::Login, &requestor)
+        };
+        event.with_user(user_id).save(conn).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_repeated_failures_lock_until_success() {
+        let pool = migrated_test_database().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let user_id = CreateUser::new("locked@example.com", "Locked User")
+            .save(&mut conn)
+            .await
+            .unwrap();
+
+        let lockout = LoginLockout::new(3, Duration::from_secs(900));
+
+        for _ in 0..2 {
+            record_login(&mut conn, user_id, false).await;
+        }
+        assert!(!lockout.is_locked(&mut conn, user_id).await.unwrap());
+
+        record_login(&mut conn, user_id, false).await;
+        assert!(lockout.is_locked(&mut conn, user_id).await.unwrap());
+
+        // Lockouts can be turned off entirely
+        let disabled = LoginLockout::new(0, Duration::from_secs(900));
+        assert!(!disabled.is_locked(&mut conn, user_id).await.unwrap());
+
+        // Failures from before a successful login no longer count
+        record_login(&mut conn, user_id, true).await;
+        record_login(&mut conn, user_id, false).await;
+        assert!(!lockout.is_locked(&mut conn, user_id).await.unwrap());
+    }
+}
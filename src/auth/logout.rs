@@ -1,23 +1,33 @@
 use axum::response::{IntoResponse, Redirect, Response};
 use axum_extra::extract::CookieJar;
+use oauth2::AccessToken;
+use url::Url;
 
-use crate::auth::{LOGIN_PATH, SESSION_COOKIE_NAME};
-use crate::database::custom_types::SessionId;
-use crate::database::models::Session;
+use crate::app::Secrets;
+use crate::auth::{audit, remove_session_cookies, OAuthClient, LOGIN_PATH};
+use crate::database::custom_types::{AuditEventType, SessionId};
+use crate::database::models::{CreateAuditEvent, OAuthProviderAccount, Session};
 use crate::database::Database;
-use crate::extractors::SessionIdentity;
-use crate::utils::remove_cookie;
+use crate::extractors::{Requestor, ServerBase, SessionIdentity};
 
 pub async fn handler(
     session: Option<SessionIdentity>,
+    requestor: Requestor,
     database: Database,
+    secrets: Secrets,
+    ServerBase(hostname): ServerBase,
     mut cookie_jar: CookieJar,
 ) -> Response {
     if let Some(sid) = session {
+        try_revoke_token(&database, &secrets, hostname, sid.id()).await;
         try_clear_session(&database, sid.id()).await;
+
+        let event =
+            CreateAuditEvent::success(AuditEventType::Logout, &requestor).with_user(sid.user_id());
+        audit::record(&database, event).await;
     }
 
-    cookie_jar = remove_cookie(SESSION_COOKIE_NAME, cookie_jar);
+    cookie_jar = remove_session_cookies(cookie_jar);
     (cookie_jar, Redirect::to(LOGIN_PATH)).into_response()
 }
 
@@ -34,3 +44,72 @@ async fn try_clear_session(database: &Database, sid: SessionId) {
         tracing::error!("failed to remove session from the db: {err}");
     }
 }
+
+/// Revokes the access token the session was created with so logging out also ends the grant the
+/// user gave us with their provider. This is best effort, the user is logged out of our side
+/// regardless of whether the provider accepted the revocation.
+async fn try_revoke_token(database: &Database, secrets: &Secrets, hostname: Url, sid: SessionId) {
+    let session = match database.acquire().await {
+        Ok(mut conn) => Session::locate(&mut conn, sid).await,
+        Err(err) => {
+            tracing::warn!("failed to acquire database connection when revoking a token: {err}");
+            return;
+        }
+    };
+
+    match session {
+        Ok(Some(session)) => revoke_provider_token(database, secrets, hostname, &session).await,
+        Ok(None) => (),
+        Err(err) => tracing::warn!("failed to look up session when revoking its token: {err}"),
+    }
+}
+
+/// Revokes the provider access token stored with the session, if there is one. Failures are
+/// logged and otherwise ignored, the session has already been or is about to be ended on our side.
+pub(crate) async fn revoke_provider_token(
+    database: &Database,
+    secrets: &Secrets,
+    hostname: Url,
+    session: &Session,
+) {
+    // Only sessions from providers that support revocation have a token stored
+    let Some(sealed_token) = session.oauth_access_token() else {
+        return;
+    };
+    let access_token = match secrets.token_cipher().open(sealed_token) {
+        Ok(token) => AccessToken::new(token),
+        Err(err) => {
+            tracing::warn!("unable to read stored access token for revocation: {err}");
+            return;
+        }
+    };
+
+    let provider =
+        match OAuthProviderAccount::lookup_by_id(database, session.oauth_provider_account_id())
+            .await
+        {
+            Ok(Some(account)) => account.provider(),
+            Ok(None) => return,
+            Err(err) => {
+                tracing::warn!("failed to look up provider account when revoking a token: {err}");
+                return;
+            }
+        };
+
+    let oauth_client = match OAuthClient::configure(provider, hostname, secrets) {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::warn!(%provider, "unable to configure client to revoke token: {err}");
+            return;
+        }
+    };
+
+    let revocation =
+        tokio::task::spawn_blocking(move || oauth_client.revoke_token(access_token)).await;
+
+    match revocation {
+        Ok(Ok(())) => (),
+        Ok(Err(err)) => tracing::warn!(%provider, "{err}"),
+        Err(err) => tracing::warn!("failed to spawn blocking task for token revocation: {err}"),
+    }
+}
@@ -1,21 +1,50 @@
+use std::time::Duration;
+
 use askama::Template;
 use axum::response::{Html, IntoResponse, Response};
-use axum::routing::get;
+use axum::routing::{delete, get};
 use axum::Router;
 
-use crate::app::State;
+use crate::app::{Secrets, State};
+use crate::background_jobs::BackoffStrategy;
+use crate::database::custom_types::LoginProvider;
 
+mod audit;
 mod login;
+mod login_anomaly;
+mod login_lockout;
 mod logout;
 mod oauth_callback;
 mod oauth_client;
+mod profile_provider;
+mod session_cookie;
+mod sessions;
+mod signup_policy;
 
+pub use login_anomaly::{
+    LoginAnomalySensitivity, LoginAnomalySensitivityError, RECENT_SESSION_WINDOW,
+};
+pub use login_lockout::LoginLockout;
 pub use oauth_client::{OAuthClient, OAuthClientError};
+pub use profile_provider::{
+    GithubProfileProvider, GoogleProfileProvider, ProfileProvider, ProfileProviderError,
+};
+pub use session_cookie::{remove_session_cookies, SessionCookie};
+pub use signup_policy::{SignupMode, SignupModeError, SignupPolicy, SignupRefusal};
 
 pub static CALLBACK_PATH_TEMPLATE: &str = "/auth/callback/{}";
 
+pub static HOST_SESSION_COOKIE_NAME: &str = "__Host-session_id";
+
 pub static LOGIN_PATH: &str = "/auth/login";
 
+/// How long to wait before retrying a request to a login provider that failed with a network
+/// error. These happen while the user is waiting on the login so the delays are kept short.
+const OAUTH_RETRY_BACKOFF: BackoffStrategy = BackoffStrategy::Exponential {
+    base: Duration::from_millis(250),
+    cap: Duration::from_secs(2),
+};
+
 pub static SESSION_COOKIE_NAME: &str = "_session_id";
 
 pub const SESSION_TTL: u64 = 28 * 24 * 60 * 60;
@@ -26,13 +55,19 @@ pub fn router(state: State) -> Router<State> {
         .route("/login", get(select_provider_handler))
         .route("/login/:provider", get(login::handler))
         .route("/logout", get(logout::handler))
+        .route("/sessions", get(sessions::list_handler))
+        .route("/sessions/:session_id", delete(sessions::revoke_handler))
         .with_state(state)
 }
 
-pub async fn select_provider_handler() -> Response {
-    LoginTemplate.into_response()
+pub async fn select_provider_handler(secrets: Secrets) -> Response {
+    let github_enabled = secrets.provider_credential(LoginProvider::Github).is_some();
+
+    LoginTemplate { github_enabled }.into_response()
 }
 
 #[derive(Template)]
 #[template(path = "login.html")]
-pub struct LoginTemplate;
+pub struct LoginTemplate {
+    github_enabled: bool,
+}
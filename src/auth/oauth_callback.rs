@@ -1,43 +1,82 @@
+use askama::Template;
 use axum::extract::{Path, Query, State};
 use axum::response::{IntoResponse, Redirect, Response};
 use axum::Json;
-use axum_extra::extract::cookie::{Cookie, SameSite};
 use axum_extra::extract::CookieJar;
-use base64::engine::general_purpose::URL_SAFE_NO_PAD as B64;
-use base64::Engine;
-use ecdsa::signature::RandomizedDigestSigner;
 use http::StatusCode;
-use jwt_simple::algorithms::ECDSAP384KeyPairLike;
 use oauth2::{AuthorizationCode, CsrfToken, TokenResponse};
 use serde::Deserialize;
+use std::net::IpAddr;
+use time::OffsetDateTime;
 use url::Url;
 
 use crate::app::State as AppState;
-use crate::auth::SESSION_COOKIE_NAME;
-use crate::auth::{OAuthClient, OAuthClientError};
+use crate::auth::{
+    audit, LoginAnomalySensitivity, OAuthClient, OAuthClientError, ProfileProviderError,
+    SignupRefusal, RECENT_SESSION_WINDOW,
+};
 use crate::database::custom_types::{
-    LoginProvider, OAuthProviderAccountId, OAuthProviderAccountIdError, ProviderId, UserId,
+    AuditEventType, LoginProvider, OAuthProviderAccountId, OAuthProviderAccountIdError, UserId,
     UserIdError,
 };
 use crate::database::models::{
-    CreateOAuthProviderAccount, CreateSession, CreateUser, OAuthStateError, SessionError,
-    UserError, VerifyOAuthState,
+    AuditEventError, CreateAuditEvent, CreateOAuthProviderAccount, CreateSession, CreateUser,
+    OAuthStateError, Session, SessionError, UserError, VerifyOAuthState,
 };
 use crate::database::models::{OAuthProviderAccount, OAuthProviderAccountError};
-use crate::database::Database;
-use crate::event_bus::{SystemEvent, UserRegistration};
-use crate::extractors::ServerBase;
+use crate::database::{Database, DatabaseConnection};
+use crate::event_bus::{SuspiciousLogin, UserRegistration};
+use crate::extractors::{session_cookie_value, Requestor, ServerBase};
 
 pub async fn handler(
     database: Database,
-    mut cookie_jar: CookieJar,
+    requestor: Requestor,
+    cookie_jar: CookieJar,
     State(state): State<AppState>,
     ServerBase(hostname): ServerBase,
     Path(provider): Path<LoginProvider>,
     Query(params): Query<CallbackParameters>,
 ) -> Result<Response, OAuthCallbackError> {
+    let login_result = complete_login(
+        &database, &requestor, cookie_jar, &state, hostname, provider, params,
+    )
+    .await;
+
+    let event = match &login_result {
+        Ok((user_id, _)) => {
+            CreateAuditEvent::success(AuditEventType::Login, &requestor).with_user(*user_id)
+        }
+        Err(err) => {
+            // Refused attempts against a locked account don't count towards keeping it locked
+            let event_type = match err {
+                OAuthCallbackError::AccountLocked(_) => AuditEventType::LoginLocked,
+                _ => AuditEventType::Login,
+            };
+
+            let event =
+                CreateAuditEvent::failure(event_type, &requestor).with_details(err.to_string());
+            match err.targeted_user() {
+                Some(user_id) => event.with_user(user_id),
+                None => event,
+            }
+        }
+    };
+    audit::record(&database, event).await;
+
+    login_result.map(|(_, response)| response)
+}
+
+async fn complete_login(
+    database: &Database,
+    requestor: &Requestor,
+    mut cookie_jar: CookieJar,
+    state: &AppState,
+    hostname: Url,
+    provider: LoginProvider,
+    params: CallbackParameters,
+) -> Result<(UserId, Response), OAuthCallbackError> {
     let verify_oauth_state =
-        VerifyOAuthState::locate_and_delete(&database, provider, params.csrf_token)
+        VerifyOAuthState::locate_and_delete(database, provider, params.csrf_token)
             .await
             .map_err(OAuthCallbackError::LookupFailed)?
             .ok_or(OAuthCallbackError::NoMatchingState)?;
@@ -46,39 +85,21 @@ pub async fn handler(
         .map_err(OAuthCallbackError::UnableToConfigureOAuth)?;
 
     let pkce_code_verifier = verify_oauth_state.pkce_code_verifier();
-    let token_response = tokio::task::spawn_blocking(move || {
-        oauth_client.validate_exchange(params.authorization_code, pkce_code_verifier)
-    })
-    .await
-    .map_err(OAuthCallbackError::SpawnFailure)?
-    .map_err(OAuthCallbackError::ValidationFailed)?;
+    let retries = state.oauth_retries();
+    let token_response = oauth_client
+        .validate_exchange(params.authorization_code, pkce_code_verifier, retries)
+        .await
+        .map_err(OAuthCallbackError::ValidationFailed)?;
 
     let access_token = token_response.access_token();
 
-    let cookie_domain = hostname
-        .host_str()
-        .expect("built from a hostname")
-        .to_string();
-    let cookie_secure = hostname.scheme() == "https";
-
-    // We're back in provider specific land for getting information about the authenticated user,
-    // todo: need to abstract this somehow for different implementors...
-
-    let user_info_url = Url::parse_with_params(
-        "https://www.googleapis.com/oauth2/v2/userinfo",
-        &[("oauth_token", access_token.secret())],
-    )
-    .expect("fixed format to be valid");
-
-    let user_info: GoogleUserProfile = reqwest::get(user_info_url)
-        .await
-        .expect("building a fixed format request to succeed")
-        .json()
+    let user_info = provider
+        .config()
+        .profile_provider()
+        .fetch_profile_with_retries(access_token.secret(), retries)
         .await
         .map_err(OAuthCallbackError::ProfileUnavailable)?;
 
-    // out of provider specific land for the most part
-
     let mut conn = database
         .acquire()
         .await
@@ -87,7 +108,7 @@ pub async fn handler(
     let maybe_provider_account_id = OAuthProviderAccountId::from_provider_account_id(
         &mut conn,
         provider,
-        user_info.google_id.clone(),
+        user_info.provider_id.clone(),
     )
     .await
     .map_err(OAuthCallbackError::FailedAccountLookup)?;
@@ -107,78 +128,143 @@ pub async fn handler(
             // unknown provider claiming the same email address
             if let Some(user_id) = existing_user {
                 tracing::warn!(user_id = ?user_id, "attempt to access account from unauthorized provider");
-                return Err(OAuthCallbackError::AlternateProvider);
+                return Err(OAuthCallbackError::AlternateProvider(user_id));
             }
 
+            state
+                .signup_policy()
+                .check(&user_info.email)
+                .map_err(OAuthCallbackError::SignupRefused)?;
+
             let new_user_id = CreateUser::new(&user_info.email, &user_info.name)
                 .save(&mut conn)
                 .await
                 .map_err(OAuthCallbackError::UserCreationFailed)?;
 
             // todo: at least log failures...
-            let _ = state.event_bus().send(
-                SystemEvent::UserRegistration,
-                &UserRegistration { id: new_user_id },
-            );
+            let _ = state
+                .event_bus()
+                .send_typed(&UserRegistration { id: new_user_id });
 
             CreateOAuthProviderAccount::new(
                 new_user_id,
                 provider,
-                user_info.google_id,
+                user_info.provider_id,
                 user_info.email.to_string(),
             )
-            .save(&database)
+            .save(database)
             .await
             .map_err(OAuthCallbackError::ProviderAccountCreationFailed)?
         }
     };
 
-    let provider_account = OAuthProviderAccount::lookup_by_id(&database, provider_account_id)
+    let provider_account = OAuthProviderAccount::lookup_by_id(database, provider_account_id)
         .await
         .map_err(OAuthCallbackError::AccountDetailLookupFailed)?
         .ok_or(OAuthCallbackError::AccountIntegrityViolation)?;
 
-    let new_session = CreateSession::new(provider_account.user_id(), provider_account.id());
+    let user_id = provider_account.user_id();
+    let locked = state
+        .login_lockout()
+        .is_locked(&mut conn, user_id)
+        .await
+        .map_err(OAuthCallbackError::LockoutCheckFailed)?;
+    if locked {
+        tracing::warn!(user_id = ?user_id, "refused login to temporarily locked account");
+        return Err(OAuthCallbackError::AccountLocked(user_id));
+    }
+
+    // Providers that issue refresh tokens have the access token renewed in the background
+    let token_expires_at = token_response
+        .expires_in()
+        .and_then(|ttl| OffsetDateTime::now_utc().checked_add(ttl.try_into().ok()?));
+    let token_cipher = state.secrets().token_cipher();
+    let sealed_refresh_token = token_response
+        .refresh_token()
+        .map(|rt| token_cipher.seal(rt.secret()));
+    OAuthProviderAccount::store_tokens(
+        &mut conn,
+        provider_account.id(),
+        &token_cipher.seal(access_token.secret()),
+        sealed_refresh_token.as_ref(),
+        token_expires_at,
+    )
+    .await
+    .map_err(OAuthCallbackError::TokenStorageFailed)?;
+
+    let mut new_session = CreateSession::new(provider_account.user_id(), provider_account.id());
     let expires_at = new_session.expires_at();
 
-    // todo: store client IP and user_agent in the session if they're available as well
+    if let Some(user_agent) = requestor.user_agent() {
+        new_session.set_user_agent(user_agent.to_string());
+    }
+
+    // The token is only worth holding on to when we can revoke it at logout
+    if provider.config().revocation_url().is_some() {
+        new_session.set_oauth_access_token(token_cipher.seal(access_token.secret()));
+    }
+
+    if let Some(client_ip) = requestor.client_ip() {
+        new_session.set_client_ip(client_ip);
+        check_login_anomaly(&mut conn, state, provider_account.user_id(), client_ip).await;
+    }
 
     let session_id = new_session
         .create(&mut conn)
         .await
         .map_err(OAuthCallbackError::SessionCreationFailed)?;
 
-    let session_enc = B64.encode(session_id.to_bytes_le());
-
-    let mut digest = hmac_sha512::sha384::Hash::new();
-    digest.update(session_enc.as_bytes());
-    let mut rng = rand::thread_rng();
-
-    let service_signing_key = state.secrets().service_signing_key();
-    let signature: ecdsa::Signature<p384::NistP384> = service_signing_key
-        .key_pair()
-        .as_ref()
-        .sign_digest_with_rng(&mut rng, digest);
+    let event = CreateAuditEvent::success(AuditEventType::SessionCreated, requestor)
+        .with_user(provider_account.user_id());
+    audit::record(database, event).await;
 
-    let auth_tag = B64.encode(signature.to_vec());
-    let session_value = [session_enc, auth_tag].join("");
+    let session_value = session_cookie_value(session_id, &state.secrets().service_signing_key());
 
-    cookie_jar = cookie_jar.add(
-        Cookie::build((SESSION_COOKIE_NAME, session_value))
-            .http_only(true)
-            .expires(expires_at)
-            .same_site(SameSite::Lax)
-            .path("/")
-            .domain(cookie_domain)
-            .secure(cookie_secure)
-            .finish(),
-    );
+    let session_cookie = state.session_cookie();
+    cookie_jar = cookie_jar.add(session_cookie.build(&hostname, session_value, expires_at));
 
     let redirect_url = verify_oauth_state
         .post_login_redirect_url()
         .unwrap_or("/".to_string());
 
-    Ok((cookie_jar, Redirect::to(&redirect_url)).into_response())
+    Ok((
+        provider_account.user_id(),
+        (cookie_jar, Redirect::to(&redirect_url)).into_response(),
+    ))
+}
+
+/// Compares the address of a login against the user's recent sessions and reports it on the event
+/// bus if it stands out. This is purely informational, failures are logged and the login is
+/// allowed to continue regardless.
+async fn check_login_anomaly(
+    conn: &mut DatabaseConnection,
+    state: &AppState,
+    user_id: UserId,
+    client_ip: IpAddr,
+) {
+    let sensitivity = state.login_anomaly_sensitivity();
+    if sensitivity == LoginAnomalySensitivity::Disabled {
+        return;
+    }
+
+    let recent_ips = match Session::recent_client_ips(conn, user_id, RECENT_SESSION_WINDOW).await {
+        Ok(ips) => ips,
+        Err(err) => {
+            tracing::warn!("unable to check login against recent sessions: {err}");
+            return;
+        }
+    };
+
+    if !sensitivity.is_anomalous(client_ip, &recent_ips) {
+        return;
+    }
+
+    tracing::warn!(user_id = ?user_id, "login from an address unlike the user's recent sessions");
+
+    let event = SuspiciousLogin { user_id, client_ip };
+    if let Err(err) = state.event_bus().send_typed(&event) {
+        tracing::warn!("failed to report suspicious login: {err}");
+    }
 }
 
 #[derive(Deserialize)]
@@ -190,28 +276,19 @@ pub struct CallbackParameters {
     csrf_token: CsrfToken,
 }
 
-#[derive(Deserialize)]
-pub struct GoogleUserProfile {
-    // This is an all numeric ID (sample one was 21 digits) that comes in as a string, probably
-    // could be stored as a number but I'd rather treat it as a unique identifier.
-    #[serde(rename = "id")]
-    google_id: ProviderId,
-
-    name: String,
-    email: String,
-    verified_email: bool,
-}
-
 #[derive(Debug, thiserror::Error)]
 pub enum OAuthCallbackError {
     #[error("account disappeared in path that guarantees its presence")]
     AccountIntegrityViolation,
 
+    #[error("account is temporarily locked after repeated failed logins")]
+    AccountLocked(UserId),
+
     #[error("failed to load details of provider account for session creation: {0}")]
     AccountDetailLookupFailed(OAuthProviderAccountError),
 
     #[error("successful login from an unauthorized provider for existing account")]
-    AlternateProvider,
+    AlternateProvider(UserId),
 
     #[error("unable to perform database operation: {0}")]
     DatabaseUnavailable(sqlx::Error),
@@ -219,6 +296,9 @@ pub enum OAuthCallbackError {
     #[error("failed to query the database for a provider account: {0}")]
     FailedAccountLookup(OAuthProviderAccountIdError),
 
+    #[error("unable to check whether the account is locked: {0}")]
+    LockoutCheckFailed(AuditEventError),
+
     #[error("unable to query OAuth states for callback parameter")]
     LookupFailed(OAuthStateError),
 
@@ -228,14 +308,17 @@ pub enum OAuthCallbackError {
     #[error("received OAuth callback query but no matching session parameters were present")]
     NoMatchingState,
 
-    #[error("unable to request user's profile: {0}")]
-    ProfileUnavailable(reqwest::Error),
+    #[error("unable to fetch user's profile: {0}")]
+    ProfileUnavailable(ProfileProviderError),
+
+    #[error("new account wasn't allowed to be created: {0}")]
+    SignupRefused(SignupRefusal),
 
     #[error("failed to create new session after successful login: {0}")]
     SessionCreationFailed(SessionError),
 
-    #[error("failed to spawn blocking task for exchange code authorization: {0}")]
-    SpawnFailure(tokio::task::JoinError),
+    #[error("failed to store the tokens issued by the provider: {0}")]
+    TokenStorageFailed(OAuthProviderAccountError),
 
     #[error("failed to configure OAuth client: {0}")]
     UnableToConfigureOAuth(OAuthClientError),
@@ -253,6 +336,18 @@ pub enum OAuthCallbackError {
     ValidationFailed(OAuthClientError),
 }
 
+impl OAuthCallbackError {
+    /// The existing account a failed login was aimed at, when it got far enough to know. These
+    /// are the failures that count towards locking the account.
+    fn targeted_user(&self) -> Option<UserId> {
+        match self {
+            OAuthCallbackError::AccountLocked(user_id)
+            | OAuthCallbackError::AlternateProvider(user_id) => Some(*user_id),
+            _ => None,
+        }
+    }
+}
+
 impl IntoResponse for OAuthCallbackError {
     fn into_response(self) -> Response {
         match self {
@@ -260,6 +355,15 @@ impl IntoResponse for OAuthCallbackError {
                 let msg = serde_json::json!({"msg": "no matching authentication state"});
                 (StatusCode::NOT_FOUND, Json(msg)).into_response()
             }
+            OAuthCallbackError::AccountLocked(_) => {
+                (StatusCode::FORBIDDEN, AccountLockedTemplate).into_response()
+            }
+            OAuthCallbackError::SignupRefused(refusal) => {
+                let template = SignupRefusedTemplate {
+                    domain_not_allowed: matches!(refusal, SignupRefusal::DomainNotAllowed),
+                };
+                (StatusCode::FORBIDDEN, template).into_response()
+            }
             _ => {
                 tracing::error!("encountered an issue completing the login process: {self}");
                 let err_msg = serde_json::json!({"msg": "backend service experienced an issue servicing the request"});
@@ -268,3 +372,15 @@ impl IntoResponse for OAuthCallbackError {
         }
     }
 }
+
+/// Shown in place of completing a login to an account that is temporarily locked.
+#[derive(Template)]
+#[template(path = "account_locked.html")]
+struct AccountLockedTemplate;
+
+/// Shown in place of creating an account when the signup policy doesn't allow it.
+#[derive(Template)]
+#[template(path = "signup_refused.html")]
+struct SignupRefusedTemplate {
+    domain_not_allowed: bool,
+}
@@ -1,17 +1,22 @@
+use std::future::Future;
+
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use http::StatusCode;
 use oauth2::basic::{BasicClient, BasicTokenType};
 use oauth2::{
-    AuthorizationCode, CsrfToken, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope,
+    AccessToken, AuthorizationCode, CsrfToken, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl,
+    RefreshToken, RequestTokenError, Scope, StandardRevocableToken,
 };
-use oauth2::{EmptyExtraTokenFields, StandardTokenResponse};
+use oauth2::{EmptyExtraTokenFields, HttpRequest, HttpResponse, StandardTokenResponse};
 use url::Url;
 
 use crate::app::Secrets;
-use crate::auth::CALLBACK_PATH_TEMPLATE;
+use crate::auth::{CALLBACK_PATH_TEMPLATE, OAUTH_RETRY_BACKOFF};
 use crate::database::custom_types::LoginProvider;
 
+type TokenResponse = StandardTokenResponse<EmptyExtraTokenFields, BasicTokenType>;
+
 pub struct OAuthClient {
     client: BasicClient,
     login_provider: LoginProvider,
@@ -23,6 +28,18 @@ impl OAuthClient {
         mut redirect_url: Url,
         secrets: &Secrets,
     ) -> Result<Self, OAuthClientError> {
+        let mut oauth_client = Self::new(login_provider, secrets)?;
+
+        redirect_url.set_path(&CALLBACK_PATH_TEMPLATE.replace("{}", &login_provider.to_string()));
+        let redirect_url = RedirectUrl::from_url(redirect_url);
+        oauth_client.client = oauth_client.client.set_redirect_uri(redirect_url);
+
+        Ok(oauth_client)
+    }
+
+    /// Builds a client without a redirect URL. This can't be used to start a login, but is enough
+    /// for working with tokens the provider has already issued.
+    pub fn new(login_provider: LoginProvider, secrets: &Secrets) -> Result<Self, OAuthClientError> {
         let provider_credentials = secrets.provider_credential(login_provider).ok_or(
             OAuthClientError::CredentialsMissing(login_provider.to_string()),
         )?;
@@ -32,16 +49,12 @@ impl OAuthClient {
         let auth_url = provider_config.auth_url();
         let token_url = provider_config.token_url();
 
-        redirect_url.set_path(&CALLBACK_PATH_TEMPLATE.replace("{}", &login_provider.to_string()));
-        let redirect_url = RedirectUrl::from_url(redirect_url);
-
         let mut client = BasicClient::new(
             provider_credentials.id(),
             Some(provider_credentials.secret()),
             auth_url,
             token_url,
-        )
-        .set_redirect_uri(redirect_url);
+        );
 
         if let Some(ru) = provider_config.revocation_url() {
             client = client.set_revocation_uri(ru);
@@ -73,17 +86,113 @@ impl OAuthClient {
         })
     }
 
-    pub fn validate_exchange(
+    /// Trades a refresh token for a new access token. Failures reaching the provider are reported
+    /// separately from the provider refusing the refresh token, only the latter requires the user
+    /// to log in again. Requests are made with the provided client, normally
+    /// `oauth2::reqwest::async_http_client`.
+    pub(crate) async fn refresh_access_token_with<C, F, RE>(
+        &self,
+        refresh_token: RefreshToken,
+        http_client: C,
+    ) -> Result<TokenResponse, OAuthClientError>
+    where
+        C: Fn(HttpRequest) -> F,
+        F: Future<Output = Result<HttpResponse, RE>>,
+        RE: std::error::Error + 'static,
+    {
+        self.client
+            .exchange_refresh_token(&refresh_token)
+            .request_async(&http_client)
+            .await
+            .map_err(|err| match err {
+                RequestTokenError::Request(err) => {
+                    OAuthClientError::RefreshUnavailable(err.to_string())
+                }
+                err => OAuthClientError::RefreshRejected(err.to_string()),
+            })
+    }
+
+    /// Asks the provider to revoke an access token it issued, ending the grant the user gave us.
+    /// This makes a blocking request to the provider's revocation URL and fails for providers
+    /// that don't have one.
+    pub fn revoke_token(&self, access_token: AccessToken) -> Result<(), OAuthClientError> {
+        self.revoke_token_with(access_token, oauth2::reqwest::http_client)
+    }
+
+    fn revoke_token_with<F, RE>(
+        &self,
+        access_token: AccessToken,
+        http_client: F,
+    ) -> Result<(), OAuthClientError>
+    where
+        F: FnOnce(HttpRequest) -> Result<HttpResponse, RE>,
+        RE: std::error::Error + 'static,
+    {
+        self.client
+            .revoke_token(StandardRevocableToken::AccessToken(access_token))
+            .map_err(|err| OAuthClientError::RevocationFailure(err.to_string()))?
+            .request(http_client)
+            .map_err(|err| OAuthClientError::RevocationFailure(err.to_string()))
+    }
+
+    /// Trades the authorization code from the callback for an access token. Exchanges that fail
+    /// to reach the provider are retried up to `retries` more times, errors reported by the
+    /// provider itself (such as an expired code) are returned immediately.
+    pub async fn validate_exchange(
+        &self,
+        authorization_code: AuthorizationCode,
+        pkce_code_verifier: PkceCodeVerifier,
+        retries: u8,
+    ) -> Result<TokenResponse, OAuthClientError> {
+        self.validate_exchange_with(
+            authorization_code,
+            pkce_code_verifier,
+            retries,
+            oauth2::reqwest::async_http_client,
+        )
+        .await
+    }
+
+    async fn validate_exchange_with<C, F, RE>(
         &self,
         authorization_code: AuthorizationCode,
         pkce_code_verifier: PkceCodeVerifier,
-    ) -> Result<StandardTokenResponse<EmptyExtraTokenFields, BasicTokenType>, OAuthClientError>
+        retries: u8,
+        http_client: C,
+    ) -> Result<TokenResponse, OAuthClientError>
+    where
+        C: Fn(HttpRequest) -> F,
+        F: Future<Output = Result<HttpResponse, RE>>,
+        RE: std::error::Error + 'static,
     {
-        self.client
-            .exchange_code(authorization_code)
-            .set_pkce_verifier(pkce_code_verifier)
-            .request(oauth2::reqwest::http_client)
-            .map_err(|err| OAuthClientError::ExchangeCodeFailure(err.to_string()))
+        let mut attempt = 0;
+
+        loop {
+            // The verifier is consumed by each attempt, only the secret inside it can be copied
+            let pkce_code_verifier = PkceCodeVerifier::new(pkce_code_verifier.secret().clone());
+
+            let result = self
+                .client
+                .exchange_code(authorization_code.clone())
+                .set_pkce_verifier(pkce_code_verifier)
+                .request_async(&http_client)
+                .await;
+
+            match result {
+                Ok(token_response) => return Ok(token_response),
+                Err(RequestTokenError::Request(err)) if attempt < retries => {
+                    attempt += 1;
+
+                    let delay = OAUTH_RETRY_BACKOFF.delay(attempt);
+                    tracing::warn!(
+                        ?delay,
+                        "token exchange failed to reach provider, retrying: {err}"
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(err) => return Err(OAuthClientError::ExchangeCodeFailure(err.to_string())),
+            }
+        }
     }
 }
 
@@ -94,6 +203,15 @@ pub enum OAuthClientError {
 
     #[error("failed to verify exchange code: {0}")]
     ExchangeCodeFailure(String),
+
+    #[error("provider refused to refresh the access token: {0}")]
+    RefreshRejected(String),
+
+    #[error("unable to reach provider to refresh the access token: {0}")]
+    RefreshUnavailable(String),
+
+    #[error("failed to revoke token with the provider: {0}")]
+    RevocationFailure(String),
 }
 
 impl IntoResponse for OAuthClientError {
@@ -111,3 +229,154 @@ pub struct OAuthChallenge {
     pub csrf_token: CsrfToken,
     pub pkce_code_verifier: PkceCodeVerifier,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::collections::BTreeMap;
+
+    use jwt_simple::prelude::ES384KeyPair;
+    // The OAuth client is built against an older version of the http crate than we use
+    use oauth2::http::{HeaderMap, Method, StatusCode as OAuthStatusCode};
+    use oauth2::TokenResponse as _;
+
+    use super::*;
+    use crate::app::{ProviderCredential, ServiceSigningKey};
+
+    fn google_client() -> OAuthClient {
+        let credentials = BTreeMap::from([(
+            LoginProvider::Google,
+            ProviderCredential::new("client-id", "client-secret"),
+        )]);
+        let signing_key = ServiceSigningKey::new(ES384KeyPair::generate());
+        let secrets = Secrets::new(credentials, signing_key);
+
+        let server_base = Url::parse("https://app.example.com").unwrap();
+        OAuthClient::configure(LoginProvider::Google, server_base, &secrets).unwrap()
+    }
+
+    fn json_response(status_code: OAuthStatusCode, body: &str) -> HttpResponse {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            oauth2::http::header::CONTENT_TYPE,
+            "application/json".parse().unwrap(),
+        );
+
+        HttpResponse {
+            status_code,
+            headers,
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exchange_retries_only_transport_errors() {
+        let client = google_client();
+        let code = AuthorizationCode::new("code".to_string());
+        let verifier = PkceCodeVerifier::new("verifier".to_string());
+
+        let attempts = Cell::new(0);
+        let flaky_client = |_request: HttpRequest| {
+            attempts.set(attempts.get() + 1);
+            let first_attempt = attempts.get() == 1;
+
+            async move {
+                if first_attempt {
+                    return Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset));
+                }
+
+                let body = r#"{"access_token":"token","token_type":"bearer"}"#;
+                Ok(json_response(OAuthStatusCode::OK, body))
+            }
+        };
+
+        let token_response = client
+            .validate_exchange_with(code.clone(), verifier, 1, flaky_client)
+            .await
+            .unwrap();
+        assert_eq!(token_response.access_token().secret(), "token");
+        assert_eq!(attempts.get(), 2);
+
+        let attempts = Cell::new(0);
+        let rejecting_client = |_request: HttpRequest| {
+            attempts.set(attempts.get() + 1);
+
+            let body = r#"{"error":"invalid_grant"}"#;
+            std::future::ready(Ok::<_, std::io::Error>(json_response(
+                OAuthStatusCode::BAD_REQUEST,
+                body,
+            )))
+        };
+
+        let verifier = PkceCodeVerifier::new("verifier".to_string());
+        let result = client
+            .validate_exchange_with(code, verifier, 3, rejecting_client)
+            .await;
+        assert!(matches!(
+            result,
+            Err(OAuthClientError::ExchangeCodeFailure(_))
+        ));
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rejections_distinguished_from_transport_errors() {
+        let client = google_client();
+        let refresh_token = RefreshToken::new("refresh-token".to_string());
+
+        let unreachable_client = |_request: HttpRequest| {
+            std::future::ready(Err::<HttpResponse, _>(std::io::Error::from(
+                std::io::ErrorKind::ConnectionReset,
+            )))
+        };
+        let result = client
+            .refresh_access_token_with(refresh_token.clone(), unreachable_client)
+            .await;
+        assert!(matches!(
+            result,
+            Err(OAuthClientError::RefreshUnavailable(_))
+        ));
+
+        let rejecting_client = |_request: HttpRequest| {
+            let body = r#"{"error":"invalid_grant"}"#;
+            std::future::ready(Ok::<_, std::io::Error>(json_response(
+                OAuthStatusCode::BAD_REQUEST,
+                body,
+            )))
+        };
+        let result = client
+            .refresh_access_token_with(refresh_token, rejecting_client)
+            .await;
+        assert!(matches!(result, Err(OAuthClientError::RefreshRejected(_))));
+    }
+
+    #[test]
+    fn test_revocation_sent_to_provider_revocation_url() {
+        let client = google_client();
+
+        let sent_request = RefCell::new(None);
+        let recording_client = |request: HttpRequest| {
+            sent_request.replace(Some(request));
+
+            Ok::<_, std::io::Error>(HttpResponse {
+                status_code: OAuthStatusCode::OK,
+                headers: HeaderMap::new(),
+                body: Vec::new(),
+            })
+        };
+
+        let access_token = AccessToken::new("access-token".to_string());
+        client
+            .revoke_token_with(access_token, recording_client)
+            .expect("revocation to succeed");
+
+        let request = sent_request.into_inner().expect("a request to be sent");
+        let revocation_url = LoginProvider::Google.config().revocation_url().unwrap();
+
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.url.as_str(), revocation_url.url().as_str());
+
+        let body = String::from_utf8(request.body).unwrap();
+        assert!(body.contains("token=access-token"));
+    }
+}
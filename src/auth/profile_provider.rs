@@ -0,0 +1,229 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use url::Url;
+
+use crate::auth::OAUTH_RETRY_BACKOFF;
+use crate::database::custom_types::ProviderId;
+use crate::utils::{http_client, HttpClient, HttpClientOptions};
+
+/// Looks up the user an access token was issued for. Each login provider exposes this in its own
+/// way, implementations take care of the provider specific requests and hand back the handful of
+/// details we need in a common form.
+#[async_trait]
+pub trait ProfileProvider: Send + Sync {
+    async fn fetch_profile(
+        &self,
+        access_token: &str,
+    ) -> Result<NormalizedProfile, ProfileProviderError>;
+
+    /// Fetches the profile, trying up to `retries` more times when the provider couldn't be
+    /// reached or had a problem of its own.
+    async fn fetch_profile_with_retries(
+        &self,
+        access_token: &str,
+        retries: u8,
+    ) -> Result<NormalizedProfile, ProfileProviderError> {
+        let mut attempt = 0;
+
+        loop {
+            match self.fetch_profile(access_token).await {
+                Ok(profile) => return Ok(profile),
+                Err(err) if err.is_retryable() && attempt < retries => {
+                    attempt += 1;
+
+                    let delay = OAUTH_RETRY_BACKOFF.delay(attempt);
+                    tracing::warn!(?delay, "profile request failed, retrying: {err}");
+                    tokio::time::sleep(delay).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// The details about an authenticated user we need from any of the providers.
+#[derive(Debug)]
+pub struct NormalizedProfile {
+    pub provider_id: ProviderId,
+    pub email: String,
+    pub name: String,
+    pub verified_email: bool,
+}
+
+/// GitHub allows users to hide their email from their public profile so it is always looked up
+/// separately, only the primary address is used.
+pub struct GithubProfileProvider;
+
+#[async_trait]
+impl ProfileProvider for GithubProfileProvider {
+    async fn fetch_profile(
+        &self,
+        access_token: &str,
+    ) -> Result<NormalizedProfile, ProfileProviderError> {
+        // GitHub rejects API requests that don't identify themselves, the shared client always sets
+        // our user agent
+        let client = profile_client();
+        let github_get = |url: &str| {
+            client.send(
+                client
+                    .get(url)
+                    .bearer_auth(access_token)
+                    .header(http::header::ACCEPT, "application/vnd.github+json"),
+            )
+        };
+
+        let github_user: GithubUserProfile = github_get("https://api.github.com/user")
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(ProfileProviderError::Unavailable)?
+            .json()
+            .await
+            .map_err(ProfileProviderError::Unavailable)?;
+
+        let emails: Vec<GithubEmail> = github_get("https://api.github.com/user/emails")
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(ProfileProviderError::Unavailable)?
+            .json()
+            .await
+            .map_err(ProfileProviderError::Unavailable)?;
+
+        let primary_email = emails
+            .into_iter()
+            .find(|email| email.primary)
+            .ok_or(ProfileProviderError::MissingEmail)?;
+
+        Ok(NormalizedProfile {
+            provider_id: ProviderId::from(github_user.id.to_string()),
+            email: primary_email.email,
+            name: github_user.name.unwrap_or(github_user.login),
+            verified_email: primary_email.verified,
+        })
+    }
+}
+
+pub struct GoogleProfileProvider;
+
+#[async_trait]
+impl ProfileProvider for GoogleProfileProvider {
+    async fn fetch_profile(
+        &self,
+        access_token: &str,
+    ) -> Result<NormalizedProfile, ProfileProviderError> {
+        let user_info_url = Url::parse_with_params(
+            "https://www.googleapis.com/oauth2/v2/userinfo",
+            &[("oauth_token", access_token)],
+        )
+        .expect("fixed format to be valid");
+
+        let client = profile_client();
+        let google_user: GoogleUserProfile = client
+            .send(client.get(user_info_url))
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(ProfileProviderError::Unavailable)?
+            .json()
+            .await
+            .map_err(ProfileProviderError::Unavailable)?;
+
+        Ok(google_user.into())
+    }
+}
+
+/// Retries are left to [`ProfileProvider::fetch_profile_with_retries`] which covers the whole
+/// profile lookup rather than the individual requests.
+fn profile_client() -> HttpClient {
+    http_client(HttpClientOptions::default())
+}
+
+#[derive(Deserialize)]
+struct GithubEmail {
+    email: String,
+    primary: bool,
+    verified: bool,
+}
+
+#[derive(Deserialize)]
+struct GithubUserProfile {
+    id: u64,
+    login: String,
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GoogleUserProfile {
+    // This is an all numeric ID (sample one was 21 digits) that comes in as a string, probably
+    // could be stored as a number but I'd rather treat it as a unique identifier.
+    #[serde(rename = "id")]
+    google_id: ProviderId,
+
+    name: String,
+    email: String,
+    verified_email: bool,
+}
+
+impl From<GoogleUserProfile> for NormalizedProfile {
+    fn from(profile: GoogleUserProfile) -> Self {
+        Self {
+            provider_id: profile.google_id,
+            email: profile.email,
+            name: profile.name,
+            verified_email: profile.verified_email,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProfileProviderError {
+    #[error("login provider didn't report a primary email address for the user")]
+    MissingEmail,
+
+    #[error("unable to request user's profile: {0}")]
+    Unavailable(reqwest::Error),
+}
+
+impl ProfileProviderError {
+    /// Whether the request could succeed if it was made again. Only problems reaching the
+    /// provider or errors on the provider's side qualify, anything wrong with the token or the
+    /// profile itself won't change.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ProfileProviderError::MissingEmail => false,
+            ProfileProviderError::Unavailable(err) => {
+                err.is_connect()
+                    || err.is_timeout()
+                    || err.status().is_some_and(|status| status.is_server_error())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_google_userinfo_normalized() {
+        let userinfo = r#"{
+            "id": "108432176025938475610",
+            "email": "jane.doe@example.com",
+            "verified_email": true,
+            "name": "Jane Doe",
+            "given_name": "Jane",
+            "family_name": "Doe",
+            "picture": "https://lh3.googleusercontent.com/a/default-user",
+            "locale": "en"
+        }"#;
+
+        let google_user: GoogleUserProfile = serde_json::from_str(userinfo).unwrap();
+        let profile = NormalizedProfile::from(google_user);
+
+        assert_eq!(
+            profile.provider_id,
+            ProviderId::from("108432176025938475610".to_string())
+        );
+        assert_eq!(profile.email, "jane.doe@example.com");
+        assert_eq!(profile.name, "Jane Doe");
+        assert!(profile.verified_email);
+    }
+}
@@ -0,0 +1,113 @@
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum_extra::extract::CookieJar;
+use http::HeaderMap;
+use time::OffsetDateTime;
+use url::Url;
+
+use crate::auth::{HOST_SESSION_COOKIE_NAME, SESSION_COOKIE_NAME};
+use crate::extractors::request_scheme;
+use crate::utils::remove_cookie;
+
+/// Decides the name and attributes of the session cookie. When the host prefix is enabled, clients
+/// reaching us over HTTPS get a `__Host-` prefixed cookie which browsers will only accept when it
+/// is secure, scoped to the root path, and not shared with any other domain. That keeps a
+/// compromised sibling subdomain from planting its own session on our users. Plain HTTP can't
+/// satisfy those requirements so the unprefixed name is always used there.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SessionCookie {
+    host_prefix: bool,
+}
+
+impl SessionCookie {
+    /// Creates the session cookie for a client that reached us at `server_base`.
+    pub fn build(
+        &self,
+        server_base: &Url,
+        value: String,
+        expires_at: OffsetDateTime,
+    ) -> Cookie<'static> {
+        let secure = server_base.scheme() == "https";
+        let name = self.name(secure);
+
+        let mut cookie = Cookie::build((name, value))
+            .http_only(true)
+            .expires(expires_at)
+            .same_site(SameSite::Lax)
+            .path("/")
+            .secure(secure);
+
+        // Browsers reject host prefixed cookies that specify any domain at all
+        if name != HOST_SESSION_COOKIE_NAME {
+            let domain = server_base
+                .host_str()
+                .expect("built from a hostname")
+                .to_string();
+            cookie = cookie.domain(domain);
+        }
+
+        cookie.finish()
+    }
+
+    /// The name the session cookie is expected under for a request with the provided headers.
+    pub fn name_for_request(&self, headers: &HeaderMap) -> &'static str {
+        self.name(request_scheme(headers) == "https")
+    }
+
+    pub fn new(host_prefix: bool) -> Self {
+        Self { host_prefix }
+    }
+
+    fn name(&self, secure: bool) -> &'static str {
+        if self.host_prefix && secure {
+            HOST_SESSION_COOKIE_NAME
+        } else {
+            SESSION_COOKIE_NAME
+        }
+    }
+}
+
+/// Expires the session cookie under either name, the setting may have changed since the client
+/// received it.
+pub fn remove_session_cookies(mut cookie_jar: CookieJar) -> CookieJar {
+    cookie_jar = remove_cookie(SESSION_COOKIE_NAME, cookie_jar);
+    remove_cookie(HOST_SESSION_COOKIE_NAME, cookie_jar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_prefix_only_used_over_https() {
+        let expires_at = OffsetDateTime::now_utc();
+        let https = Url::parse("https://app.example.com").unwrap();
+        let http = Url::parse("http://app.example.com").unwrap();
+
+        let prefixed = SessionCookie::new(true);
+
+        let cookie = prefixed.build(&https, "value".to_string(), expires_at);
+        assert_eq!(cookie.name(), HOST_SESSION_COOKIE_NAME);
+        assert_eq!(cookie.domain(), None);
+        assert_eq!(cookie.path(), Some("/"));
+        assert_eq!(cookie.secure(), Some(true));
+
+        let cookie = prefixed.build(&http, "value".to_string(), expires_at);
+        assert_eq!(cookie.name(), SESSION_COOKIE_NAME);
+        assert_eq!(cookie.domain(), Some("app.example.com"));
+        assert_eq!(cookie.secure(), Some(false));
+
+        let cookie = SessionCookie::new(false).build(&https, "value".to_string(), expires_at);
+        assert_eq!(cookie.name(), SESSION_COOKIE_NAME);
+        assert_eq!(cookie.domain(), Some("app.example.com"));
+        assert_eq!(cookie.secure(), Some(true));
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(prefixed.name_for_request(&headers), SESSION_COOKIE_NAME);
+
+        headers.insert("x-forwarded-proto", "https".parse().unwrap());
+        assert_eq!(
+            prefixed.name_for_request(&headers),
+            HOST_SESSION_COOKIE_NAME
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use axum::extract::{Path, State};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::StatusCode;
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::app::Secrets;
+use crate::auth::audit;
+use crate::auth::logout::revoke_provider_token;
+use crate::database::custom_types::{AuditEventType, SessionId};
+use crate::database::models::{CreateAuditEvent, Session};
+use crate::database::Database;
+use crate::event_bus::{EventBus, SessionRevoked};
+use crate::extractors::{Requestor, ServerBase, SessionIdentity};
+
+/// Lists the sessions the user currently has open, including the one making the request.
+pub async fn list_handler(
+    session: SessionIdentity,
+    database: Database,
+) -> Result<Response, SessionsError> {
+    let mut conn = database
+        .acquire()
+        .await
+        .map_err(SessionsError::DatabaseUnavailable)?;
+
+    let sessions = Session::list_for_user(&mut conn, session.user_id())
+        .await
+        .map_err(SessionsError::LookupFailed)?;
+
+    let summaries: Vec<_> = sessions
+        .iter()
+        .map(|s| SessionSummary::new(s, s.id() == session.id()))
+        .collect();
+
+    Ok((StatusCode::OK, Json(summaries)).into_response())
+}
+
+/// Ends one of the user's sessions, typically one on another device. Sessions that belong to
+/// someone else are reported as missing so their existence isn't revealed. Like logging out, this
+/// also revokes the access token the session was created with at its provider.
+pub async fn revoke_handler(
+    session: SessionIdentity,
+    requestor: Requestor,
+    database: Database,
+    secrets: Secrets,
+    ServerBase(hostname): ServerBase,
+    State(event_bus): State<EventBus>,
+    Path(session_id): Path<SessionId>,
+) -> Result<Response, SessionsError> {
+    let mut conn = database
+        .acquire()
+        .await
+        .map_err(SessionsError::DatabaseUnavailable)?;
+
+    // The stored token is removed along with the session, it has to be read out beforehand
+    let revoked_session = Session::locate(&mut conn, session_id)
+        .await
+        .map_err(SessionsError::LookupFailed)?
+        .filter(|s| s.user_id() == session.user_id())
+        .ok_or(SessionsError::NotFound)?;
+
+    let revoked = Session::revoke(&mut conn, session_id, session.user_id())
+        .await
+        .map_err(SessionsError::RevocationFailed)?;
+
+    if !revoked {
+        return Err(SessionsError::NotFound);
+    }
+
+    // Release the connection, revoking the token needs one of its own
+    drop(conn);
+
+    // Connected clients watch for this to drop anything still using the session
+    if let Err(err) = event_bus.send_typed(&SessionRevoked { session_id }) {
+        tracing::warn!("failed to announce session revocation: {err}");
+    }
+
+    revoke_provider_token(&database, &secrets, hostname, &revoked_session).await;
+
+    let event = CreateAuditEvent::success(AuditEventType::SessionRevoked, &requestor)
+        .with_user(session.user_id())
+        .with_details(format!("revoked session {session_id}"));
+    audit::record(&database, event).await;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[derive(Serialize)]
+struct SessionSummary {
+    id: SessionId,
+    client_ip: Option<String>,
+    user_agent: Option<String>,
+    current: bool,
+
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    expires_at: OffsetDateTime,
+}
+
+impl SessionSummary {
+    fn new(session: &Session, current: bool) -> Self {
+        Self {
+            id: session.id(),
+            client_ip: session.client_ip().map(String::from),
+            user_agent: session.user_agent().map(String::from),
+            current,
+
+            created_at: session.created_at(),
+            expires_at: session.expires_at(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SessionsError {
+    #[error("unable to acquire database connection: {0}")]
+    DatabaseUnavailable(sqlx::Error),
+
+    #[error("failed to look up the user's sessions: {0}")]
+    LookupFailed(sqlx::Error),
+
+    #[error("no session owned by the user matched the requested ID")]
+    NotFound,
+
+    #[error("failed to revoke session: {0}")]
+    RevocationFailed(sqlx::Error),
+}
+
+impl IntoResponse for SessionsError {
+    fn into_response(self) -> Response {
+        match self {
+            SessionsError::NotFound => {
+                let msg = serde_json::json!({"msg": "session not found"});
+                (StatusCode::NOT_FOUND, Json(msg)).into_response()
+            }
+            _ => {
+                tracing::error!("encountered an issue managing user sessions: {self}");
+                let err_msg = serde_json::json!({"msg": "backend service experienced an issue servicing the request"});
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(err_msg)).into_response()
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::app::AdminList;
+
+/// Who is allowed to create an account by logging in for the first time. Users that already have
+/// an account can always log in, and the configured admins can always sign up so a closed
+/// deployment can still be set up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SignupMode {
+    /// Anyone with a verified email address can sign up
+    #[default]
+    Open,
+
+    /// Nobody but the admins can sign up, there is no way to invite anyone else yet
+    Invite,
+
+    /// Only email addresses at one of the allowed domains can sign up
+    Domain,
+}
+
+impl Display for SignupMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            SignupMode::Open => "open",
+            SignupMode::Invite => "invite",
+            SignupMode::Domain => "domain",
+        };
+
+        f.write_str(msg)
+    }
+}
+
+impl FromStr for SignupMode {
+    type Err = SignupModeError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        match val.trim().to_lowercase().as_str() {
+            "open" => Ok(SignupMode::Open),
+            "invite" => Ok(SignupMode::Invite),
+            "domain" => Ok(SignupMode::Domain),
+            _ => Err(SignupModeError::Unknown(val.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignupModeError {
+    #[error("unknown signup mode '{0}', expected one of open, invite, or domain")]
+    Unknown(String),
+}
+
+/// Decides whether a first time login is allowed to create a new account. Domains are compared
+/// case-insensitively, matching how emails are stored on users.
+#[derive(Clone, Default)]
+pub struct SignupPolicy {
+    admin_list: AdminList,
+    allowed_domains: Arc<BTreeSet<String>>,
+    mode: SignupMode,
+}
+
+impl SignupPolicy {
+    pub fn check(&self, email: &str) -> Result<(), SignupRefusal> {
+        if self.mode == SignupMode::Open || self.admin_list.contains(email) {
+            return Ok(());
+        }
+
+        match self.mode {
+            SignupMode::Open => Ok(()),
+            SignupMode::Invite => Err(SignupRefusal::Closed),
+            SignupMode::Domain => {
+                let domain = email
+                    .trim()
+                    .rsplit_once('@')
+                    .map(|(_, domain)| domain.to_lowercase());
+
+                match domain {
+                    Some(domain) if self.allowed_domains.contains(&domain) => Ok(()),
+                    _ => Err(SignupRefusal::DomainNotAllowed),
+                }
+            }
+        }
+    }
+
+    pub fn new(
+        mode: SignupMode,
+        allowed_domains: &[impl AsRef<str>],
+        admin_list: AdminList,
+    ) -> Self {
+        let allowed_domains = allowed_domains
+            .iter()
+            .map(|d| d.as_ref().trim().trim_start_matches('@').to_lowercase())
+            .filter(|d| !d.is_empty())
+            .collect();
+
+        Self {
+            admin_list,
+            allowed_domains: Arc::new(allowed_domains),
+            mode,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignupRefusal {
+    #[error("signups are closed")]
+    Closed,
+
+    #[error("signups aren't allowed from this email domain")]
+    DomainNotAllowed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_signups_allow_anyone() {
+        let policy = SignupPolicy::new(SignupMode::Open, &[] as &[&str], AdminList::default());
+        assert!(policy.check("anyone@example.com").is_ok());
+    }
+
+    #[test]
+    fn test_invite_signups_only_allow_admins() {
+        let admins = AdminList::new(&["ops@example.com"]);
+        let policy = SignupPolicy::new(SignupMode::Invite, &[] as &[&str], admins);
+
+        assert!(policy.check("Ops@Example.com").is_ok());
+        assert!(matches!(
+            policy.check("dev@example.com"),
+            Err(SignupRefusal::Closed)
+        ));
+    }
+
+    #[test]
+    fn test_domain_signups_match_allowed_domains() {
+        let admins = AdminList::new(&["ops@elsewhere.net"]);
+        let policy = SignupPolicy::new(SignupMode::Domain, &["Example.com", "@corp.io"], admins);
+
+        assert!(policy.check("dev@EXAMPLE.com").is_ok());
+        assert!(policy.check("dev@corp.io").is_ok());
+        assert!(policy.check("ops@elsewhere.net").is_ok());
+
+        for email in ["dev@elsewhere.net", "dev@sub.example.com", "not-an-email"] {
+            assert!(matches!(
+                policy.check(email),
+                Err(SignupRefusal::DomainNotAllowed)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_mode_parsing() {
+        assert_eq!("Invite".parse::<SignupMode>().unwrap(), SignupMode::Invite);
+        assert_eq!(
+            " domain ".parse::<SignupMode>().unwrap(),
+            SignupMode::Domain
+        );
+        assert!("closed".parse::<SignupMode>().is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+/// How long a failed job waits before its next attempt. The delay is based on the attempt that
+/// just failed, starting from 1 for the first attempt of a job.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackoffStrategy {
+    /// Wait the same amount of time between every attempt
+    Fixed(Duration),
+
+    /// Wait the provided amount of time for each attempt that has failed so far
+    Linear(Duration),
+
+    /// Double the delay with each failed attempt, never waiting longer than the cap
+    Exponential { base: Duration, cap: Duration },
+}
+
+impl BackoffStrategy {
+    pub fn delay(&self, attempt: u8) -> Duration {
+        match self {
+            Self::Fixed(delay) => *delay,
+            Self::Linear(step) => step.saturating_mul(u32::from(attempt)),
+            Self::Exponential { base, cap } => {
+                let factor = 2u32.saturating_pow(u32::from(attempt));
+                base.saturating_mul(factor).min(*cap)
+            }
+        }
+    }
+}
+
+impl Default for BackoffStrategy {
+    /// Retries after 4, 8, 16... seconds, waiting at most an hour between attempts.
+    fn default() -> Self {
+        Self::Exponential {
+            base: Duration::from_secs(2),
+            cap: Duration::from_secs(3_600),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strategy_delays() {
+        let step = Duration::from_secs(10);
+
+        assert_eq!(BackoffStrategy::Fixed(step).delay(5), step);
+        assert_eq!(BackoffStrategy::Linear(step).delay(3), step * 3);
+
+        let exponential = BackoffStrategy::default();
+        assert_eq!(exponential.delay(1), Duration::from_secs(4));
+        assert_eq!(exponential.delay(2), Duration::from_secs(8));
+        assert_eq!(exponential.delay(u8::MAX), Duration::from_secs(3_600));
+    }
+}
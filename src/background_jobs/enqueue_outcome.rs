@@ -0,0 +1,23 @@
+use crate::database::custom_types::BackgroundJobId;
+
+/// What happened when a job was handed to a store. Jobs with a unique key aren't added again while
+/// an equivalent job is still waiting to run, the caller is told which job it collided with
+/// instead so it can keep track of that one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnqueueOutcome {
+    /// A new job was added to the store
+    Created { job_id: BackgroundJobId },
+
+    /// An equivalent job was already waiting to run so nothing new was added
+    Deduplicated { existing_id: BackgroundJobId },
+}
+
+impl EnqueueOutcome {
+    /// The ID of the job that will carry out the work, whether or not it was just created.
+    pub fn job_id(&self) -> BackgroundJobId {
+        match self {
+            Self::Created { job_id } => *job_id,
+            Self::Deduplicated { existing_id } => *existing_id,
+        }
+    }
+}
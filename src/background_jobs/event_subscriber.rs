@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use sqlx::SqlitePool;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+use crate::background_jobs::impls::RecordRegistrationJob;
+use crate::background_jobs::{
+    BasicTaskStore, EnqueueOutcome, JobLike, JobLikeExt, JobStore, JobStoreError,
+};
+use crate::database::Database;
+use crate::event_bus::{BusEvent, EventBus, SystemEvent, UserRegistration};
+use crate::ShutdownSignal;
+
+type EnqueueFn = Arc<
+    dyn Fn(SqlitePool, Vec<u8>) -> BoxFuture<'static, Result<EnqueueOutcome, EventSubscriberError>>
+        + Send
+        + Sync,
+>;
+
+/// Enqueues background jobs in response to events on the bus, keeping the code producing an event
+/// unaware of the work that follows from it. Each event type may trigger any number of jobs, each
+/// enqueued onto the store it was routed to.
+///
+/// Events are only seen while the subscriber is running, anything sent while the service is down
+/// or that is skipped after falling behind the bus never has its jobs enqueued. Work that can't be
+/// lost should be enqueued alongside the change that caused it instead.
+#[derive(Clone)]
+pub struct EventSubscriber {
+    database: Database,
+    routes: BTreeMap<SystemEvent, Vec<EnqueueFn>>,
+}
+
+impl EventSubscriber {
+    async fn dispatch(&self, event: SystemEvent, payload: Vec<u8>) {
+        let Some(routes) = self.routes.get(&event) else {
+            return;
+        };
+
+        for enqueue in routes {
+            let pool = SqlitePool::clone(&self.database);
+
+            match enqueue(pool, payload.clone()).await {
+                Ok(outcome) => tracing::debug!(?event, ?outcome, "enqueued job for event"),
+                Err(err) => tracing::error!(?event, "failed to enqueue job for event: {err}"),
+            }
+        }
+    }
+
+    /// The subscriber the service runs. Every job enqueued in response to an event is declared
+    /// here so the full mapping can be seen in one place, the pools running these jobs need to
+    /// register each of them.
+    pub fn declared(database: Database) -> Self {
+        Self::new(database).route::<UserRegistration, RecordRegistrationJob, BasicTaskStore>(
+            RecordRegistrationJob::from,
+        )
+    }
+
+    pub fn new(database: Database) -> Self {
+        Self {
+            database,
+            routes: BTreeMap::new(),
+        }
+    }
+
+    /// Enqueues the job produced by `to_job` onto the store `S` every time an `E` event is seen.
+    pub fn route<E, J, S>(mut self, to_job: fn(E) -> J) -> Self
+    where
+        E: BusEvent + 'static,
+        J: JobLike,
+        S: JobStore<Connection = SqlitePool>,
+    {
+        let enqueue: EnqueueFn = Arc::new(move |mut pool, payload| {
+            Box::pin(async move {
+                let event = E::decode(&payload).map_err(EventSubscriberError::Decode)?;
+
+                to_job(event)
+                    .enqueue::<S>(&mut pool)
+                    .await
+                    .map_err(EventSubscriberError::Enqueue)
+            })
+        });
+
+        self.routes.entry(E::EVENT).or_default().push(enqueue);
+        self
+    }
+
+    /// Starts enqueuing jobs for events sent from this point on until shutdown begins or the bus
+    /// goes away.
+    pub fn start(self, event_bus: &EventBus, mut shutdown_rx: ShutdownSignal) -> JoinHandle<()> {
+        let events: Vec<SystemEvent> = self.routes.keys().copied().collect();
+
+        // Subscribing before the task is spawned means nothing sent after this returns is missed
+        let mut stream = event_bus.subscribe_filtered(&events);
+
+        tokio::spawn(async move {
+            loop {
+                let next = tokio::select! {
+                    _ = shutdown_rx.changed() => break,
+                    next = stream.next() => next,
+                };
+
+                match next {
+                    Some(Ok((event, payload))) => self.dispatch(event, payload).await,
+                    Some(Err(RecvError::Lagged(count))) => {
+                        tracing::warn!("event subscriber fell behind and missed {count} events");
+                    }
+                    Some(Err(RecvError::Closed)) | None => break,
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EventSubscriberError {
+    #[error("payload didn't match the event type: {0}")]
+    Decode(bincode::Error),
+
+    #[error("unable to enqueue the job: {0}")]
+    Enqueue(JobStoreError),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::background_jobs::impls::TestJob;
+    use crate::background_jobs::BasicTaskContext;
+    use crate::database::custom_types::{BackgroundJobState, SessionId, UserId};
+    use crate::database::models::BackgroundJob;
+    use crate::event_bus::TestEvent;
+    use crate::tests::prelude::*;
+    use crate::ShutdownReason;
+
+    async fn wait_for_scheduled(store: &BasicTaskStore) -> Vec<BackgroundJob> {
+        for _ in 0..50 {
+            let enqueued = store
+                .list_by_state(BackgroundJobState::Scheduled, 10)
+                .await
+                .unwrap();
+
+            if !enqueued.is_empty() {
+                return enqueued;
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        Vec::new()
+    }
+
+    #[tokio::test]
+    async fn test_declared_routes_record_registrations() {
+        let database = Database::new(migrated_test_database().await);
+        let store = BasicTaskStore::new(BasicTaskContext::new(database.clone(), 10));
+        let event_bus = EventBus::default();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(None);
+
+        let handle = EventSubscriber::declared(database).start(&event_bus, shutdown_rx);
+
+        event_bus
+            .send_typed(&UserRegistration {
+                id: UserId::from(Uuid::new_v4()),
+            })
+            .unwrap();
+
+        let enqueued = wait_for_scheduled(&store).await;
+        assert_eq!(enqueued.len(), 1);
+        assert_eq!(enqueued[0].name(), RecordRegistrationJob::JOB_NAME);
+
+        shutdown_tx.send(Some(ShutdownReason::Interrupt)).unwrap();
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_events_enqueue_their_routed_jobs() {
+        let database = Database::new(migrated_test_database().await);
+        let store = BasicTaskStore::new(BasicTaskContext::new(database.clone(), 10));
+        let event_bus = EventBus::default();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(None);
+
+        let handle = EventSubscriber::new(database)
+            .route::<TestEvent, _, BasicTaskStore>(|_| TestJob::<()>::new(7))
+            .start(&event_bus, shutdown_rx);
+
+        event_bus
+            .send_typed(&TestEvent {
+                session_id: SessionId::from(Uuid::new_v4()),
+            })
+            .unwrap();
+
+        let enqueued = wait_for_scheduled(&store).await;
+        assert_eq!(enqueued.len(), 1);
+        assert_eq!(enqueued[0].name(), "test_job");
+
+        shutdown_tx.send(Some(ShutdownReason::Interrupt)).unwrap();
+        handle.await.unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::background_jobs::{JobLike, JobOutcome, QueueName, ValidationError};
+use crate::database::models::{CreateEmbedding, EmbeddingError};
+use crate::database::Database;
+use crate::llm::hugging_face::EMBEDDING_MODEL;
+use crate::llm::{Embedder, EmbedderError};
+
+#[derive(Clone)]
+pub struct EmbedTaskContext {
+    database: Database,
+    embedder: Embedder,
+}
+
+impl EmbedTaskContext {
+    pub fn database(&self) -> &Database {
+        &self.database
+    }
+
+    pub fn embedder(&self) -> &Embedder {
+        &self.embedder
+    }
+
+    pub fn new(database: Database, embedder: Embedder) -> Self {
+        Self { database, embedder }
+    }
+}
+
+/// Generates and stores embeddings for a set of texts outside of the request path. Bulk ingestion
+/// should split its corpus across several of these jobs rather than embedding it inline.
+#[derive(Deserialize, Serialize)]
+pub struct EmbedJob {
+    texts: Vec<String>,
+}
+
+impl EmbedJob {
+    pub fn new(texts: Vec<String>) -> Self {
+        Self { texts }
+    }
+}
+
+#[async_trait]
+impl JobLike for EmbedJob {
+    /// Large batches can take minutes on a CPU, this is only the default and the pool running
+    /// these is usually given the configured embedding job timeout instead.
+    const EXECUTION_TIMEOUT: Duration = Duration::from_secs(600);
+
+    const JOB_NAME: &'static str = "embed_job";
+
+    const QUEUE_NAME: QueueName = QueueName::EMBEDDING;
+
+    type Error = EmbedJobError;
+    type Context = EmbedTaskContext;
+
+    fn validate(&self) -> Result<(), ValidationError> {
+        if self.texts.is_empty() {
+            return Err(ValidationError::new("no texts were provided to embed"));
+        }
+
+        Ok(())
+    }
+
+    async fn run(&self, ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+        let embeddings = ctx.embedder().embed_batch(&self.texts).await?;
+
+        let mut transaction = ctx
+            .database()
+            .begin()
+            .await
+            .map_err(EmbedJobError::Transaction)?;
+
+        for (content, vector) in self.texts.iter().zip(embeddings.iter()) {
+            CreateEmbedding::new(EMBEDDING_MODEL, content, vector)
+                .save(&mut transaction)
+                .await?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(EmbedJobError::Transaction)?;
+
+        Ok(JobOutcome::Complete)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmbedJobError {
+    #[error("failed to generate embeddings: {0}")]
+    EmbeddingFailed(#[from] EmbedderError),
+
+    #[error("failed to store embedding: {0}")]
+    StoreFailed(#[from] EmbeddingError),
+
+    #[error("an error occurred with a transaction operation: {0}")]
+    Transaction(sqlx::Error),
+}
@@ -1,5 +1,18 @@
+mod embed_job;
+mod prune_runs_job;
+mod record_registration_job;
+mod refresh_oauth_tokens_job;
+// Example job used to exercise the worker machinery, it has no place in release builds
+#[cfg(test)]
 mod test_job;
 mod tick_task;
 
-pub use test_job::TestJob;
+pub use embed_job::{EmbedJob, EmbedJobError, EmbedTaskContext};
+pub use prune_runs_job::{PruneRunsJob, PruneRunsJobError};
+pub use record_registration_job::{RecordRegistrationJob, RecordRegistrationJobError};
+pub use refresh_oauth_tokens_job::{
+    RefreshOAuthTokensContext, RefreshOAuthTokensJob, RefreshOAuthTokensJobError,
+};
+#[cfg(test)]
+pub use test_job::{TestJob, TestJobError};
 pub use tick_task::{TickMessage, TickTask, TickTaskError};
@@ -0,0 +1,47 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::background_jobs::{BasicTaskContext, JobLike, JobOutcome};
+use crate::database::custom_types::UniqueTaskKey;
+use crate::database::models::{BackgroundRun, BackgroundRunError};
+
+/// Bounds the size of the background run history. Jobs that are retried frequently accumulate a
+/// run for every attempt, this keeps only the most recent runs of each job as configured in the
+/// [`BasicTaskContext`].
+#[derive(Default, Deserialize, Serialize)]
+pub struct PruneRunsJob;
+
+#[async_trait]
+impl JobLike for PruneRunsJob {
+    const JOB_NAME: &'static str = "prune_runs_job";
+
+    type Error = PruneRunsJobError;
+    type Context = BasicTaskContext;
+
+    async fn run(&self, ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+        let mut conn = ctx
+            .database()
+            .acquire()
+            .await
+            .map_err(PruneRunsJobError::Connection)?;
+
+        let removed = BackgroundRun::prune_history(&mut conn, ctx.run_retention()).await?;
+        tracing::debug!(removed, "pruned background run history");
+
+        Ok(JobOutcome::Complete)
+    }
+
+    /// There is never a reason to have more than one of these waiting to run.
+    async fn unique_key(&self) -> Option<UniqueTaskKey> {
+        Some(UniqueTaskKey::from("prune_runs"))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PruneRunsJobError {
+    #[error("failed to acquire connection from pool: {0}")]
+    Connection(sqlx::Error),
+
+    #[error("failed to prune run history: {0}")]
+    PruneFailed(#[from] BackgroundRunError),
+}
@@ -0,0 +1,52 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::background_jobs::{BasicTaskContext, JobLike, JobOutcome};
+use crate::database::custom_types::{AuditEventType, UserId};
+use crate::database::models::{AuditEventError, CreateAuditEvent};
+use crate::event_bus::UserRegistration;
+
+/// Adds a newly registered user to the audit log. This is enqueued from the
+/// [`UserRegistration`] event rather than by the login flow that created the account.
+#[derive(Deserialize, Serialize)]
+pub struct RecordRegistrationJob {
+    user_id: UserId,
+}
+
+impl From<UserRegistration> for RecordRegistrationJob {
+    fn from(event: UserRegistration) -> Self {
+        Self { user_id: event.id }
+    }
+}
+
+#[async_trait]
+impl JobLike for RecordRegistrationJob {
+    const JOB_NAME: &'static str = "record_registration_job";
+
+    type Error = RecordRegistrationJobError;
+    type Context = BasicTaskContext;
+
+    async fn run(&self, ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+        let mut conn = ctx
+            .database()
+            .acquire()
+            .await
+            .map_err(RecordRegistrationJobError::Connection)?;
+
+        CreateAuditEvent::unattended(AuditEventType::Registration)
+            .with_user(self.user_id)
+            .save(&mut conn)
+            .await?;
+
+        Ok(JobOutcome::Complete)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecordRegistrationJobError {
+    #[error("failed to acquire connection from pool: {0}")]
+    Connection(sqlx::Error),
+
+    #[error("failed to record the registration: {0}")]
+    RecordFailed(#[from] AuditEventError),
+}
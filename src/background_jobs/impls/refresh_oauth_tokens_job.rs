@@ -0,0 +1,368 @@
+use std::future::Future;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use oauth2::{HttpRequest, HttpResponse, RefreshToken, TokenResponse};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::app::Secrets;
+use crate::auth::{OAuthClient, OAuthClientError};
+use crate::background_jobs::{JobLike, JobOutcome};
+use crate::database::custom_types::UniqueTaskKey;
+use crate::database::models::{
+    ExpiringProviderToken, OAuthProviderAccount, OAuthProviderAccountError,
+};
+use crate::database::Database;
+use crate::event_bus::{EventBus, ReauthenticationRequired};
+
+/// Access tokens expiring within this window are renewed. This needs to comfortably exceed the
+/// interval the job is scheduled at so tokens are replaced before anything notices they expired.
+const REFRESH_WINDOW: time::Duration = time::Duration::minutes(15);
+
+/// The most accounts handled by a single run, anything left over is picked up by the next one.
+const REFRESH_BATCH_SIZE: u32 = 100;
+
+#[derive(Clone)]
+pub struct RefreshOAuthTokensContext {
+    database: Database,
+    event_bus: EventBus,
+    secrets: Secrets,
+}
+
+impl RefreshOAuthTokensContext {
+    pub fn database(&self) -> &Database {
+        &self.database
+    }
+
+    pub fn event_bus(&self) -> &EventBus {
+        &self.event_bus
+    }
+
+    pub fn new(database: Database, event_bus: EventBus, secrets: Secrets) -> Self {
+        Self {
+            database,
+            event_bus,
+            secrets,
+        }
+    }
+
+    pub fn secrets(&self) -> &Secrets {
+        &self.secrets
+    }
+}
+
+/// Keeps provider access tokens usable for as long as the user stays linked. Accounts whose token
+/// is about to expire are renewed with their refresh token, when the provider refuses the refresh
+/// token the account is flagged and the user is asked to log in with the provider again.
+#[derive(Default, Deserialize, Serialize)]
+pub struct RefreshOAuthTokensJob;
+
+#[async_trait]
+impl JobLike for RefreshOAuthTokensJob {
+    /// Each account makes its own request to a provider, a full batch can take a while.
+    const EXECUTION_TIMEOUT: Duration = Duration::from_secs(300);
+
+    /// Providers that rotate refresh tokens invalidate the old one on use, two instances
+    /// refreshing the same account would leave one of them holding a dead token.
+    const GLOBAL_SINGLETON: bool = true;
+
+    const JOB_NAME: &'static str = "refresh_oauth_tokens_job";
+
+    type Error = RefreshOAuthTokensJobError;
+    type Context = RefreshOAuthTokensContext;
+
+    async fn run(&self, ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+        refresh_expiring(&ctx, oauth2::reqwest::async_http_client).await?;
+        Ok(JobOutcome::Complete)
+    }
+
+    /// There is never a reason to have more than one of these waiting to run.
+    async fn unique_key(&self) -> Option<UniqueTaskKey> {
+        Some(UniqueTaskKey::from("refresh_oauth_tokens"))
+    }
+}
+
+/// Renews every account in the next batch of expiring tokens. No connection is held while talking
+/// to the providers, a slow provider would otherwise keep one out of the pool for the whole batch.
+async fn refresh_expiring<C, F, RE>(
+    ctx: &RefreshOAuthTokensContext,
+    http_client: C,
+) -> Result<(), RefreshOAuthTokensJobError>
+where
+    C: Fn(HttpRequest) -> F,
+    F: Future<Output = Result<HttpResponse, RE>>,
+    RE: std::error::Error + 'static,
+{
+    let expiring = {
+        let mut conn = ctx
+            .database()
+            .acquire()
+            .await
+            .map_err(RefreshOAuthTokensJobError::Connection)?;
+
+        let expiring_before = OffsetDateTime::now_utc() + REFRESH_WINDOW;
+        OAuthProviderAccount::expiring_tokens(&mut conn, expiring_before, REFRESH_BATCH_SIZE)
+            .await
+            .map_err(RefreshOAuthTokensJobError::LookupFailed)?
+    };
+
+    for account in expiring {
+        refresh_account(ctx, account, &http_client).await?;
+    }
+
+    Ok(())
+}
+
+/// Renews the access token of a single account. Failing to reach the provider leaves the account
+/// alone to be tried again on the next run.
+async fn refresh_account<C, F, RE>(
+    ctx: &RefreshOAuthTokensContext,
+    account: ExpiringProviderToken,
+    http_client: C,
+) -> Result<(), RefreshOAuthTokensJobError>
+where
+    C: Fn(HttpRequest) -> F,
+    F: Future<Output = Result<HttpResponse, RE>>,
+    RE: std::error::Error + 'static,
+{
+    let provider = account.provider;
+
+    // Providers can be disabled after accounts were linked with them
+    let oauth_client = match OAuthClient::new(provider, ctx.secrets()) {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::warn!(%provider, "unable to refresh provider access token: {err}");
+            return Ok(());
+        }
+    };
+
+    // A refresh token we can no longer read is as good as one the provider refused
+    let token_cipher = ctx.secrets().token_cipher();
+    let refresh_result = match token_cipher.open(&account.refresh_token) {
+        Ok(refresh_token) => {
+            oauth_client
+                .refresh_access_token_with(RefreshToken::new(refresh_token), http_client)
+                .await
+        }
+        Err(err) => Err(OAuthClientError::RefreshRejected(err.to_string())),
+    };
+
+    match refresh_result {
+        Ok(token_response) => {
+            let expires_at = token_response
+                .expires_in()
+                .and_then(|ttl| OffsetDateTime::now_utc().checked_add(ttl.try_into().ok()?));
+            let sealed_refresh_token = token_response
+                .refresh_token()
+                .map(|rt| token_cipher.seal(rt.secret()));
+
+            let mut conn = ctx
+                .database()
+                .acquire()
+                .await
+                .map_err(RefreshOAuthTokensJobError::Connection)?;
+
+            OAuthProviderAccount::store_tokens(
+                &mut conn,
+                account.id,
+                &token_cipher.seal(token_response.access_token().secret()),
+                sealed_refresh_token.as_ref(),
+                expires_at,
+            )
+            .await
+            .map_err(RefreshOAuthTokensJobError::UpdateFailed)?;
+        }
+        Err(OAuthClientError::RefreshRejected(reason)) => {
+            tracing::info!(user_id = ?account.user_id, %provider, "provider refused to refresh access token: {reason}");
+
+            let mut conn = ctx
+                .database()
+                .acquire()
+                .await
+                .map_err(RefreshOAuthTokensJobError::Connection)?;
+
+            OAuthProviderAccount::mark_needs_reauthentication(&mut conn, account.id)
+                .await
+                .map_err(RefreshOAuthTokensJobError::UpdateFailed)?;
+
+            let event = ReauthenticationRequired {
+                user_id: account.user_id,
+                provider,
+            };
+            if let Err(err) = ctx.event_bus().send_typed(&event) {
+                tracing::warn!("failed to announce required reauthentication: {err}");
+            }
+        }
+        Err(err) => {
+            tracing::warn!(%provider, "unable to refresh provider access token, will try again: {err}");
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RefreshOAuthTokensJobError {
+    #[error("failed to acquire connection from pool: {0}")]
+    Connection(sqlx::Error),
+
+    #[error("failed to find accounts with expiring tokens: {0}")]
+    LookupFailed(OAuthProviderAccountError),
+
+    #[error("failed to update the tokens of an account: {0}")]
+    UpdateFailed(OAuthProviderAccountError),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use jwt_simple::prelude::ES384KeyPair;
+    // The OAuth client is built against an older version of the http crate than we use
+    use oauth2::http::{HeaderMap, StatusCode as OAuthStatusCode};
+
+    use super::*;
+    use crate::app::{ProviderCredential, ServiceSigningKey};
+    use crate::database::custom_types::{LoginProvider, OAuthProviderAccountId, ProviderId};
+    use crate::database::models::{CreateOAuthProviderAccount, CreateUser};
+    use crate::event_bus::BusEvent;
+    use crate::tests::prelude::*;
+
+    fn json_response(status_code: OAuthStatusCode, body: &str) -> HttpResponse {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            oauth2::http::header::CONTENT_TYPE,
+            "application/json".parse().unwrap(),
+        );
+
+        HttpResponse {
+            status_code,
+            headers,
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    async fn expiring_account(ctx: &RefreshOAuthTokensContext) -> OAuthProviderAccountId {
+        let mut conn = ctx.database().acquire().await.unwrap();
+
+        let user_id = CreateUser::new("refresh@example.com", "Refresh User")
+            .save(&mut conn)
+            .await
+            .unwrap();
+        let account_id = CreateOAuthProviderAccount::new(
+            user_id,
+            LoginProvider::Google,
+            ProviderId::from("refresh-user".to_string()),
+            "refresh@example.com".to_string(),
+        )
+        .save(ctx.database())
+        .await
+        .unwrap();
+
+        let token_cipher = ctx.secrets().token_cipher();
+        OAuthProviderAccount::store_tokens(
+            &mut conn,
+            account_id,
+            &token_cipher.seal("old-access"),
+            Some(&token_cipher.seal("old-refresh")),
+            Some(OffsetDateTime::now_utc()),
+        )
+        .await
+        .unwrap();
+
+        account_id
+    }
+
+    async fn still_expiring(ctx: &RefreshOAuthTokensContext) -> Vec<ExpiringProviderToken> {
+        let mut conn = ctx.database().acquire().await.unwrap();
+        let expiring_before = OffsetDateTime::now_utc() + REFRESH_WINDOW;
+
+        OAuthProviderAccount::expiring_tokens(&mut conn, expiring_before, REFRESH_BATCH_SIZE)
+            .await
+            .unwrap()
+    }
+
+    async fn test_context() -> RefreshOAuthTokensContext {
+        let credentials = BTreeMap::from([(
+            LoginProvider::Google,
+            ProviderCredential::new("client-id", "client-secret"),
+        )]);
+        let signing_key = ServiceSigningKey::new(ES384KeyPair::generate());
+
+        RefreshOAuthTokensContext::new(
+            Database::new(migrated_test_database().await),
+            EventBus::default(),
+            Secrets::new(credentials, signing_key),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_refreshed_tokens_are_stored_sealed() {
+        let ctx = test_context().await;
+        let account_id = expiring_account(&ctx).await;
+
+        let renewing_client = |request: HttpRequest| {
+            let body = String::from_utf8(request.body).unwrap();
+            assert!(body.contains("refresh_token=old-refresh"));
+
+            let body = r#"{"access_token":"new-access","token_type":"bearer","expires_in":3600,"refresh_token":"new-refresh"}"#;
+            std::future::ready(Ok::<_, std::io::Error>(json_response(
+                OAuthStatusCode::OK,
+                body,
+            )))
+        };
+        refresh_expiring(&ctx, renewing_client).await.unwrap();
+        assert!(still_expiring(&ctx).await.is_empty());
+
+        // Push the renewed token back into the window to read the stored refresh token
+        let mut conn = ctx.database().acquire().await.unwrap();
+        let token_cipher = ctx.secrets().token_cipher();
+        OAuthProviderAccount::store_tokens(
+            &mut conn,
+            account_id,
+            &token_cipher.seal("new-access"),
+            None,
+            Some(OffsetDateTime::now_utc()),
+        )
+        .await
+        .unwrap();
+        drop(conn);
+
+        let expiring = still_expiring(&ctx).await;
+        assert_eq!(expiring.len(), 1);
+        assert_ne!(expiring[0].refresh_token.as_str(), "new-refresh");
+        assert_eq!(
+            token_cipher.open(&expiring[0].refresh_token).unwrap(),
+            "new-refresh"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_only_rejected_refreshes_require_reauthentication() {
+        let ctx = test_context().await;
+        expiring_account(&ctx).await;
+
+        let unreachable_client = |_request: HttpRequest| {
+            std::future::ready(Err::<HttpResponse, _>(std::io::Error::from(
+                std::io::ErrorKind::ConnectionReset,
+            )))
+        };
+        refresh_expiring(&ctx, unreachable_client).await.unwrap();
+        assert_eq!(still_expiring(&ctx).await.len(), 1);
+
+        let mut events = ctx.event_bus().subscribe();
+        let rejecting_client = |_request: HttpRequest| {
+            let body = r#"{"error":"invalid_grant"}"#;
+            std::future::ready(Ok::<_, std::io::Error>(json_response(
+                OAuthStatusCode::BAD_REQUEST,
+                body,
+            )))
+        };
+        refresh_expiring(&ctx, rejecting_client).await.unwrap();
+
+        assert!(still_expiring(&ctx).await.is_empty());
+        let (event, _) = events.try_recv().unwrap();
+        assert_eq!(event, ReauthenticationRequired::EVENT);
+    }
+}
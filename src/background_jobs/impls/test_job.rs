@@ -2,7 +2,7 @@ use async_trait::async_trait;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-use crate::background_jobs::JobLike;
+use crate::background_jobs::{JobLike, JobOutcome};
 
 #[derive(Deserialize, Serialize)]
 pub struct TestJob<C: Clone + Send + Sync + 'static> {
@@ -26,7 +26,7 @@ impl<C: Clone + Send + Sync + 'static> JobLike for TestJob<C> {
     type Error = TestJobError;
     type Context = C;
 
-    async fn run(&self, _ctx: Self::Context) -> Result<(), Self::Error> {
+    async fn run(&self, _ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
         let mut rng = rand::thread_rng();
 
         if rng.gen_bool(0.1) {
@@ -35,7 +35,7 @@ impl<C: Clone + Send + Sync + 'static> JobLike for TestJob<C> {
 
         tracing::info!("the test task value is {}", self.number);
 
-        Ok(())
+        Ok(JobOutcome::Complete)
     }
 }
 
@@ -2,26 +2,27 @@ use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 
-use crate::background_jobs::{EventTaskContext, JobLike};
+use crate::background_jobs::{EventTaskContext, JobLike, JobOutcome, QueueName};
 use crate::database::custom_types::UniqueTaskKey;
-use crate::event_bus::{EventBusError, SystemEvent};
+use crate::event_bus::{BusEvent, EventBusError, SystemEvent};
 
-#[derive(Deserialize, Serialize)]
+#[derive(Default, Deserialize, Serialize)]
 pub struct TickTask;
 
 #[async_trait]
 impl JobLike for TickTask {
     const JOB_NAME: &'static str = "tick_task";
 
+    const QUEUE_NAME: QueueName = QueueName::EVENTED;
+
     type Error = TickTaskError;
     type Context = EventTaskContext;
 
-    async fn run(&self, ctx: Self::Context) -> Result<(), Self::Error> {
-        ctx.event_bus()
-            .send(SystemEvent::Tick, &TickMessage::now())
+    async fn run(&self, ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+        ctx.publish(&TickMessage::now())
             .map_err(TickTaskError::SendFailed)?;
 
-        Ok(())
+        Ok(JobOutcome::Complete)
     }
 
     /// We only ever want a single one of these enqueued at a time so this uses a fixed unique key
@@ -36,11 +37,21 @@ pub struct TickMessage {
     time: OffsetDateTime,
 }
 
+impl BusEvent for TickMessage {
+    const EVENT: SystemEvent = SystemEvent::Tick;
+
+    fn to_client_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        serde_json::to_value(ClientTick { time: self.time })
+    }
+}
+
 impl TickMessage {
+    pub fn at(time: OffsetDateTime) -> Self {
+        Self { time }
+    }
+
     fn now() -> Self {
-        Self {
-            time: OffsetDateTime::now_utc(),
-        }
+        Self::at(OffsetDateTime::now_utc())
     }
 
     pub fn time(&self) -> OffsetDateTime {
@@ -48,6 +59,14 @@ impl TickMessage {
     }
 }
 
+/// We use a different encoding of time when communicating outside of our applications as the byte
+/// serialized version is more efficient but not a standard others want to interact with.
+#[derive(Serialize)]
+struct ClientTick {
+    #[serde(with = "time::serde::rfc3339")]
+    time: OffsetDateTime,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum TickTaskError {
     #[error("failed to send tick: {0}")]
@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+/// What a job that ran without error wants done with it. Most jobs are simply complete, but some
+/// know they need to run again later for reasons that aren't a failure (such as being rate limited
+/// by an upstream service). Deferring a job this way doesn't use up one of its attempts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JobOutcome {
+    #[default]
+    Complete,
+
+    /// Run the same job again once the duration has elapsed
+    RetryAfter(Duration),
+}
+
+impl From<()> for JobOutcome {
+    fn from(_: ()) -> Self {
+        Self::Complete
+    }
+}
@@ -1,20 +1,36 @@
 #![allow(dead_code)]
 
+mod backoff_strategy;
 mod catch_panic_future;
+mod enqueue_outcome;
+mod event_subscriber;
 pub mod impls;
 mod interface;
+mod job_outcome;
 mod queue_config;
+mod queue_name;
+mod recurring_schedule;
+mod scheduler;
 mod stores;
 mod worker;
 mod worker_pool;
 
-use catch_panic_future::{CatchPanicFuture, CaughtPanic};
-pub use queue_config::QueueConfig;
+pub use backoff_strategy::BackoffStrategy;
+pub(crate) use catch_panic_future::{CatchPanicFuture, CaughtPanic};
+pub use enqueue_outcome::EnqueueOutcome;
+pub use event_subscriber::{EventSubscriber, EventSubscriberError};
+pub use job_outcome::JobOutcome;
+pub use queue_config::{QueueConfig, QueueConfigs, QueueConfigsError};
+pub use queue_name::{QueueName, QueueNameError};
+pub use recurring_schedule::{CronSchedule, RecurringSchedule, RecurringScheduleError};
+use scheduler::run_recurring_job;
 pub use stores::basic_task_store::{BasicTaskContext, BasicTaskStore};
 pub use stores::event_task_store::{EventTaskContext, EventTaskStore};
-pub use stores::JobStoreError;
-use stores::{ExecuteJobFn, JobExecError, JobStore, StateFn};
+pub(crate) use stores::BackoffFn;
+use stores::{ExecuteJobFn, JobExecError, StateFn};
+pub use stores::{JobStore, JobStoreError};
 use worker::Worker;
+use worker_pool::RegisteredJob;
 pub use worker_pool::WorkerPool;
 
 use std::time::Duration;
@@ -23,29 +39,81 @@ use axum::async_trait;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
-use crate::database::custom_types::{BackgroundJobId, BackgroundRunId, UniqueTaskKey};
+use crate::database::custom_types::{BackgroundJobId, PayloadEncoding, UniqueTaskKey};
 use crate::database::models::BackgroundJob;
 
+/// How long a job is allowed to run before it is cancelled, unless its type sets its own
+/// [`JobLike::EXECUTION_TIMEOUT`].
 const JOB_EXECUTION_TIMEOUT: Duration = Duration::from_secs(30);
 
 const MAXIMUM_CHECK_DELAY: Duration = Duration::from_millis(250);
 
 #[async_trait]
 pub trait JobLike: Serialize + DeserializeOwned + Sync + Send + 'static {
+    /// Jobs still running after this long are cancelled and the attempt counts as failed. The
+    /// job stops at whichever await point it is sitting on, so work that needs to be all or
+    /// nothing should happen inside a transaction.
+    ///
+    /// Jobs enqueued with
+    /// [`BasicTaskStore::enqueue_for_request`](crate::background_jobs::BasicTaskStore::enqueue_for_request)
+    /// are also bound by the deadline of the request waiting on them and are cut off at whichever
+    /// comes first. A job stopped by the deadline is cancelled rather than retried.
+    const EXECUTION_TIMEOUT: Duration = JOB_EXECUTION_TIMEOUT;
+
+    /// Jobs that must never run concurrently with another instance of themselves, anywhere in the
+    /// cluster. Workers take a lock named after the job before running it, an instance that finds
+    /// the lock already held is put back in the queue to try again shortly without using up an
+    /// attempt.
+    const GLOBAL_SINGLETON: bool = false;
+
     const JOB_NAME: &'static str;
 
     const MAX_ATTEMPTS: u8 = 3;
 
-    const QUEUE_NAME: &'static str = "default";
+    /// Due jobs with a higher priority are claimed before any others waiting in the same queue,
+    /// regardless of how long those have been waiting. Negative values are allowed for work that
+    /// should only run once the queue is otherwise clear.
+    const PRIORITY: i16 = 0;
+
+    const QUEUE_NAME: QueueName = QueueName::DEFAULT;
+
+    /// How the job is serialized when it is stored. JSON keeps payloads easy to inspect, jobs with
+    /// large payloads or on high volume queues may want the more compact MessagePack instead.
+    /// Changing this is safe for jobs that are already queued, each job is decoded using the
+    /// format it was stored with.
+    const PAYLOAD_ENCODING: PayloadEncoding = PayloadEncoding::Json;
 
     type Context: Clone + Send + 'static;
     type Error: std::error::Error;
 
-    async fn run(&self, ctx: Self::Context) -> Result<(), Self::Error>;
+    /// Performs the work of the job. Returning an error counts as a failed attempt, jobs that
+    /// expect to need running again later should return [`JobOutcome::RetryAfter`] instead.
+    async fn run(&self, ctx: Self::Context) -> Result<JobOutcome, Self::Error>;
+
+    /// How long to wait after the provided attempt fails before the job is tried again. Jobs
+    /// that need something other than the default exponential backoff can override this,
+    /// usually by delegating to a different [`BackoffStrategy`].
+    fn backoff(attempt: u8) -> Duration {
+        BackoffStrategy::default().delay(attempt)
+    }
+
+    /// Whether a failed attempt is worth trying again. Errors that will never go away on their
+    /// own, such as bad input or a resource that no longer exists, can return false here to have
+    /// the job marked dead immediately instead of working through the rest of its attempts.
+    fn is_retryable(_error: &Self::Error) -> bool {
+        true
+    }
 
     async fn unique_key(&self) -> Option<UniqueTaskKey> {
         None
     }
+
+    /// Checked before the job is written to the store. Jobs that could never succeed should be
+    /// rejected here so the mistake surfaces at the call site instead of as a dead job after
+    /// exhausting all of its attempts.
+    fn validate(&self) -> Result<(), ValidationError> {
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -53,7 +121,7 @@ pub trait JobLikeExt {
     async fn enqueue<S: JobStore>(
         self,
         connection: &mut S::Connection,
-    ) -> Result<BackgroundJobId, JobStoreError>;
+    ) -> Result<EnqueueOutcome, JobStoreError>;
 }
 
 #[async_trait]
@@ -64,11 +132,22 @@ where
     async fn enqueue<S: JobStore>(
         self,
         connection: &mut S::Connection,
-    ) -> Result<BackgroundJobId, JobStoreError> {
+    ) -> Result<EnqueueOutcome, JobStoreError> {
+        self.validate()?;
         S::enqueue(connection, self).await
     }
 }
 
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ValidationError(String);
+
+impl ValidationError {
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self(reason.into())
+    }
+}
+
 //fn sort_jobs(a: &BackgroundJob, b: &BackgroundJob) -> Ordering {
 //    match a.attempt_run_at.cmp(&b.attempt_run_at) {
 //        Ordering::Equal => a.scheduled_at.cmp(&b.scheduled_at),
@@ -298,3 +377,44 @@ where
 //        Ok(())
 //    }
 //}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::tests::prelude::*;
+
+    #[derive(Deserialize, Serialize)]
+    struct CountdownJob {
+        remaining: i32,
+    }
+
+    #[async_trait]
+    impl JobLike for CountdownJob {
+        const JOB_NAME: &'static str = "countdown_job";
+
+        type Context = ();
+        type Error = std::io::Error;
+
+        async fn run(&self, _ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+            Ok(JobOutcome::Complete)
+        }
+
+        fn validate(&self) -> Result<(), ValidationError> {
+            if self.remaining < 0 {
+                return Err(ValidationError::new("countdown can't start below zero"));
+            }
+
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_jobs_rejected_at_enqueue() {
+        let job = CountdownJob { remaining: -1 };
+
+        let result = job.enqueue::<TestJobStore>(&mut ()).await;
+        assert!(matches!(result, Err(JobStoreError::InvalidJob(_))));
+    }
+}
@@ -1,16 +1,21 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::background_jobs::{QueueName, QueueNameError};
+
 // todo: rename WorkerConfig
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct QueueConfig {
-    name: &'static str,
+    name: QueueName,
     worker_count: usize,
 }
 
 impl QueueConfig {
-    pub fn name(&self) -> &'static str {
+    pub fn name(&self) -> QueueName {
         self.name
     }
 
-    pub fn new(name: &'static str) -> Self {
+    pub fn new(name: QueueName) -> Self {
         Self {
             name,
             worker_count: 1,
@@ -26,3 +31,99 @@ impl QueueConfig {
         self.worker_count
     }
 }
+
+/// The queues this deployment runs workers for and how many workers each of them gets. Every
+/// known queue gets a single worker unless declared otherwise, declarations take the form
+/// `name=count` separated by commas (such as `default=4,embedding=1`).
+///
+/// Queues themselves are still defined by the jobs that use them, this only controls how they are
+/// serviced so worker counts can be tuned per deployment without a rebuild.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueueConfigs(BTreeMap<QueueName, QueueConfig>);
+
+impl QueueConfigs {
+    pub fn get(&self, name: QueueName) -> Option<&QueueConfig> {
+        self.0.get(&name)
+    }
+}
+
+impl Default for QueueConfigs {
+    fn default() -> Self {
+        let configs = QueueName::ALL
+            .into_iter()
+            .map(|name| (name, QueueConfig::new(name)))
+            .collect();
+
+        Self(configs)
+    }
+}
+
+impl FromStr for QueueConfigs {
+    type Err = QueueConfigsError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        let mut configs = Self::default();
+
+        for declaration in val.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let (name, count) = declaration
+                .split_once('=')
+                .ok_or_else(|| QueueConfigsError::MalformedDeclaration(declaration.to_string()))?;
+
+            let name: QueueName = name.trim().parse()?;
+            let worker_count: usize = count
+                .trim()
+                .parse()
+                .map_err(|_| QueueConfigsError::MalformedDeclaration(declaration.to_string()))?;
+
+            if worker_count == 0 {
+                return Err(QueueConfigsError::NoWorkers(name));
+            }
+
+            configs
+                .0
+                .insert(name, QueueConfig::new(name).set_worker_count(worker_count));
+        }
+
+        Ok(configs)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum QueueConfigsError {
+    #[error("queue declaration '{0}' should look like 'name=count'")]
+    MalformedDeclaration(String),
+
+    #[error("queue '{0}' needs at least one worker")]
+    NoWorkers(QueueName),
+
+    #[error("{0}")]
+    UnknownQueue(#[from] QueueNameError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_declared_worker_counts() {
+        let configs: QueueConfigs = "default=4, embedding=2".parse().unwrap();
+
+        let worker_count = |name| configs.get(name).unwrap().worker_count();
+        assert_eq!(worker_count(QueueName::DEFAULT), 4);
+        assert_eq!(worker_count(QueueName::EMBEDDING), 2);
+        assert_eq!(worker_count(QueueName::EVENTED), 1);
+
+        assert!(matches!(
+            "basic=1".parse::<QueueConfigs>(),
+            Err(QueueConfigsError::UnknownQueue(_))
+        ));
+        assert!(matches!(
+            "default".parse::<QueueConfigs>(),
+            Err(QueueConfigsError::MalformedDeclaration(_))
+        ));
+        assert!(matches!(
+            "evented=0".parse::<QueueConfigs>(),
+            Err(QueueConfigsError::NoWorkers(QueueName::EVENTED))
+        ));
+    }
+}
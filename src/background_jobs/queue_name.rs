@@ -0,0 +1,51 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// Identifies the queue a job is placed on and that workers pull from. Jobs and worker
+/// configurations should always refer to one of the known queues defined here rather than
+/// constructing their own, a typo'd name would otherwise silently produce a queue that no
+/// worker ever services.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct QueueName(&'static str);
+
+impl QueueName {
+    /// Used by any job that doesn't specify its own queue.
+    pub const DEFAULT: QueueName = QueueName("default");
+
+    /// Model inference jobs, these are slow and resource hungry and are kept isolated so they
+    /// can't hold up anything else.
+    pub const EMBEDDING: QueueName = QueueName("embedding");
+
+    /// Jobs that need access to the event bus.
+    pub const EVENTED: QueueName = QueueName("evented");
+
+    /// Every queue known to the service.
+    pub const ALL: [QueueName; 3] = [QueueName::DEFAULT, QueueName::EMBEDDING, QueueName::EVENTED];
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl Display for QueueName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl FromStr for QueueName {
+    type Err = QueueNameError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        QueueName::ALL
+            .into_iter()
+            .find(|queue| queue.as_str() == val)
+            .ok_or_else(|| QueueNameError::Unknown(val.to_string()))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum QueueNameError {
+    #[error("'{0}' is not a known queue")]
+    Unknown(String),
+}
@@ -0,0 +1,268 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use time::OffsetDateTime;
+
+/// Searching for the next matching time of a cron expression gives up after this many years.
+/// Expressions that can never match (such as the 31st of February) would otherwise search
+/// forever.
+const CRON_SEARCH_YEARS: i32 = 5;
+
+/// How often a recurring job should be enqueued.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecurringSchedule {
+    /// Run the job once per interval, measured from the last time it was enqueued
+    Interval(Duration),
+
+    /// Run the job whenever the (UTC) time matches the cron expression
+    Cron(CronSchedule),
+}
+
+impl RecurringSchedule {
+    pub fn cron(expression: &str) -> Result<Self, RecurringScheduleError> {
+        Ok(Self::Cron(expression.parse()?))
+    }
+
+    pub fn every(interval: Duration) -> Self {
+        Self::Interval(interval)
+    }
+
+    /// When a job that has never been enqueued should first run. Interval jobs run right away,
+    /// cron jobs wait for the next time matching their expression.
+    pub fn first_due(&self, now: OffsetDateTime) -> Option<OffsetDateTime> {
+        match self {
+            Self::Interval(_) => Some(now),
+            Self::Cron(cron) => cron.next_after(now),
+        }
+    }
+
+    /// The first time after the provided one that the job should be enqueued. Returns `None`
+    /// when the schedule will never be due again.
+    pub fn next_after(&self, after: OffsetDateTime) -> Option<OffsetDateTime> {
+        match self {
+            Self::Interval(interval) => after.checked_add((*interval).try_into().ok()?),
+            Self::Cron(cron) => cron.next_after(after),
+        }
+    }
+}
+
+/// A standard five field cron expression (minute, hour, day of month, month, day of week).
+/// Each field supports `*`, single values, ranges (`1-5`), lists (`1,15`), and steps (`*/15` or
+/// `0-30/10`). Days of the week run from 0 (Sunday) to 6, 7 is also accepted for Sunday. Like
+/// cron, when both day fields are restricted (don't start with `*`) a day matching either of them
+/// is due.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+
+    days_of_month_restricted: bool,
+    days_of_week_restricted: bool,
+}
+
+impl CronSchedule {
+    fn day_matches(&self, time: &OffsetDateTime) -> bool {
+        let dom_match = bit_set(self.days_of_month, time.day());
+        let dow_match = bit_set(self.days_of_week, time.weekday().number_days_from_sunday());
+
+        match (self.days_of_month_restricted, self.days_of_week_restricted) {
+            (true, true) => dom_match || dow_match,
+            (true, false) => dom_match,
+            (false, true) => dow_match,
+            (false, false) => true,
+        }
+    }
+
+    pub fn next_after(&self, after: OffsetDateTime) -> Option<OffsetDateTime> {
+        let after = after.to_offset(time::UtcOffset::UTC);
+        let give_up_year = after.year() + CRON_SEARCH_YEARS;
+
+        let mut candidate = after
+            .replace_second(0)
+            .ok()?
+            .replace_nanosecond(0)
+            .ok()?
+            .checked_add(time::Duration::MINUTE)?;
+
+        // Rather than checking every minute, skip over whole days and hours that can't match
+        while candidate.year() <= give_up_year {
+            if !bit_set(self.months, u8::from(candidate.month())) || !self.day_matches(&candidate) {
+                candidate = candidate.replace_time(time::Time::MIDNIGHT) + time::Duration::DAY;
+                continue;
+            }
+
+            if !bit_set(self.hours, candidate.hour()) {
+                candidate = candidate.replace_minute(0).ok()? + time::Duration::HOUR;
+                continue;
+            }
+
+            if !bit_set(self.minutes, candidate.minute()) {
+                candidate += time::Duration::MINUTE;
+                continue;
+            }
+
+            return Some(candidate);
+        }
+
+        None
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = RecurringScheduleError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = val.split_whitespace().collect();
+
+        let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+            return Err(RecurringScheduleError::FieldCount(fields.len()));
+        };
+
+        let mut days_of_week_bits = parse_field(days_of_week, 0, 7)?;
+        // Sunday can be written as either 0 or 7
+        if bit_set(days_of_week_bits, 7) {
+            days_of_week_bits |= 1;
+        }
+
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days_of_month: parse_field(days_of_month, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            days_of_week: days_of_week_bits,
+
+            days_of_month_restricted: !days_of_month.starts_with('*'),
+            days_of_week_restricted: !days_of_week.starts_with('*'),
+        })
+    }
+}
+
+fn bit_set(bits: u64, value: u8) -> bool {
+    bits & (1 << value) != 0
+}
+
+/// Parses a single cron field into a bitmask of the values it matches.
+fn parse_field(field: &str, min: u8, max: u8) -> Result<u64, RecurringScheduleError> {
+    let invalid = || RecurringScheduleError::InvalidField(field.to_string());
+    let parse_value = |val: &str| -> Result<u8, RecurringScheduleError> {
+        match val.parse::<u8>() {
+            Ok(num) if (min..=max).contains(&num) => Ok(num),
+            _ => Err(invalid()),
+        }
+    };
+
+    let mut bits = 0u64;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u8>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(invalid()),
+            },
+            None => (part, 1),
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (parse_value(start)?, parse_value(end)?),
+                None => {
+                    let value = parse_value(range)?;
+                    // A step on a single value runs from that value to the end of the field
+                    if part.contains('/') {
+                        (value, max)
+                    } else {
+                        (value, value)
+                    }
+                }
+            },
+        };
+
+        if start > end {
+            return Err(invalid());
+        }
+
+        for value in (start..=end).step_by(usize::from(step)) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecurringScheduleError {
+    #[error("cron expressions need exactly five fields, found {0}")]
+    FieldCount(usize),
+
+    #[error("cron field '{0}' is not valid")]
+    InvalidField(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use time::format_description::well_known::Rfc3339;
+
+    use super::*;
+
+    fn at(timestamp: &str) -> OffsetDateTime {
+        OffsetDateTime::parse(timestamp, &Rfc3339).unwrap()
+    }
+
+    #[test]
+    fn test_interval_schedule() {
+        let schedule = RecurringSchedule::every(Duration::from_secs(90));
+        let next = schedule.next_after(at("2024-04-18T10:00:30Z"));
+
+        assert_eq!(next, Some(at("2024-04-18T10:02:00Z")));
+    }
+
+    #[test]
+    fn test_cron_schedules() {
+        let start = at("2024-04-18T10:07:12Z");
+
+        let every_minute = RecurringSchedule::cron("* * * * *").unwrap();
+        assert_eq!(
+            every_minute.next_after(start),
+            Some(at("2024-04-18T10:08:00Z"))
+        );
+
+        let quarter_hourly = RecurringSchedule::cron("*/15 * * * *").unwrap();
+        assert_eq!(
+            quarter_hourly.next_after(start),
+            Some(at("2024-04-18T10:15:00Z"))
+        );
+
+        // The 18th is a Thursday, the next weekday morning run is on Friday
+        let weekday_mornings = RecurringSchedule::cron("30 9 * * 1-5").unwrap();
+        assert_eq!(
+            weekday_mornings.next_after(start),
+            Some(at("2024-04-19T09:30:00Z"))
+        );
+
+        let sundays = RecurringSchedule::cron("0 0 * * 7").unwrap();
+        assert_eq!(sundays.next_after(start), Some(at("2024-04-21T00:00:00Z")));
+
+        let never = RecurringSchedule::cron("0 0 31 2 *").unwrap();
+        assert_eq!(never.next_after(start), None);
+    }
+
+    #[test]
+    fn test_invalid_cron_expressions() {
+        for expression in [
+            "* * * *",
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(
+                RecurringSchedule::cron(expression).is_err(),
+                "{expression} should be rejected"
+            );
+        }
+    }
+}
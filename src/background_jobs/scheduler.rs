@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use time::OffsetDateTime;
+use tokio::sync::watch::Receiver;
+
+use crate::background_jobs::{JobLike, JobStore, RecurringSchedule};
+
+/// How long to wait before trying again when the store couldn't be reached.
+const STORE_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Enqueues a new instance of the job each time its schedule comes due until shutdown. When the
+/// job is next due is worked out from the last time it was added to the store so restarting the
+/// service doesn't reset the schedule. The job's unique key keeps duplicates from piling up if
+/// the workers fall behind.
+pub(crate) async fn run_recurring_job<JL, S, T>(
+    store: S,
+    schedule: RecurringSchedule,
+    mut shutdown_signal: Receiver<T>,
+) where
+    JL: JobLike + Default,
+    S: JobStore,
+{
+    // A job that is deduplicated by its unique key doesn't add anything to the store, this keeps
+    // us from enqueuing it over and over until the next time it is actually due
+    let mut last_attempted: Option<OffsetDateTime> = None;
+
+    loop {
+        let now = OffsetDateTime::now_utc();
+
+        let wait = match store.last_scheduled(JL::JOB_NAME).await {
+            Ok(last_scheduled) => {
+                let next_due = match last_scheduled.max(last_attempted) {
+                    Some(reference) => schedule.next_after(reference),
+                    None => schedule.first_due(now),
+                };
+
+                let Some(next_due) = next_due else {
+                    tracing::warn!(job_name = JL::JOB_NAME, "recurring job will never be due");
+                    return;
+                };
+
+                // A due time in the past fails the conversion, those are due right away
+                Duration::try_from(next_due - now).unwrap_or(Duration::ZERO)
+            }
+            Err(err) => {
+                tracing::error!(
+                    job_name = JL::JOB_NAME,
+                    "unable to check when recurring job was last scheduled: {err}"
+                );
+
+                STORE_RETRY_DELAY
+            }
+        };
+
+        if !wait.is_zero() {
+            if wait_for_shutdown(&mut shutdown_signal, wait).await {
+                return;
+            }
+
+            continue;
+        }
+
+        let enqueued = match store.enqueue_recurring(JL::default()).await {
+            Ok(outcome) => {
+                tracing::debug!(job_name = JL::JOB_NAME, ?outcome, "enqueued recurring job");
+                last_attempted = Some(now);
+                true
+            }
+            Err(err) => {
+                tracing::error!(
+                    job_name = JL::JOB_NAME,
+                    "failed to enqueue recurring job: {err}"
+                );
+                false
+            }
+        };
+
+        if !enqueued && wait_for_shutdown(&mut shutdown_signal, STORE_RETRY_DELAY).await {
+            return;
+        }
+    }
+}
+
+/// Waits for the provided duration, returning early with `true` if shutdown was signaled.
+async fn wait_for_shutdown<T>(shutdown_signal: &mut Receiver<T>, duration: Duration) -> bool {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => false,
+        _ = shutdown_signal.changed() => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use serde::{Deserialize, Serialize};
+    use sqlx::SqlitePool;
+    use tokio::sync::watch;
+
+    use super::*;
+
+    use crate::background_jobs::{BasicTaskContext, BasicTaskStore, JobOutcome};
+    use crate::database::Database;
+    use crate::tests::prelude::*;
+
+    #[derive(Default, Deserialize, Serialize)]
+    struct HeartbeatJob;
+
+    #[async_trait]
+    impl JobLike for HeartbeatJob {
+        const JOB_NAME: &'static str = "heartbeat_job";
+
+        type Context = ();
+        type Error = std::io::Error;
+
+        async fn run(&self, _ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+            Ok(JobOutcome::Complete)
+        }
+    }
+
+    async fn job_count(pool: &SqlitePool) -> i64 {
+        sqlx::query_scalar!("SELECT COUNT(*) FROM background_jobs;")
+            .fetch_one(pool)
+            .await
+            .expect("job count")
+            .into()
+    }
+
+    /// Runs the scheduler for a short time and then shuts it down.
+    async fn run_briefly(store: BasicTaskStore, schedule: RecurringSchedule) {
+        let (shutdown_tx, shutdown_rx) = watch::channel(());
+        let handle = tokio::spawn(run_recurring_job::<HeartbeatJob, _, _>(
+            store,
+            schedule,
+            shutdown_rx,
+        ));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        shutdown_tx.send(()).unwrap();
+
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .expect("scheduler to stop on shutdown")
+            .expect("scheduler to not panic");
+    }
+
+    #[tokio::test]
+    async fn test_schedule_survives_restarts() {
+        let pool = migrated_test_database().await;
+        let store = BasicTaskStore::new(BasicTaskContext::new(Database::new(pool.clone()), 10));
+        let hourly = RecurringSchedule::every(Duration::from_secs(3_600));
+
+        run_briefly(store.clone(), hourly.clone()).await;
+        assert_eq!(job_count(&pool).await, 1);
+
+        // The job was just scheduled, starting up again shouldn't enqueue it a second time. The
+        // job has no unique key so this relies entirely on the schedule being picked up from the
+        // store
+        run_briefly(store, hourly).await;
+        assert_eq!(job_count(&pool).await, 1);
+    }
+}
@@ -1,20 +1,40 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
 use sqlx::SqlitePool;
+use time::OffsetDateTime;
 
-use crate::background_jobs::stores::{JobStore, JobStoreError};
-use crate::background_jobs::JobLike;
-use crate::database::custom_types::{BackgroundJobId, BackgroundJobState, BackgroundRunId};
-use crate::database::models::{BackgroundJob, BackgroundJobError, CreateBackgroundJob};
-use crate::database::Database;
+use crate::background_jobs::stores::{BackoffFn, JobStore, JobStoreError};
+use crate::background_jobs::{EnqueueOutcome, JobLike, QueueName};
+use crate::database::custom_types::{BackgroundJobId, BackgroundJobState, BackgroundRunState};
+use crate::database::models::{
+    BackgroundJob, BackgroundJobError, BackgroundRun, BackgroundRunError, CreateBackgroundJob,
+    CreateBackgroundRun, JobLock, JobLockError,
+};
+use crate::database::{Database, DatabaseConnection};
+use crate::http_server::RequestDeadline;
 
 #[derive(Clone)]
 pub struct BasicTaskContext {
     database: Database,
+    run_retention: u32,
 }
 
 impl BasicTaskContext {
-    pub fn new(database: Database) -> Self {
-        Self { database }
+    pub fn database(&self) -> &Database {
+        &self.database
+    }
+
+    pub fn new(database: Database, run_retention: u32) -> Self {
+        Self {
+            database,
+            run_retention,
+        }
+    }
+
+    /// The number of finished runs kept for each job, older runs are pruned.
+    pub fn run_retention(&self) -> u32 {
+        self.run_retention
     }
 }
 
@@ -28,64 +48,450 @@ impl BasicTaskStore {
         self.context.clone()
     }
 
+    async fn create_in_tx<JL: JobLike>(
+        tx: &mut DatabaseConnection,
+        job: JL,
+        deadline: RequestDeadline,
+    ) -> Result<EnqueueOutcome, JobStoreError> {
+        job.validate()?;
+
+        let unique_key = job.unique_key().await;
+
+        if let Some(key) = &unique_key {
+            if let Some(existing_id) = key.existing(&mut *tx).await? {
+                return Ok(EnqueueOutcome::Deduplicated { existing_id });
+            }
+        }
+
+        let mut new_job = CreateBackgroundJob::now(
+            JL::JOB_NAME,
+            JL::QUEUE_NAME.as_str(),
+            unique_key.as_ref(),
+            &job,
+        );
+
+        if let Some(deadline) = deadline.at() {
+            new_job = new_job.with_deadline(deadline);
+        }
+
+        let background_job_id = new_job
+            .save(&mut *tx)
+            .await
+            .map_err(BasicStoreError::BackgroundJob)?;
+
+        Ok(EnqueueOutcome::Created {
+            job_id: background_job_id,
+        })
+    }
+
+    /// Enqueues a job that the handler of a request is going to wait on. The job carries the
+    /// request's deadline along and the worker cuts it off at whichever comes first, the job's own
+    /// execution timeout or the deadline. A job still waiting to run once the deadline has passed
+    /// is cancelled without being run as nothing is waiting on the result anymore.
+    ///
+    /// A job that is deduplicated against an existing one keeps the existing job's deadline.
+    pub async fn enqueue_for_request<JL: JobLike>(
+        pool: &SqlitePool,
+        job: JL,
+        deadline: RequestDeadline,
+    ) -> Result<EnqueueOutcome, JobStoreError> {
+        let mut conn = pool.begin().await.map_err(BasicStoreError::Connection)?;
+        let outcome = Self::create_in_tx(&mut conn, job, deadline).await?;
+        conn.commit().await.map_err(BasicStoreError::Transaction)?;
+
+        Ok(outcome)
+    }
+
+    /// Enqueues a job as part of a transaction the caller is already holding. The job is only
+    /// visible to workers once that transaction commits and is discarded along with everything
+    /// else if it is rolled back, allowing a job to be atomically tied to the data that triggered
+    /// it. Use [`JobLikeExt::enqueue`](crate::background_jobs::JobLikeExt::enqueue) when there is
+    /// nothing else that needs to be committed with the job.
+    pub async fn enqueue_in_tx<JL: JobLike>(
+        tx: &mut DatabaseConnection,
+        job: JL,
+    ) -> Result<EnqueueOutcome, JobStoreError> {
+        Self::create_in_tx(tx, job, RequestDeadline::default()).await
+    }
+
     pub fn new(context: BasicTaskContext) -> Self {
         Self { context }
     }
+
+    /// Moves an in progress job back to being scheduled without touching its attempts, closing out
+    /// its current run in the provided state.
+    async fn return_to_queue(
+        &self,
+        id: BackgroundJobId,
+        attempt_run_at: OffsetDateTime,
+        run_state: BackgroundRunState,
+    ) -> Result<(), JobStoreError> {
+        let mut tx = self
+            .context
+            .database()
+            .begin()
+            .await
+            .map_err(BasicStoreError::Connection)?;
+
+        let returned = BackgroundJob::reschedule(&mut tx, id, attempt_run_at)
+            .await
+            .map_err(BasicStoreError::BackgroundJob)?;
+
+        if !returned {
+            return Err(not_in_progress(&mut tx, id).await);
+        }
+
+        BackgroundRun::finish(&mut tx, id, run_state)
+            .await
+            .map_err(BasicStoreError::BackgroundRun)?;
+
+        tx.commit().await.map_err(BasicStoreError::Transaction)?;
+
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl JobStore for BasicTaskStore {
     type Connection = SqlitePool;
 
+    async fn acquire_lock(
+        &self,
+        name: &str,
+        holder: &str,
+        lease: Duration,
+    ) -> Result<bool, JobStoreError> {
+        let mut conn = self
+            .context
+            .database()
+            .acquire()
+            .await
+            .map_err(BasicStoreError::Connection)?;
+
+        let acquired = JobLock::acquire(&mut conn, name, holder, lease)
+            .await
+            .map_err(BasicStoreError::JobLock)?;
+
+        Ok(acquired)
+    }
+
     //async fn cancel(&self, id: BackgroundJobId) -> Result<(), JobStoreError> {
     //    self.update_state(id, BackgroundJobState::Cancelled).await
     //}
 
+    async fn dead_letter(
+        &self,
+        queue_name: QueueName,
+        limit: usize,
+    ) -> Result<Vec<BackgroundJob>, JobStoreError> {
+        let mut conn = self
+            .context
+            .database()
+            .acquire()
+            .await
+            .map_err(BasicStoreError::Connection)?;
+
+        let jobs = BackgroundJob::dead_letter(&mut conn, queue_name.as_str(), limit)
+            .await
+            .map_err(BasicStoreError::BackgroundJob)?;
+
+        Ok(jobs)
+    }
+
     async fn enqueue<JL: JobLike>(
         pool: &mut Self::Connection,
         job: JL,
-    ) -> Result<BackgroundJobId, JobStoreError>
+    ) -> Result<EnqueueOutcome, JobStoreError>
     where
         Self: Sized,
     {
         let mut conn = pool.begin().await.map_err(BasicStoreError::Connection)?;
-        let unique_key = job.unique_key().await;
+        let outcome = Self::enqueue_in_tx(&mut conn, job).await?;
+        conn.commit().await.map_err(BasicStoreError::Transaction)?;
 
-        if let Some(key) = &unique_key {
-            if let Some(existing_id) = key.existing(&mut conn).await? {
-                return Ok(existing_id);
-            }
-        }
+        Ok(outcome)
+    }
 
-        let background_job_id =
-            CreateBackgroundJob::now(JL::JOB_NAME, JL::QUEUE_NAME, unique_key.as_ref(), &job)
-                .save(&mut conn)
-                .await
-                .map_err(BasicStoreError::BackgroundJob)?;
+    async fn enqueue_recurring<JL: JobLike>(&self, job: JL) -> Result<EnqueueOutcome, JobStoreError>
+    where
+        Self: Sized,
+    {
+        let mut pool = SqlitePool::clone(self.context.database());
+        Self::enqueue(&mut pool, job).await
+    }
 
-        conn.commit().await.map_err(BasicStoreError::Transaction)?;
+    async fn find(&self, id: BackgroundJobId) -> Result<Option<BackgroundJob>, JobStoreError> {
+        let mut conn = self
+            .context
+            .database()
+            .acquire()
+            .await
+            .map_err(BasicStoreError::Connection)?;
 
-        Ok(background_job_id)
+        let job = BackgroundJob::find(&mut conn, id)
+            .await
+            .map_err(BasicStoreError::BackgroundJob)?;
+
+        Ok(job)
+    }
+
+    async fn last_scheduled(
+        &self,
+        job_name: &str,
+    ) -> Result<Option<OffsetDateTime>, JobStoreError> {
+        let mut conn = self
+            .context
+            .database()
+            .acquire()
+            .await
+            .map_err(BasicStoreError::Connection)?;
+
+        let last_scheduled = BackgroundJob::last_scheduled_at(&mut conn, job_name)
+            .await
+            .map_err(BasicStoreError::BackgroundJob)?;
+
+        Ok(last_scheduled)
+    }
+
+    async fn list_by_state(
+        &self,
+        state: BackgroundJobState,
+        limit: usize,
+    ) -> Result<Vec<BackgroundJob>, JobStoreError> {
+        let mut conn = self
+            .context
+            .database()
+            .acquire()
+            .await
+            .map_err(BasicStoreError::Connection)?;
+
+        let jobs = BackgroundJob::list_by_state(&mut conn, state, limit)
+            .await
+            .map_err(BasicStoreError::BackgroundJob)?;
+
+        Ok(jobs)
     }
 
     async fn next(
         &self,
-        _queue_name: &str,
-        _job_names: &[&str],
+        queue_name: QueueName,
+        job_names: &[&str],
+        lease: Duration,
     ) -> Result<Option<BackgroundJob>, JobStoreError> {
-        todo!()
+        let mut tx = self
+            .context
+            .database()
+            .begin()
+            .await
+            .map_err(BasicStoreError::Connection)?;
+
+        let job = BackgroundJob::claim_next(&mut tx, queue_name.as_str(), job_names, lease)
+            .await
+            .map_err(BasicStoreError::BackgroundJob)?;
+
+        if let Some(job) = &job {
+            // Only a job whose lease ran out still has a run in progress, the worker that started
+            // it never finished it
+            let abandoned = BackgroundRun::finish(&mut tx, job.id(), BackgroundRunState::Errored)
+                .await
+                .map_err(BasicStoreError::BackgroundRun)?;
+
+            if abandoned > 0 {
+                tracing::warn!(id = ?job.id(), "reclaimed job abandoned by its worker");
+            }
+
+            CreateBackgroundRun::new(&job.id(), job.current_attempt())
+                .save(&mut tx)
+                .await
+                .map_err(BasicStoreError::BackgroundRun)?;
+        }
+
+        tx.commit().await.map_err(BasicStoreError::Transaction)?;
+
+        Ok(job)
+    }
+
+    async fn release(
+        &self,
+        id: BackgroundJobId,
+        attempt_run_at: OffsetDateTime,
+    ) -> Result<(), JobStoreError> {
+        self.return_to_queue(id, attempt_run_at, BackgroundRunState::Cancelled)
+            .await
+    }
+
+    async fn release_lock(&self, name: &str, holder: &str) -> Result<(), JobStoreError> {
+        let mut conn = self
+            .context
+            .database()
+            .acquire()
+            .await
+            .map_err(BasicStoreError::Connection)?;
+
+        JobLock::release(&mut conn, name, holder)
+            .await
+            .map_err(BasicStoreError::JobLock)?;
+
+        Ok(())
+    }
+
+    async fn requeue(&self, id: BackgroundJobId) -> Result<(), JobStoreError> {
+        let mut conn = self
+            .context
+            .database()
+            .acquire()
+            .await
+            .map_err(BasicStoreError::Connection)?;
+
+        let requeued = BackgroundJob::requeue(&mut conn, id)
+            .await
+            .map_err(BasicStoreError::BackgroundJob)?;
+
+        if requeued {
+            return Ok(());
+        }
+
+        match BackgroundJob::state_of(&mut conn, id).await {
+            Ok(Some(_)) => Err(JobStoreError::NotDead(id)),
+            Ok(None) => Err(JobStoreError::UnknownJob(id)),
+            Err(err) => Err(BasicStoreError::BackgroundJob(err).into()),
+        }
+    }
+
+    async fn requeue_dead_by_name(
+        &self,
+        job_name: &str,
+        limit: usize,
+    ) -> Result<u64, JobStoreError> {
+        let mut conn = self
+            .context
+            .database()
+            .acquire()
+            .await
+            .map_err(BasicStoreError::Connection)?;
+
+        let requeued = BackgroundJob::requeue_dead_by_name(&mut conn, job_name, limit)
+            .await
+            .map_err(BasicStoreError::BackgroundJob)?;
+
+        Ok(requeued)
+    }
+
+    async fn reschedule(
+        &self,
+        id: BackgroundJobId,
+        attempt_run_at: OffsetDateTime,
+    ) -> Result<(), JobStoreError> {
+        // The run itself went fine, the job just asked for another one
+        self.return_to_queue(id, attempt_run_at, BackgroundRunState::Completed)
+            .await
     }
 
-    async fn retry(&self, _id: BackgroundJobId) -> Result<Option<BackgroundRunId>, JobStoreError> {
-        todo!()
+    async fn retry(
+        &self,
+        id: BackgroundJobId,
+        backoff_fn: BackoffFn,
+    ) -> Result<Option<OffsetDateTime>, JobStoreError> {
+        let mut tx = self
+            .context
+            .database()
+            .begin()
+            .await
+            .map_err(BasicStoreError::Connection)?;
+
+        let job = BackgroundJob::find(&mut tx, id)
+            .await
+            .map_err(BasicStoreError::BackgroundJob)?
+            .ok_or(JobStoreError::UnknownJob(id))?;
+
+        if !matches!(job.state(), BackgroundJobState::Active) {
+            return Err(JobStoreError::NotInProgress(id));
+        }
+
+        BackgroundRun::finish(&mut tx, id, BackgroundRunState::Errored)
+            .await
+            .map_err(BasicStoreError::BackgroundRun)?;
+
+        if job.current_attempt() >= job.maximum_attempts() {
+            tracing::warn!(?id, "job failed with no more attempts remaining");
+
+            BackgroundJob::transition(
+                &mut tx,
+                id,
+                BackgroundJobState::Active,
+                BackgroundJobState::Dead,
+            )
+            .await
+            .map_err(BasicStoreError::BackgroundJob)?;
+
+            tx.commit().await.map_err(BasicStoreError::Transaction)?;
+
+            return Ok(None);
+        }
+
+        let failed_attempt = u8::try_from(job.current_attempt().number()).unwrap_or(u8::MAX);
+        let backoff = backoff_fn(failed_attempt);
+        let next_attempt = job.current_attempt().next();
+        let attempt_run_at = OffsetDateTime::now_utc() + backoff;
+
+        tracing::info!(?id, ?backoff, "job will be retried");
+
+        BackgroundJob::schedule_attempt(&mut tx, id, next_attempt, attempt_run_at)
+            .await
+            .map_err(BasicStoreError::BackgroundJob)?;
+
+        tx.commit().await.map_err(BasicStoreError::Transaction)?;
+
+        Ok(Some(attempt_run_at))
     }
 
     async fn update_state(
         &self,
-        _id: BackgroundJobId,
-        _new_state: BackgroundJobState,
+        id: BackgroundJobId,
+        new_state: BackgroundJobState,
     ) -> Result<(), JobStoreError> {
-        todo!()
+        // Putting a job back in the queue has to go through retry or reschedule so the attempt
+        // and next run time get updated along with it
+        let run_state = match new_state {
+            BackgroundJobState::Complete => BackgroundRunState::Completed,
+            BackgroundJobState::Cancelled => BackgroundRunState::Cancelled,
+            BackgroundJobState::Dead => BackgroundRunState::Errored,
+            BackgroundJobState::Scheduled | BackgroundJobState::Active => {
+                return Err(JobStoreError::IllegalTransition(id, new_state));
+            }
+        };
+
+        let mut tx = self
+            .context
+            .database()
+            .begin()
+            .await
+            .map_err(BasicStoreError::Connection)?;
+
+        let updated = BackgroundJob::transition(&mut tx, id, BackgroundJobState::Active, new_state)
+            .await
+            .map_err(BasicStoreError::BackgroundJob)?;
+
+        if !updated {
+            return Err(not_in_progress(&mut tx, id).await);
+        }
+
+        BackgroundRun::finish(&mut tx, id, run_state)
+            .await
+            .map_err(BasicStoreError::BackgroundRun)?;
+
+        tx.commit().await.map_err(BasicStoreError::Transaction)?;
+
+        Ok(())
+    }
+}
+
+/// Works out why a job couldn't be updated as an in progress job.
+async fn not_in_progress(conn: &mut DatabaseConnection, id: BackgroundJobId) -> JobStoreError {
+    match BackgroundJob::state_of(conn, id).await {
+        Ok(Some(_)) => JobStoreError::NotInProgress(id),
+        Ok(None) => JobStoreError::UnknownJob(id),
+        Err(err) => BasicStoreError::BackgroundJob(err).into(),
     }
 }
 
@@ -94,9 +500,15 @@ pub enum BasicStoreError {
     #[error("background job query failed: {0}")]
     BackgroundJob(BackgroundJobError),
 
+    #[error("background run query failed: {0}")]
+    BackgroundRun(BackgroundRunError),
+
     #[error("failed to acquire connection from pool: {0}")]
     Connection(sqlx::Error),
 
+    #[error("job lock query failed: {0}")]
+    JobLock(JobLockError),
+
     #[error("an error occurred with a transaction operation: {0}")]
     Transaction(sqlx::Error),
 }
@@ -106,3 +518,592 @@ impl From<BasicStoreError> for JobStoreError {
         JobStoreError::StoreBackendUnavailable(value.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    use crate::background_jobs::impls::TestJob;
+    use crate::background_jobs::JobOutcome;
+    use crate::database::custom_types::UniqueTaskKey;
+    use crate::database::models::CreateUser;
+    use crate::tests::prelude::*;
+
+    #[derive(Deserialize, Serialize)]
+    struct UrgentJob;
+
+    #[async_trait]
+    impl JobLike for UrgentJob {
+        const JOB_NAME: &'static str = "urgent_job";
+        const PRIORITY: i16 = 10;
+
+        type Context = ();
+        type Error = std::io::Error;
+
+        async fn run(&self, _ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+            Ok(JobOutcome::Complete)
+        }
+    }
+
+    #[derive(Deserialize, Serialize)]
+    struct KeyedJob;
+
+    #[async_trait]
+    impl JobLike for KeyedJob {
+        const JOB_NAME: &'static str = "keyed_job";
+
+        type Context = ();
+        type Error = std::io::Error;
+
+        async fn run(&self, _ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+            Ok(JobOutcome::Complete)
+        }
+
+        async fn unique_key(&self) -> Option<UniqueTaskKey> {
+            Some(UniqueTaskKey::from("keyed_job"))
+        }
+    }
+
+    async fn row_counts(pool: &SqlitePool) -> (i64, i64) {
+        let users = sqlx::query_scalar!("SELECT COUNT(*) FROM users;")
+            .fetch_one(pool)
+            .await
+            .expect("user count");
+        let jobs = sqlx::query_scalar!("SELECT COUNT(*) FROM background_jobs;")
+            .fetch_one(pool)
+            .await
+            .expect("job count");
+
+        (users.into(), jobs.into())
+    }
+
+    /// Uses up every attempt of a job without waiting out the backoff in between.
+    async fn kill_job(store: &BasicTaskStore, pool: &SqlitePool, id: BackgroundJobId) {
+        let job_names = [TestJob::<()>::JOB_NAME];
+        let queue_name = TestJob::<()>::QUEUE_NAME;
+
+        loop {
+            let job = store
+                .next(queue_name, &job_names, CLAIM_LEASE)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(job.id(), id);
+
+            let next_attempt = store.retry(id, TestJob::<()>::backoff).await.unwrap();
+            if next_attempt.is_none() {
+                break;
+            }
+
+            let mut conn = pool.acquire().await.unwrap();
+            BackgroundJob::run_now(&mut conn, id).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dead_jobs_can_be_requeued() {
+        let pool = migrated_test_database().await;
+        let store = BasicTaskStore::new(BasicTaskContext::new(Database::new(pool.clone()), 10));
+
+        let job_names = [TestJob::<()>::JOB_NAME];
+        let queue_name = TestJob::<()>::QUEUE_NAME;
+
+        let id = BasicTaskStore::enqueue(&mut pool.clone(), TestJob::<()>::new(1))
+            .await
+            .expect("enqueue")
+            .job_id();
+
+        // Only dead jobs can be given a fresh start
+        let live = store.requeue(id).await;
+        assert!(matches!(live, Err(JobStoreError::NotDead(_))));
+
+        let unknown = store
+            .requeue(BackgroundJobId::from(uuid::Uuid::new_v4()))
+            .await;
+        assert!(matches!(unknown, Err(JobStoreError::UnknownJob(_))));
+
+        kill_job(&store, &pool, id).await;
+
+        let dead = store.dead_letter(queue_name, 10).await.unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].id(), id);
+
+        let other_queue = store.dead_letter(QueueName::EMBEDDING, 10).await;
+        assert!(other_queue.unwrap().is_empty());
+
+        store.requeue(id).await.expect("requeue");
+        assert!(store.dead_letter(queue_name, 10).await.unwrap().is_empty());
+
+        let reclaimed = store
+            .next(queue_name, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap();
+        let reclaimed = reclaimed.expect("requeued job to be runnable");
+        assert_eq!(reclaimed.id(), id);
+        assert_eq!(reclaimed.current_attempt().number(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dead_jobs_requeued_in_bulk_by_name() {
+        let pool = migrated_test_database().await;
+        let store = BasicTaskStore::new(BasicTaskContext::new(Database::new(pool.clone()), 10));
+
+        let job_names = [TestJob::<()>::JOB_NAME];
+        let queue_name = TestJob::<()>::QUEUE_NAME;
+
+        for number in 0..3 {
+            let id = BasicTaskStore::enqueue(&mut pool.clone(), TestJob::<()>::new(number))
+                .await
+                .expect("enqueue")
+                .job_id();
+            kill_job(&store, &pool, id).await;
+        }
+
+        let other = store.requeue_dead_by_name("other_job", 10).await.unwrap();
+        assert_eq!(other, 0);
+
+        // Batches are capped by the limit, the rest are left for the next request
+        let requeued = store
+            .requeue_dead_by_name(TestJob::<()>::JOB_NAME, 2)
+            .await
+            .unwrap();
+        assert_eq!(requeued, 2);
+        assert_eq!(store.dead_letter(queue_name, 10).await.unwrap().len(), 1);
+
+        let reclaimed = store
+            .next(queue_name, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap();
+        let reclaimed = reclaimed.expect("requeued job to be runnable");
+        assert_eq!(reclaimed.current_attempt().number(), 1);
+
+        let requeued = store
+            .requeue_dead_by_name(TestJob::<()>::JOB_NAME, 2)
+            .await
+            .unwrap();
+        assert_eq!(requeued, 1);
+        assert!(store.dead_letter(queue_name, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_unique_jobs_report_existing_job() {
+        let pool = migrated_test_database().await;
+
+        let first = BasicTaskStore::enqueue(&mut pool.clone(), KeyedJob)
+            .await
+            .expect("enqueue");
+        let EnqueueOutcome::Created { job_id } = first else {
+            panic!("first job to be created, got {first:?}");
+        };
+
+        let second = BasicTaskStore::enqueue(&mut pool.clone(), KeyedJob)
+            .await
+            .expect("enqueue");
+        assert_eq!(
+            second,
+            EnqueueOutcome::Deduplicated {
+                existing_id: job_id
+            }
+        );
+        assert_eq!(row_counts(&pool).await.1, 1);
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_for_request_carries_deadline() {
+        let pool = migrated_test_database().await;
+        let store = BasicTaskStore::new(BasicTaskContext::new(Database::new(pool.clone()), 10));
+
+        let deadline = (OffsetDateTime::now_utc() + time::Duration::seconds(5))
+            .replace_nanosecond(0)
+            .unwrap();
+        let waited_on = BasicTaskStore::enqueue_for_request(
+            &pool,
+            TestJob::<()>::new(1),
+            RequestDeadline::new(deadline),
+        )
+        .await
+        .expect("enqueue")
+        .job_id();
+
+        let unbounded = BasicTaskStore::enqueue_for_request(
+            &pool,
+            TestJob::<()>::new(2),
+            RequestDeadline::default(),
+        )
+        .await
+        .expect("enqueue")
+        .job_id();
+
+        let job = store.find(waited_on).await.unwrap().expect("known job");
+        assert_eq!(job.deadline(), Some(deadline));
+
+        let job = store.find(unbounded).await.unwrap().expect("known job");
+        assert_eq!(job.deadline(), None);
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_in_tx_follows_transaction() {
+        let pool = migrated_test_database().await;
+
+        let mut tx = pool.begin().await.expect("transaction");
+        CreateUser::new("rollback@example.com", "Rollback")
+            .save(&mut tx)
+            .await
+            .expect("user creation");
+        BasicTaskStore::enqueue_in_tx(&mut tx, TestJob::<()>::new(1))
+            .await
+            .expect("enqueue");
+        tx.rollback().await.expect("rollback");
+
+        assert_eq!(row_counts(&pool).await, (0, 0));
+
+        let mut tx = pool.begin().await.expect("transaction");
+        CreateUser::new("commit@example.com", "Commit")
+            .save(&mut tx)
+            .await
+            .expect("user creation");
+        BasicTaskStore::enqueue_in_tx(&mut tx, TestJob::<()>::new(2))
+            .await
+            .expect("enqueue");
+        tx.commit().await.expect("commit");
+
+        assert_eq!(row_counts(&pool).await, (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_find_and_list_jobs_by_state() {
+        let pool = migrated_test_database().await;
+        let store = BasicTaskStore::new(BasicTaskContext::new(Database::new(pool.clone()), 10));
+
+        let mut ids = Vec::new();
+        for num in 0..3 {
+            let id = BasicTaskStore::enqueue(&mut pool.clone(), TestJob::<()>::new(num))
+                .await
+                .expect("enqueue")
+                .job_id();
+            ids.push(id);
+        }
+
+        let job = store.find(ids[0]).await.unwrap().expect("known job");
+        assert_eq!(job.id(), ids[0]);
+        assert!(matches!(job.state(), BackgroundJobState::Scheduled));
+
+        let unknown = BackgroundJobId::from(uuid::Uuid::new_v4());
+        assert!(store.find(unknown).await.unwrap().is_none());
+
+        let job_names = [TestJob::<()>::JOB_NAME];
+        let claimed = store
+            .next(TestJob::<()>::QUEUE_NAME, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let active = store.list_by_state(BackgroundJobState::Active, 10).await;
+        let active = active.unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].id(), claimed.id());
+
+        let scheduled = store.list_by_state(BackgroundJobState::Scheduled, 10).await;
+        assert_eq!(scheduled.unwrap().len(), 2);
+
+        let limited = store.list_by_state(BackgroundJobState::Scheduled, 1).await;
+        assert_eq!(limited.unwrap().len(), 1);
+
+        let dead = store.list_by_state(BackgroundJobState::Dead, 10).await;
+        assert!(dead.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_next_claims_due_jobs_once() {
+        let pool = migrated_test_database().await;
+        let store = BasicTaskStore::new(BasicTaskContext::new(Database::new(pool.clone()), 10));
+
+        let future_job = TestJob::<()>::new(1);
+        let mut conn = pool.acquire().await.expect("connection");
+        CreateBackgroundJob::run_at(
+            TestJob::<()>::JOB_NAME,
+            TestJob::<()>::QUEUE_NAME.as_str(),
+            None,
+            &future_job,
+            OffsetDateTime::now_utc() + Duration::from_secs(3_600),
+        )
+        .save(&mut conn)
+        .await
+        .expect("future job");
+        drop(conn);
+
+        let job_names = [TestJob::<()>::JOB_NAME];
+        let queue_name = TestJob::<()>::QUEUE_NAME;
+
+        assert!(store
+            .next(queue_name, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap()
+            .is_none());
+
+        let due_id = BasicTaskStore::enqueue(&mut pool.clone(), TestJob::<()>::new(2))
+            .await
+            .expect("enqueue")
+            .job_id();
+
+        // Only the names the worker knows about should be claimed
+        let unknown = store
+            .next(queue_name, &["other_job"], CLAIM_LEASE)
+            .await
+            .unwrap();
+        assert!(unknown.is_none());
+
+        let claimed = store
+            .next(queue_name, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap();
+        let claimed = claimed.expect("due job to be claimed");
+        assert_eq!(claimed.id(), due_id);
+        assert!(matches!(claimed.state(), BackgroundJobState::Active));
+
+        assert!(store
+            .next(queue_name, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_next_prefers_higher_priority_jobs() {
+        let pool = migrated_test_database().await;
+        let store = BasicTaskStore::new(BasicTaskContext::new(Database::new(pool.clone()), 10));
+
+        // The regular job has been waiting longer but the urgent one should still go first
+        let backlog_job = TestJob::<()>::new(1);
+        let mut conn = pool.acquire().await.expect("connection");
+        let backlog_id = CreateBackgroundJob::run_at(
+            TestJob::<()>::JOB_NAME,
+            TestJob::<()>::QUEUE_NAME.as_str(),
+            None,
+            &backlog_job,
+            OffsetDateTime::now_utc() - Duration::from_secs(60),
+        )
+        .save(&mut conn)
+        .await
+        .expect("backlog job");
+        drop(conn);
+
+        let urgent_id = BasicTaskStore::enqueue(&mut pool.clone(), UrgentJob)
+            .await
+            .expect("enqueue")
+            .job_id();
+
+        let job_names = [TestJob::<()>::JOB_NAME, UrgentJob::JOB_NAME];
+        let queue_name = TestJob::<()>::QUEUE_NAME;
+
+        let first = store
+            .next(queue_name, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.id(), urgent_id);
+        assert_eq!(first.priority(), UrgentJob::PRIORITY);
+
+        let second = store
+            .next(queue_name, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(second.id(), backlog_id);
+        assert_eq!(second.priority(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_next_reclaims_jobs_with_expired_leases() {
+        let pool = migrated_test_database().await;
+        let store = BasicTaskStore::new(BasicTaskContext::new(Database::new(pool.clone()), 10));
+
+        let job_names = [TestJob::<()>::JOB_NAME];
+        let queue_name = TestJob::<()>::QUEUE_NAME;
+
+        let id = BasicTaskStore::enqueue(&mut pool.clone(), TestJob::<()>::new(1))
+            .await
+            .expect("enqueue")
+            .job_id();
+
+        // A worker that claimed the job and then went away without ever finishing it
+        let abandoned = store.next(queue_name, &job_names, Duration::ZERO).await;
+        assert_eq!(abandoned.unwrap().expect("job to be claimed").id(), id);
+
+        let reclaimed = store.next(queue_name, &job_names, CLAIM_LEASE).await;
+        let reclaimed = reclaimed.unwrap().expect("abandoned job to be reclaimed");
+        assert_eq!(reclaimed.id(), id);
+        assert_eq!(reclaimed.current_attempt().number(), 1);
+        assert_eq!(finished_runs(&pool).await, vec!["errored"]);
+
+        // Claims that are still leased are left with their worker
+        assert!(store
+            .next(queue_name, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reschedule_defers_without_using_attempt() {
+        let pool = migrated_test_database().await;
+        let store = BasicTaskStore::new(BasicTaskContext::new(Database::new(pool.clone()), 10));
+
+        let job_names = [TestJob::<()>::JOB_NAME];
+        let queue_name = TestJob::<()>::QUEUE_NAME;
+
+        let id = BasicTaskStore::enqueue(&mut pool.clone(), TestJob::<()>::new(1))
+            .await
+            .expect("enqueue")
+            .job_id();
+        let claimed = store
+            .next(queue_name, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+        let attempt = claimed.current_attempt();
+
+        let run_at = OffsetDateTime::now_utc() + Duration::from_secs(60);
+        store.reschedule(id, run_at).await.expect("reschedule");
+
+        // A job can only be deferred while it is being run
+        let repeated = store.reschedule(id, run_at).await;
+        assert!(matches!(repeated, Err(JobStoreError::NotInProgress(_))));
+
+        // The job is back in the queue, just not runnable yet
+        assert!(store
+            .next(queue_name, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap()
+            .is_none());
+
+        let mut conn = pool.acquire().await.unwrap();
+        BackgroundJob::run_now(&mut conn, id).await.unwrap();
+        drop(conn);
+
+        let reclaimed = store
+            .next(queue_name, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reclaimed.id(), id);
+        assert_eq!(reclaimed.current_attempt(), attempt);
+    }
+
+    async fn finished_runs(pool: &SqlitePool) -> Vec<String> {
+        sqlx::query_scalar!(
+            "SELECT state FROM background_runs WHERE finished_at IS NOT NULL ORDER BY attempt;"
+        )
+        .fetch_all(pool)
+        .await
+        .expect("run states")
+    }
+
+    #[tokio::test]
+    async fn test_retry_backs_off_until_dead() {
+        let pool = migrated_test_database().await;
+        let store = BasicTaskStore::new(BasicTaskContext::new(Database::new(pool.clone()), 10));
+
+        let job_names = [TestJob::<()>::JOB_NAME];
+        let queue_name = TestJob::<()>::QUEUE_NAME;
+
+        let id = BasicTaskStore::enqueue(&mut pool.clone(), TestJob::<()>::new(1))
+            .await
+            .expect("enqueue")
+            .job_id();
+
+        for expected_backoff in [4, 8] {
+            let claimed = store
+                .next(queue_name, &job_names, CLAIM_LEASE)
+                .await
+                .unwrap()
+                .unwrap();
+            let failed_attempt = claimed.current_attempt();
+
+            let before = OffsetDateTime::now_utc();
+            let run_at = store
+                .retry(id, TestJob::<()>::backoff)
+                .await
+                .expect("retry")
+                .expect("more attempts");
+            let after = OffsetDateTime::now_utc();
+
+            let backoff = Duration::from_secs(expected_backoff);
+            assert!(run_at >= before + backoff && run_at <= after + backoff);
+
+            // The next attempt isn't available until the backoff has passed
+            assert!(store
+                .next(queue_name, &job_names, CLAIM_LEASE)
+                .await
+                .unwrap()
+                .is_none());
+
+            let mut conn = pool.acquire().await.unwrap();
+            let job = BackgroundJob::find(&mut conn, id).await.unwrap().unwrap();
+            assert_eq!(job.current_attempt(), failed_attempt.next());
+            assert!(matches!(job.state(), BackgroundJobState::Scheduled));
+
+            BackgroundJob::run_now(&mut conn, id).await.unwrap();
+        }
+
+        // The final attempt failing leaves the job dead
+        store
+            .next(queue_name, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+        let final_retry = store.retry(id, TestJob::<()>::backoff).await;
+        assert!(final_retry.expect("retry").is_none());
+
+        let mut conn = pool.acquire().await.unwrap();
+        let state = BackgroundJob::state_of(&mut conn, id).await.unwrap();
+        assert!(matches!(state, Some(BackgroundJobState::Dead)));
+        drop(conn);
+
+        assert_eq!(finished_runs(&pool).await, vec!["errored"; 3]);
+    }
+
+    #[tokio::test]
+    async fn test_update_state_requires_in_progress_job() {
+        let pool = migrated_test_database().await;
+        let store = BasicTaskStore::new(BasicTaskContext::new(Database::new(pool.clone()), 10));
+
+        let id = BasicTaskStore::enqueue(&mut pool.clone(), TestJob::<()>::new(1))
+            .await
+            .expect("enqueue")
+            .job_id();
+
+        let unclaimed = store.update_state(id, BackgroundJobState::Complete).await;
+        assert!(matches!(unclaimed, Err(JobStoreError::NotInProgress(_))));
+
+        let job_names = [TestJob::<()>::JOB_NAME];
+        store
+            .next(TestJob::<()>::QUEUE_NAME, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let requeued = store.update_state(id, BackgroundJobState::Scheduled).await;
+        assert!(matches!(
+            requeued,
+            Err(JobStoreError::IllegalTransition(
+                _,
+                BackgroundJobState::Scheduled
+            ))
+        ));
+
+        store
+            .update_state(id, BackgroundJobState::Complete)
+            .await
+            .expect("completion");
+        assert_eq!(finished_runs(&pool).await, vec!["completed"]);
+
+        let repeated = store.update_state(id, BackgroundJobState::Cancelled).await;
+        assert!(matches!(repeated, Err(JobStoreError::NotInProgress(_))));
+    }
+}
@@ -1,13 +1,15 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
 use sqlx::SqlitePool;
+use time::OffsetDateTime;
 
-use crate::background_jobs::stores::{JobStore, JobStoreError};
-use crate::background_jobs::JobLike;
-use crate::database::custom_types::{BackgroundJobId, BackgroundJobState, BackgroundRunId};
+use crate::background_jobs::stores::{BackoffFn, JobStore, JobStoreError};
+use crate::background_jobs::{BasicTaskStore, EnqueueOutcome, JobLike, QueueName};
+use crate::database::custom_types::{BackgroundJobId, BackgroundJobState};
 use crate::database::models::BackgroundJob;
-
 use crate::database::Database;
-use crate::event_bus::EventBus;
+use crate::event_bus::{BusEvent, EventBus, EventBusError};
 
 #[derive(Clone)]
 pub struct EventTaskContext {
@@ -30,11 +32,25 @@ impl EventTaskContext {
             event_bus,
         }
     }
+
+    /// Sends an event from a job, returning how many subscribers received it. Nobody listening
+    /// isn't a failure, idle servers regularly have no subscribers and retrying wouldn't change
+    /// that. An event that can't be encoded is a bug in the job and is returned as an error so the
+    /// job fails.
+    pub fn publish<E: BusEvent>(&self, event: &E) -> Result<usize, EventBusError> {
+        match self.event_bus.send_typed(event) {
+            Err(EventBusError::SendFailed(_)) => Ok(0),
+            result => result,
+        }
+    }
 }
 
+/// Evented jobs are stored alongside every other job, so the bookkeeping for them is shared with
+/// [`BasicTaskStore`] and only the context handed to the jobs differs.
 #[derive(Clone)]
 pub struct EventTaskStore {
     context: EventTaskContext,
+    jobs: BasicTaskStore,
 }
 
 impl EventTaskStore {
@@ -42,8 +58,8 @@ impl EventTaskStore {
         self.context.clone()
     }
 
-    pub fn new(context: EventTaskContext) -> Self {
-        Self { context }
+    pub fn new(context: EventTaskContext, jobs: BasicTaskStore) -> Self {
+        Self { context, jobs }
     }
 }
 
@@ -51,52 +67,223 @@ impl EventTaskStore {
 impl JobStore for EventTaskStore {
     type Connection = SqlitePool;
 
+    async fn acquire_lock(
+        &self,
+        name: &str,
+        holder: &str,
+        lease: Duration,
+    ) -> Result<bool, JobStoreError> {
+        self.jobs.acquire_lock(name, holder, lease).await
+    }
+
     //async fn cancel(&self, id: BackgroundJobId) -> Result<(), JobStoreError> {
     //    self.update_state(id, BackgroundJobState::Cancelled).await
     //}
 
+    async fn dead_letter(
+        &self,
+        queue_name: QueueName,
+        limit: usize,
+    ) -> Result<Vec<BackgroundJob>, JobStoreError> {
+        self.jobs.dead_letter(queue_name, limit).await
+    }
+
     async fn enqueue<T: JobLike>(
-        _pool: &mut Self::Connection,
-        _task: T,
-    ) -> Result<BackgroundJobId, JobStoreError>
+        pool: &mut Self::Connection,
+        task: T,
+    ) -> Result<EnqueueOutcome, JobStoreError>
+    where
+        Self: Sized,
+    {
+        BasicTaskStore::enqueue(pool, task).await
+    }
+
+    async fn enqueue_recurring<T: JobLike>(&self, task: T) -> Result<EnqueueOutcome, JobStoreError>
     where
         Self: Sized,
     {
-        todo!()
+        self.jobs.enqueue_recurring(task).await
+    }
+
+    async fn find(&self, id: BackgroundJobId) -> Result<Option<BackgroundJob>, JobStoreError> {
+        self.jobs.find(id).await
+    }
+
+    async fn last_scheduled(
+        &self,
+        job_name: &str,
+    ) -> Result<Option<OffsetDateTime>, JobStoreError> {
+        self.jobs.last_scheduled(job_name).await
+    }
+
+    async fn list_by_state(
+        &self,
+        state: BackgroundJobState,
+        limit: usize,
+    ) -> Result<Vec<BackgroundJob>, JobStoreError> {
+        self.jobs.list_by_state(state, limit).await
     }
 
     async fn next(
         &self,
-        _queue_name: &str,
-        _task_names: &[&str],
+        queue_name: QueueName,
+        task_names: &[&str],
+        lease: Duration,
     ) -> Result<Option<BackgroundJob>, JobStoreError> {
-        todo!()
+        self.jobs.next(queue_name, task_names, lease).await
     }
 
-    async fn retry(&self, _id: BackgroundJobId) -> Result<Option<BackgroundRunId>, JobStoreError> {
-        todo!()
+    async fn release(
+        &self,
+        id: BackgroundJobId,
+        attempt_run_at: OffsetDateTime,
+    ) -> Result<(), JobStoreError> {
+        self.jobs.release(id, attempt_run_at).await
+    }
+
+    async fn release_lock(&self, name: &str, holder: &str) -> Result<(), JobStoreError> {
+        self.jobs.release_lock(name, holder).await
+    }
+
+    async fn requeue(&self, id: BackgroundJobId) -> Result<(), JobStoreError> {
+        self.jobs.requeue(id).await
+    }
+
+    async fn requeue_dead_by_name(
+        &self,
+        job_name: &str,
+        limit: usize,
+    ) -> Result<u64, JobStoreError> {
+        self.jobs.requeue_dead_by_name(job_name, limit).await
+    }
+
+    async fn reschedule(
+        &self,
+        id: BackgroundJobId,
+        attempt_run_at: OffsetDateTime,
+    ) -> Result<(), JobStoreError> {
+        self.jobs.reschedule(id, attempt_run_at).await
+    }
+
+    async fn retry(
+        &self,
+        id: BackgroundJobId,
+        backoff_fn: BackoffFn,
+    ) -> Result<Option<OffsetDateTime>, JobStoreError> {
+        self.jobs.retry(id, backoff_fn).await
     }
 
     async fn update_state(
         &self,
-        _id: BackgroundJobId,
-        _new_state: BackgroundJobState,
+        id: BackgroundJobId,
+        new_state: BackgroundJobState,
     ) -> Result<(), JobStoreError> {
-        todo!()
+        self.jobs.update_state(id, new_state).await
     }
 }
 
-#[derive(Debug, thiserror::Error)]
-pub enum EventStoreError {
-    #[error("failed to acquire connection from pool: {0}")]
-    ConnError(sqlx::Error),
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use time::OffsetDateTime;
 
-    #[error("an error occurred with a transaction operation: {0}")]
-    TransactionError(sqlx::Error),
-}
+    use super::*;
+    use crate::background_jobs::impls::{TickMessage, TickTask};
+    use crate::background_jobs::BasicTaskContext;
+    use crate::event_bus::SystemEvent;
+    use crate::tests::prelude::*;
+
+    /// An event whose payload can never be encoded.
+    struct Unencodable;
+
+    impl BusEvent for Unencodable {
+        const EVENT: SystemEvent = SystemEvent::Tick;
+    }
+
+    impl<'de> Deserialize<'de> for Unencodable {
+        fn deserialize<D: Deserializer<'de>>(_: D) -> Result<Self, D::Error> {
+            Ok(Unencodable)
+        }
+    }
+
+    impl Serialize for Unencodable {
+        fn serialize<S: Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("never encodable"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_only_fails_on_unencodable_events() {
+        let context =
+            EventTaskContext::new(Database::new(test_database().await), EventBus::default());
+        let tick = TickMessage::at(OffsetDateTime::UNIX_EPOCH);
+
+        assert_eq!(context.publish(&tick).unwrap(), 0);
+
+        let _subscriber = context.event_bus().subscribe();
+        assert_eq!(context.publish(&tick).unwrap(), 1);
+
+        assert!(matches!(
+            context.publish(&Unencodable),
+            Err(EventBusError::Serialization(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_recurring_jobs_scheduled_and_claimed() {
+        let database = Database::new(migrated_test_database().await);
+        let context = EventTaskContext::new(database.clone(), EventBus::default());
+        let store = EventTaskStore::new(
+            context,
+            BasicTaskStore::new(BasicTaskContext::new(database, 10)),
+        );
+
+        assert!(store
+            .last_scheduled(TickTask::JOB_NAME)
+            .await
+            .unwrap()
+            .is_none());
+
+        store.enqueue_recurring(TickTask).await.unwrap();
+        assert!(store
+            .last_scheduled(TickTask::JOB_NAME)
+            .await
+            .unwrap()
+            .is_some());
+
+        let job = store
+            .next(QueueName::EVENTED, &[TickTask::JOB_NAME], CLAIM_LEASE)
+            .await
+            .unwrap()
+            .expect("tick to be claimable");
+        store
+            .update_state(job.id(), BackgroundJobState::Complete)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rescheduled_jobs_return_to_the_queue() {
+        let database = Database::new(migrated_test_database().await);
+        let context = EventTaskContext::new(database.clone(), EventBus::default());
+        let store = EventTaskStore::new(
+            context,
+            BasicTaskStore::new(BasicTaskContext::new(database, 10)),
+        );
+
+        store.enqueue_recurring(TickTask).await.unwrap();
+        let job = store
+            .next(QueueName::EVENTED, &[TickTask::JOB_NAME], CLAIM_LEASE)
+            .await
+            .unwrap()
+            .expect("tick to be claimable");
+
+        store
+            .reschedule(job.id(), OffsetDateTime::now_utc())
+            .await
+            .unwrap();
 
-impl From<EventStoreError> for JobStoreError {
-    fn from(value: EventStoreError) -> Self {
-        JobStoreError::StoreBackendUnavailable(value.into())
+        let job = store.find(job.id()).await.unwrap().unwrap();
+        assert!(matches!(job.state(), BackgroundJobState::Scheduled));
     }
 }
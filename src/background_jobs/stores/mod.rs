@@ -3,20 +3,27 @@ pub(crate) mod event_task_store;
 
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use futures::Future;
+use time::OffsetDateTime;
 
 use crate::background_jobs::{
-    BackgroundJob, BackgroundJobId, BackgroundRunId, CaughtPanic, JobLike,
+    BackgroundJob, BackgroundJobId, CaughtPanic, EnqueueOutcome, JobLike, JobOutcome, QueueName,
+    ValidationError,
 };
-use crate::database::custom_types::BackgroundJobState;
+use crate::database::custom_types::{BackgroundJobState, PayloadEncoding, PayloadEncodingError};
+
+/// Calculates the delay before a failed job is retried, see [`JobLike::backoff`].
+pub(crate) type BackoffFn = fn(u8) -> Duration;
 
 pub(crate) type ExecuteJobFn<Context> = Arc<
     dyn Fn(
-            serde_json::Value,
+            PayloadEncoding,
+            Vec<u8>,
             Context,
-        ) -> Pin<Box<dyn Future<Output = Result<(), JobExecError>> + Send>>
+        ) -> Pin<Box<dyn Future<Output = Result<JobOutcome, JobExecError>> + Send>>
         + Send
         + Sync,
 >;
@@ -24,13 +31,16 @@ pub(crate) type ExecuteJobFn<Context> = Arc<
 #[derive(Debug, thiserror::Error)]
 pub enum JobExecError {
     #[error("job deserialization failed: {0}")]
-    DeserializationFailed(#[from] serde_json::Error),
+    DeserializationFailed(#[from] PayloadEncodingError),
 
     #[error("job execution failed: {0}")]
     ExecutionFailed(String),
 
     #[error("job panicked: {0}")]
     Panicked(#[from] CaughtPanic),
+
+    #[error("job failed permanently: {0}")]
+    PermanentFailure(String),
 }
 
 #[async_trait]
@@ -40,25 +50,110 @@ pub trait JobStore: Send + Sync + 'static {
     // todo: I need to make the identifier type a trait parameter, and disconnect the database
     // background job type from the background jobs themselves...
 
+    /// Takes the named lock for the holder if no one else holds an unexpired lease on it,
+    /// returning whether it was acquired. These are used to keep a job from running in more than
+    /// one place at a time, see [`JobLike::GLOBAL_SINGLETON`].
+    async fn acquire_lock(
+        &self,
+        name: &str,
+        holder: &str,
+        lease: Duration,
+    ) -> Result<bool, JobStoreError>;
+
     async fn cancel(&self, id: BackgroundJobId) -> Result<(), JobStoreError> {
         self.update_state(id, BackgroundJobState::Cancelled).await
     }
 
+    /// Up to `limit` of the jobs in the queue that used up all of their attempts, most recently
+    /// failed first. These stay around until an operator decides to [`JobStore::requeue`] them.
+    async fn dead_letter(
+        &self,
+        queue_name: QueueName,
+        limit: usize,
+    ) -> Result<Vec<BackgroundJob>, JobStoreError>;
+
+    /// Adds a job to the store unless it has a unique key matching a job that is still waiting to
+    /// run, in which case the existing job is reported back instead.
     async fn enqueue<T: JobLike>(
         conn: &mut Self::Connection,
         task: T,
-    ) -> Result<BackgroundJobId, JobStoreError>
+    ) -> Result<EnqueueOutcome, JobStoreError>
+    where
+        Self: Sized;
+
+    /// Enqueues a job using the store's own connection. This is used for the recurring jobs the
+    /// worker pool schedules itself.
+    async fn enqueue_recurring<T: JobLike>(&self, task: T) -> Result<EnqueueOutcome, JobStoreError>
     where
         Self: Sized;
 
+    async fn find(&self, id: BackgroundJobId) -> Result<Option<BackgroundJob>, JobStoreError>;
+
+    /// When a job with the provided name was last added to the store, if it ever has been.
+    async fn last_scheduled(&self, job_name: &str)
+        -> Result<Option<OffsetDateTime>, JobStoreError>;
+
+    /// Up to `limit` of the jobs currently in the provided state, most recently attempted first.
+    async fn list_by_state(
+        &self,
+        state: BackgroundJobState,
+        limit: usize,
+    ) -> Result<Vec<BackgroundJob>, JobStoreError>;
+
+    /// Claims the next job in the queue with one of the provided names. The claim is leased to the
+    /// caller, if the job is still in progress once the lease runs out its worker is assumed to
+    /// have gone away and the job can be claimed again.
     async fn next(
         &self,
-        queue_name: &str,
+        queue_name: QueueName,
         task_names: &[&str],
+        lease: Duration,
     ) -> Result<Option<BackgroundJob>, JobStoreError>;
 
-    async fn retry(&self, id: BackgroundJobId) -> Result<Option<BackgroundRunId>, JobStoreError>;
+    /// Puts an in progress job back in the queue to be run at the provided time when its worker
+    /// had to abandon it, such as during a shutdown or when another instance of the job holds its
+    /// lock. The interrupted attempt isn't counted against the job.
+    async fn release(
+        &self,
+        id: BackgroundJobId,
+        attempt_run_at: OffsetDateTime,
+    ) -> Result<(), JobStoreError>;
 
+    /// Gives up a lock taken with [`JobStore::acquire_lock`] if the holder still has it.
+    async fn release_lock(&self, name: &str, holder: &str) -> Result<(), JobStoreError>;
+
+    /// Gives a dead job a fresh set of attempts and makes it runnable immediately, usually after
+    /// whatever caused it to fail has been fixed.
+    async fn requeue(&self, id: BackgroundJobId) -> Result<(), JobStoreError>;
+
+    /// Gives up to `limit` of the dead jobs with the provided name a fresh set of attempts in one
+    /// go, such as after fixing a bug that killed a batch of them. Returns how many were requeued.
+    async fn requeue_dead_by_name(
+        &self,
+        job_name: &str,
+        limit: usize,
+    ) -> Result<u64, JobStoreError>;
+
+    /// Returns an in progress job to the queue to be run again at the provided time. This is for
+    /// jobs that asked to be deferred and doesn't count against the job's attempts.
+    async fn reschedule(
+        &self,
+        id: BackgroundJobId,
+        attempt_run_at: OffsetDateTime,
+    ) -> Result<(), JobStoreError>;
+
+    /// Records the failure of an in progress job and schedules its next attempt after the delay
+    /// given by the backoff function, returning when that attempt will be run. Jobs that have used
+    /// up all of their attempts are marked as dead and `None` is returned instead.
+    async fn retry(
+        &self,
+        id: BackgroundJobId,
+        backoff_fn: BackoffFn,
+    ) -> Result<Option<OffsetDateTime>, JobStoreError>;
+
+    /// Moves an in progress job into one of its final states. Jobs are only moved back into the
+    /// queue by the store itself through [`JobStore::retry`], [`JobStore::reschedule`], and
+    /// [`JobStore::release`].
     async fn update_state(
         &self,
         id: BackgroundJobId,
@@ -71,9 +166,21 @@ pub enum JobStoreError {
     #[error("detected corruption in database: {0}")]
     DataCorruption(Box<dyn std::error::Error>),
 
+    #[error("job {0} can't be moved into the {1} state")]
+    IllegalTransition(BackgroundJobId, BackgroundJobState),
+
+    #[error("job failed validation: {0}")]
+    InvalidJob(#[from] ValidationError),
+
     #[error("the store backend experienced an error: {0}")]
     StoreBackendUnavailable(Box<dyn std::error::Error>),
 
+    #[error("job {0} isn't dead")]
+    NotDead(BackgroundJobId),
+
+    #[error("job {0} isn't in progress")]
+    NotInProgress(BackgroundJobId),
+
     #[error("unable to find job with ID {0}")]
     UnknownJob(BackgroundJobId),
 }
@@ -1,11 +1,25 @@
 use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
 
+use time::OffsetDateTime;
 use tokio::sync::watch::Receiver;
 
 use crate::background_jobs::{
-    BackgroundJob, CatchPanicFuture, ExecuteJobFn, JobStore, JobStoreError, QueueConfig, StateFn,
-    MAXIMUM_CHECK_DELAY,
+    BackgroundJob, CatchPanicFuture, JobExecError, JobOutcome, JobStore, JobStoreError,
+    QueueConfig, RegisteredJob, StateFn, MAXIMUM_CHECK_DELAY,
 };
+use crate::database::custom_types::BackgroundJobState;
+
+/// Extra time added to the leases on claimed jobs and singleton job locks beyond the longest the
+/// job could run for, so the lease doesn't expire while the holder is still finishing up with the
+/// store.
+const LEASE_MARGIN: Duration = Duration::from_secs(30);
+
+/// How long an instance of a singleton job waits before trying again when it finds another
+/// instance already holds the lock.
+const LOCKED_JOB_RETRY_DELAY: Duration = Duration::from_secs(5);
 
 pub struct Worker<Context, S>
 where
@@ -17,9 +31,13 @@ where
 
     context_data_fn: StateFn<Context>,
     store: S,
-    job_registry: BTreeMap<&'static str, ExecuteJobFn<Context>>,
+    job_registry: BTreeMap<&'static str, RegisteredJob<Context>>,
 
-    shutdown_signal: Option<Receiver<()>>,
+    /// Carries how long a running job has to finish once shutdown begins
+    shutdown_signal: Option<Receiver<Duration>>,
+
+    /// The longest a job could be given to drain, shutdown may allow less
+    drain_timeout: Duration,
 }
 
 impl<Context, S> Worker<Context, S>
@@ -32,8 +50,9 @@ where
         queue_config: QueueConfig,
         context_data_fn: StateFn<Context>,
         store: S,
-        job_registry: BTreeMap<&'static str, ExecuteJobFn<Context>>,
-        shutdown_signal: Option<Receiver<()>>,
+        job_registry: BTreeMap<&'static str, RegisteredJob<Context>>,
+        shutdown_signal: Option<Receiver<Duration>>,
+        drain_timeout: Duration,
     ) -> Self {
         Self {
             name,
@@ -42,83 +61,262 @@ where
             store,
             job_registry,
             shutdown_signal,
+            drain_timeout,
         }
     }
 
-    async fn run(&self, job: BackgroundJob) -> Result<(), WorkerError> {
-        let deserialize_and_run_job_fn = self
-            .job_registry
-            .get(job.name())
-            .ok_or(WorkerError::UnregisteredJobName(job.name().to_string()))?
-            .clone();
+    async fn execute(
+        &self,
+        job: &BackgroundJob,
+        registered_job: &RegisteredJob<Context>,
+    ) -> Result<(), WorkerError> {
+        let (time_limit, limited_by_deadline) =
+            execution_limit(job, registered_job.execution_timeout());
+
+        if limited_by_deadline && time_limit.is_zero() {
+            tracing::warn!(id = ?job.id(), "job's deadline passed before it could be run");
+            return self.expire(job, Duration::ZERO).await;
+        }
+
+        let deserialize_and_run_job_fn = registered_job.execute_fn();
 
         // create a new JobRun for the job
 
-        let payload = job.payload().ok_or(WorkerError::PayloadMissing)?.clone();
+        let payload = job.payload().ok_or(WorkerError::PayloadMissing)?.to_vec();
+        let payload_encoding = job.payload_encoding();
         let safe_runner = CatchPanicFuture::wrap({
             let context = (self.context_data_fn)();
-            async move { deserialize_and_run_job_fn(payload, context).await }
+            async move { deserialize_and_run_job_fn(payload_encoding, payload, context).await }
         });
 
         // an error here occurs only when the job panicks, deserialization and regular job
         // execution errors are handled next
         //
-        // todo: should note the job as having panicked if that's why this failed. There is also a
-        // chance that the worker is corrupted in some way by the panic so I should set a flag on
-        // this worker and handle two consecutive panics as a worker problem. The second job
-        // triggering the panic should be presumed innocent and restored to a runnable state.
-        let _job_result = match safe_runner.await {
+        // todo: there is a chance that the worker is corrupted in some way by a panic so I should
+        // set a flag on this worker and handle two consecutive panics as a worker problem. The
+        // second job triggering the panic should be presumed innocent and restored to a runnable
+        // state.
+        let started_at = Instant::now();
+        // Boxed to erase the job's future type, without it the compiler can't prove the worker's
+        // own future is Send once this is handed off to be drained
+        let timed_run: Pin<Box<dyn Future<Output = _> + Send>> =
+            Box::pin(tokio::time::timeout(time_limit, safe_runner));
+        let drained_run = run_until_drained(timed_run, self.shutdown_signal.clone()).await;
+        let execution_time = started_at.elapsed();
+
+        let Some(timed_run) = drained_run else {
+            tracing::warn!(
+                id = ?job.id(),
+                ?execution_time,
+                "job didn't finish before the drain deadline, returning it to the queue"
+            );
+
+            self.store
+                .release(job.id(), OffsetDateTime::now_utc())
+                .await
+                .map_err(WorkerError::ReleaseJobFailed)?;
+            self.record_execution(job, execution_time, "abandoned");
+
+            return Ok(());
+        };
+
+        // The job future is dropped when the timeout elapses which is what actually stops it, it
+        // won't be polled again and the worker is free to pick up the next job
+        let Ok(run_result) = timed_run else {
+            if limited_by_deadline {
+                tracing::warn!(
+                    id = ?job.id(),
+                    ?execution_time,
+                    "job ran past its deadline and was cancelled"
+                );
+
+                return self.expire(job, execution_time).await;
+            }
+
+            tracing::error!(id = ?job.id(), ?execution_time, "job timed out and was cancelled");
+
+            let next_attempt = self
+                .store
+                .retry(job.id(), registered_job.backoff_fn())
+                .await
+                .map_err(WorkerError::RetryJobFailed)?;
+
+            let outcome = match next_attempt {
+                Some(_) => "timed_out",
+                None => "dead",
+            };
+            self.record_execution(job, execution_time, outcome);
+
+            return Ok(());
+        };
+
+        let job_result = match run_result {
             Ok(tr) => tr,
             Err(err) => {
-                tracing::error!("job panicked: {err}");
-
-                // todo: save panic message into the job.error and save it back to the memory
-                // store somehow...
-                //self.store
-                //    .update_state(job.id, BackgroundJobState::Panicked)
-                //    .await
-                //    .map_err(WorkerError::UpdateJobStatusFailed)?;
-
-                // we didn't complete successfully, but we do want to keep processing jobs for
-                // now. We may be corrupted due to the panic somehow if additional errors crop up.
-                // Left as future work to handle this edge case.
+                tracing::error!(id = ?job.id(), ?execution_time, "job panicked: {err}");
+
+                // A panic is treated like any other failure, the job gets its remaining attempts
+                // before it is given up on
+                let next_attempt = self
+                    .store
+                    .retry(job.id(), registered_job.backoff_fn())
+                    .await
+                    .map_err(WorkerError::RetryJobFailed)?;
+
+                let outcome = match next_attempt {
+                    Some(_) => "panicked",
+                    None => "dead",
+                };
+                self.record_execution(job, execution_time, outcome);
+
                 return Ok(());
             }
         };
 
-        //match job_result {
-        //    Ok(_) => {
-        //        self.store
-        //            .update_state(job.id, BackgroundJobState::Complete)
-        //            .await
-        //            .map_err(WorkerError::UpdateJobStatusFailed)?;
-        //    }
-        //    Err(err) => {
-        //        tracing::error!("job failed with error: {err}");
-
-        //        self.store
-        //            .update_state(job.id, BackgroundJobState::Error)
-        //            .await
-        //            .map_err(WorkerError::UpdateJobStatusFailed)?;
-
-        //        self.store
-        //            .retry(job.id)
-        //            .await
-        //            .map_err(WorkerError::RetryJobFailed)?;
-        //    }
-        //}
+        let outcome = match job_result {
+            Ok(JobOutcome::Complete) => {
+                self.store
+                    .update_state(job.id(), BackgroundJobState::Complete)
+                    .await
+                    .map_err(WorkerError::UpdateJobStatusFailed)?;
+
+                "completed"
+            }
+            Ok(JobOutcome::RetryAfter(delay)) => {
+                tracing::info!(id = ?job.id(), ?delay, "job requested to be run again later");
+
+                self.store
+                    .reschedule(job.id(), OffsetDateTime::now_utc() + delay)
+                    .await
+                    .map_err(WorkerError::RescheduleJobFailed)?;
+
+                "rescheduled"
+            }
+            Err(err @ JobExecError::PermanentFailure(_)) => {
+                tracing::error!(id = ?job.id(), "job failed with a permanent error: {err}");
+
+                self.store
+                    .update_state(job.id(), BackgroundJobState::Dead)
+                    .await
+                    .map_err(WorkerError::UpdateJobStatusFailed)?;
+
+                "dead"
+            }
+            Err(err) => {
+                tracing::error!(id = ?job.id(), "job failed with error: {err}");
+
+                let next_attempt = self
+                    .store
+                    .retry(job.id(), registered_job.backoff_fn())
+                    .await
+                    .map_err(WorkerError::RetryJobFailed)?;
+
+                match next_attempt {
+                    Some(_) => "failed",
+                    None => "dead",
+                }
+            }
+        };
+
+        self.record_execution(job, execution_time, outcome);
+
+        Ok(())
+    }
+
+    /// Cancels a job whose deadline has passed. Whatever was waiting on it has given up so there
+    /// is no point in trying it again.
+    async fn expire(
+        &self,
+        job: &BackgroundJob,
+        execution_time: Duration,
+    ) -> Result<(), WorkerError> {
+        self.store
+            .update_state(job.id(), BackgroundJobState::Cancelled)
+            .await
+            .map_err(WorkerError::UpdateJobStatusFailed)?;
+        self.record_execution(job, execution_time, "expired");
+
+        Ok(())
+    }
+
+    async fn run(&self, job: BackgroundJob) -> Result<(), WorkerError> {
+        let registered_job = self
+            .job_registry
+            .get(job.name())
+            .ok_or(WorkerError::UnregisteredJobName(job.name().to_string()))?;
+
+        if !registered_job.is_global_singleton() {
+            return self.execute(&job, registered_job).await;
+        }
+
+        // The lease covers the longest the job could legitimately hold the lock for, if this
+        // worker dies before releasing it the next instance only has to wait for it to expire
+        let holder = job.id().to_string();
+        let lease = registered_job.execution_timeout() + self.drain_timeout + LEASE_MARGIN;
+
+        let acquired = self
+            .store
+            .acquire_lock(job.name(), &holder, lease)
+            .await
+            .map_err(WorkerError::AcquireLockFailed)?;
+
+        if !acquired {
+            tracing::info!(
+                id = ?job.id(),
+                "another instance of the job is already running, returning it to the queue"
+            );
+
+            self.store
+                .release(job.id(), OffsetDateTime::now_utc() + LOCKED_JOB_RETRY_DELAY)
+                .await
+                .map_err(WorkerError::ReleaseJobFailed)?;
+            self.record_execution(&job, Duration::ZERO, "skipped");
+
+            return Ok(());
+        }
+
+        // A store error here stops the worker, the lock is left to expire along with its lease
+        self.execute(&job, registered_job).await?;
+
+        if let Err(err) = self.store.release_lock(job.name(), &holder).await {
+            tracing::warn!(
+                id = ?job.id(),
+                "failed to release job lock, it will expire instead: {err}"
+            );
+        }
 
         Ok(())
     }
 
+    // todo: there is no metrics exporter yet. Once there is this should feed a histogram of the
+    // execution time and a counter of the outcomes, both labeled by the job name and queue. Job
+    // names are static so the label cardinality stays bounded.
+    fn record_execution(&self, job: &BackgroundJob, execution_time: Duration, outcome: &str) {
+        tracing::info!(
+            job_name = job.name(),
+            queue = self.queue_config.name().as_str(),
+            ?execution_time,
+            outcome,
+            "job execution finished"
+        );
+    }
+
     pub async fn run_jobs(&mut self) -> Result<(), WorkerError> {
         let relevant_job_names: Vec<&'static str> = self.job_registry.keys().cloned().collect();
 
+        // Claims are held long enough for the slowest job this worker runs to finish, anything
+        // still running past that has been abandoned and can be picked up by another worker
+        let longest_execution = self
+            .job_registry
+            .values()
+            .map(|registered_job| registered_job.execution_timeout())
+            .max()
+            .unwrap_or_default();
+        let claim_lease = longest_execution + self.drain_timeout + LEASE_MARGIN;
+
         loop {
-            // check to see if its time to shutdown the worker
-            //
-            // todo: turn this into a select with a short fallback timeout on job execution to try
-            // and finish it within our graceful shutdown window
+            // check to see if its time to shutdown the worker, a job that is already running when
+            // the signal arrives is given a chance to finish by `run_until_drained`
             if let Some(shutdown_signal) = &self.shutdown_signal {
                 match shutdown_signal.has_changed() {
                     Ok(true) => return Ok(()),
@@ -129,7 +327,7 @@ where
 
             let next_job = self
                 .store
-                .next(self.queue_config.name(), &relevant_job_names)
+                .next(self.queue_config.name(), &relevant_job_names, claim_lease)
                 .await
                 .map_err(WorkerError::StoreUnavailable)?;
 
@@ -165,14 +363,67 @@ where
     }
 }
 
+/// How long a job is given to run and whether that was limited by the job's deadline. Jobs
+/// normally get their registered execution timeout. Jobs enqueued on behalf of a request carry the
+/// request's deadline and get whichever is sooner of the two, one whose deadline has already
+/// passed gets no time at all. Either way the job is never given longer than its own timeout.
+fn execution_limit(job: &BackgroundJob, execution_timeout: Duration) -> (Duration, bool) {
+    let Some(deadline) = job.deadline() else {
+        return (execution_timeout, false);
+    };
+
+    // A deadline in the past can't be converted and leaves nothing left
+    let remaining = Duration::try_from(deadline - OffsetDateTime::now_utc()).unwrap_or_default();
+
+    if remaining < execution_timeout {
+        (remaining, true)
+    } else {
+        (execution_timeout, false)
+    }
+}
+
+/// Drives a job to completion. If the worker is asked to shut down part way through, the job is
+/// given until the drain timeout sent along with the shutdown signal to finish before it is
+/// dropped and `None` is returned.
+async fn run_until_drained<F: Future>(
+    job_run: F,
+    shutdown_signal: Option<Receiver<Duration>>,
+) -> Option<F::Output> {
+    let Some(mut shutdown_signal) = shutdown_signal else {
+        return Some(job_run.await);
+    };
+
+    tokio::pin!(job_run);
+
+    // A closed channel is treated the same as a shutdown request, there is nothing left that could
+    // tell us to stop anymore
+    tokio::select! {
+        output = &mut job_run => return Some(output),
+        _ = shutdown_signal.changed() => (),
+    }
+
+    let drain_timeout = *shutdown_signal.borrow();
+    tracing::info!(?drain_timeout, "shutdown requested, draining in-flight job");
+    tokio::time::timeout(drain_timeout, job_run).await.ok()
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum WorkerError {
+    #[error("failed to acquire the lock for a singleton job: {0}")]
+    AcquireLockFailed(JobStoreError),
+
     #[error("worker detected an error in the shutdown channel and forced and immediate exit")]
     EmergencyShutdown,
 
     #[error("attempted to run job that already had its payload cleared")]
     PayloadMissing,
 
+    #[error("failed to return an abandoned job to the queue: {0}")]
+    ReleaseJobFailed(JobStoreError),
+
+    #[error("failed to return a deferred job to the queue: {0}")]
+    RescheduleJobFailed(JobStoreError),
+
     #[error("failed to enqueue a failed job for re-execution: {0}")]
     RetryJobFailed(JobStoreError),
 
@@ -185,3 +436,411 @@ pub enum WorkerError {
     #[error("during execution of a dequeued job, encountered unregistered job '{0}'")]
     UnregisteredJobName(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    use crate::background_jobs::{
+        BackoffStrategy, BasicTaskContext, BasicTaskStore, JobLike, JobLikeExt, QueueName,
+    };
+    use crate::database::Database;
+    use crate::tests::prelude::*;
+
+    const DEFERRAL: Duration = Duration::from_secs(60);
+
+    #[derive(Deserialize, Serialize)]
+    struct DeferredJob;
+
+    #[async_trait]
+    impl JobLike for DeferredJob {
+        const JOB_NAME: &'static str = "deferred_job";
+
+        type Context = ();
+        type Error = std::io::Error;
+
+        async fn run(&self, _ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+            Ok(JobOutcome::RetryAfter(DEFERRAL))
+        }
+    }
+
+    #[derive(Deserialize, Serialize)]
+    struct SlowJob;
+
+    #[async_trait]
+    impl JobLike for SlowJob {
+        const EXECUTION_TIMEOUT: Duration = Duration::from_millis(50);
+        const JOB_NAME: &'static str = "slow_job";
+
+        type Context = ();
+        type Error = std::io::Error;
+
+        async fn run(&self, _ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+            tokio::time::sleep(Duration::from_secs(3_600)).await;
+            Ok(JobOutcome::Complete)
+        }
+    }
+
+    #[derive(Deserialize, Serialize)]
+    struct FlakyJob;
+
+    #[async_trait]
+    impl JobLike for FlakyJob {
+        const JOB_NAME: &'static str = "flaky_job";
+
+        type Context = ();
+        type Error = std::io::Error;
+
+        async fn run(&self, _ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+            Err(std::io::ErrorKind::ConnectionRefused.into())
+        }
+
+        fn backoff(attempt: u8) -> Duration {
+            BackoffStrategy::Linear(Duration::from_secs(90)).delay(attempt)
+        }
+    }
+
+    #[derive(Deserialize, Serialize)]
+    struct InvalidInputJob;
+
+    #[async_trait]
+    impl JobLike for InvalidInputJob {
+        const JOB_NAME: &'static str = "invalid_input_job";
+
+        type Context = ();
+        type Error = std::io::Error;
+
+        async fn run(&self, _ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+            Err(std::io::ErrorKind::InvalidInput.into())
+        }
+
+        fn is_retryable(error: &Self::Error) -> bool {
+            error.kind() != std::io::ErrorKind::InvalidInput
+        }
+    }
+
+    #[derive(Deserialize, Serialize)]
+    struct PanickingJob;
+
+    #[async_trait]
+    impl JobLike for PanickingJob {
+        const JOB_NAME: &'static str = "panicking_job";
+
+        type Context = ();
+        type Error = std::io::Error;
+
+        async fn run(&self, _ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+            panic!("job blew up");
+        }
+    }
+
+    /// Stands in for a job a request handler is waiting on, it won't finish before any deadline.
+    #[derive(Deserialize, Serialize)]
+    struct WaitedOnJob;
+
+    #[async_trait]
+    impl JobLike for WaitedOnJob {
+        const JOB_NAME: &'static str = "waited_on_job";
+
+        type Context = ();
+        type Error = std::io::Error;
+
+        async fn run(&self, _ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+            tokio::time::sleep(Duration::from_secs(3_600)).await;
+            Ok(JobOutcome::Complete)
+        }
+    }
+
+    /// Tracks how many instances of [`SingletonJob`] are running at the same time.
+    #[derive(Clone, Default)]
+    struct RunningCount {
+        current: Arc<AtomicUsize>,
+        max: Arc<AtomicUsize>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    struct SingletonJob;
+
+    #[async_trait]
+    impl JobLike for SingletonJob {
+        const GLOBAL_SINGLETON: bool = true;
+        const JOB_NAME: &'static str = "singleton_job";
+
+        type Context = RunningCount;
+        type Error = std::io::Error;
+
+        async fn run(&self, ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+            let running = ctx.current.fetch_add(1, Ordering::SeqCst) + 1;
+            ctx.max.fetch_max(running, Ordering::SeqCst);
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            ctx.current.fetch_sub(1, Ordering::SeqCst);
+
+            Ok(JobOutcome::Complete)
+        }
+    }
+
+    fn test_worker(store: TestJobStore) -> Worker<(), TestJobStore> {
+        let job_registry = BTreeMap::from([
+            (DeferredJob::JOB_NAME, RegisteredJob::new::<DeferredJob>()),
+            (FlakyJob::JOB_NAME, RegisteredJob::new::<FlakyJob>()),
+            (
+                InvalidInputJob::JOB_NAME,
+                RegisteredJob::new::<InvalidInputJob>(),
+            ),
+            (PanickingJob::JOB_NAME, RegisteredJob::new::<PanickingJob>()),
+            (SlowJob::JOB_NAME, RegisteredJob::new::<SlowJob>()),
+            (WaitedOnJob::JOB_NAME, RegisteredJob::new::<WaitedOnJob>()),
+        ]);
+
+        Worker::new(
+            "test_worker".to_string(),
+            QueueConfig::new(QueueName::DEFAULT),
+            Arc::new(|| ()),
+            store,
+            job_registry,
+            None,
+            Duration::from_secs(1),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_reschedules_job() {
+        let job = stored_job(DeferredJob).await;
+        let job_id = job.id();
+
+        let store = TestJobStore::default();
+        let worker = test_worker(store.clone());
+
+        let before = OffsetDateTime::now_utc();
+        worker.run(job).await.expect("deferred job to be handled");
+
+        let rescheduled = store.rescheduled();
+        assert_eq!(rescheduled.len(), 1);
+
+        let (id, attempt_run_at) = rescheduled[0];
+        assert_eq!(id, job_id);
+        assert!(attempt_run_at >= before + DEFERRAL);
+        assert!(store.retried().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_job_retried_with_its_backoff() {
+        let job = stored_job(FlakyJob).await;
+        let job_id = job.id();
+
+        let store = TestJobStore::default();
+        let worker = test_worker(store.clone());
+
+        worker.run(job).await.expect("failed job to be handled");
+
+        let retried = store.retried();
+        assert_eq!(retried.len(), 1);
+
+        let (id, backoff_fn) = retried[0];
+        assert_eq!(id, job_id);
+        assert_eq!(backoff_fn(2), Duration::from_secs(180));
+    }
+
+    #[tokio::test]
+    async fn test_permanent_errors_skip_remaining_attempts() {
+        let transient_job = stored_job(FlakyJob).await;
+        let transient_id = transient_job.id();
+        let permanent_job = stored_job(InvalidInputJob).await;
+        let permanent_id = permanent_job.id();
+
+        let store = TestJobStore::default();
+        let worker = test_worker(store.clone());
+
+        worker.run(transient_job).await.expect("job to be handled");
+        worker.run(permanent_job).await.expect("job to be handled");
+
+        let retried = store.retried();
+        assert_eq!(retried.len(), 1);
+        assert_eq!(retried[0].0, transient_id);
+
+        let updated = store.updated();
+        assert_eq!(updated.len(), 1);
+        assert_eq!(updated[0].0, permanent_id);
+        assert!(matches!(updated[0].1, BackgroundJobState::Dead));
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_job_cancelled_and_retried() {
+        let slow_job = stored_job(SlowJob).await;
+        let slow_id = slow_job.id();
+        let flaky_job = stored_job(FlakyJob).await;
+
+        let store = TestJobStore::default();
+        let worker = test_worker(store.clone());
+
+        let started_at = Instant::now();
+        let timed_run = tokio::time::timeout(Duration::from_secs(5), worker.run(slow_job)).await;
+        timed_run
+            .expect("slow job to be cancelled")
+            .expect("timed out job to be handled");
+        assert!(started_at.elapsed() >= SlowJob::EXECUTION_TIMEOUT);
+
+        // The worker is free to carry on with the next job
+        worker.run(flaky_job).await.expect("next job to run");
+
+        let retried = store.retried();
+        assert_eq!(retried.len(), 2);
+        assert_eq!(retried[0].0, slow_id);
+    }
+
+    #[tokio::test]
+    async fn test_panicked_job_retried() {
+        let panicking_job = stored_job(PanickingJob).await;
+        let panicking_id = panicking_job.id();
+        let flaky_job = stored_job(FlakyJob).await;
+
+        let store = TestJobStore::default();
+        let worker = test_worker(store.clone());
+
+        worker
+            .run(panicking_job)
+            .await
+            .expect("panicked job to be handled");
+
+        // The worker is free to carry on with the next job
+        worker.run(flaky_job).await.expect("next job to run");
+
+        let retried = store.retried();
+        assert_eq!(retried.len(), 2);
+        assert_eq!(retried[0].0, panicking_id);
+        assert!(store.updated().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_job_cut_off_at_its_deadline() {
+        let deadline = OffsetDateTime::now_utc() + time::Duration::milliseconds(50);
+        let job = stored_job_with_deadline(WaitedOnJob, deadline).await;
+        let job_id = job.id();
+
+        let store = TestJobStore::default();
+        let worker = test_worker(store.clone());
+
+        let timed_run = tokio::time::timeout(Duration::from_secs(5), worker.run(job)).await;
+        timed_run
+            .expect("job to be cut off at its deadline")
+            .expect("expired job to be handled");
+
+        // Nothing is waiting on the job anymore so it isn't tried again
+        assert!(store.retried().is_empty());
+
+        let updated = store.updated();
+        assert_eq!(updated.len(), 1);
+        assert_eq!(updated[0].0, job_id);
+        assert!(matches!(updated[0].1, BackgroundJobState::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn test_job_past_its_deadline_not_run() {
+        let deadline = OffsetDateTime::now_utc() - time::Duration::seconds(1);
+        let job = stored_job_with_deadline(FlakyJob, deadline).await;
+        let job_id = job.id();
+
+        let store = TestJobStore::default();
+        let worker = test_worker(store.clone());
+
+        worker.run(job).await.expect("expired job to be handled");
+
+        // The job would have failed and been retried had it been run
+        assert!(store.retried().is_empty());
+
+        let updated = store.updated();
+        assert_eq!(updated.len(), 1);
+        assert_eq!(updated[0].0, job_id);
+        assert!(matches!(updated[0].1, BackgroundJobState::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn test_later_deadline_keeps_execution_timeout() {
+        let deadline = OffsetDateTime::now_utc() + time::Duration::hours(1);
+        let job = stored_job_with_deadline(SlowJob, deadline).await;
+        let job_id = job.id();
+
+        let store = TestJobStore::default();
+        let worker = test_worker(store.clone());
+
+        let timed_run = tokio::time::timeout(Duration::from_secs(5), worker.run(job)).await;
+        timed_run
+            .expect("slow job to be cancelled")
+            .expect("timed out job to be handled");
+
+        // The job's own timeout cut it off first, that's an ordinary failure with attempts left
+        let retried = store.retried();
+        assert_eq!(retried.len(), 1);
+        assert_eq!(retried[0].0, job_id);
+        assert!(store.updated().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_singleton_job_only_runs_one_instance() {
+        let mut pool = migrated_test_database().await;
+        let store = BasicTaskStore::new(BasicTaskContext::new(Database::new(pool.clone()), 10));
+
+        for _ in 0..2 {
+            SingletonJob
+                .enqueue::<BasicTaskStore>(&mut pool)
+                .await
+                .unwrap();
+        }
+
+        let job_names = [SingletonJob::JOB_NAME];
+        let first = store
+            .next(QueueName::DEFAULT, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap();
+        let second = store
+            .next(QueueName::DEFAULT, &job_names, CLAIM_LEASE)
+            .await
+            .unwrap();
+
+        let running = RunningCount::default();
+        let worker: Worker<RunningCount, BasicTaskStore> = Worker::new(
+            "test_worker".to_string(),
+            QueueConfig::new(QueueName::DEFAULT),
+            Arc::new({
+                let running = running.clone();
+                move || running.clone()
+            }),
+            store.clone(),
+            BTreeMap::from([(SingletonJob::JOB_NAME, RegisteredJob::new::<SingletonJob>())]),
+            None,
+            Duration::from_secs(1),
+        );
+
+        let (first, second) = tokio::join!(
+            worker.run(first.expect("first job")),
+            worker.run(second.expect("second job")),
+        );
+        first.expect("first job to be handled");
+        second.expect("second job to be handled");
+
+        assert_eq!(running.max.load(Ordering::SeqCst), 1);
+
+        // The instance that found the lock held went back to the queue without running
+        let waiting = store
+            .list_by_state(BackgroundJobState::Scheduled, 10)
+            .await
+            .unwrap();
+        assert_eq!(waiting.len(), 1);
+        assert_eq!(waiting[0].current_attempt().number(), 1);
+
+        // The lock was given up once the running instance finished
+        let lease = Duration::from_secs(60);
+        let acquired = store
+            .acquire_lock(SingletonJob::JOB_NAME, "test", lease)
+            .await;
+        assert!(acquired.unwrap());
+    }
+}
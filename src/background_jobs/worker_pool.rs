@@ -3,6 +3,7 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
 
+use axum::extract::FromRef;
 use futures::future::join_all;
 use futures::Future;
 use tokio::sync::watch;
@@ -10,10 +11,19 @@ use tokio::task::JoinHandle;
 use tokio::time::timeout;
 
 use crate::background_jobs::{
-    ExecuteJobFn, JobExecError, JobLike, JobStore, QueueConfig, StateFn, Worker,
+    run_recurring_job, BackoffFn, ExecuteJobFn, JobExecError, JobLike, JobOutcome, JobStore,
+    QueueConfig, QueueConfigs, QueueName, RecurringSchedule, StateFn, Worker,
 };
+use crate::database::custom_types::PayloadEncoding;
+use crate::SubsystemOutcome;
 
-const WORKER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a job that is already running when the pool is shut down is given to finish before
+/// it is abandoned and returned to the queue.
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Extra time allowed beyond the drain timeout for workers to stop, covering the bookkeeping for
+/// any jobs that had to be abandoned.
+const DEFAULT_WORKER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Clone)]
 pub struct WorkerPool<Context, S>
@@ -23,10 +33,13 @@ where
 {
     context_data_fn: StateFn<Context>,
     job_store: S,
-    job_registry: BTreeMap<&'static str, ExecuteJobFn<Context>>,
+    job_registry: BTreeMap<&'static str, RegisteredJob<Context>>,
+    recurring_jobs: Vec<SpawnSchedulerFn<S>>,
 
-    worker_queues: BTreeMap<&'static str, Vec<&'static str>>,
-    worker_configs: BTreeMap<&'static str, QueueConfig>,
+    drain_timeout: Duration,
+    shutdown_timeout: Duration,
+    worker_queues: BTreeMap<QueueName, Vec<&'static str>>,
+    worker_configs: BTreeMap<QueueName, QueueConfig>,
 }
 
 impl<Context, S> WorkerPool<Context, S>
@@ -40,6 +53,18 @@ where
         self
     }
 
+    /// Adds workers for each queue used by the job types registered so far, sized according to
+    /// the declared queue configs. This needs to be called after all job types are registered.
+    pub fn add_declared_workers(mut self, declared: &QueueConfigs) -> Self {
+        for queue_name in self.worker_queues.keys() {
+            if let Some(config) = declared.get(*queue_name) {
+                self.worker_configs.insert(*queue_name, config.clone());
+            }
+        }
+
+        self
+    }
+
     pub fn new<A>(job_store: S, context_data_fn: A) -> Self
     where
         A: Fn() -> Context + Send + Sync + 'static,
@@ -49,15 +74,22 @@ where
 
             job_store,
             job_registry: BTreeMap::new(),
+            recurring_jobs: Vec::new(),
 
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            shutdown_timeout: DEFAULT_WORKER_SHUTDOWN_TIMEOUT,
             worker_configs: BTreeMap::new(),
             worker_queues: BTreeMap::new(),
         }
     }
 
+    /// Registers a job type for the pool's workers to run. The job's context is derived from the
+    /// pool's context so job types that need different contexts can share a pool, and with it the
+    /// workers of their queue.
     pub fn register_job_type<TL>(mut self) -> Self
     where
-        TL: JobLike<Context = Context>,
+        TL: JobLike,
+        TL::Context: FromRef<Context>,
     {
         self.worker_queues
             .entry(TL::QUEUE_NAME)
@@ -65,25 +97,68 @@ where
             .push(TL::JOB_NAME);
 
         self.job_registry
-            .insert(TL::JOB_NAME, Arc::new(deserialize_and_run_job::<TL>));
+            .insert(TL::JOB_NAME, RegisteredJob::new::<TL>());
 
         self
     }
 
-    pub async fn start<F>(self, shutdown_signal: F) -> Result<JoinHandle<()>, WorkerPoolError>
+    /// Overrides how long the already registered job type `TL` may run before it is cancelled,
+    /// for job types whose [`JobLike::EXECUTION_TIMEOUT`] depends on the deployment.
+    pub fn with_execution_timeout<TL>(mut self, execution_timeout: Duration) -> Self
     where
-        F: Future<Output = ()> + Send + 'static,
+        TL: JobLike,
+    {
+        if let Some(registered_job) = self.job_registry.get_mut(TL::JOB_NAME) {
+            registered_job.execution_timeout = execution_timeout;
+        }
+
+        self
+    }
+
+    /// Registers a job type that the pool enqueues itself whenever its schedule comes due. The
+    /// job is otherwise run like any other job on its queue.
+    pub fn register_recurring_job_type<TL>(mut self, schedule: RecurringSchedule) -> Self
+    where
+        TL: JobLike + Default,
+        TL::Context: FromRef<Context>,
+    {
+        self.recurring_jobs
+            .push(Arc::new(move |store, shutdown_rx| {
+                tokio::spawn(run_recurring_job::<TL, S, Duration>(
+                    store,
+                    schedule.clone(),
+                    shutdown_rx,
+                ))
+            }));
+
+        self.register_job_type::<TL>()
+    }
+
+    /// Spawns the workers and schedulers, returning a handle that resolves once they have all
+    /// stopped after `shutdown_signal` resolves, or once they have been given up on.
+    ///
+    /// The signal may resolve with the most time the caller is willing to wait for the pool to
+    /// stop. When it does the drain and shutdown timeouts are shortened to fit within it, running
+    /// jobs get whatever is left after the shutdown timeout is set aside to return them to the
+    /// queue.
+    pub async fn start<F>(
+        self,
+        shutdown_signal: F,
+    ) -> Result<JoinHandle<SubsystemOutcome>, WorkerPoolError>
+    where
+        F: Future<Output = Option<Duration>> + Send + 'static,
     {
         for (queue_name, queue_tracked_jobs) in self.worker_queues.iter() {
             if !self.worker_configs.contains_key(queue_name) {
                 return Err(WorkerPoolError::QueueNotConfigured(
-                    queue_name,
+                    *queue_name,
                     queue_tracked_jobs.clone(),
                 ));
             }
         }
 
-        let (inner_shutdown_tx, inner_shutdown_rx) = watch::channel(());
+        // The value sent is how long workers give their running job to finish
+        let (inner_shutdown_tx, inner_shutdown_rx) = watch::channel(self.drain_timeout);
         let mut worker_handles = Vec::new();
 
         for (queue_name, queue_config) in self.worker_configs.iter() {
@@ -100,6 +175,7 @@ where
                     self.job_store.clone(),
                     self.job_registry.clone(),
                     Some(inner_shutdown_rx.clone()),
+                    self.drain_timeout,
                 );
 
                 let worker_handle = tokio::spawn(async move {
@@ -112,7 +188,17 @@ where
             }
         }
 
+        for spawn_scheduler in self.recurring_jobs.iter() {
+            let scheduler_handle =
+                spawn_scheduler(self.job_store.clone(), inner_shutdown_rx.clone());
+            worker_handles.push(scheduler_handle);
+        }
+
+        let drain_timeout = self.drain_timeout;
+        let shutdown_timeout = self.shutdown_timeout;
         let shutdown_guard = tokio::spawn(async move {
+            let mut worker_handles = worker_handles;
+
             // Wait until we receive a shutdown signal directly or the channel errors out due to
             // the other side being dropped
             //
@@ -120,16 +206,24 @@ where
             // them dies I want it to be captured, and ideally started back up again but that would
             // require more plumbing. For now a worker panic should be caught as a worker pool
             // error and ultimately a server shutdown
-            let _ = shutdown_signal.await;
+            let budget = shutdown_signal.await;
+
+            let (drain_timeout, worker_shutdown_timeout) = match budget {
+                Some(budget) => (
+                    drain_timeout.min(budget.saturating_sub(shutdown_timeout)),
+                    (drain_timeout + shutdown_timeout).min(budget),
+                ),
+                None => (drain_timeout, drain_timeout + shutdown_timeout),
+            };
 
             // In either case, its time to shut things down. Let's try and notify our workers for
             // graceful shutdown.
-            let _ = inner_shutdown_tx.send(());
+            let _ = inner_shutdown_tx.send(drain_timeout);
 
             // try and collect error from workers but if it takes too long abandon them
             let worker_errors: Vec<_> = match timeout(
-                WORKER_SHUTDOWN_TIMEOUT,
-                join_all(worker_handles),
+                worker_shutdown_timeout,
+                join_all(worker_handles.iter_mut()),
             )
             .await
             {
@@ -139,45 +233,333 @@ where
                     .map(Result::unwrap_err)
                     .collect(),
                 Err(_) => {
-                    tracing::warn!("timed out waiting for workers to shutdown, not reporting outstanding errors");
-                    Vec::new()
+                    let in_flight = worker_handles.iter().filter(|h| !h.is_finished()).count();
+                    tracing::warn!(in_flight, "timed out waiting for workers to shutdown, not reporting outstanding errors");
+                    return SubsystemOutcome::TimedOut { in_flight };
                 }
             };
 
             if worker_errors.is_empty() {
                 tracing::info!("worker pool shutdown gracefully");
+                SubsystemOutcome::Clean
             } else {
                 tracing::error!(
                     "workers reported the following errors during shutdown:\n{:?}",
                     worker_errors
                 );
+                SubsystemOutcome::Failed(format!("{} workers failed", worker_errors.len()))
             }
         });
 
         Ok(shutdown_guard)
     }
+
+    /// Sets how long jobs that are running when the pool is shut down have to finish. Jobs still
+    /// running once it passes are cancelled and put back in the queue to be picked up again.
+    pub fn with_drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = drain_timeout;
+        self
+    }
+
+    /// Sets how much longer than the drain timeout workers have to stop before the pool gives up
+    /// waiting on them, covering the bookkeeping for any jobs that had to be abandoned.
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    /// The number of workers the pool runs once started, across all of its queues.
+    pub fn worker_count(&self) -> usize {
+        self.worker_configs
+            .values()
+            .map(QueueConfig::worker_count)
+            .sum()
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum WorkerPoolError {
     #[error("found queue '{0}' defined by job(s) {1:?} without a queue config")]
-    QueueNotConfigured(&'static str, Vec<&'static str>),
+    QueueNotConfigured(QueueName, Vec<&'static str>),
+}
+
+type SpawnSchedulerFn<S> =
+    Arc<dyn Fn(S, watch::Receiver<Duration>) -> JoinHandle<()> + Send + Sync>;
+
+/// Everything a worker needs to know about a registered job type to run it.
+#[derive(Clone)]
+pub(crate) struct RegisteredJob<Context> {
+    backoff_fn: BackoffFn,
+    execute_fn: ExecuteJobFn<Context>,
+    execution_timeout: Duration,
+    global_singleton: bool,
 }
 
-fn deserialize_and_run_job<JL>(
-    payload: serde_json::Value,
-    context: JL::Context,
-) -> Pin<Box<dyn Future<Output = Result<(), JobExecError>> + Send>>
+impl<Context> RegisteredJob<Context>
+where
+    Context: Clone + Send + 'static,
+{
+    pub(crate) fn backoff_fn(&self) -> BackoffFn {
+        self.backoff_fn
+    }
+
+    pub(crate) fn execute_fn(&self) -> ExecuteJobFn<Context> {
+        self.execute_fn.clone()
+    }
+
+    pub(crate) fn execution_timeout(&self) -> Duration {
+        self.execution_timeout
+    }
+
+    pub(crate) fn is_global_singleton(&self) -> bool {
+        self.global_singleton
+    }
+
+    pub(crate) fn new<JL>() -> Self
+    where
+        JL: JobLike,
+        JL::Context: FromRef<Context>,
+    {
+        Self {
+            backoff_fn: JL::backoff,
+            execute_fn: Arc::new(deserialize_and_run_job::<JL, Context>),
+            execution_timeout: JL::EXECUTION_TIMEOUT,
+            global_singleton: JL::GLOBAL_SINGLETON,
+        }
+    }
+}
+
+fn deserialize_and_run_job<JL, Context>(
+    encoding: PayloadEncoding,
+    payload: Vec<u8>,
+    context: Context,
+) -> Pin<Box<dyn Future<Output = Result<JobOutcome, JobExecError>> + Send>>
 where
     JL: JobLike,
+    JL::Context: FromRef<Context>,
 {
+    let context: JL::Context = FromRef::from_ref(&context);
+
     Box::pin(async move {
-        let job: JL = serde_json::from_value(payload)?;
+        let job: JL = encoding.decode_payload(&payload)?;
 
         match job.run(context).await {
-            Ok(_) => Ok(()),
+            Ok(outcome) => Ok(outcome),
             // todo: should try and serialize the error if possible
-            Err(run_err) => Err(JobExecError::ExecutionFailed(run_err.to_string())),
+            Err(run_err) if JL::is_retryable(&run_err) => {
+                Err(JobExecError::ExecutionFailed(run_err.to_string()))
+            }
+            Err(run_err) => Err(JobExecError::PermanentFailure(run_err.to_string())),
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use async_trait::async_trait;
+    use serde::{Deserialize, Serialize};
+    use tokio::sync::Notify;
+
+    use super::*;
+
+    use crate::database::custom_types::BackgroundJobState;
+    use crate::tests::prelude::*;
+
+    #[derive(Clone)]
+    struct SlowJobProgress {
+        started: Arc<Notify>,
+        finished: Arc<AtomicBool>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    struct SlowJob;
+
+    #[async_trait]
+    impl JobLike for SlowJob {
+        const JOB_NAME: &'static str = "slow_job";
+
+        type Context = SlowJobProgress;
+        type Error = std::io::Error;
+
+        async fn run(&self, ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+            ctx.started.notify_one();
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            ctx.finished.store(true, Ordering::SeqCst);
+
+            Ok(JobOutcome::Complete)
+        }
+    }
+
+    /// Needs only part of the context the slow job uses so the two can share a pool.
+    #[derive(Clone)]
+    struct QuickJobStarted(Arc<Notify>);
+
+    impl FromRef<SlowJobProgress> for QuickJobStarted {
+        fn from_ref(progress: &SlowJobProgress) -> Self {
+            Self(progress.started.clone())
+        }
+    }
+
+    #[derive(Deserialize, Serialize)]
+    struct QuickJob;
+
+    #[async_trait]
+    impl JobLike for QuickJob {
+        const JOB_NAME: &'static str = "quick_job";
+
+        type Context = QuickJobStarted;
+        type Error = std::io::Error;
+
+        async fn run(&self, ctx: Self::Context) -> Result<JobOutcome, Self::Error> {
+            ctx.0.notify_one();
+            Ok(JobOutcome::Complete)
+        }
+    }
+
+    fn slow_job_progress() -> SlowJobProgress {
+        SlowJobProgress {
+            started: Arc::new(Notify::new()),
+            finished: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Starts a pool running the slow job and signals it to shutdown as soon as the job starts,
+    /// returning once the pool has stopped.
+    async fn shutdown_during_slow_job(
+        store: TestJobStore,
+        progress: SlowJobProgress,
+        drain_timeout: Duration,
+        budget: Option<Duration>,
+    ) {
+        let (shutdown_tx, mut shutdown_rx) = watch::channel(());
+
+        let context = progress.clone();
+        let pool_handle = WorkerPool::new(store, move || context.clone())
+            .register_job_type::<SlowJob>()
+            .add_declared_workers(&QueueConfigs::default())
+            .with_drain_timeout(drain_timeout)
+            .start(async move {
+                let _ = shutdown_rx.changed().await;
+                budget
+            })
+            .await
+            .expect("pool to start");
+
+        progress.started.notified().await;
+        shutdown_tx.send(()).unwrap();
+
+        let shutdown_timeout = drain_timeout + DEFAULT_WORKER_SHUTDOWN_TIMEOUT;
+        timeout(budget.unwrap_or(shutdown_timeout), pool_handle)
+            .await
+            .expect("pool to stop within the shutdown timeout")
+            .expect("pool shutdown to not panic");
+    }
+
+    #[test]
+    fn test_execution_timeout_override() {
+        let pool = WorkerPool::new(TestJobStore::default(), slow_job_progress)
+            .register_job_type::<SlowJob>()
+            .with_execution_timeout::<SlowJob>(Duration::from_secs(600));
+
+        let registered_job = &pool.job_registry[SlowJob::JOB_NAME];
+        assert_eq!(registered_job.execution_timeout(), Duration::from_secs(600));
+    }
+
+    #[tokio::test]
+    async fn test_job_types_on_a_queue_share_its_workers() {
+        let progress = slow_job_progress();
+        let store = TestJobStore::with_jobs([stored_job(QuickJob).await]);
+        let declared: QueueConfigs = "default=3".parse().unwrap();
+
+        let context = progress.clone();
+        let pool = WorkerPool::new(store.clone(), move || context.clone())
+            .register_job_type::<SlowJob>()
+            .register_job_type::<QuickJob>()
+            .add_declared_workers(&declared);
+
+        // The declared count covers every job type on the queue, not each of them
+        assert_eq!(pool.worker_count(), 3);
+
+        let (shutdown_tx, mut shutdown_rx) = watch::channel(());
+        let pool_handle = pool
+            .start(async move {
+                let _ = shutdown_rx.changed().await;
+                None
+            })
+            .await
+            .expect("pool to start");
+
+        // The quick job is run with the part of the pool's context it needs
+        timeout(Duration::from_secs(5), progress.started.notified())
+            .await
+            .expect("quick job to run");
+        shutdown_tx.send(()).unwrap();
+
+        timeout(Duration::from_secs(5), pool_handle)
+            .await
+            .expect("pool to stop")
+            .expect("pool shutdown to not panic");
+
+        let updated = store.updated();
+        assert_eq!(updated.len(), 1);
+        assert!(matches!(updated[0].1, BackgroundJobState::Complete));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_in_progress_job() {
+        let progress = slow_job_progress();
+        let store = TestJobStore::with_jobs([stored_job(SlowJob).await]);
+
+        shutdown_during_slow_job(
+            store.clone(),
+            progress.clone(),
+            Duration::from_secs(2),
+            None,
+        )
+        .await;
+
+        assert!(progress.finished.load(Ordering::SeqCst));
+        assert!(store.released().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_releases_jobs_past_drain_timeout() {
+        let progress = slow_job_progress();
+        let job = stored_job(SlowJob).await;
+        let job_id = job.id();
+        let store = TestJobStore::with_jobs([job]);
+
+        shutdown_during_slow_job(
+            store.clone(),
+            progress.clone(),
+            Duration::from_millis(50),
+            None,
+        )
+        .await;
+
+        assert!(!progress.finished.load(Ordering::SeqCst));
+        assert_eq!(store.released(), vec![job_id]);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_budget_caps_drain_timeout() {
+        let progress = slow_job_progress();
+        let job = stored_job(SlowJob).await;
+        let job_id = job.id();
+        let store = TestJobStore::with_jobs([job]);
+
+        // The drain timeout would let the job finish but the budget leaves no time for it
+        shutdown_during_slow_job(
+            store.clone(),
+            progress.clone(),
+            Duration::from_secs(2),
+            Some(Duration::from_millis(250)),
+        )
+        .await;
+
+        assert!(!progress.finished.load(Ordering::SeqCst));
+        assert_eq!(store.released(), vec![job_id]);
+    }
+}
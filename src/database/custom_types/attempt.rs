@@ -5,7 +5,7 @@ use sqlx::error::BoxDynError;
 use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
 use sqlx::{Decode, Encode, Sqlite, Type};
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 #[serde(transparent)]
 pub struct Attempt(usize);
 
@@ -14,6 +14,10 @@ impl Attempt {
         Self(self.0 + 1)
     }
 
+    pub fn number(self) -> usize {
+        self.0
+    }
+
     pub fn zero() -> Self {
         Self(0)
     }
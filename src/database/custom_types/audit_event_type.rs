@@ -0,0 +1,123 @@
+use std::fmt::{self, Display, Formatter};
+
+use serde::{Serialize, Serializer};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Decode, Encode, Sqlite, Type};
+
+/// The security relevant actions recorded in the audit log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditEventType {
+    /// A user replaced one of their API keys with a newly generated one
+    ApiKeyRotated,
+
+    Login,
+
+    /// A login that was refused because the account was temporarily locked after repeated failures
+    LoginLocked,
+
+    Logout,
+
+    /// A new account was created, recorded shortly after the fact by a background job
+    Registration,
+
+    SessionCreated,
+
+    /// A user ended one of their sessions from the session list, usually one on another device
+    SessionRevoked,
+}
+
+impl Decode<'_, Sqlite> for AuditEventType {
+    fn decode(value: SqliteValueRef<'_>) -> Result<Self, BoxDynError> {
+        let inner_val = <&str as Decode<Sqlite>>::decode(value)?;
+        Self::try_from(inner_val).map_err(Into::into)
+    }
+}
+
+impl Encode<'_, Sqlite> for AuditEventType {
+    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'_>>) -> IsNull {
+        args.push(SqliteArgumentValue::Text(self.to_string().into()));
+        IsNull::No
+    }
+}
+
+impl Serialize for AuditEventType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Type<Sqlite> for AuditEventType {
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <&str as Type<Sqlite>>::compatible(ty)
+    }
+
+    fn type_info() -> SqliteTypeInfo {
+        <&str as Type<Sqlite>>::type_info()
+    }
+}
+
+impl Display for AuditEventType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            AuditEventType::ApiKeyRotated => "api_key_rotated",
+            AuditEventType::Login => "login",
+            AuditEventType::LoginLocked => "login_locked",
+            AuditEventType::Logout => "logout",
+            AuditEventType::Registration => "registration",
+            AuditEventType::SessionCreated => "session_created",
+            AuditEventType::SessionRevoked => "session_revoked",
+        };
+
+        f.write_str(msg)
+    }
+}
+
+impl TryFrom<&str> for AuditEventType {
+    type Error = AuditEventTypeError;
+
+    fn try_from(val: &str) -> Result<Self, AuditEventTypeError> {
+        let variant = match val {
+            "api_key_rotated" => AuditEventType::ApiKeyRotated,
+            "login" => AuditEventType::Login,
+            "login_locked" => AuditEventType::LoginLocked,
+            "logout" => AuditEventType::Logout,
+            "registration" => AuditEventType::Registration,
+            "session_created" => AuditEventType::SessionCreated,
+            "session_revoked" => AuditEventType::SessionRevoked,
+            _ => return Err(AuditEventTypeError::InvalidValue(val.to_string())),
+        };
+
+        Ok(variant)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditEventTypeError {
+    #[error("attempted to decode unknown audit event type '{0}'")]
+    InvalidValue(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_roundtripping() {
+        for event in [
+            AuditEventType::ApiKeyRotated,
+            AuditEventType::Login,
+            AuditEventType::LoginLocked,
+            AuditEventType::Logout,
+            AuditEventType::Registration,
+            AuditEventType::SessionCreated,
+            AuditEventType::SessionRevoked,
+        ] {
+            let encoded = event.to_string();
+            assert_eq!(AuditEventType::try_from(encoded.as_str()).unwrap(), event);
+        }
+
+        assert!(AuditEventType::try_from("unknown").is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt::{self, Display, Formatter};
+
+use serde::{Serialize, Serializer};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Decode, Encode, Sqlite, Type};
+
+/// Whether the action recorded in an audit log entry was allowed to complete.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+impl Decode<'_, Sqlite> for AuditOutcome {
+    fn decode(value: SqliteValueRef<'_>) -> Result<Self, BoxDynError> {
+        let inner_val = <&str as Decode<Sqlite>>::decode(value)?;
+        Self::try_from(inner_val).map_err(Into::into)
+    }
+}
+
+impl Encode<'_, Sqlite> for AuditOutcome {
+    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'_>>) -> IsNull {
+        args.push(SqliteArgumentValue::Text(self.to_string().into()));
+        IsNull::No
+    }
+}
+
+impl Serialize for AuditOutcome {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Type<Sqlite> for AuditOutcome {
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <&str as Type<Sqlite>>::compatible(ty)
+    }
+
+    fn type_info() -> SqliteTypeInfo {
+        <&str as Type<Sqlite>>::type_info()
+    }
+}
+
+impl Display for AuditOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            AuditOutcome::Success => "success",
+            AuditOutcome::Failure => "failure",
+        };
+
+        f.write_str(msg)
+    }
+}
+
+impl TryFrom<&str> for AuditOutcome {
+    type Error = AuditOutcomeError;
+
+    fn try_from(val: &str) -> Result<Self, AuditOutcomeError> {
+        let variant = match val {
+            "success" => AuditOutcome::Success,
+            "failure" => AuditOutcome::Failure,
+            _ => return Err(AuditOutcomeError::InvalidValue(val.to_string())),
+        };
+
+        Ok(variant)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditOutcomeError {
+    #[error("attempted to decode unknown audit outcome '{0}'")]
+    InvalidValue(String),
+}
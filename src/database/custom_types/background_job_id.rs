@@ -5,7 +5,7 @@ use uuid::Uuid;
 
 use crate::database::custom_types::Did;
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize, sqlx::Type)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, sqlx::Type)]
 #[sqlx(transparent)]
 pub struct BackgroundJobId(Did);
 
@@ -9,9 +9,28 @@ use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
 use sqlx::{Decode, Encode, Sqlite, Type};
 use uuid::Uuid;
 
+/// The database identifier backing all of the typed ID wrappers (such as
+/// [`crate::database::custom_types::UserId`]). Despite the name this isn't a decentralized
+/// identifier, it is a random UUID that is stored in its 16 byte little-endian form in `BLOB`
+/// columns.
+///
+/// Rows normally receive their identifier from the database's `randomblob(16)` column default.
+/// When an identifier needs to be known before a row is written, [`Did::generate`] can be used
+/// instead. Identifiers that arrive as text (path segments, log lines, admin tooling) can be parsed
+/// back with [`Did::try_from`], which accepts the same hyphenated form [`Display`] produces.
+///
+/// This is re-exported from the crate root so code built on top of the template can create and
+/// store identifiers compatible with the existing tables.
 #[derive(Clone, Copy, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct Did(Uuid);
 
+impl Did {
+    /// Creates a new random identifier.
+    pub fn generate() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
 impl Decode<'_, Sqlite> for Did {
     fn decode(value: SqliteValueRef<'_>) -> Result<Self, BoxDynError> {
         let inner_val = <Vec<u8> as Decode<Sqlite>>::decode(value)?;
@@ -73,10 +92,29 @@ impl From<Uuid> for Did {
     }
 }
 
+impl From<Did> for Uuid {
+    fn from(val: Did) -> Self {
+        val.0
+    }
+}
+
+impl TryFrom<&str> for Did {
+    type Error = DidError;
+
+    fn try_from(val: &str) -> Result<Self, DidError> {
+        Uuid::parse_str(val)
+            .map(Self)
+            .map_err(DidError::InvalidText)
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum DidError {
     #[error("the UUID representation doesn't contain the correct number of bytes")]
     CorruptSize,
+
+    #[error("the text isn't a valid UUID representation: {0}")]
+    InvalidText(uuid::Error),
 }
 
 #[cfg(test)]
@@ -92,6 +130,25 @@ mod test {
     // unfortunately means that I need to test _into_ their interface to ensure they're behaving
     // the way the code in this repository expects.
 
+    #[test]
+    fn test_generation() {
+        let first = Did::generate();
+        let second = Did::generate();
+
+        assert_ne!(first, second);
+        assert_eq!(first.get_version(), Some(uuid::Version::Random));
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let did = Did::generate();
+        let parsed = Did::try_from(did.to_string().as_str()).expect("valid text");
+        assert_eq!(parsed, did);
+
+        let err = Did::try_from("not-a-did").expect_err("invalid text");
+        assert!(matches!(err, DidError::InvalidText(_)));
+    }
+
     #[tokio::test]
     async fn test_sqlx_decoding() {
         let db_pool = test_database().await;
@@ -1,7 +1,82 @@
+use std::fmt::{self, Display, Formatter};
+
+use jwt_simple::prelude::ES384PublicKey;
+use sha2::{Digest, Sha256};
+
+/// Identifies a public key by the SHA-256 digest of its compressed point. This is the key ID used
+/// in the header of tokens signed by the key and is written out as lowercase hex.
+///
+/// Both the service key and user API keys are identified this way. Signing sets the key ID with
+/// [`Fingerprint::from_public_key`] and verification reads it back with
+/// [`Fingerprint::from_key_id`], neither side should derive or parse key IDs any other way.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Fingerprint([u8; 32]);
 
 impl Fingerprint {
-    pub fn from_hex_str(hex: &str) -> Result<Self, &str> {
-        todo!()
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn from_hex_str(hex: &str) -> Result<Self, FingerprintError> {
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(hex, &mut bytes).map_err(FingerprintError::InvalidHex)?;
+        Ok(Self(bytes))
+    }
+
+    /// Parses the key ID from a token header. Only the exact form produced when signing is
+    /// accepted, so a key ID that differs in case is rejected rather than quietly matching.
+    pub fn from_key_id(key_id: &str) -> Result<Self, FingerprintError> {
+        let fingerprint = Self::from_hex_str(key_id)?;
+
+        if fingerprint.to_string() != key_id {
+            return Err(FingerprintError::NonCanonicalKeyId);
+        }
+
+        Ok(fingerprint)
+    }
+
+    pub fn from_public_key(public_key: &ES384PublicKey) -> Self {
+        Self(Sha256::digest(public_key.to_bytes()).into())
+    }
+}
+
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FingerprintError {
+    #[error("fingerprint wasn't 32 bytes of hex: {0}")]
+    InvalidHex(hex::FromHexError),
+
+    #[error("key ID wasn't written as lowercase hex")]
+    NonCanonicalKeyId,
+}
+
+#[cfg(test)]
+mod tests {
+    use jwt_simple::prelude::ES384KeyPair;
+
+    use super::*;
+
+    #[test]
+    fn test_key_id_round_trip() {
+        let public_key = ES384KeyPair::generate().public_key();
+        let fingerprint = Fingerprint::from_public_key(&public_key);
+
+        let key_id = fingerprint.to_string();
+        assert_eq!(key_id.len(), 64);
+        assert_eq!(Fingerprint::from_key_id(&key_id).unwrap(), fingerprint);
+
+        assert!(Fingerprint::from_key_id(&key_id[1..]).is_err());
+
+        let mixed_case = "Ab".repeat(32);
+        assert!(Fingerprint::from_hex_str(&mixed_case).is_ok());
+        assert!(matches!(
+            Fingerprint::from_key_id(&mixed_case),
+            Err(FingerprintError::NonCanonicalKeyId)
+        ));
     }
 }
@@ -7,6 +7,7 @@ use sqlx::error::BoxDynError;
 use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
 use sqlx::{Decode, Encode, Sqlite, Type};
 
+use crate::auth::{GithubProfileProvider, GoogleProfileProvider};
 use crate::database::custom_types::LoginProviderConfig;
 
 static LOGIN_PROVIDER_CONFIGS: phf::Map<u8, LoginProviderConfig> = phf::phf_map! {
@@ -18,12 +19,23 @@ static LOGIN_PROVIDER_CONFIGS: phf::Map<u8, LoginProviderConfig> = phf::phf_map!
             "https://www.googleapis.com/auth/userinfo.email",
             "https://www.googleapis.com/auth/userinfo.profile"
         ],
+        &GoogleProfileProvider,
+    ),
+    // GitHub only supports revoking tokens through its REST API using the app's credentials rather
+    // than a standard revocation endpoint
+    2u8 => LoginProviderConfig::new(
+        "https://github.com/login/oauth/authorize",
+        Some("https://github.com/login/oauth/access_token"),
+        None,
+        &["read:user", "user:email"],
+        &GithubProfileProvider,
     ),
 };
 
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum LoginProvider {
+    Github,
     Google,
 }
 
@@ -31,6 +43,7 @@ impl LoginProvider {
     pub fn as_u8(&self) -> u8 {
         match &self {
             LoginProvider::Google => 1,
+            LoginProvider::Github => 2,
         }
     }
 
@@ -42,6 +55,7 @@ impl LoginProvider {
 
     pub fn parse_str(val: &str) -> Result<Self, LoginProviderError> {
         match val {
+            "github" => Ok(LoginProvider::Github),
             "google" => Ok(LoginProvider::Google),
             _ => Err(LoginProviderError::UnknownProvider),
         }
@@ -75,6 +89,7 @@ impl Type<Sqlite> for LoginProvider {
 impl Display for LoginProvider {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let msg = match &self {
+            LoginProvider::Github => "github",
             LoginProvider::Google => "google",
         };
 
@@ -105,6 +120,21 @@ mod test {
     // unfortunately means that I need to test _into_ their interface to ensure they're behaving
     // the way the code in this repository expects.
 
+    #[test]
+    fn test_parsing_provider_names() {
+        assert_eq!(
+            LoginProvider::parse_str("github").unwrap(),
+            LoginProvider::Github
+        );
+        assert_eq!(
+            LoginProvider::parse_str("google").unwrap(),
+            LoginProvider::Google
+        );
+        assert_eq!(LoginProvider::Github.to_string(), "github");
+
+        assert!(LoginProvider::parse_str("GitHub").is_err());
+    }
+
     #[tokio::test]
     async fn test_sqlx_decoding() {
         let db_pool = test_database().await;
@@ -1,10 +1,13 @@
 use oauth2::{AuthUrl, RevocationUrl, TokenUrl};
 
+use crate::auth::ProfileProvider;
+
 pub struct LoginProviderConfig {
     auth_url: &'static str,
     token_url: Option<&'static str>,
     revocation_url: Option<&'static str>,
     scopes: &'static [&'static str],
+    profile_provider: &'static dyn ProfileProvider,
 }
 
 impl LoginProviderConfig {
@@ -17,15 +20,22 @@ impl LoginProviderConfig {
         token_url: Option<&'static str>,
         revocation_url: Option<&'static str>,
         scopes: &'static [&'static str],
+        profile_provider: &'static dyn ProfileProvider,
     ) -> Self {
         Self {
             auth_url,
             token_url,
             revocation_url,
             scopes,
+            profile_provider,
         }
     }
 
+    /// Looks up who an access token issued by this provider belongs to.
+    pub fn profile_provider(&self) -> &'static dyn ProfileProvider {
+        self.profile_provider
+    }
+
     pub fn revocation_url(&self) -> Option<RevocationUrl> {
         self.revocation_url.map(|ru| {
             RevocationUrl::new(ru.to_string()).expect("static revocation url to be valid")
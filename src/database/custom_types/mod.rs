@@ -2,34 +2,46 @@
 
 mod api_key_id;
 mod attempt;
+mod audit_event_type;
+mod audit_log_id;
+mod audit_outcome;
 mod background_job_id;
 mod background_job_state;
 mod background_run_id;
 mod background_run_state;
 mod db_bool;
 mod did;
+mod embedding_id;
 mod fingerprint;
 mod login_provider;
 mod login_provider_config;
 mod oauth_provider_account_id;
+mod payload_encoding;
 mod provider_id;
+mod sealed_token;
 mod session_id;
 mod unique_task_key;
 mod user_id;
 
 pub use api_key_id::ApiKeyId;
 pub use attempt::Attempt;
+pub use audit_event_type::{AuditEventType, AuditEventTypeError};
+pub use audit_log_id::AuditLogId;
+pub use audit_outcome::{AuditOutcome, AuditOutcomeError};
 pub use background_job_id::BackgroundJobId;
 pub use background_job_state::{BackgroundJobState, BackgroundJobStateError};
 pub use background_run_id::BackgroundRunId;
 pub use background_run_state::{BackgroundRunState, BackgroundRunStateError};
 pub use db_bool::{DbBool, DbBoolError};
 pub use did::{Did, DidError};
-pub use fingerprint::Fingerprint;
+pub use embedding_id::EmbeddingId;
+pub use fingerprint::{Fingerprint, FingerprintError};
 pub use login_provider::{LoginProvider, LoginProviderError};
 pub use login_provider_config::LoginProviderConfig;
 pub use oauth_provider_account_id::{OAuthProviderAccountId, OAuthProviderAccountIdError};
+pub use payload_encoding::{PayloadEncoding, PayloadEncodingError};
 pub use provider_id::ProviderId;
+pub use sealed_token::SealedToken;
 pub use session_id::SessionId;
 pub use unique_task_key::{UniqueTaskKey, UniqueTaskKeyError};
 pub use user_id::{UserId, UserIdError};
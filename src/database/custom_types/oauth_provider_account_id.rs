@@ -5,7 +5,7 @@ use uuid::Uuid;
 use crate::database::custom_types::{Did, LoginProvider, ProviderId};
 use crate::database::DatabaseConnection;
 
-#[derive(Clone, Copy, Debug, sqlx::Type)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, sqlx::Type)]
 #[sqlx(transparent)]
 pub struct OAuthProviderAccountId(Did);
 
@@ -0,0 +1,163 @@
+use std::fmt::{self, Display, Formatter};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Decode, Encode, Sqlite, Type};
+
+/// The format a background job's payload is serialized with. This is recorded alongside each job
+/// so payloads can always be decoded with the format they were written in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PayloadEncoding {
+    /// Larger and slower to parse, but readable when inspecting the jobs table directly
+    #[default]
+    Json,
+
+    /// A compact binary encoding, better suited to large payloads and high volume queues
+    MessagePack,
+}
+
+impl PayloadEncoding {
+    pub fn decode_payload<T: DeserializeOwned>(
+        &self,
+        payload: &[u8],
+    ) -> Result<T, PayloadEncodingError> {
+        match self {
+            PayloadEncoding::Json => {
+                serde_json::from_slice(payload).map_err(PayloadEncodingError::InvalidJson)
+            }
+            PayloadEncoding::MessagePack => {
+                rmp_serde::from_slice(payload).map_err(PayloadEncodingError::InvalidMessagePack)
+            }
+        }
+    }
+
+    pub fn encode_payload<T: Serialize>(
+        &self,
+        payload: &T,
+    ) -> Result<Vec<u8>, PayloadEncodingError> {
+        match self {
+            PayloadEncoding::Json => {
+                serde_json::to_vec(payload).map_err(PayloadEncodingError::JsonEncoding)
+            }
+            PayloadEncoding::MessagePack => {
+                rmp_serde::to_vec_named(payload).map_err(PayloadEncodingError::MessagePackEncoding)
+            }
+        }
+    }
+}
+
+impl Decode<'_, Sqlite> for PayloadEncoding {
+    fn decode(value: SqliteValueRef<'_>) -> Result<Self, BoxDynError> {
+        let inner_val = <&str as Decode<Sqlite>>::decode(value)?;
+        Self::try_from(inner_val).map_err(Into::into)
+    }
+}
+
+impl Encode<'_, Sqlite> for PayloadEncoding {
+    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'_>>) -> IsNull {
+        args.push(SqliteArgumentValue::Text(self.to_string().into()));
+        IsNull::No
+    }
+}
+
+impl Type<Sqlite> for PayloadEncoding {
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <&str as Type<Sqlite>>::compatible(ty)
+    }
+
+    fn type_info() -> SqliteTypeInfo {
+        <&str as Type<Sqlite>>::type_info()
+    }
+}
+
+impl Display for PayloadEncoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            PayloadEncoding::Json => "json",
+            PayloadEncoding::MessagePack => "msgpack",
+        };
+
+        f.write_str(msg)
+    }
+}
+
+impl TryFrom<&str> for PayloadEncoding {
+    type Error = PayloadEncodingError;
+
+    fn try_from(val: &str) -> Result<Self, PayloadEncodingError> {
+        let variant = match val {
+            "json" => PayloadEncoding::Json,
+            "msgpack" => PayloadEncoding::MessagePack,
+            _ => return Err(PayloadEncodingError::InvalidValue(val.to_string())),
+        };
+
+        Ok(variant)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PayloadEncodingError {
+    #[error("payload was not valid JSON: {0}")]
+    InvalidJson(serde_json::Error),
+
+    #[error("payload was not valid MessagePack: {0}")]
+    InvalidMessagePack(rmp_serde::decode::Error),
+
+    #[error("attempted to decode unknown payload encoding '{0}'")]
+    InvalidValue(String),
+
+    #[error("failed to encode payload as JSON: {0}")]
+    JsonEncoding(serde_json::Error),
+
+    #[error("failed to encode payload as MessagePack: {0}")]
+    MessagePackEncoding(rmp_serde::encode::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Payload {
+        texts: Vec<String>,
+        count: u64,
+        ratio: f32,
+        note: Option<String>,
+    }
+
+    fn sample() -> Payload {
+        Payload {
+            texts: vec!["first".to_string(), "second".to_string()],
+            count: u64::MAX,
+            ratio: 0.25,
+            note: None,
+        }
+    }
+
+    #[test]
+    fn test_payload_roundtrips() {
+        for encoding in [PayloadEncoding::Json, PayloadEncoding::MessagePack] {
+            let encoded = encoding.encode_payload(&sample()).unwrap();
+            let decoded: Payload = encoding.decode_payload(&encoded).unwrap();
+            assert_eq!(decoded, sample(), "{encoding} roundtrip");
+
+            let name = encoding.to_string();
+            assert_eq!(PayloadEncoding::try_from(name.as_str()).unwrap(), encoding);
+        }
+    }
+
+    #[test]
+    fn test_encodings_are_not_interchangeable() {
+        let encoded = PayloadEncoding::MessagePack
+            .encode_payload(&sample())
+            .unwrap();
+        assert!(PayloadEncoding::Json
+            .decode_payload::<Payload>(&encoded)
+            .is_err());
+    }
+}
@@ -0,0 +1,17 @@
+/// A token issued by a login provider that has been encrypted with the service's
+/// [`TokenCipher`](crate::app::TokenCipher). Provider tokens are only ever stored in this form.
+#[derive(Clone, Debug, Eq, PartialEq, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct SealedToken(String);
+
+impl SealedToken {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SealedToken {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
@@ -6,7 +6,7 @@ use uuid::Uuid;
 use crate::database::custom_types::Did;
 use crate::database::DatabaseConnection;
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize, sqlx::Type)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, sqlx::Type)]
 #[sqlx(transparent)]
 pub struct UserId(Did);
 
@@ -37,6 +37,12 @@ impl From<Uuid> for UserId {
     }
 }
 
+impl From<UserId> for Uuid {
+    fn from(val: UserId) -> Self {
+        Uuid::from(val.0)
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum UserIdError {
     #[error("failed to lookup user ID: {0}")]
@@ -0,0 +1,185 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::task::JoinHandle;
+
+use crate::database::Database;
+use crate::ShutdownSignal;
+
+/// How often the database is checked while it is behaving.
+const HEALTHY_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The first retry after a failure, each consecutive failure doubles this up to the maximum.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+const MAXIMUM_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Individual checks that take longer than this are treated as failures. A healthy SQLite
+/// database should answer these nearly instantly.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The number of consecutive failed checks before the database is considered unavailable. A single
+/// failure is common enough during heavy write contention that it shouldn't pull the service out
+/// of rotation.
+const UNAVAILABLE_THRESHOLD: u32 = 3;
+
+/// Tracks whether the database has been usable recently. A background monitor periodically checks
+/// it, backing off while it is failing, so the service reports itself as not ready while the
+/// database is inaccessible and recovers on its own once it is usable again. The pool replaces
+/// broken connections on its own when they're next acquired, the checks are what drive that to
+/// happen while nothing else is using the database.
+#[derive(Clone)]
+pub struct DatabaseHealth {
+    report: Arc<Mutex<HealthReport>>,
+}
+
+impl DatabaseHealth {
+    fn backoff(&self) -> Duration {
+        let failures = self.report().consecutive_failures;
+        if failures == 0 {
+            return HEALTHY_CHECK_INTERVAL;
+        }
+
+        let multiplier = 2u32.saturating_pow(failures - 1);
+        INITIAL_RETRY_DELAY
+            .saturating_mul(multiplier)
+            .min(MAXIMUM_RETRY_DELAY)
+    }
+
+    pub async fn check(&self, database: &Database) {
+        let query = sqlx::query("SELECT 1;").fetch_one(&**database);
+
+        match tokio::time::timeout(CHECK_TIMEOUT, query).await {
+            Ok(Ok(_)) => self.record_success(),
+            Ok(Err(err)) => self.record_failure(err.to_string()),
+            Err(_) => self.record_failure("check timed out".to_string()),
+        }
+    }
+
+    /// Checks the database until the shutdown signal is received.
+    pub fn monitor(&self, database: Database, mut shutdown_rx: ShutdownSignal) -> JoinHandle<()> {
+        let health = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                health.check(&database).await;
+
+                tokio::select! {
+                    _ = shutdown_rx.changed() => break,
+                    _ = tokio::time::sleep(health.backoff()) => {},
+                }
+            }
+        })
+    }
+
+    pub fn new() -> Self {
+        Self {
+            report: Arc::new(Mutex::new(HealthReport::default())),
+        }
+    }
+
+    fn record_failure(&self, error: String) {
+        let mut report = self.report.lock().expect("lock to not be poisoned");
+        report.consecutive_failures = report.consecutive_failures.saturating_add(1);
+
+        let previous_status = report.status;
+        report.status = if report.consecutive_failures >= UNAVAILABLE_THRESHOLD {
+            DatabaseStatus::Unavailable
+        } else {
+            DatabaseStatus::Degraded
+        };
+
+        if report.status == DatabaseStatus::Unavailable && previous_status != report.status {
+            tracing::error!(%error, "database is unavailable");
+        } else {
+            tracing::warn!(%error, failures = report.consecutive_failures, "database check failed");
+        }
+
+        report.last_error = Some(error);
+    }
+
+    fn record_success(&self) {
+        let mut report = self.report.lock().expect("lock to not be poisoned");
+
+        if report.status == DatabaseStatus::Unavailable {
+            tracing::info!("database has recovered");
+        }
+
+        report.consecutive_failures = 0;
+        report.last_error = None;
+        report.last_success_at = Some(OffsetDateTime::now_utc());
+        report.status = DatabaseStatus::Healthy;
+    }
+
+    pub fn report(&self) -> HealthReport {
+        self.report.lock().expect("lock to not be poisoned").clone()
+    }
+
+    pub fn status(&self) -> DatabaseStatus {
+        self.report().status
+    }
+}
+
+impl Default for DatabaseHealth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct HealthReport {
+    pub status: DatabaseStatus,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_success_at: Option<OffsetDateTime>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatabaseStatus {
+    /// The most recent check succeeded, or no check has failed yet
+    #[default]
+    Healthy,
+
+    /// Recent checks have failed but not enough of them to give up on the database
+    Degraded,
+
+    /// Enough consecutive checks have failed that the database should be considered unusable
+    Unavailable,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_transitions_and_backoff() {
+        let health = DatabaseHealth::new();
+        assert_eq!(health.status(), DatabaseStatus::Healthy);
+        assert_eq!(health.backoff(), HEALTHY_CHECK_INTERVAL);
+
+        health.record_failure("disk full".to_string());
+        assert_eq!(health.status(), DatabaseStatus::Degraded);
+        assert_eq!(health.backoff(), INITIAL_RETRY_DELAY);
+
+        health.record_failure("disk full".to_string());
+        health.record_failure("disk full".to_string());
+        assert_eq!(health.status(), DatabaseStatus::Unavailable);
+        assert_eq!(health.backoff(), INITIAL_RETRY_DELAY * 4);
+
+        for _ in 0..20 {
+            health.record_failure("disk full".to_string());
+        }
+        assert_eq!(health.backoff(), MAXIMUM_RETRY_DELAY);
+
+        health.record_success();
+        let report = health.report();
+        assert_eq!(report.status, DatabaseStatus::Healthy);
+        assert_eq!(report.consecutive_failures, 0);
+        assert!(report.last_error.is_none());
+        assert!(report.last_success_at.is_some());
+    }
+}
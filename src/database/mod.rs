@@ -2,14 +2,28 @@ pub mod custom_types;
 pub mod models;
 pub mod sqlite;
 
+mod health_monitor;
+
+pub use health_monitor::{DatabaseHealth, DatabaseStatus};
+
 use std::convert::Infallible;
+use std::future::Future;
 use std::ops::Deref;
+use std::time::Duration;
 
 use axum::async_trait;
 use axum::extract::{FromRef, FromRequestParts};
 use http::request::Parts;
 use sqlx::SqlitePool;
 
+use crate::background_jobs::BackoffStrategy;
+
+/// The delay between attempts to set up the database while the service is starting.
+pub const CONNECT_BACKOFF: BackoffStrategy = BackoffStrategy::Exponential {
+    base: Duration::from_millis(500),
+    cap: Duration::from_secs(10),
+};
+
 #[derive(Clone)]
 pub struct Database(SqlitePool);
 
@@ -18,9 +32,7 @@ pub type DatabaseConnection = sqlx::SqliteConnection;
 impl Database {
     pub async fn connect(db_url: &url::Url) -> Result<Self, DatabaseSetupError> {
         // todo: I should figure out a way to delay the actual connection and running of migrations,
-        // and reflect the service being unavailable in the readiness check until they're complete. If
-        // our connection fails we should try a couple of times with a backoff before failing the
-        // entire service...
+        // and reflect the service being unavailable in the readiness check until they're complete.
         //
         // maybe a tokio task with a channel or shared state directly that can be consumed by the
         // healthcheck and database extractor... Maybe this state belongs on the database executor
@@ -37,11 +49,62 @@ impl Database {
         ))
     }
 
+    /// Connects to and migrates the database like [`Database::connect`], trying again after a
+    /// delay when either step fails. Gives up once `max_attempts` have been made, or immediately
+    /// if the database type isn't one we support.
+    pub async fn connect_with_retry(
+        db_url: &url::Url,
+        max_attempts: u8,
+        backoff: BackoffStrategy,
+    ) -> Result<Self, DatabaseSetupError> {
+        retry_setup(max_attempts, backoff, || Self::connect(db_url)).await
+    }
+
     pub fn new(pool: SqlitePool) -> Self {
         Self(pool)
     }
 }
 
+async fn retry_setup<F, Fut>(
+    max_attempts: u8,
+    backoff: BackoffStrategy,
+    mut setup: F,
+) -> Result<Database, DatabaseSetupError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Database, DatabaseSetupError>>,
+{
+    let mut attempt = 1;
+
+    loop {
+        tracing::debug!(attempt, max_attempts, "setting up database");
+
+        match setup().await {
+            Ok(database) => return Ok(database),
+            Err(err @ DatabaseSetupError::UnknownDbType(_)) => return Err(err),
+            Err(err) if attempt >= max_attempts => {
+                tracing::error!(
+                    attempt,
+                    max_attempts,
+                    "database setup failed, giving up: {err}"
+                );
+                return Err(err);
+            }
+            Err(err) => {
+                let delay = backoff.delay(attempt);
+                tracing::warn!(
+                    attempt,
+                    max_attempts,
+                    ?delay,
+                    "database setup failed, retrying: {err}"
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
 impl Deref for Database {
     type Target = SqlitePool;
 
@@ -74,3 +137,35 @@ pub enum DatabaseSetupError {
     #[error("requested database type was not recognized: {0}")]
     UnknownDbType(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_setup_retried_until_attempts_exhausted() {
+        let db_url = url::Url::parse("sqlite:///nonexistent/directory/service.db").unwrap();
+        let backoff = BackoffStrategy::Fixed(Duration::from_millis(1));
+
+        let mut attempts = 0;
+        let result = retry_setup(3, backoff, || {
+            attempts += 1;
+            Database::connect(&db_url)
+        })
+        .await;
+
+        assert!(matches!(result, Err(DatabaseSetupError::Unavailable(_))));
+        assert_eq!(attempts, 3);
+
+        let db_url = url::Url::parse("postgres://localhost/service").unwrap();
+        let mut attempts = 0;
+        let result = retry_setup(3, backoff, || {
+            attempts += 1;
+            Database::connect(&db_url)
+        })
+        .await;
+
+        assert!(matches!(result, Err(DatabaseSetupError::UnknownDbType(_))));
+        assert_eq!(attempts, 1);
+    }
+}
@@ -1,21 +1,195 @@
 use time::OffsetDateTime;
 
 use crate::database::custom_types::{ApiKeyId, Fingerprint, UserId};
+use crate::database::DatabaseConnection;
 
+/// A public key a user has registered to sign their own API tokens with. The key is stored as its
+/// SEC1 encoded point and identified by its [`Fingerprint`].
 #[derive(sqlx::FromRow)]
 pub struct ApiKey {
     id: ApiKeyId,
     user_id: UserId,
 
     name: Option<String>,
+    #[allow(dead_code)]
     fingerprint: Vec<u8>,
     public_key: Vec<u8>,
 
+    #[allow(dead_code)]
     created_at: OffsetDateTime,
+    #[allow(dead_code)]
+    revoked_at: Option<OffsetDateTime>,
 }
 
 impl ApiKey {
-    pub fn from_fingerprint(fingerprint: &Fingerprint) -> Result<ApiKey, &str> {
-        todo!()
+    /// Finds the key with the fingerprint as long as it hasn't been revoked yet, keys that are
+    /// scheduled to be revoked are still returned until that time passes.
+    pub async fn from_fingerprint(
+        conn: &mut DatabaseConnection,
+        fingerprint: &Fingerprint,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let fingerprint = fingerprint.as_bytes();
+        let now = OffsetDateTime::now_utc();
+
+        sqlx::query_as!(
+            Self,
+            r#"SELECT
+                   id as 'id: ApiKeyId',
+                   user_id as 'user_id: UserId',
+                   name,
+                   fingerprint,
+                   public_key,
+                   created_at,
+                   revoked_at
+                 FROM api_keys
+                 WHERE fingerprint = $1
+                   AND (revoked_at IS NULL OR revoked_at > $2);"#,
+            fingerprint,
+            now,
+        )
+        .fetch_optional(&mut *conn)
+        .await
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    #[cfg(test)]
+    pub fn revoked_at(&self) -> Option<OffsetDateTime> {
+        self.revoked_at
+    }
+
+    /// Replaces this key with a new public key under the same name. The current key keeps working
+    /// until `revoke_at`, returns `None` without registering the new key when this key had already
+    /// been scheduled for revocation. This should be run inside a transaction.
+    pub async fn rotate(
+        &self,
+        conn: &mut DatabaseConnection,
+        public_key: &[u8],
+        fingerprint: &Fingerprint,
+        revoke_at: OffsetDateTime,
+    ) -> Result<Option<ApiKeyId>, sqlx::Error> {
+        let revoked = sqlx::query!(
+            "UPDATE api_keys SET revoked_at = $1 WHERE id = $2 AND revoked_at IS NULL;",
+            revoke_at,
+            self.id,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        if revoked.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        let fingerprint = fingerprint.as_bytes();
+        let new_id = sqlx::query_scalar!(
+            r#"INSERT INTO api_keys (user_id, name, fingerprint, public_key)
+                   VALUES ($1, $2, $3, $4)
+                   RETURNING id as 'id: ApiKeyId';"#,
+            self.user_id,
+            self.name,
+            fingerprint,
+            public_key,
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(Some(new_id))
+    }
+
+    pub fn user_id(&self) -> UserId {
+        self.user_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use jwt_simple::prelude::*;
+
+    use super::*;
+    use crate::database::models::CreateUser;
+    use crate::tests::prelude::*;
+
+    fn generate_key() -> (Vec<u8>, Fingerprint) {
+        let public_key = ES384KeyPair::generate().public_key();
+        (
+            public_key.to_bytes(),
+            Fingerprint::from_public_key(&public_key),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_rotated_key_valid_until_revoked() {
+        let pool = migrated_test_database().await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        let user_id = CreateUser::new("keys@example.com", "Key User")
+            .save(&mut conn)
+            .await
+            .unwrap();
+
+        let (public_key, old_fingerprint) = generate_key();
+        sqlx::query("INSERT INTO api_keys (user_id, name, fingerprint, public_key) VALUES ($1, 'ci', $2, $3);")
+            .bind(user_id)
+            .bind(old_fingerprint.as_bytes())
+            .bind(&public_key)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+
+        let old_key = ApiKey::from_fingerprint(&mut conn, &old_fingerprint)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // The old key keeps working during the grace window alongside the new one
+        let (new_public_key, new_fingerprint) = generate_key();
+        let grace_ends = OffsetDateTime::now_utc() + Duration::from_secs(300);
+        old_key
+            .rotate(&mut conn, &new_public_key, &new_fingerprint, grace_ends)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(ApiKey::from_fingerprint(&mut conn, &old_fingerprint)
+            .await
+            .unwrap()
+            .is_some());
+        let new_key = ApiKey::from_fingerprint(&mut conn, &new_fingerprint)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(new_key.user_id(), user_id);
+        assert!(new_key.revoked_at().is_none());
+
+        // A key can only be rotated once
+        let (another_public_key, another_fingerprint) = generate_key();
+        let rotated_again = old_key
+            .rotate(
+                &mut conn,
+                &another_public_key,
+                &another_fingerprint,
+                grace_ends,
+            )
+            .await
+            .unwrap();
+        assert!(rotated_again.is_none());
+
+        // Once the grace window has passed the old key is no longer found
+        let grace_ended = OffsetDateTime::now_utc() - Duration::from_secs(1);
+        sqlx::query("UPDATE api_keys SET revoked_at = $1 WHERE fingerprint = $2;")
+            .bind(grace_ended)
+            .bind(old_fingerprint.as_bytes())
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+
+        assert!(ApiKey::from_fingerprint(&mut conn, &old_fingerprint)
+            .await
+            .unwrap()
+            .is_none());
     }
 }
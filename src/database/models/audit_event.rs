@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::database::custom_types::{AuditEventType, AuditLogId, AuditOutcome, UserId};
+use crate::database::DatabaseConnection;
+use crate::extractors::Requestor;
+
+/// A record of a security relevant action. These are kept for compliance and incident
+/// investigation and are never modified once written.
+pub struct CreateAuditEvent {
+    user_id: Option<UserId>,
+
+    event: AuditEventType,
+    outcome: AuditOutcome,
+
+    client_ip: Option<String>,
+    user_agent: Option<String>,
+    details: Option<String>,
+}
+
+impl CreateAuditEvent {
+    pub fn failure(event: AuditEventType, requestor: &Requestor) -> Self {
+        Self::new(event, AuditOutcome::Failure, requestor)
+    }
+
+    fn new(event: AuditEventType, outcome: AuditOutcome, requestor: &Requestor) -> Self {
+        Self {
+            user_id: None,
+
+            event,
+            outcome,
+
+            client_ip: requestor.client_ip().map(|ip| ip.to_string()),
+            user_agent: requestor.user_agent().map(String::from),
+            details: None,
+        }
+    }
+
+    pub async fn save(self, conn: &mut DatabaseConnection) -> Result<AuditLogId, AuditEventError> {
+        sqlx::query_scalar!(
+            r#"INSERT INTO audit_log (user_id, event, outcome, client_ip, user_agent, details)
+                   VALUES ($1, $2, $3, $4, $5, $6)
+                   RETURNING id as 'id: AuditLogId';"#,
+            self.user_id,
+            self.event,
+            self.outcome,
+            self.client_ip,
+            self.user_agent,
+            self.details,
+        )
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(AuditEventError::SaveFailed)
+    }
+
+    pub fn success(event: AuditEventType, requestor: &Requestor) -> Self {
+        Self::new(event, AuditOutcome::Success, requestor)
+    }
+
+    /// An event the service records on its own, outside of any request, so there are no client
+    /// details to include.
+    pub fn unattended(event: AuditEventType) -> Self {
+        Self {
+            user_id: None,
+
+            event,
+            outcome: AuditOutcome::Success,
+
+            client_ip: None,
+            user_agent: None,
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: impl Into<String>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+
+    pub fn with_user(mut self, user_id: UserId) -> Self {
+        self.user_id = Some(user_id);
+        self
+    }
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct AuditEvent {
+    id: AuditLogId,
+    user_id: Option<UserId>,
+
+    event: AuditEventType,
+    outcome: AuditOutcome,
+
+    client_ip: Option<String>,
+    user_agent: Option<String>,
+    details: Option<String>,
+
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+}
+
+impl AuditEvent {
+    /// The most recent audit events, newest first, optionally restricted to a single user.
+    pub async fn recent(
+        conn: &mut DatabaseConnection,
+        user_id: Option<UserId>,
+        limit: u32,
+    ) -> Result<Vec<Self>, AuditEventError> {
+        sqlx::query_as!(
+            AuditEvent,
+            r#"SELECT id as 'id: AuditLogId', user_id as 'user_id: UserId',
+                      event as 'event: AuditEventType', outcome as 'outcome: AuditOutcome',
+                      client_ip, user_agent, details, created_at
+                   FROM audit_log
+                   WHERE $1 IS NULL OR user_id = $1
+                   ORDER BY created_at DESC
+                   LIMIT $2;"#,
+            user_id,
+            limit,
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(AuditEventError::LookupFailed)
+    }
+
+    /// How many failed logins aimed at the user were recorded within the window, ignoring any
+    /// that came before their most recent successful login.
+    pub async fn recent_login_failures(
+        conn: &mut DatabaseConnection,
+        user_id: UserId,
+        window: Duration,
+    ) -> Result<i64, AuditEventError> {
+        let window_modifier = format!("-{} seconds", window.as_secs());
+
+        sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as 'failures!: i64'
+                   FROM audit_log
+                   WHERE user_id = $1
+                       AND event = 'login'
+                       AND outcome = 'failure'
+                       AND created_at >= DATETIME('now', $2)
+                       AND rowid > COALESCE(
+                           (SELECT MAX(rowid) FROM audit_log
+                               WHERE user_id = $1 AND event = 'login' AND outcome = 'success'),
+                           0
+                       );"#,
+            user_id,
+            window_modifier,
+        )
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(AuditEventError::LookupFailed)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditEventError {
+    #[error("failed to lookup audit events: {0}")]
+    LookupFailed(sqlx::Error),
+
+    #[error("failed to save audit event: {0}")]
+    SaveFailed(sqlx::Error),
+}
@@ -1,7 +1,12 @@
+use std::time::Duration;
+
 use time::OffsetDateTime;
 
 use crate::background_jobs::JobLike;
-use crate::database::custom_types::{Attempt, BackgroundJobId, BackgroundJobState, UniqueTaskKey};
+use crate::database::custom_types::{
+    Attempt, BackgroundJobId, BackgroundJobState, PayloadEncoding, PayloadEncodingError,
+    UniqueTaskKey,
+};
 use crate::database::DatabaseConnection;
 
 pub struct CreateBackgroundJob<'a, JL>
@@ -15,6 +20,7 @@ where
     task: &'a JL,
 
     attempt_run_at: OffsetDateTime,
+    deadline: Option<OffsetDateTime>,
 }
 
 impl<'a, JL: JobLike> CreateBackgroundJob<'a, JL> {
@@ -46,29 +52,42 @@ impl<'a, JL: JobLike> CreateBackgroundJob<'a, JL> {
             unique_key,
             task,
             attempt_run_at,
+            deadline: None,
         }
     }
 
+    /// Marks the job as being waited on by something that gives up at `deadline`, the job is cut
+    /// off once it passes.
+    pub fn with_deadline(mut self, deadline: OffsetDateTime) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
     pub async fn save(
         self,
         conn: &mut DatabaseConnection,
     ) -> Result<BackgroundJobId, BackgroundJobError> {
-        let payload = serde_json::to_string(self.task)
+        let payload_encoding = JL::PAYLOAD_ENCODING;
+        let payload = payload_encoding
+            .encode_payload(self.task)
             .map_err(BackgroundJobError::PayloadSerializationFailed)?;
 
         sqlx::query_scalar!(
             r#"INSERT INTO background_jobs (name, queue_name, unique_key, state,
-                       current_attempt, maximum_attempts, payload, attempt_run_at)
-                   VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                       maximum_attempts, payload, payload_encoding, priority, attempt_run_at,
+                       deadline)
+                   VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
                    RETURNING id as 'id: BackgroundJobId';"#,
             self.name,
             self.queue_name,
             self.unique_key,
             BackgroundJobState::Scheduled,
-            0u8,
             JL::MAX_ATTEMPTS,
             payload,
+            payload_encoding,
+            JL::PRIORITY,
             self.attempt_run_at,
+            self.deadline,
         )
         .fetch_one(&mut *conn)
         .await
@@ -90,31 +109,417 @@ pub struct BackgroundJob {
     current_attempt: Attempt,
     maximum_attempts: Attempt,
 
-    payload: Option<serde_json::Value>,
+    payload: Option<Vec<u8>>,
+    payload_encoding: PayloadEncoding,
+    priority: i16,
 
     scheduled_at: OffsetDateTime,
     attempt_run_at: OffsetDateTime,
+
+    deadline: Option<OffsetDateTime>,
 }
 
 impl BackgroundJob {
+    pub fn attempt_run_at(&self) -> OffsetDateTime {
+        self.attempt_run_at
+    }
+
+    /// Atomically claims the next runnable job from the queue, marking it active and leasing it to
+    /// the caller for the provided duration. Only jobs whose name is one of the provided job names
+    /// are considered, either scheduled jobs whose next attempt is due or active jobs whose lease
+    /// has expired as the worker running them has gone away. The highest priority of those is
+    /// claimed first with ties going to the longest waiting job. The update is guarded on the job
+    /// still being claimable so a job can only ever be claimed by a single worker.
+    pub async fn claim_next(
+        conn: &mut DatabaseConnection,
+        queue_name: &str,
+        job_names: &[&str],
+        lease: Duration,
+    ) -> Result<Option<Self>, BackgroundJobError> {
+        let now = OffsetDateTime::now_utc();
+        let lease_expires_at = now + lease;
+
+        // SQLite has no support for binding lists so the names are passed in as a JSON array
+        let job_names = serde_json::to_string(job_names).expect("string slices to serialize");
+
+        sqlx::query_as!(
+            BackgroundJob,
+            r#"UPDATE background_jobs SET state = $1, lease_expires_at = $6
+                   WHERE id = (
+                       SELECT id FROM background_jobs
+                           WHERE queue_name = $3
+                               AND name IN (SELECT value FROM json_each($4))
+                               AND (
+                                   (state = $2 AND attempt_run_at <= $5)
+                                       OR (state = $1 AND lease_expires_at <= $5)
+                               )
+                           ORDER BY priority DESC, attempt_run_at ASC, scheduled_at ASC
+                           LIMIT 1
+                   ) AND (state = $2 OR (state = $1 AND lease_expires_at <= $5))
+                   RETURNING
+                       id as 'id!: BackgroundJobId',
+                       name as 'name!',
+                       queue_name as 'queue_name!',
+                       unique_key as 'unique_key: UniqueTaskKey',
+                       state as 'state!: BackgroundJobState',
+                       current_attempt as 'current_attempt!: Attempt',
+                       maximum_attempts as 'maximum_attempts!: Attempt',
+                       payload,
+                       payload_encoding as 'payload_encoding!: PayloadEncoding',
+                       priority as 'priority!: i16',
+                       scheduled_at as 'scheduled_at!: OffsetDateTime',
+                       attempt_run_at as 'attempt_run_at!: OffsetDateTime',
+                       deadline as 'deadline: OffsetDateTime';"#,
+            BackgroundJobState::Active,
+            BackgroundJobState::Scheduled,
+            queue_name,
+            job_names,
+            now,
+            lease_expires_at,
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(BackgroundJobError::Claiming)
+    }
+
+    pub fn current_attempt(&self) -> Attempt {
+        self.current_attempt
+    }
+
+    /// When whatever is waiting on the job gives up on it, if anything is.
+    pub fn deadline(&self) -> Option<OffsetDateTime> {
+        self.deadline
+    }
+
+    /// Up to `limit` of the jobs in the queue that ran out of attempts, most recently failed
+    /// first.
+    pub async fn dead_letter(
+        conn: &mut DatabaseConnection,
+        queue_name: &str,
+        limit: usize,
+    ) -> Result<Vec<Self>, BackgroundJobError> {
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+
+        sqlx::query_as!(
+            BackgroundJob,
+            r#"SELECT
+                   id as 'id: BackgroundJobId',
+                   name,
+                   queue_name,
+                   unique_key as 'unique_key: UniqueTaskKey',
+                   state as 'state: BackgroundJobState',
+                   current_attempt as 'current_attempt: Attempt',
+                   maximum_attempts as 'maximum_attempts: Attempt',
+                   payload,
+                   payload_encoding as 'payload_encoding: PayloadEncoding',
+                   priority as 'priority: i16',
+                   scheduled_at as 'scheduled_at: OffsetDateTime',
+                   attempt_run_at as 'attempt_run_at: OffsetDateTime',
+                   deadline as 'deadline: OffsetDateTime'
+                 FROM background_jobs
+                 WHERE queue_name = $1 AND state = $2
+                 ORDER BY attempt_run_at DESC
+                 LIMIT $3;"#,
+            queue_name,
+            BackgroundJobState::Dead,
+            limit,
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(BackgroundJobError::Locating)
+    }
+
+    pub async fn find(
+        conn: &mut DatabaseConnection,
+        id: BackgroundJobId,
+    ) -> Result<Option<Self>, BackgroundJobError> {
+        sqlx::query_as!(
+            BackgroundJob,
+            r#"SELECT
+                   id as 'id: BackgroundJobId',
+                   name,
+                   queue_name,
+                   unique_key as 'unique_key: UniqueTaskKey',
+                   state as 'state: BackgroundJobState',
+                   current_attempt as 'current_attempt: Attempt',
+                   maximum_attempts as 'maximum_attempts: Attempt',
+                   payload,
+                   payload_encoding as 'payload_encoding: PayloadEncoding',
+                   priority as 'priority: i16',
+                   scheduled_at as 'scheduled_at: OffsetDateTime',
+                   attempt_run_at as 'attempt_run_at: OffsetDateTime',
+                   deadline as 'deadline: OffsetDateTime'
+                 FROM background_jobs
+                 WHERE id = $1;"#,
+            id,
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(BackgroundJobError::Locating)
+    }
+
     pub fn id(&self) -> BackgroundJobId {
         self.id
     }
 
+    /// When a job with the provided name was most recently added to the queue, regardless of
+    /// what state it is currently in.
+    pub async fn last_scheduled_at(
+        conn: &mut DatabaseConnection,
+        name: &str,
+    ) -> Result<Option<OffsetDateTime>, BackgroundJobError> {
+        sqlx::query_scalar!(
+            r#"SELECT scheduled_at as 'scheduled_at: OffsetDateTime' FROM background_jobs
+                   WHERE name = $1
+                   ORDER BY scheduled_at DESC
+                   LIMIT 1;"#,
+            name,
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(BackgroundJobError::Locating)
+    }
+
+    /// Jobs currently in the provided state, those that have most recently been (or next will
+    /// be) attempted first.
+    pub async fn list_by_state(
+        conn: &mut DatabaseConnection,
+        state: BackgroundJobState,
+        limit: usize,
+    ) -> Result<Vec<Self>, BackgroundJobError> {
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+
+        sqlx::query_as!(
+            BackgroundJob,
+            r#"SELECT
+                   id as 'id: BackgroundJobId',
+                   name,
+                   queue_name,
+                   unique_key as 'unique_key: UniqueTaskKey',
+                   state as 'state: BackgroundJobState',
+                   current_attempt as 'current_attempt: Attempt',
+                   maximum_attempts as 'maximum_attempts: Attempt',
+                   payload,
+                   payload_encoding as 'payload_encoding: PayloadEncoding',
+                   priority as 'priority: i16',
+                   scheduled_at as 'scheduled_at: OffsetDateTime',
+                   attempt_run_at as 'attempt_run_at: OffsetDateTime',
+                   deadline as 'deadline: OffsetDateTime'
+                 FROM background_jobs
+                 WHERE state = $1
+                 ORDER BY attempt_run_at DESC
+                 LIMIT $2;"#,
+            state,
+            limit,
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(BackgroundJobError::Locating)
+    }
+
+    pub fn maximum_attempts(&self) -> Attempt {
+        self.maximum_attempts
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 
-    pub fn payload(&self) -> Option<&serde_json::Value> {
-        self.payload.as_ref()
+    pub fn payload(&self) -> Option<&[u8]> {
+        self.payload.as_deref()
+    }
+
+    pub fn payload_encoding(&self) -> PayloadEncoding {
+        self.payload_encoding
+    }
+
+    /// Jobs with a higher priority are run ahead of others in the same queue that are also due.
+    pub fn priority(&self) -> i16 {
+        self.priority
+    }
+
+    pub fn queue_name(&self) -> &str {
+        &self.queue_name
+    }
+
+    /// Gives a dead job a fresh set of attempts starting right away. Returns false when the job
+    /// doesn't exist or isn't dead.
+    pub async fn requeue(
+        conn: &mut DatabaseConnection,
+        id: BackgroundJobId,
+    ) -> Result<bool, BackgroundJobError> {
+        let now = OffsetDateTime::now_utc();
+
+        let result = sqlx::query!(
+            r#"UPDATE background_jobs SET state = $1, current_attempt = 1, attempt_run_at = $2
+                   WHERE id = $3 AND state = $4;"#,
+            BackgroundJobState::Scheduled,
+            now,
+            id,
+            BackgroundJobState::Dead,
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(BackgroundJobError::Updating)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Gives up to `limit` of the dead jobs with the provided name a fresh set of attempts starting
+    /// right away, most recently failed first. Returns the number of jobs that were requeued.
+    pub async fn requeue_dead_by_name(
+        conn: &mut DatabaseConnection,
+        name: &str,
+        limit: usize,
+    ) -> Result<u64, BackgroundJobError> {
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let now = OffsetDateTime::now_utc();
+
+        let result = sqlx::query!(
+            r#"UPDATE background_jobs SET state = $1, current_attempt = 1, attempt_run_at = $2
+                   WHERE id IN (
+                       SELECT id FROM background_jobs
+                           WHERE name = $3 AND state = $4
+                           ORDER BY attempt_run_at DESC
+                           LIMIT $5
+                   );"#,
+            BackgroundJobState::Scheduled,
+            now,
+            name,
+            BackgroundJobState::Dead,
+            limit,
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(BackgroundJobError::Updating)?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Returns an active job to the queue so it runs again at the provided time. The job keeps its
+    /// current attempt as being deferred isn't a failure. Returns whether the job was updated,
+    /// jobs that aren't active are left alone.
+    pub async fn reschedule(
+        conn: &mut DatabaseConnection,
+        id: BackgroundJobId,
+        attempt_run_at: OffsetDateTime,
+    ) -> Result<bool, BackgroundJobError> {
+        let result = sqlx::query!(
+            "UPDATE background_jobs SET state = $1, attempt_run_at = $2 WHERE id = $3 AND state = $4;",
+            BackgroundJobState::Scheduled,
+            attempt_run_at,
+            id,
+            BackgroundJobState::Active,
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(BackgroundJobError::Updating)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Moves the next attempt of a scheduled job up to the current time. Returns whether the job
+    /// was updated, jobs that aren't scheduled are left alone.
+    pub async fn run_now(
+        conn: &mut DatabaseConnection,
+        id: BackgroundJobId,
+    ) -> Result<bool, BackgroundJobError> {
+        let now = OffsetDateTime::now_utc();
+
+        let result = sqlx::query!(
+            "UPDATE background_jobs SET attempt_run_at = $1 WHERE id = $2 AND state = $3;",
+            now,
+            id,
+            BackgroundJobState::Scheduled,
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(BackgroundJobError::Updating)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Queues up the next attempt of an active job that failed. Returns whether the job was
+    /// updated, jobs that aren't active are left alone.
+    pub async fn schedule_attempt(
+        conn: &mut DatabaseConnection,
+        id: BackgroundJobId,
+        attempt: Attempt,
+        attempt_run_at: OffsetDateTime,
+    ) -> Result<bool, BackgroundJobError> {
+        let result = sqlx::query!(
+            r#"UPDATE background_jobs SET state = $1, current_attempt = $2, attempt_run_at = $3
+                   WHERE id = $4 AND state = $5;"#,
+            BackgroundJobState::Scheduled,
+            attempt,
+            attempt_run_at,
+            id,
+            BackgroundJobState::Active,
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(BackgroundJobError::Updating)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub fn scheduled_at(&self) -> OffsetDateTime {
+        self.scheduled_at
+    }
+
+    pub fn state(&self) -> BackgroundJobState {
+        self.state
+    }
+
+    pub async fn state_of(
+        conn: &mut DatabaseConnection,
+        id: BackgroundJobId,
+    ) -> Result<Option<BackgroundJobState>, BackgroundJobError> {
+        sqlx::query_scalar!(
+            "SELECT state as 'state: BackgroundJobState' FROM background_jobs WHERE id = $1;",
+            id,
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(BackgroundJobError::Locating)
+    }
+
+    /// Moves the job into a new state, but only if it is currently in the expected state. Returns
+    /// whether the job was updated.
+    pub async fn transition(
+        conn: &mut DatabaseConnection,
+        id: BackgroundJobId,
+        from: BackgroundJobState,
+        to: BackgroundJobState,
+    ) -> Result<bool, BackgroundJobError> {
+        let result = sqlx::query!(
+            "UPDATE background_jobs SET state = $1 WHERE id = $2 AND state = $3;",
+            to,
+            id,
+            from,
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(BackgroundJobError::Updating)?;
+
+        Ok(result.rows_affected() > 0)
     }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum BackgroundJobError {
+    #[error("failed to claim background job: {0}")]
+    Claiming(sqlx::Error),
+
+    #[error("failed to lookup background job: {0}")]
+    Locating(sqlx::Error),
+
     #[error("failed to serialize task payload: {0}")]
-    PayloadSerializationFailed(serde_json::Error),
+    PayloadSerializationFailed(PayloadEncodingError),
 
     #[error("failed to save background job: {0}")]
     SaveFailed(sqlx::Error),
+
+    #[error("failed to update background job: {0}")]
+    Updating(sqlx::Error),
 }
@@ -7,14 +7,21 @@ use crate::database::{
 
 pub struct CreateBackgroundRun<'a> {
     background_job_id: &'a BackgroundJobId,
+    attempt: Attempt,
 }
 
 impl<'a> CreateBackgroundRun<'a> {
+    pub fn new(background_job_id: &'a BackgroundJobId, attempt: Attempt) -> Self {
+        Self {
+            background_job_id,
+            attempt,
+        }
+    }
+
     pub async fn save(
         self,
         conn: &mut DatabaseConnection,
     ) -> Result<BackgroundRunId, BackgroundRunError> {
-        let attempt = Attempt::zero();
         let started_at = OffsetDateTime::now_utc();
 
         sqlx::query_scalar!(
@@ -22,7 +29,7 @@ impl<'a> CreateBackgroundRun<'a> {
                    VALUES ($1, $2, $3, $4)
                    RETURNING id as 'id: BackgroundRunId';"#,
             self.background_job_id,
-            attempt,
+            self.attempt,
             BackgroundRunState::Running,
             started_at,
         )
@@ -47,8 +54,71 @@ pub struct BackgroundRun {
     finished_at: Option<OffsetDateTime>,
 }
 
+impl BackgroundRun {
+    /// Records the end of any run of the job that is still in progress. Returns the number of runs
+    /// that were finished, normally this will be either zero or one.
+    pub async fn finish(
+        conn: &mut DatabaseConnection,
+        background_job_id: BackgroundJobId,
+        state: BackgroundRunState,
+    ) -> Result<u64, BackgroundRunError> {
+        let finished_at = OffsetDateTime::now_utc();
+
+        let result = sqlx::query!(
+            r#"UPDATE background_runs SET state = $1, finished_at = $2
+                   WHERE background_job_id = $3 AND state = $4;"#,
+            state,
+            finished_at,
+            background_job_id,
+            BackgroundRunState::Running,
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(BackgroundRunError::Updating)?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Removes all but the most recent `retained` finished runs of every job, returning the number
+    /// of runs removed. Runs that are still in progress are never removed or counted against the
+    /// limit.
+    pub async fn prune_history(
+        conn: &mut DatabaseConnection,
+        retained: u32,
+    ) -> Result<u64, BackgroundRunError> {
+        let retained = i64::from(retained);
+
+        let result = sqlx::query!(
+            r#"DELETE FROM background_runs
+                   WHERE id IN (
+                       SELECT id FROM (
+                           SELECT id, ROW_NUMBER() OVER (
+                               PARTITION BY background_job_id ORDER BY started_at DESC
+                           ) AS run_rank
+                           FROM background_runs
+                           WHERE state != $1
+                       )
+                       WHERE run_rank > $2
+                   );"#,
+            BackgroundRunState::Running,
+            retained,
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(BackgroundRunError::PruneFailed)?;
+
+        Ok(result.rows_affected())
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum BackgroundRunError {
+    #[error("failed to prune background run history: {0}")]
+    PruneFailed(sqlx::Error),
+
     #[error("failed to save background run: {0}")]
     SaveFailed(sqlx::Error),
+
+    #[error("failed to update background run: {0}")]
+    Updating(sqlx::Error),
 }
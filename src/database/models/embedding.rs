@@ -0,0 +1,45 @@
+use crate::database::custom_types::EmbeddingId;
+use crate::database::DatabaseConnection;
+
+pub struct CreateEmbedding<'a> {
+    model: &'a str,
+    content: &'a str,
+    vector: &'a [f32],
+}
+
+impl<'a> CreateEmbedding<'a> {
+    pub fn new(model: &'a str, content: &'a str, vector: &'a [f32]) -> Self {
+        Self {
+            model,
+            content,
+            vector,
+        }
+    }
+
+    pub async fn save(self, conn: &mut DatabaseConnection) -> Result<EmbeddingId, EmbeddingError> {
+        // Vectors are stored as the raw little-endian bytes of each component
+        let vector: Vec<u8> = self
+            .vector
+            .iter()
+            .flat_map(|component| component.to_le_bytes())
+            .collect();
+
+        sqlx::query_scalar!(
+            r#"INSERT INTO embeddings (model, content, vector)
+                   VALUES ($1, $2, $3)
+                   RETURNING id as 'id: EmbeddingId';"#,
+            self.model,
+            self.content,
+            vector,
+        )
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(EmbeddingError::SaveFailed)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmbeddingError {
+    #[error("failed to save embedding: {0}")]
+    SaveFailed(sqlx::Error),
+}
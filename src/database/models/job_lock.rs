@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use time::OffsetDateTime;
+
+use crate::database::DatabaseConnection;
+
+/// A named lock shared by every instance of the service through the database. Locks are leased
+/// rather than held indefinitely, once a lease expires the lock is free to be taken by anyone else
+/// which covers holders that die without releasing it.
+pub struct JobLock;
+
+impl JobLock {
+    /// Attempts to take the named lock for the holder until the lease runs out. Returns whether the
+    /// lock was acquired, this fails when someone else holds an unexpired lease on it.
+    pub async fn acquire(
+        conn: &mut DatabaseConnection,
+        name: &str,
+        holder: &str,
+        lease: Duration,
+    ) -> Result<bool, JobLockError> {
+        let acquired_at = OffsetDateTime::now_utc();
+        let expires_at = acquired_at + lease;
+
+        let result = sqlx::query!(
+            r#"INSERT INTO job_locks (name, holder, acquired_at, expires_at)
+                   VALUES ($1, $2, $3, $4)
+                   ON CONFLICT (name) DO UPDATE
+                       SET holder = excluded.holder,
+                           acquired_at = excluded.acquired_at,
+                           expires_at = excluded.expires_at
+                       WHERE job_locks.expires_at <= excluded.acquired_at;"#,
+            name,
+            holder,
+            acquired_at,
+            expires_at,
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(JobLockError::Acquiring)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Gives up the named lock, but only if it is still held by the provided holder. A holder
+    /// whose lease expired may have already lost the lock to someone else. Returns whether the
+    /// lock was released.
+    pub async fn release(
+        conn: &mut DatabaseConnection,
+        name: &str,
+        holder: &str,
+    ) -> Result<bool, JobLockError> {
+        let result = sqlx::query!(
+            "DELETE FROM job_locks WHERE name = $1 AND holder = $2;",
+            name,
+            holder,
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(JobLockError::Releasing)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum JobLockError {
+    #[error("failed to acquire job lock: {0}")]
+    Acquiring(sqlx::Error),
+
+    #[error("failed to release job lock: {0}")]
+    Releasing(sqlx::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tests::prelude::*;
+
+    #[tokio::test]
+    async fn test_locks_exclusive_until_released_or_expired() {
+        let pool = migrated_test_database().await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        let lease = Duration::from_secs(60);
+        assert!(JobLock::acquire(&mut conn, "nightly", "one", lease)
+            .await
+            .unwrap());
+        assert!(!JobLock::acquire(&mut conn, "nightly", "two", lease)
+            .await
+            .unwrap());
+
+        // Locks are independent of each other
+        assert!(JobLock::acquire(&mut conn, "hourly", "two", lease)
+            .await
+            .unwrap());
+
+        // Only the holder can release a lock
+        assert!(!JobLock::release(&mut conn, "nightly", "two").await.unwrap());
+        assert!(JobLock::release(&mut conn, "nightly", "one").await.unwrap());
+        assert!(JobLock::acquire(&mut conn, "nightly", "two", lease)
+            .await
+            .unwrap());
+
+        // A holder that never releases its lock only keeps it until the lease runs out
+        assert!(JobLock::acquire(&mut conn, "weekly", "one", Duration::ZERO)
+            .await
+            .unwrap());
+        assert!(JobLock::acquire(&mut conn, "weekly", "two", lease)
+            .await
+            .unwrap());
+        assert!(!JobLock::release(&mut conn, "weekly", "one").await.unwrap());
+    }
+}
@@ -1,18 +1,25 @@
 #![allow(unused_imports)]
 
 mod api_key;
+mod audit_event;
 mod background_job;
 mod background_run;
+mod embedding;
+mod job_lock;
 mod oauth_provider_account;
 mod oauth_state;
 mod session;
 mod user;
 
 pub use api_key::ApiKey;
+pub use audit_event::{AuditEvent, AuditEventError, CreateAuditEvent};
 pub use background_job::{BackgroundJob, BackgroundJobError, CreateBackgroundJob};
-pub use background_run::BackgroundRun;
+pub use background_run::{BackgroundRun, BackgroundRunError, CreateBackgroundRun};
+pub use embedding::{CreateEmbedding, EmbeddingError};
+pub use job_lock::{JobLock, JobLockError};
 pub use oauth_provider_account::{
-    CreateOAuthProviderAccount, OAuthProviderAccount, OAuthProviderAccountError,
+    CreateOAuthProviderAccount, ExpiringProviderToken, OAuthProviderAccount,
+    OAuthProviderAccountError,
 };
 pub use oauth_state::{CreateOAuthState, OAuthStateError, VerifyOAuthState};
 pub use session::{CreateSession, Session, SessionError};
@@ -4,8 +4,10 @@ use std::ops::Deref;
 
 use time::OffsetDateTime;
 
-use crate::database::custom_types::{LoginProvider, OAuthProviderAccountId, ProviderId, UserId};
-use crate::database::Database;
+use crate::database::custom_types::{
+    LoginProvider, OAuthProviderAccountId, ProviderId, SealedToken, UserId,
+};
+use crate::database::{Database, DatabaseConnection};
 
 pub struct CreateOAuthProviderAccount {
     user_id: UserId,
@@ -62,6 +64,34 @@ pub struct OAuthProviderAccount {
 }
 
 impl OAuthProviderAccount {
+    /// Accounts holding a refresh token whose access token expires before the provided time,
+    /// soonest first. Accounts already waiting on the user to log in again are skipped.
+    pub async fn expiring_tokens(
+        conn: &mut DatabaseConnection,
+        expiring_before: OffsetDateTime,
+        limit: u32,
+    ) -> Result<Vec<ExpiringProviderToken>, OAuthProviderAccountError> {
+        sqlx::query_as!(
+            ExpiringProviderToken,
+            r#"SELECT
+                        id as 'id: OAuthProviderAccountId',
+                        user_id as 'user_id: UserId',
+                        provider as 'provider: LoginProvider',
+                        refresh_token as 'refresh_token!: SealedToken'
+                    FROM oauth_provider_accounts
+                    WHERE refresh_token IS NOT NULL
+                        AND needs_reauthentication = false
+                        AND access_token_expires_at <= $1
+                    ORDER BY access_token_expires_at ASC
+                    LIMIT $2;"#,
+            expiring_before,
+            limit,
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(OAuthProviderAccountError::LookupFailed)
+    }
+
     pub fn id(&self) -> OAuthProviderAccountId {
         self.id
     }
@@ -88,6 +118,23 @@ impl OAuthProviderAccount {
         .map_err(OAuthProviderAccountError::LookupFailed)
     }
 
+    /// Flags the account as needing the user to log in with the provider again, its tokens will
+    /// no longer be refreshed until they do.
+    pub async fn mark_needs_reauthentication(
+        conn: &mut DatabaseConnection,
+        id: OAuthProviderAccountId,
+    ) -> Result<(), OAuthProviderAccountError> {
+        sqlx::query!(
+            "UPDATE oauth_provider_accounts SET needs_reauthentication = true WHERE id = $1;",
+            id,
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(OAuthProviderAccountError::SaveFailed)?;
+
+        Ok(())
+    }
+
     pub fn provider(&self) -> LoginProvider {
         self.provider
     }
@@ -100,11 +147,48 @@ impl OAuthProviderAccount {
         self.provider_id.clone()
     }
 
+    /// Records the tokens most recently issued for the account and clears any earlier need to
+    /// reauthenticate. Providers don't always issue a new refresh token alongside a new access
+    /// token, the existing one is kept when none is provided.
+    pub async fn store_tokens(
+        conn: &mut DatabaseConnection,
+        id: OAuthProviderAccountId,
+        access_token: &SealedToken,
+        refresh_token: Option<&SealedToken>,
+        expires_at: Option<OffsetDateTime>,
+    ) -> Result<(), OAuthProviderAccountError> {
+        sqlx::query!(
+            r#"UPDATE oauth_provider_accounts
+                 SET access_token = $2,
+                     refresh_token = COALESCE($3, refresh_token),
+                     access_token_expires_at = $4,
+                     needs_reauthentication = false
+                 WHERE id = $1;"#,
+            id,
+            access_token,
+            refresh_token,
+            expires_at,
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(OAuthProviderAccountError::SaveFailed)?;
+
+        Ok(())
+    }
+
     pub fn user_id(&self) -> UserId {
         self.user_id
     }
 }
 
+/// An account whose access token needs to be renewed with its refresh token.
+pub struct ExpiringProviderToken {
+    pub id: OAuthProviderAccountId,
+    pub user_id: UserId,
+    pub provider: LoginProvider,
+    pub refresh_token: SealedToken,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum OAuthProviderAccountError {
     #[error("failed to lookup oauth provider account: {0}")]
@@ -113,3 +197,82 @@ pub enum OAuthProviderAccountError {
     #[error("failed to save oauth provider account: {0}")]
     SaveFailed(sqlx::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::database::models::CreateUser;
+    use crate::tests::prelude::*;
+
+    #[tokio::test]
+    async fn test_only_refreshable_tokens_reported_as_expiring() {
+        let database = Database::new(migrated_test_database().await);
+        let mut conn = database.acquire().await.unwrap();
+
+        let user_id = CreateUser::new("tokens@example.com", "Token User")
+            .save(&mut conn)
+            .await
+            .unwrap();
+        let account_id = CreateOAuthProviderAccount::new(
+            user_id,
+            LoginProvider::Google,
+            ProviderId::from("tokens-user".to_string()),
+            "tokens@example.com".to_string(),
+        )
+        .save(&database)
+        .await
+        .unwrap();
+
+        let now = OffsetDateTime::now_utc();
+        let soon = now + time::Duration::minutes(5);
+
+        // Without a refresh token there is nothing that can be done about an expiring token
+        let first = SealedToken::from("first".to_string());
+        OAuthProviderAccount::store_tokens(&mut conn, account_id, &first, None, Some(now))
+            .await
+            .unwrap();
+        let expiring = OAuthProviderAccount::expiring_tokens(&mut conn, soon, 10)
+            .await
+            .unwrap();
+        assert!(expiring.is_empty());
+
+        let second = SealedToken::from("second".to_string());
+        let refresh = SealedToken::from("refresh".to_string());
+        OAuthProviderAccount::store_tokens(
+            &mut conn,
+            account_id,
+            &second,
+            Some(&refresh),
+            Some(now),
+        )
+        .await
+        .unwrap();
+        let expiring = OAuthProviderAccount::expiring_tokens(&mut conn, soon, 10)
+            .await
+            .unwrap();
+        assert_eq!(expiring.len(), 1);
+        assert_eq!(expiring[0].id, account_id);
+        assert_eq!(expiring[0].refresh_token, refresh);
+
+        // Renewed tokens that don't come with a refresh token keep the existing one
+        let later = now + time::Duration::hours(1);
+        let third = SealedToken::from("third".to_string());
+        OAuthProviderAccount::store_tokens(&mut conn, account_id, &third, None, Some(later))
+            .await
+            .unwrap();
+        let expiring = OAuthProviderAccount::expiring_tokens(&mut conn, later, 10)
+            .await
+            .unwrap();
+        assert_eq!(expiring.len(), 1);
+        assert_eq!(expiring[0].refresh_token, refresh);
+
+        OAuthProviderAccount::mark_needs_reauthentication(&mut conn, account_id)
+            .await
+            .unwrap();
+        let expiring = OAuthProviderAccount::expiring_tokens(&mut conn, later, 10)
+            .await
+            .unwrap();
+        assert!(expiring.is_empty());
+    }
+}
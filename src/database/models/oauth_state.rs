@@ -1,12 +1,15 @@
+use std::net::IpAddr;
 use std::ops::Deref;
 
 use oauth2::{CsrfToken, PkceCodeVerifier};
 
+use crate::app::{record_secret_access, SecretKind};
 use crate::database::custom_types::LoginProvider;
 use crate::database::Database;
 
 pub struct CreateOAuthState {
     provider: LoginProvider,
+    client_ip: Option<String>,
     csrf_token: CsrfToken,
     pkce_code_verifier: PkceCodeVerifier,
     post_login_redirect_url: Option<String>,
@@ -14,18 +17,20 @@ pub struct CreateOAuthState {
 
 impl CreateOAuthState {
     fn csrf_token_secret(&self) -> String {
-        tracing::debug!("accessing OAuth CSRF token secret");
+        record_secret_access(SecretKind::OAuthCsrfToken);
         self.csrf_token.secret().to_string()
     }
 
     pub fn new(
         provider: LoginProvider,
+        client_ip: Option<IpAddr>,
         csrf_token: CsrfToken,
         pkce_code_verifier: PkceCodeVerifier,
         post_login_redirect_url: Option<String>,
     ) -> Self {
         Self {
             provider,
+            client_ip: client_ip.map(|ip| ip.to_string()),
             csrf_token,
             pkce_code_verifier,
             post_login_redirect_url,
@@ -33,26 +38,44 @@ impl CreateOAuthState {
     }
 
     fn pkce_code_verifier_secret(&self) -> String {
-        tracing::debug!("accessing OAuth PKCE code verification secret");
+        record_secret_access(SecretKind::OAuthPkceVerifier);
         self.pkce_code_verifier.secret().to_string()
     }
 
-    pub async fn save(self, database: &Database) -> Result<(), OAuthStateError> {
+    /// Records the state of a login that has been started, refusing to when the client's address
+    /// already has `outstanding_limit` unexpired logins in progress. Requests without a known
+    /// address all share the same allowance. The check and insert happen in a single statement so
+    /// concurrent logins can't slip past the limit.
+    pub async fn save(
+        self,
+        database: &Database,
+        outstanding_limit: u32,
+    ) -> Result<(), OAuthStateError> {
         let csrf_token_secret = self.csrf_token_secret();
         let pkce_code_verifier_secret = self.pkce_code_verifier_secret();
 
-        sqlx::query_scalar!(
-            r#"INSERT INTO oauth_state (provider, csrf_token_secret, pkce_code_verifier_secret, post_login_redirect_url)
-                   VALUES ($1, $2, $3, $4);"#,
+        let result = sqlx::query!(
+            r#"INSERT INTO oauth_state (provider, client_ip, csrf_token_secret, pkce_code_verifier_secret, post_login_redirect_url)
+                   SELECT $1, $2, $3, $4, $5
+                   WHERE (
+                       SELECT COUNT(*) FROM oauth_state
+                           WHERE client_ip IS $2 AND created_at >= DATETIME('now', '-5 minute')
+                   ) < $6;"#,
             self.provider,
+            self.client_ip,
             csrf_token_secret,
             pkce_code_verifier_secret,
             self.post_login_redirect_url,
+            outstanding_limit,
         )
         .execute(database.deref())
         .await
         .map_err(OAuthStateError::Creating)?;
 
+        if result.rows_affected() == 0 {
+            return Err(OAuthStateError::TooManyOutstanding);
+        }
+
         Ok(())
     }
 }
@@ -69,7 +92,7 @@ impl VerifyOAuthState {
         provider: LoginProvider,
         csrf_token: CsrfToken,
     ) -> Result<(), OAuthStateError> {
-        tracing::debug!("accessing OAuth CSRF token secret to delete session");
+        record_secret_access(SecretKind::OAuthCsrfToken);
         let csrf_token_secret = csrf_token.secret().to_string();
 
         sqlx::query_as!(
@@ -90,7 +113,7 @@ impl VerifyOAuthState {
         provider: LoginProvider,
         csrf_token: CsrfToken,
     ) -> Result<Option<Self>, OAuthStateError> {
-        tracing::debug!("accessing OAuth CSRF token secret to locate session");
+        record_secret_access(SecretKind::OAuthCsrfToken);
         let csrf_token_secret = csrf_token.secret().to_string();
 
         sqlx::query_as!(
@@ -140,4 +163,44 @@ pub enum OAuthStateError {
 
     #[error("failed to delete existing database session: {0}")]
     Deleting(sqlx::Error),
+
+    #[error("client already has the maximum number of logins in progress")]
+    TooManyOutstanding,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use oauth2::PkceCodeChallenge;
+
+    use super::*;
+    use crate::tests::prelude::*;
+
+    fn login_state(client_ip: IpAddr) -> CreateOAuthState {
+        let (_, pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
+        CreateOAuthState::new(
+            LoginProvider::Google,
+            Some(client_ip),
+            CsrfToken::new_random(),
+            pkce_code_verifier,
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_outstanding_logins_limited_per_client() {
+        let database = Database::new(migrated_test_database().await);
+        let flooding_ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 10));
+        let other_ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 20));
+
+        for _ in 0..5 {
+            login_state(flooding_ip).save(&database, 5).await.unwrap();
+        }
+
+        let result = login_state(flooding_ip).save(&database, 5).await;
+        assert!(matches!(result, Err(OAuthStateError::TooManyOutstanding)));
+
+        login_state(other_ip).save(&database, 5).await.unwrap();
+    }
 }
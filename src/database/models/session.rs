@@ -1,14 +1,13 @@
 #![allow(dead_code)]
 
-// todo: record ip address of session login source
 // todo: implement remembered device as part of sessions
-//use std::net::IpAddr;
+use std::net::IpAddr;
 use std::time::Duration;
 
 use time::OffsetDateTime;
 
 use crate::auth::SESSION_TTL;
-use crate::database::custom_types::{OAuthProviderAccountId, SessionId, UserId};
+use crate::database::custom_types::{OAuthProviderAccountId, SealedToken, SessionId, UserId};
 use crate::database::DatabaseConnection;
 
 #[derive(Debug)]
@@ -18,6 +17,7 @@ pub struct CreateSession {
 
     client_ip: Option<String>,
     user_agent: Option<String>,
+    oauth_access_token: Option<SealedToken>,
 
     expires_at: OffsetDateTime,
 }
@@ -40,13 +40,15 @@ impl CreateSession {
     pub async fn create(self, conn: &mut DatabaseConnection) -> Result<SessionId, SessionError> {
         sqlx::query_scalar!(
             r#"INSERT INTO sessions
-                (user_id, oauth_provider_account_id, client_ip, user_agent, expires_at)
-                VALUES ($1, $2, $3, $4, $5)
+                (user_id, oauth_provider_account_id, client_ip, user_agent, oauth_access_token,
+                    expires_at)
+                VALUES ($1, $2, $3, $4, $5, $6)
                 RETURNING id as 'id: SessionId';"#,
             self.user_id,
             self.oauth_provider_account_id,
             self.client_ip,
             self.user_agent,
+            self.oauth_access_token,
             self.expires_at,
         )
         .fetch_one(&mut *conn)
@@ -63,15 +65,23 @@ impl CreateSession {
 
             client_ip: None,
             user_agent: None,
+            oauth_access_token: None,
 
             expires_at,
         }
     }
 
-    //pub fn set_client_ip(&mut self, client_ip: IpAddr) -> &mut Self {
-    //    self.client_ip = Some(client_ip);
-    //    self
-    //}
+    pub fn set_client_ip(&mut self, client_ip: IpAddr) -> &mut Self {
+        self.client_ip = Some(client_ip.to_string());
+        self
+    }
+
+    /// Remembers the access token the provider issued for this login so it can be revoked when
+    /// the session is logged out.
+    pub fn set_oauth_access_token(&mut self, access_token: SealedToken) -> &mut Self {
+        self.oauth_access_token = Some(access_token);
+        self
+    }
 
     pub fn set_user_agent(&mut self, user_agent: String) -> &mut Self {
         self.user_agent = Some(user_agent);
@@ -88,12 +98,17 @@ pub struct Session {
 
     client_ip: Option<String>,
     user_agent: Option<String>,
+    oauth_access_token: Option<SealedToken>,
 
     created_at: OffsetDateTime,
     expires_at: OffsetDateTime,
 }
 
 impl Session {
+    pub fn client_ip(&self) -> Option<&str> {
+        self.client_ip.as_deref()
+    }
+
     pub fn created_at(&self) -> OffsetDateTime {
         self.created_at
     }
@@ -116,6 +131,34 @@ impl Session {
         self.id
     }
 
+    /// The sessions belonging to a user that haven't expired yet, newest first.
+    pub async fn list_for_user(
+        conn: &mut DatabaseConnection,
+        user_id: UserId,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let now = OffsetDateTime::now_utc();
+
+        sqlx::query_as!(
+            Self,
+            r#"SELECT
+                   id as 'id: SessionId',
+                   user_id as 'user_id: UserId',
+                   oauth_provider_account_id as 'oauth_provider_account_id: OAuthProviderAccountId',
+                   client_ip,
+                   user_agent,
+                   oauth_access_token as 'oauth_access_token: SealedToken',
+                   created_at,
+                   expires_at
+                 FROM sessions
+                 WHERE user_id = $1 AND expires_at > $2
+                 ORDER BY created_at DESC;"#,
+            user_id,
+            now,
+        )
+        .fetch_all(&mut *conn)
+        .await
+    }
+
     pub async fn locate(
         conn: &mut DatabaseConnection,
         id: SessionId,
@@ -128,6 +171,7 @@ impl Session {
                    oauth_provider_account_id as 'oauth_provider_account_id: OAuthProviderAccountId',
                    client_ip,
                    user_agent,
+                   oauth_access_token as 'oauth_access_token: SealedToken',
                    created_at,
                    expires_at
                  FROM sessions
@@ -138,10 +182,61 @@ impl Session {
         .await
     }
 
+    pub fn oauth_access_token(&self) -> Option<&SealedToken> {
+        self.oauth_access_token.as_ref()
+    }
+
     pub fn oauth_provider_account_id(&self) -> OAuthProviderAccountId {
         self.oauth_provider_account_id
     }
 
+    /// The addresses the user's most recent sessions were created from, sessions without a
+    /// recorded address are skipped.
+    pub async fn recent_client_ips(
+        conn: &mut DatabaseConnection,
+        user_id: UserId,
+        limit: u32,
+    ) -> Result<Vec<IpAddr>, sqlx::Error> {
+        let client_ips = sqlx::query_scalar!(
+            r#"SELECT client_ip as 'client_ip!'
+                 FROM sessions
+                 WHERE user_id = $1 AND client_ip IS NOT NULL
+                 ORDER BY created_at DESC
+                 LIMIT $2;"#,
+            user_id,
+            limit,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        Ok(client_ips
+            .into_iter()
+            .filter_map(|ip| ip.parse().ok())
+            .collect())
+    }
+
+    /// Deletes a session on behalf of a user. Sessions belonging to anyone else are left alone,
+    /// the return value indicates whether a session was actually removed.
+    pub async fn revoke(
+        conn: &mut DatabaseConnection,
+        id: SessionId,
+        user_id: UserId,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM sessions WHERE id = $1 AND user_id = $2;",
+            id,
+            user_id,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+
     pub fn user_id(&self) -> UserId {
         self.user_id
     }
@@ -152,3 +247,66 @@ pub enum SessionError {
     #[error("saving the session to the database failed: {0}")]
     SaveFailed(sqlx::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::database::custom_types::{LoginProvider, ProviderId};
+    use crate::database::models::{CreateOAuthProviderAccount, CreateUser};
+    use crate::database::Database;
+    use crate::tests::prelude::*;
+
+    async fn create_user(database: &Database, email: &str) -> (UserId, OAuthProviderAccountId) {
+        let mut conn = database.acquire().await.unwrap();
+        let user_id = CreateUser::new(email, "Session User")
+            .save(&mut conn)
+            .await
+            .unwrap();
+
+        let provider_account_id = CreateOAuthProviderAccount::new(
+            user_id,
+            LoginProvider::Google,
+            ProviderId::from(email.to_string()),
+            email.to_string(),
+        )
+        .save(database)
+        .await
+        .unwrap();
+
+        (user_id, provider_account_id)
+    }
+
+    #[tokio::test]
+    async fn test_sessions_only_revoked_by_their_owner() {
+        let database = Database::new(migrated_test_database().await);
+        let (owner_id, owner_account_id) = create_user(&database, "owner@example.com").await;
+        let (other_id, _) = create_user(&database, "other@example.com").await;
+
+        let mut conn = database.acquire().await.unwrap();
+        let session_id = CreateSession::new(owner_id, owner_account_id)
+            .create(&mut conn)
+            .await
+            .unwrap();
+
+        assert!(Session::list_for_user(&mut conn, other_id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert!(!Session::revoke(&mut conn, session_id, other_id)
+            .await
+            .unwrap());
+        let sessions = Session::list_for_user(&mut conn, owner_id).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id(), session_id);
+
+        assert!(Session::revoke(&mut conn, session_id, owner_id)
+            .await
+            .unwrap());
+        assert!(Session::list_for_user(&mut conn, owner_id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -40,8 +40,23 @@ pub struct User {
     created_at: OffsetDateTime,
 }
 
+impl User {
+    pub async fn email_for(
+        conn: &mut DatabaseConnection,
+        user_id: UserId,
+    ) -> Result<Option<String>, UserError> {
+        sqlx::query_scalar!("SELECT email FROM users WHERE id = $1;", user_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(UserError::LookupFailed)
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum UserError {
+    #[error("failed to lookup user: {0}")]
+    LookupFailed(sqlx::Error),
+
     #[error("failed to save new user: {0}")]
     SaveFailed(sqlx::Error),
 }
@@ -32,6 +32,17 @@ pub async fn connect_sqlite(url: &Url) -> Result<SqlitePool, DatabaseSetupError>
         .map_err(DatabaseSetupError::Unavailable)
 }
 
+/// The number of migrations that have been successfully applied to the database and the number
+/// this build of the service knows about.
+pub async fn migration_status(pool: &SqlitePool) -> Result<(usize, usize), sqlx::Error> {
+    let applied: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM _sqlx_migrations WHERE success = true;")
+            .fetch_one(pool)
+            .await?;
+
+    Ok((applied as usize, MIGRATOR.iter().count()))
+}
+
 pub async fn migrate_sqlite(pool: &SqlitePool) -> Result<(), DatabaseSetupError> {
     MIGRATOR
         .run(pool)
@@ -1,6 +1,35 @@
+use std::collections::BTreeSet;
+
 use bincode::Options;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+mod registry;
+
+pub use registry::BusEventRegistry;
+
+/// A payload carried on the bus, tied to the type of event it is always sent as.
+pub trait BusEvent: Serialize + DeserializeOwned {
+    const EVENT: SystemEvent;
+
+    fn decode(payload: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::DefaultOptions::new().deserialize(payload)
+    }
+
+    /// The form of the event handed to clients outside of the service.
+    fn to_client_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        serde_json::to_value(self)
+    }
+}
+
+/// How many events are held for subscribers before the slowest ones start missing them.
+pub const DEFAULT_EVENT_BUS_CAPACITY: usize = 1_024;
+
+/// The events a filtered subscriber is interested in, as they come off the bus.
+pub type BusStream = BoxStream<'static, Result<(SystemEvent, Vec<u8>), RecvError>>;
 
 #[derive(Clone)]
 pub struct EventBus {
@@ -8,35 +37,61 @@ pub struct EventBus {
 }
 
 impl EventBus {
-    pub fn new() -> Self {
-        let (bus, _) = broadcast::channel(1_024);
+    /// Subscribers that fall more than `capacity` events behind skip ahead to the oldest event
+    /// still held. The capacity must be greater than zero.
+    pub fn new(capacity: usize) -> Self {
+        let (bus, _) = broadcast::channel(capacity);
         Self { bus }
     }
 
-    pub fn send(
-        &self,
-        event: SystemEvent,
-        payload: &impl Serialize,
-    ) -> Result<usize, EventBusError> {
+    /// Publishes an event tagged with the type it is always sent as, so subscribers can rely on
+    /// the payload matching the tag.
+    pub fn send_typed<E: BusEvent>(&self, event: &E) -> Result<usize, EventBusError> {
         let bin_code_config = bincode::DefaultOptions::new();
 
         let bytes = bin_code_config
-            .serialize(payload)
+            .serialize(event)
             .map_err(EventBusError::Serialization)?;
 
         self.bus
-            .send((event, bytes))
+            .send((E::EVENT, bytes))
             .map_err(EventBusError::SendFailed)
     }
 
+    /// How many receivers are currently listening for events.
+    pub fn subscriber_count(&self) -> usize {
+        self.bus.receiver_count()
+    }
+
     pub fn subscribe(&self) -> broadcast::Receiver<(SystemEvent, Vec<u8>)> {
         self.bus.subscribe()
     }
+
+    /// Receives only the listed events, anything else is skipped without its payload being looked
+    /// at. Falling behind the bus is still reported as [`RecvError::Lagged`], counting the skipped
+    /// events of every type. The stream ends once the bus has been dropped.
+    pub fn subscribe_filtered(&self, events: &[SystemEvent]) -> BusStream {
+        let events: BTreeSet<SystemEvent> = events.iter().copied().collect();
+
+        stream::unfold((self.subscribe(), events), |(mut rx, events)| async move {
+            loop {
+                match rx.recv().await {
+                    Ok((event, payload)) if events.contains(&event) => {
+                        return Some((Ok((event, payload)), (rx, events)));
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Closed) => return None,
+                    Err(err) => return Some((Err(err), (rx, events))),
+                }
+            }
+        })
+        .boxed()
+    }
 }
 
 impl Default for EventBus {
     fn default() -> Self {
-        Self::new()
+        Self::new(DEFAULT_EVENT_BUS_CAPACITY)
     }
 }
 
@@ -49,25 +104,125 @@ pub enum EventBusError {
     Serialization(bincode::Error),
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 #[serde(rename_all = "snake_case")]
 #[non_exhaustive]
 pub enum SystemEvent {
+    ReauthenticationRequired,
+    SessionRevoked,
+    SuspiciousLogin,
     TestEvent,
     Tick,
     UserRegistration,
 }
 
+use crate::database::custom_types::UserId;
+
+/// A user successfully logged in from an address unlike any of their recent sessions. These
+/// contain client addresses and are only intended for internal consumers.
+#[derive(Deserialize, Serialize)]
+pub struct SuspiciousLogin {
+    pub user_id: UserId,
+    pub client_ip: std::net::IpAddr,
+}
+
+impl BusEvent for SuspiciousLogin {
+    const EVENT: SystemEvent = SystemEvent::SuspiciousLogin;
+}
+
+use crate::database::custom_types::LoginProvider;
+
+/// The provider refused to renew the access token for one of the user's linked accounts. The user
+/// needs to log in with that provider again before anything relying on the account will work.
+#[derive(Deserialize, Serialize)]
+pub struct ReauthenticationRequired {
+    pub user_id: UserId,
+    pub provider: LoginProvider,
+}
+
+impl BusEvent for ReauthenticationRequired {
+    const EVENT: SystemEvent = SystemEvent::ReauthenticationRequired;
+}
+
 use crate::database::custom_types::SessionId;
 
+/// A user ended one of their sessions remotely. Anything still connected using that session needs
+/// to be disconnected.
+#[derive(Deserialize, Serialize)]
+pub struct SessionRevoked {
+    pub session_id: SessionId,
+}
+
+impl BusEvent for SessionRevoked {
+    const EVENT: SystemEvent = SystemEvent::SessionRevoked;
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct TestEvent {
     pub session_id: SessionId,
 }
 
-use crate::database::custom_types::UserId;
+impl BusEvent for TestEvent {
+    const EVENT: SystemEvent = SystemEvent::TestEvent;
+}
 
 #[derive(Deserialize, Serialize)]
 pub struct UserRegistration {
     pub id: UserId,
 }
+
+impl BusEvent for UserRegistration {
+    const EVENT: SystemEvent = SystemEvent::UserRegistration;
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn test_event() -> TestEvent {
+        TestEvent {
+            session_id: SessionId::from(Uuid::new_v4()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_typed_events_tagged_with_their_type() {
+        let event_bus = EventBus::default();
+        let mut rx = event_bus.subscribe();
+
+        let event = test_event();
+        event_bus.send_typed(&event).unwrap();
+
+        let (event_type, payload) = rx.recv().await.unwrap();
+        assert_eq!(event_type, SystemEvent::TestEvent);
+        assert_eq!(
+            TestEvent::decode(&payload).unwrap().session_id,
+            event.session_id
+        );
+    }
+
+    #[tokio::test]
+    async fn test_filtered_subscriptions_skip_other_events() {
+        let event_bus = EventBus::default();
+        let mut ticks_only = event_bus.subscribe_filtered(&[SystemEvent::Tick]);
+
+        event_bus.send_typed(&test_event()).unwrap();
+        event_bus
+            .send_typed(&UserRegistration {
+                id: UserId::from(Uuid::new_v4()),
+            })
+            .unwrap();
+        event_bus
+            .send_typed(&crate::background_jobs::impls::TickMessage::at(
+                time::OffsetDateTime::UNIX_EPOCH,
+            ))
+            .unwrap();
+        drop(event_bus);
+
+        let (event_type, _) = ticks_only.next().await.unwrap().unwrap();
+        assert_eq!(event_type, SystemEvent::Tick);
+        assert!(ticks_only.next().await.is_none());
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+
+use crate::event_bus::{BusEvent, SystemEvent};
+
+type DecodeFn = fn(&[u8]) -> Result<serde_json::Value, BusEventDecodeError>;
+
+/// Turns the binary payloads on the bus back into JSON for the event types that have been
+/// registered. Events that were never registered have no decoder, which is how events that should
+/// stay inside the service are kept that way.
+#[derive(Clone, Default)]
+pub struct BusEventRegistry {
+    decoders: BTreeMap<SystemEvent, DecodeFn>,
+}
+
+impl BusEventRegistry {
+    /// Decodes the payload of an event, producing nothing when the event type isn't registered.
+    pub fn decode(
+        &self,
+        event: SystemEvent,
+        payload: &[u8],
+    ) -> Option<Result<serde_json::Value, BusEventDecodeError>> {
+        self.decoders.get(&event).map(|decode| decode(payload))
+    }
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<E: BusEvent>(mut self) -> Self {
+        self.decoders.insert(E::EVENT, decode_as::<E>);
+        self
+    }
+}
+
+fn decode_as<E: BusEvent>(payload: &[u8]) -> Result<serde_json::Value, BusEventDecodeError> {
+    let event = E::decode(payload).map_err(BusEventDecodeError::Payload)?;
+    event.to_client_json().map_err(BusEventDecodeError::Json)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BusEventDecodeError {
+    #[error("event couldn't be represented as JSON: {0}")]
+    Json(serde_json::Error),
+
+    #[error("payload didn't match the event type: {0}")]
+    Payload(bincode::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use bincode::Options;
+    use serde::Serialize;
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::background_jobs::impls::TickMessage;
+    use crate::database::custom_types::{LoginProvider, SessionId, UserId};
+    use crate::event_bus::{
+        ReauthenticationRequired, SessionRevoked, SuspiciousLogin, TestEvent, UserRegistration,
+    };
+
+    fn encode(event: &impl Serialize) -> Vec<u8> {
+        bincode::DefaultOptions::new().serialize(event).unwrap()
+    }
+
+    fn round_trip<E: BusEvent>(event: E) -> serde_json::Value {
+        let registry = BusEventRegistry::new().register::<E>();
+        registry
+            .decode(E::EVENT, &encode(&event))
+            .expect("event to be registered")
+            .expect("payload to decode")
+    }
+
+    #[test]
+    fn test_events_round_trip() {
+        let user_id = UserId::from(uuid::Uuid::new_v4());
+        let session_id = SessionId::from(uuid::Uuid::new_v4());
+
+        let json = round_trip(ReauthenticationRequired {
+            user_id,
+            provider: LoginProvider::Google,
+        });
+        assert_eq!(json["user_id"], serde_json::to_value(user_id).unwrap());
+
+        let json = round_trip(SessionRevoked { session_id });
+        assert_eq!(
+            json["session_id"],
+            serde_json::to_value(session_id).unwrap()
+        );
+
+        let json = round_trip(SuspiciousLogin {
+            user_id,
+            client_ip: std::net::Ipv4Addr::LOCALHOST.into(),
+        });
+        assert_eq!(json["client_ip"], "127.0.0.1");
+
+        let json = round_trip(TestEvent { session_id });
+        assert_eq!(
+            json["session_id"],
+            serde_json::to_value(session_id).unwrap()
+        );
+
+        let json = round_trip(UserRegistration { id: user_id });
+        assert_eq!(json["id"], serde_json::to_value(user_id).unwrap());
+
+        // Ticks are handed out with a standard timestamp rather than the internal encoding
+        let json = round_trip(TickMessage::at(OffsetDateTime::UNIX_EPOCH));
+        assert_eq!(json["time"], "1970-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn test_unregistered_and_mismatched_events() {
+        let registry = BusEventRegistry::new().register::<UserRegistration>();
+        let payload = encode(&UserRegistration {
+            id: UserId::from(uuid::Uuid::new_v4()),
+        });
+
+        assert!(registry
+            .decode(SystemEvent::SuspiciousLogin, &payload)
+            .is_none());
+        assert!(matches!(
+            registry.decode(SystemEvent::UserRegistration, &[]),
+            Some(Err(BusEventDecodeError::Payload(_)))
+        ));
+    }
+}
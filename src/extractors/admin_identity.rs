@@ -0,0 +1,89 @@
+use axum::async_trait;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::response::{IntoResponse, Response};
+use http::request::Parts;
+use http::StatusCode;
+
+use crate::api::ApiError;
+use crate::app::AdminList;
+use crate::database::custom_types::UserId;
+use crate::database::models::{User, UserError};
+use crate::database::Database;
+use crate::extractors::session_identity::SessionIdentityError;
+use crate::extractors::SessionIdentity;
+
+/// A session belonging to one of the users configured as an administrator. Requests from anyone
+/// else are rejected as forbidden.
+pub struct AdminIdentity {
+    session: SessionIdentity,
+}
+
+impl AdminIdentity {
+    pub fn user_id(&self) -> UserId {
+        self.session.user_id()
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminIdentity
+where
+    AdminList: FromRef<S>,
+    Database: FromRef<S>,
+    SessionIdentity: FromRequestParts<S, Rejection = SessionIdentityError>,
+    S: Send + Sync,
+{
+    type Rejection = AdminIdentityError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let session = SessionIdentity::from_request_parts(parts, state).await?;
+
+        let admin_list = AdminList::from_ref(state);
+        if admin_list.is_empty() {
+            return Err(AdminIdentityError::NotAnAdmin);
+        }
+
+        let database = Database::from_ref(state);
+        let mut conn = database
+            .acquire()
+            .await
+            .map_err(AdminIdentityError::DatabaseConnection)?;
+
+        match User::email_for(&mut conn, session.user_id()).await? {
+            Some(email) if admin_list.contains(&email) => Ok(AdminIdentity { session }),
+            _ => Err(AdminIdentityError::NotAnAdmin),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AdminIdentityError {
+    #[error("issue with database connection: {0}")]
+    DatabaseConnection(sqlx::Error),
+
+    #[error("unable to lookup user: {0}")]
+    LookupFailed(#[from] UserError),
+
+    #[error("user is not an administrator")]
+    NotAnAdmin,
+
+    #[error("no valid session: {0}")]
+    Session(#[from] SessionIdentityError),
+}
+
+impl IntoResponse for AdminIdentityError {
+    fn into_response(self) -> Response {
+        match self {
+            AdminIdentityError::NotAnAdmin => ApiError::new(
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                "administrator access is required",
+            )
+            .into_response(),
+            AdminIdentityError::Session(err) => err.into_response(),
+            err => {
+                tracing::error!("failed to verify administrator: {err}");
+                ApiError::internal().into_response()
+            }
+        }
+    }
+}
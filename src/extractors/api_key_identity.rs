@@ -1,34 +1,20 @@
 #![allow(dead_code)]
 
 use std::collections::HashSet;
-use std::sync::OnceLock;
 
 use axum::extract::{FromRef, FromRequestParts};
 use axum::response::{IntoResponse, Response};
-use axum::{async_trait, Json, RequestPartsExt};
-use axum_extra::typed_header::TypedHeaderRejection;
-use axum_extra::TypedHeader;
-use headers::authorization::Bearer;
-use headers::Authorization;
+use axum::{async_trait, Json};
 use http::request::Parts;
-use http::StatusCode;
+use http::{header, HeaderMap, StatusCode};
 use jwt_simple::prelude::*;
-use regex::Regex;
+use jwt_simple::JWTError;
 use uuid::Uuid;
 
-use crate::database::custom_types::Fingerprint;
+use crate::database::custom_types::{Fingerprint, UserId};
 use crate::database::models::ApiKey;
 use crate::database::Database;
 
-/// Defines the maximum length of time we consider any individual token valid in seconds. If the
-/// expiration is still in the future, but it was issued more than this many seconds in the past
-/// we'll reject the token even if its otherwise valid.
-const MAXIMUM_TOKEN_AGE: u64 = 900;
-
-static KEY_ID_PATTERN: &str = r"^[0-9a-f]{64}$";
-
-static KEY_ID_VALIDATOR: OnceLock<Regex> = OnceLock::new();
-
 pub struct ApiKeyIdentity {
     user_id: Uuid,
     key_id: String,
@@ -47,106 +33,213 @@ impl ApiKeyIdentity {
 #[async_trait]
 impl<S> FromRequestParts<S> for ApiKeyIdentity
 where
+    ApiTokenTiming: FromRef<S>,
     Database: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = ApiKeyIdentityError;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let key_validator = KEY_ID_VALIDATOR.get_or_init(|| Regex::new(KEY_ID_PATTERN).unwrap());
+        let token = bearer_token(&parts.headers)?;
 
-        let TypedHeader(Authorization(bearer)) = parts
-            .extract::<TypedHeader<Authorization<Bearer>>>()
-            .await
-            .map_err(ApiKeyIdentityError::MissingHeader)?;
+        let database = Database::from_ref(state);
+        let timing = ApiTokenTiming::from_ref(state);
+        identify(&database, &timing, token).await
+    }
+}
 
-        let raw_token = bearer.token();
+/// How strictly the times in API tokens are checked.
+#[derive(Clone, Copy, Debug)]
+pub struct ApiTokenTiming {
+    clock_skew: std::time::Duration,
+    max_age: std::time::Duration,
+}
 
-        let unvalidated_header =
-            Token::decode_metadata(raw_token).map_err(ApiKeyIdentityError::CorruptHeader)?;
+impl ApiTokenTiming {
+    /// # Arguments
+    ///
+    /// * `clock_skew` - How far the client's clock may be from ours in either direction.
+    /// * `max_age` - Tokens issued longer ago than this are rejected even if they haven't
+    ///   expired yet.
+    pub fn new(clock_skew: std::time::Duration, max_age: std::time::Duration) -> Self {
+        Self {
+            clock_skew,
+            max_age,
+        }
+    }
+}
 
-        let key_id = match unvalidated_header.key_id() {
-            Some(kid) if key_validator.is_match(kid) => kid.to_string(),
-            Some(_) => return Err(ApiKeyIdentityError::InvalidKeyId),
-            None => return Err(ApiKeyIdentityError::MissingKeyId),
-        };
+/// Pulls the token out of the Authorization header, telling apart a missing header, one using
+/// some other scheme, and a bearer scheme with nothing after it.
+fn bearer_token(headers: &HeaderMap) -> Result<&str, ApiKeyIdentityError> {
+    let value = headers
+        .get(header::AUTHORIZATION)
+        .ok_or(ApiKeyIdentityError::MissingHeader)?
+        .to_str()
+        .map_err(|_| ApiKeyIdentityError::MalformedHeader)?
+        .trim();
+
+    let (scheme, token) = value.split_once(' ').unwrap_or((value, ""));
+    if !scheme.eq_ignore_ascii_case("Bearer") {
+        return Err(ApiKeyIdentityError::MalformedHeader);
+    }
 
-        let database = Database::from_ref(state);
-        let mut conn = database
+    match token.trim() {
+        "" => Err(ApiKeyIdentityError::EmptyToken),
+        token => Ok(token),
+    }
+}
+
+/// Finds the key a token claims to have been signed with, based on the key ID in its header.
+#[async_trait]
+pub trait SessionKeyProvider {
+    type Error: std::error::Error + Send + Sync;
+
+    async fn lookup(&self, fingerprint: &Fingerprint) -> Result<Option<SessionKey>, Self::Error>;
+}
+
+/// A key that tokens can be verified against, along with the only user those tokens are allowed
+/// to act as.
+pub struct SessionKey {
+    public_key: ES384PublicKey,
+    user_id: UserId,
+}
+
+/// Looks up the API keys users have registered with us.
+#[async_trait]
+impl SessionKeyProvider for Database {
+    type Error = ApiKeyIdentityError;
+
+    async fn lookup(&self, fingerprint: &Fingerprint) -> Result<Option<SessionKey>, Self::Error> {
+        let mut conn = self
             .acquire()
             .await
             .map_err(ApiKeyIdentityError::DatabaseUnavailable)?;
 
-        let fingerprint = Fingerprint::from_hex_str(&key_id).expect("valid fingerprint");
-        let _api_key = ApiKey::from_fingerprint(&fingerprint);
-
-        // todo create a generic "SessionKeyProvider" that takes a key ID and returns an
-        //   appropriate verification key, should use that instead of a JwtKey directly
-        //   I can implement a static provider that matches the token key against our regular
-        //   one.
-        //
-        //#[axum::async_trait]
-        //trait SessionKeyProvider {
-        //    type Error: std::error::Error + Send + Sync;
-        //
-        //    async fn lookup(key_id: &str) -> Result<SessionKey, Self::Error>;
-        //}
-
-        let _verification_options = VerificationOptions {
-            accept_future: false,
-            // todo: tokens should be intended for us, make this a configurable service name we can
-            // re-use and reference
-            allowed_audiences: Some(HashSet::from_strings(&[env!("CARGO_PKG_NAME")])),
-            max_validity: Some(Duration::from_secs(MAXIMUM_TOKEN_AGE)),
-            time_tolerance: Some(Duration::from_secs(15)),
-            ..Default::default()
+        let api_key = ApiKey::from_fingerprint(&mut conn, fingerprint)
+            .await
+            .map_err(ApiKeyIdentityError::DatabaseUnavailable)?;
+
+        let Some(api_key) = api_key else {
+            return Ok(None);
         };
 
-        //let claims = jwt_key
-        //    .as_ref()
-        //    .public_key()
-        //    .verify_token::<NoCustomClaims>(&raw_token, Some(verification_options))
-        //    .map_err(Self::Rejection::validation_failed)?;
-
-        //if claims.nonce.is_none() {
-        //    return Err(Self::Rejection::NonceMissing);
-        //}
-
-        // TODO: When the JWT is validated we should record the issued_at timestamp and record it
-        // associated to the specific API key. Future requests should compare against the issued
-        // time to prevent replay attacks from old tokens. We do keep the token age short to limit
-        // the possibility of this happening and should also check based on IP. Might want to treat
-        // these as sessions of a sort even to capture the same kind of metrics and streamline
-        // authorization checks into a single session type.
-
-        //// todo: validate subject is present, do I need any extra validation?
-        //tracing::info!("{claims:?}");
-        //let user_id = match &claims.subject {
-        //    Some(sub) => Uuid::parse_str(sub).map_err(|_| Self::Rejection::SubjectInvalid)?,
-        //    None => return Err(Self::Rejection::SubjectMissing),
-        //};
-
-        //Ok(ApiKeyIdentity { user_id, key_id })
-        todo!()
+        let public_key = ES384PublicKey::from_bytes(api_key.public_key())
+            .map_err(ApiKeyIdentityError::CorruptKey)?;
+
+        Ok(Some(SessionKey {
+            public_key,
+            user_id: api_key.user_id(),
+        }))
     }
 }
 
+async fn identify<P>(
+    key_provider: &P,
+    timing: &ApiTokenTiming,
+    raw_token: &str,
+) -> Result<ApiKeyIdentity, ApiKeyIdentityError>
+where
+    P: SessionKeyProvider + Sync,
+    ApiKeyIdentityError: From<P::Error>,
+{
+    // A compact JWT is always a header, payload, and signature separated by dots
+    let segments: Vec<_> = raw_token.split('.').collect();
+    if segments.len() != 3 || segments.iter().any(|s| s.is_empty()) {
+        return Err(ApiKeyIdentityError::NotJwt);
+    }
+
+    let unvalidated_header =
+        Token::decode_metadata(raw_token).map_err(ApiKeyIdentityError::CorruptHeader)?;
+
+    let key_id = unvalidated_header
+        .key_id()
+        .ok_or(ApiKeyIdentityError::MissingKeyId)?
+        .to_string();
+
+    let fingerprint =
+        Fingerprint::from_key_id(&key_id).map_err(|_| ApiKeyIdentityError::InvalidKeyId)?;
+    let session_key = key_provider
+        .lookup(&fingerprint)
+        .await?
+        .ok_or(ApiKeyIdentityError::UnknownKey)?;
+
+    let verification_options = VerificationOptions {
+        accept_future: false,
+        // todo: tokens should be intended for us, make this a configurable service name we can
+        // re-use and reference
+        allowed_audiences: Some(HashSet::from_strings(&[env!("CARGO_PKG_NAME")])),
+        max_validity: Some(Duration::from_secs(timing.max_age.as_secs())),
+        time_tolerance: Some(Duration::from_secs(timing.clock_skew.as_secs())),
+        ..Default::default()
+    };
+
+    let claims = session_key
+        .public_key
+        .verify_token::<NoCustomClaims>(raw_token, Some(verification_options))
+        .map_err(|err| {
+            // Tokens from the future mean the client's clock is ahead of ours, a client can't
+            // fix that by signing a new token so make sure someone finds out about it
+            if let Some(JWTError::ClockDrift | JWTError::TokenNotValidYet) = err.downcast_ref() {
+                tracing::warn!(
+                    key_id,
+                    clock_skew = timing.clock_skew.as_secs(),
+                    "rejected an API token issued in the future, the client's clock is likely wrong"
+                );
+            }
+
+            ApiKeyIdentityError::ValidationFailed(err)
+        })?;
+
+    if claims.nonce.is_none() {
+        return Err(ApiKeyIdentityError::NonceMissing);
+    }
+
+    // TODO: When the JWT is validated we should record the issued_at timestamp and record it
+    // associated to the specific API key. Future requests should compare against the issued
+    // time to prevent replay attacks from old tokens. We do keep the token age short to limit
+    // the possibility of this happening and should also check based on IP. Might want to treat
+    // these as sessions of a sort even to capture the same kind of metrics and streamline
+    // authorization checks into a single session type.
+
+    let user_id = match &claims.subject {
+        Some(sub) => Uuid::parse_str(sub).map_err(|_| ApiKeyIdentityError::SubjectInvalid)?,
+        None => return Err(ApiKeyIdentityError::SubjectMissing),
+    };
+
+    // A key can only ever be used to act as the user that registered it
+    if UserId::from(user_id) != session_key.user_id {
+        return Err(ApiKeyIdentityError::SubjectMismatch);
+    }
+
+    Ok(ApiKeyIdentity { user_id, key_id })
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ApiKeyIdentityError {
     #[error("provided JWT had an invalid or corrupt header")]
     CorruptHeader(jwt_simple::Error),
 
+    #[error("stored API key couldn't be parsed: {0}")]
+    CorruptKey(jwt_simple::Error),
+
     #[error("database connection error: {0}")]
     DatabaseUnavailable(sqlx::Error),
 
+    #[error("authorization header didn't include a bearer token")]
+    EmptyToken,
+
     #[error("key ID included in JWT header did not match our expected format")]
     InvalidKeyId,
 
     #[error("unable to find JWT verification key in server state")]
     KeyUnavailable,
 
+    #[error("authorization header wasn't a bearer token")]
+    MalformedHeader,
+
     #[error("authenticated route was missing authorization header")]
-    MissingHeader(TypedHeaderRejection),
+    MissingHeader,
 
     #[error("no key ID was included in the JWT header")]
     MissingKeyId,
@@ -154,12 +247,21 @@ pub enum ApiKeyIdentityError {
     #[error("no nonce was included in the token")]
     NonceMissing,
 
+    #[error("bearer token wasn't a JWT")]
+    NotJwt,
+
     #[error("provided subject was not a valid UUID")]
     SubjectInvalid,
 
     #[error("no subject was included in the token")]
     SubjectMissing,
 
+    #[error("token subject wasn't the owner of the key it was signed with")]
+    SubjectMismatch,
+
+    #[error("no API key matched the key ID in the JWT header")]
+    UnknownKey,
+
     #[error("validation of the provided JWT failed")]
     ValidationFailed(jwt_simple::Error),
 }
@@ -169,15 +271,363 @@ impl IntoResponse for ApiKeyIdentityError {
         use ApiKeyIdentityError::*;
 
         match self {
-            KeyUnavailable => {
+            CorruptKey(_) | DatabaseUnavailable(_) | KeyUnavailable => {
+                tracing::error!("{self}");
                 let err_msg =
                     serde_json::json!({ "status": "authentication services unavailable" });
                 (StatusCode::INTERNAL_SERVER_ERROR, Json(err_msg)).into_response()
             }
-            _ => {
-                let err_msg = serde_json::json!({ "status": "invalid bearer token" });
+            // The request never got as far as naming a key, telling the client exactly what was
+            // wrong with it doesn't reveal anything. None of these messages include details of
+            // the underlying errors.
+            CorruptHeader(_) | EmptyToken | InvalidKeyId | MalformedHeader | MissingHeader
+            | MissingKeyId | NotJwt => {
+                let err_msg = serde_json::json!({ "status": self.to_string() });
                 (StatusCode::BAD_REQUEST, Json(err_msg)).into_response()
             }
+            // Anything past this point could be used to probe which keys exist and who they
+            // belong to, so all of these look the same from the outside
+            NonceMissing | SubjectInvalid | SubjectMismatch | SubjectMissing | UnknownKey
+            | ValidationFailed(_) => {
+                let err_msg = serde_json::json!({ "status": "invalid bearer token" });
+                (StatusCode::UNAUTHORIZED, Json(err_msg)).into_response()
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use http::header::AUTHORIZATION;
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::database::models::CreateUser;
+    use crate::tests::prelude::*;
+
+    struct TestKey {
+        database: Database,
+        key_pair: ES384KeyPair,
+        user_id: Uuid,
+    }
+
+    /// Registers a new key for a new user.
+    async fn registered_key() -> TestKey {
+        let database = Database::new(migrated_test_database().await);
+        let mut conn = database.acquire().await.unwrap();
+
+        let user_id = CreateUser::new("api@example.com", "API User")
+            .save(&mut conn)
+            .await
+            .unwrap();
+
+        let key_pair = ES384KeyPair::generate();
+        let public_key = key_pair.public_key();
+        let fingerprint = Fingerprint::from_public_key(&public_key);
+
+        sqlx::query("INSERT INTO api_keys (user_id, fingerprint, public_key) VALUES ($1, $2, $3);")
+            .bind(user_id)
+            .bind(fingerprint.as_bytes())
+            .bind(public_key.to_bytes())
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+
+        TestKey {
+            database: database.clone(),
+            key_pair: key_pair.with_key_id(&fingerprint.to_string()),
+            user_id: Uuid::from(user_id),
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestState {
+        database: Database,
+        timing: ApiTokenTiming,
+    }
+
+    impl FromRef<TestState> for ApiTokenTiming {
+        fn from_ref(state: &TestState) -> Self {
+            state.timing
+        }
+    }
+
+    impl FromRef<TestState> for Database {
+        fn from_ref(state: &TestState) -> Self {
+            state.database.clone()
+        }
+    }
+
+    fn default_timing() -> ApiTokenTiming {
+        ApiTokenTiming::new(
+            std::time::Duration::from_secs(15),
+            std::time::Duration::from_secs(900),
+        )
+    }
+
+    fn claims_for(user_id: Uuid) -> JWTClaims<NoCustomClaims> {
+        Claims::create(Duration::from_secs(300))
+            .with_audience(env!("CARGO_PKG_NAME"))
+            .with_subject(user_id)
+            .with_nonce("test-nonce")
+    }
+
+    async fn identify_request(
+        database: &Database,
+        token: &str,
+    ) -> Result<ApiKeyIdentity, ApiKeyIdentityError> {
+        let authorization = format!("Bearer {token}");
+        identify_with(database, default_timing(), Some(&authorization)).await
+    }
+
+    async fn identify_with_header(
+        database: &Database,
+        authorization: Option<&str>,
+    ) -> Result<ApiKeyIdentity, ApiKeyIdentityError> {
+        identify_with(database, default_timing(), authorization).await
+    }
+
+    async fn identify_with(
+        database: &Database,
+        timing: ApiTokenTiming,
+        authorization: Option<&str>,
+    ) -> Result<ApiKeyIdentity, ApiKeyIdentityError> {
+        let mut request = http::Request::get("/");
+        if let Some(authorization) = authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+
+        let state = TestState {
+            database: database.clone(),
+            timing,
+        };
+        ApiKeyIdentity::from_request_parts(&mut parts, &state).await
+    }
+
+    #[tokio::test]
+    async fn test_malformed_authorization_rejected() {
+        let key = registered_key().await;
+
+        let result = identify_with_header(&key.database, None).await;
+        assert!(matches!(result, Err(ApiKeyIdentityError::MissingHeader)));
+
+        for header in ["Basic dXNlcjpwYXNz", "Bearertoken", ""] {
+            let result = identify_with_header(&key.database, Some(header)).await;
+            assert!(matches!(result, Err(ApiKeyIdentityError::MalformedHeader)));
+        }
+
+        for header in ["Bearer", "Bearer ", "bearer    "] {
+            let result = identify_with_header(&key.database, Some(header)).await;
+            assert!(matches!(result, Err(ApiKeyIdentityError::EmptyToken)));
+        }
+
+        for token in ["not-a-jwt", "a.b", "a..c", "a.b.c.d"] {
+            let result = identify_request(&key.database, token).await;
+            assert!(matches!(result, Err(ApiKeyIdentityError::NotJwt)));
+        }
+
+        let result = identify_request(&key.database, "e30.e30.c2ln").await;
+        assert!(matches!(result, Err(ApiKeyIdentityError::CorruptHeader(_))));
+
+        let response = ApiKeyIdentityError::EmptyToken.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["status"],
+            "authorization header didn't include a bearer token"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_or_invalid_key_id_rejected() {
+        let key = registered_key().await;
+
+        let without_key_id = ES384KeyPair::generate();
+        let token = without_key_id.sign(claims_for(key.user_id)).unwrap();
+        let result = identify_request(&key.database, &token).await;
+        assert!(matches!(result, Err(ApiKeyIdentityError::MissingKeyId)));
+
+        let bad_key_id = ES384KeyPair::generate().with_key_id("not-a-fingerprint");
+        let token = bad_key_id.sign(claims_for(key.user_id)).unwrap();
+        let result = identify_request(&key.database, &token).await;
+        assert!(matches!(result, Err(ApiKeyIdentityError::InvalidKeyId)));
+    }
+
+    #[tokio::test]
+    async fn test_valid_token_identifies_key_owner() {
+        let key = registered_key().await;
+        let token = key.key_pair.sign(claims_for(key.user_id)).unwrap();
+
+        let identity = identify_request(&key.database, &token).await.unwrap();
+        assert_eq!(identity.user_id(), &key.user_id);
+        assert_eq!(identity.key_id(), key.key_pair.key_id().as_deref().unwrap());
+
+        // Keys can't be used to act as anyone other than their owner
+        let token = key.key_pair.sign(claims_for(Uuid::new_v4())).unwrap();
+        let result = identify_request(&key.database, &token).await;
+        assert!(matches!(result, Err(ApiKeyIdentityError::SubjectMismatch)));
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_rejected() {
+        let key = registered_key().await;
+
+        let mut claims = claims_for(key.user_id);
+        let now = Clock::now_since_epoch();
+        claims.issued_at = Some(now - Duration::from_secs(600));
+        claims.invalid_before = claims.issued_at;
+        claims.expires_at = Some(now - Duration::from_secs(300));
+
+        let token = key.key_pair.sign(claims).unwrap();
+        let result = identify_request(&key.database, &token).await;
+        assert!(matches!(
+            result,
+            Err(ApiKeyIdentityError::ValidationFailed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_clock_skew_tolerance_configurable() {
+        let key = registered_key().await;
+
+        // A client whose clock is a minute ahead of ours
+        let mut claims = claims_for(key.user_id);
+        let skew = Duration::from_secs(60);
+        claims.issued_at = claims.issued_at.map(|iat| iat + skew);
+        claims.invalid_before = claims.invalid_before.map(|nbf| nbf + skew);
+        claims.expires_at = claims.expires_at.map(|exp| exp + skew);
+
+        let token = key.key_pair.sign(claims).unwrap();
+        let authorization = format!("Bearer {token}");
+
+        let result = identify_with(&key.database, default_timing(), Some(&authorization)).await;
+        assert!(matches!(
+            result,
+            Err(ApiKeyIdentityError::ValidationFailed(_))
+        ));
+
+        let loose = ApiTokenTiming::new(
+            std::time::Duration::from_secs(120),
+            std::time::Duration::from_secs(900),
+        );
+        let identity = identify_with(&key.database, loose, Some(&authorization))
+            .await
+            .unwrap();
+        assert_eq!(identity.user_id(), &key.user_id);
+    }
+
+    #[tokio::test]
+    async fn test_max_token_age_configurable() {
+        let key = registered_key().await;
+
+        let mut claims = claims_for(key.user_id);
+        claims.issued_at = Some(Clock::now_since_epoch() - Duration::from_secs(120));
+        claims.invalid_before = claims.issued_at;
+
+        let token = key.key_pair.sign(claims).unwrap();
+        let authorization = format!("Bearer {token}");
+
+        let strict = ApiTokenTiming::new(
+            std::time::Duration::from_secs(15),
+            std::time::Duration::from_secs(60),
+        );
+        let result = identify_with(&key.database, strict, Some(&authorization)).await;
+        assert!(matches!(
+            result,
+            Err(ApiKeyIdentityError::ValidationFailed(_))
+        ));
+
+        assert!(identify_request(&key.database, &token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_rotated_key_accepted_until_grace_window_ends() {
+        let key = registered_key().await;
+        let old_fingerprint = Fingerprint::from_public_key(&key.key_pair.public_key());
+
+        let replacement = ES384KeyPair::generate();
+        let new_fingerprint = Fingerprint::from_public_key(&replacement.public_key());
+        let replacement = replacement.with_key_id(&new_fingerprint.to_string());
+
+        let mut conn = key.database.acquire().await.unwrap();
+        let grace_ends = OffsetDateTime::now_utc() + std::time::Duration::from_secs(300);
+        ApiKey::from_fingerprint(&mut conn, &old_fingerprint)
+            .await
+            .unwrap()
+            .unwrap()
+            .rotate(
+                &mut conn,
+                &replacement.public_key().to_bytes(),
+                &new_fingerprint,
+                grace_ends,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Requests signed with either key are accepted while the old one is being phased out
+        let old_token = key.key_pair.sign(claims_for(key.user_id)).unwrap();
+        let new_token = replacement.sign(claims_for(key.user_id)).unwrap();
+        assert!(identify_request(&key.database, &old_token).await.is_ok());
+        assert!(identify_request(&key.database, &new_token).await.is_ok());
+
+        let grace_ended = OffsetDateTime::now_utc() - std::time::Duration::from_secs(1);
+        sqlx::query("UPDATE api_keys SET revoked_at = $1 WHERE fingerprint = $2;")
+            .bind(grace_ended)
+            .bind(old_fingerprint.as_bytes())
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+
+        let result = identify_request(&key.database, &old_token).await;
+        assert!(matches!(result, Err(ApiKeyIdentityError::UnknownKey)));
+        assert!(identify_request(&key.database, &new_token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_key_and_forged_subject_indistinguishable() {
+        let key = registered_key().await;
+
+        let unregistered = ES384KeyPair::generate();
+        let fingerprint = Fingerprint::from_public_key(&unregistered.public_key());
+        let unregistered = unregistered.with_key_id(&fingerprint.to_string());
+        let unknown_key_token = unregistered.sign(claims_for(key.user_id)).unwrap();
+
+        let forged_subject_token = key.key_pair.sign(claims_for(Uuid::new_v4())).unwrap();
+
+        let mut responses = Vec::new();
+        for token in [unknown_key_token, forged_subject_token] {
+            let err = match identify_request(&key.database, &token).await {
+                Ok(_) => panic!("token should have been rejected"),
+                Err(err) => err,
+            };
+
+            let response = err.into_response();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            responses.push((status, body));
+        }
+
+        assert_eq!(responses[0].0, StatusCode::UNAUTHORIZED);
+        assert_eq!(responses[0], responses[1]);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_key_rejected() {
+        let key = registered_key().await;
+
+        let unregistered = ES384KeyPair::generate();
+        let fingerprint = Fingerprint::from_public_key(&unregistered.public_key());
+        let unregistered = unregistered.with_key_id(&fingerprint.to_string());
+
+        let token = unregistered.sign(claims_for(key.user_id)).unwrap();
+        let result = identify_request(&key.database, &token).await;
+        assert!(matches!(result, Err(ApiKeyIdentityError::UnknownKey)));
+    }
+}
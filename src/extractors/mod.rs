@@ -1,9 +1,12 @@
+mod admin_identity;
 mod api_key_identity;
 mod requestor;
 mod server_base;
 mod session_identity;
 
-pub use api_key_identity::ApiKeyIdentity;
+pub use admin_identity::AdminIdentity;
+pub use api_key_identity::{ApiKeyIdentity, ApiTokenTiming};
 pub use requestor::Requestor;
-pub use server_base::ServerBase;
+pub use server_base::{request_scheme, ServerBase};
 pub use session_identity::SessionIdentity;
+pub(crate) use session_identity::{session_cookie_value, signed_session_id};
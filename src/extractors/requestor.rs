@@ -1,16 +1,33 @@
+use std::net::{IpAddr, SocketAddr};
+
 use async_trait::async_trait;
-use axum::extract::FromRequestParts;
+use axum::extract::{ConnectInfo, FromRef, FromRequestParts};
 use http::request::Parts;
 
+use crate::app::BotClassifier;
+
 pub struct Requestor {
     do_not_track: bool,
 
-    //client_ip: std::net::IpAddr,
-    //user_agent: String,
+    client_ip: Option<IpAddr>,
+    is_bot: bool,
     referrer: Option<String>,
+    user_agent: Option<String>,
 }
 
 impl Requestor {
+    /// The address of the peer that connected to us. This will only be missing when the service
+    /// isn't being run with connection information available (such as in tests).
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        self.client_ip
+    }
+
+    /// Whether the user agent identified this request as coming from a crawler or other automated
+    /// client. Metrics collection should generally ignore these requests.
+    pub fn is_bot(&self) -> bool {
+        self.is_bot
+    }
+
     pub fn is_private(&self) -> bool {
         self.do_not_track
     }
@@ -27,11 +44,16 @@ impl Requestor {
             self.referrer.clone()
         }
     }
+
+    pub fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
 }
 
 #[async_trait]
 impl<S> FromRequestParts<S> for Requestor
 where
+    BotClassifier: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = ();
@@ -39,9 +61,16 @@ where
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let mut requestor = Self {
             do_not_track: false,
+            client_ip: None,
+            is_bot: false,
             referrer: None,
+            user_agent: None,
         };
 
+        if let Some(ConnectInfo(addr)) = parts.extensions.get::<ConnectInfo<SocketAddr>>() {
+            requestor.client_ip = Some(addr.ip());
+        }
+
         for val in parts.headers.get_all(http::header::REFERER) {
             if let Ok(new_ref) = val.to_str() {
                 requestor.referrer = match requestor.referrer {
@@ -51,6 +80,13 @@ where
             }
         }
 
+        requestor.user_agent = parts
+            .headers
+            .get(http::header::USER_AGENT)
+            .and_then(|ua| ua.to_str().ok())
+            .map(String::from);
+        requestor.is_bot = BotClassifier::from_ref(state).is_bot(requestor.user_agent());
+
         if let Some(dnt_val) = parts.headers.get(http::header::DNT) {
             if dnt_val == "1" {
                 requestor.do_not_track = true;
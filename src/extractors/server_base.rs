@@ -1,32 +1,70 @@
 use axum::async_trait;
 use axum::extract::rejection::HostRejection;
-use axum::extract::{FromRequestParts, Host};
+use axum::extract::{FromRef, FromRequestParts, Host};
+use axum::response::{IntoResponse, Response};
 use http::request::Parts;
+use http::{HeaderMap, StatusCode};
 use url::Url;
 
+use crate::app::AllowedHosts;
+
 const X_FORWARDED_SCHEME_HEADER_KEY: &str = "X-Forwarded-Proto";
 
+/// The scheme and host the client used to reach us, suitable for building absolute URLs back to
+/// the service. Only hosts in the [`AllowedHosts`] are ever produced.
 pub struct ServerBase(pub Url);
 
 #[async_trait]
 impl<S> FromRequestParts<S> for ServerBase
 where
+    AllowedHosts: FromRef<S>,
     S: Send + Sync,
 {
-    type Rejection = HostRejection;
+    type Rejection = ServerBaseRejection;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let connection_scheme = parts
-            .headers
-            .get(X_FORWARDED_SCHEME_HEADER_KEY)
-            .and_then(|scheme| scheme.to_str().ok())
-            .unwrap_or("http")
-            .to_string();
-
-        let host = Host::from_request_parts(parts, state).await?;
+        let connection_scheme = request_scheme(&parts.headers).to_string();
+
+        let host = Host::from_request_parts(parts, state)
+            .await
+            .map_err(ServerBaseRejection::UnknownHost)?;
+
+        if !AllowedHosts::from_ref(state).is_allowed(&host.0) {
+            return Err(ServerBaseRejection::DisallowedHost);
+        }
+
         let url = Url::parse(&format!("{connection_scheme}://{}", host.0))
-            .expect("built value to be valid");
+            .map_err(|_| ServerBaseRejection::DisallowedHost)?;
 
         Ok(ServerBase(url))
     }
 }
+
+/// The scheme the client used to reach us, as reported by a proxy in front of the service. Requests
+/// without the header are assumed to be plain HTTP.
+pub fn request_scheme(headers: &HeaderMap) -> &str {
+    headers
+        .get(X_FORWARDED_SCHEME_HEADER_KEY)
+        .and_then(|scheme| scheme.to_str().ok())
+        .unwrap_or("http")
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ServerBaseRejection {
+    #[error("request was addressed to a host that isn't allowed")]
+    DisallowedHost,
+
+    #[error("unable to determine the host of the request: {0}")]
+    UnknownHost(HostRejection),
+}
+
+impl IntoResponse for ServerBaseRejection {
+    fn into_response(self) -> Response {
+        match self {
+            ServerBaseRejection::DisallowedHost => {
+                (StatusCode::BAD_REQUEST, "invalid host").into_response()
+            }
+            ServerBaseRejection::UnknownHost(rejection) => rejection.into_response(),
+        }
+    }
+}
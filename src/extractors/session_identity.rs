@@ -4,19 +4,50 @@ use axum::response::{IntoResponse, Redirect, Response};
 use axum_extra::extract::cookie::CookieJar;
 use base64::engine::general_purpose::URL_SAFE_NO_PAD as B64;
 use base64::Engine;
-use ecdsa::signature::DigestVerifier;
+use ecdsa::elliptic_curve::generic_array::typenum::Unsigned;
+use ecdsa::signature::{DigestVerifier, RandomizedDigestSigner};
 use http::request::Parts;
 use jwt_simple::prelude::*;
 use time::OffsetDateTime;
 use uuid::Uuid;
 
-use crate::app::ServiceVerificationKey;
-use crate::auth::{LOGIN_PATH, SESSION_COOKIE_NAME};
+use crate::app::{ServiceSigningKey, ServiceVerificationKey};
+use crate::auth::{remove_session_cookies, SessionCookie, LOGIN_PATH};
 use crate::database::custom_types::{OAuthProviderAccountId, SessionId, UserId};
 use crate::database::models::Session;
 use crate::database::Database;
 use crate::extractors::Requestor;
-use crate::utils::remove_cookie;
+
+/// The service key signs the encoded session ID with ECDSA over this curve.
+type SessionSigningCurve = p384::NistP384;
+
+/// The signature carried alongside the session ID in the session cookie.
+pub(crate) type SessionSignature = ecdsa::Signature<SessionSigningCurve>;
+
+/// Session IDs are UUIDs, stored in the cookie as their raw bytes.
+const SESSION_ID_LEN: usize = 16;
+
+/// Fixed size signatures are the two scalars of the curve concatenated together.
+const SESSION_SIGNATURE_LEN: usize = <ecdsa::SignatureSize<SessionSigningCurve> as Unsigned>::USIZE;
+
+/// Identifies the layout of the rest of the cookie. Changing how sessions are identified or
+/// signed needs a new version so cookies issued before the change are recognized and rejected
+/// rather than misread.
+const SESSION_COOKIE_VERSION: &str = "v1";
+
+/// Ends the version prefix, this never appears in the URL-safe base64 used for the rest.
+const SESSION_COOKIE_VERSION_SEPARATOR: char = '.';
+
+const SESSION_COOKIE_PREFIX_LEN: usize = SESSION_COOKIE_VERSION.len() + 1;
+
+/// Both parts following the version are unpadded URL-safe base64. The session ID comes first,
+/// directly followed by the signature over the version prefix and encoded session ID.
+const SESSION_ID_ENCODED_LEN: usize = unpadded_b64_len(SESSION_ID_LEN);
+
+const SESSION_SIGNATURE_ENCODED_LEN: usize = unpadded_b64_len(SESSION_SIGNATURE_LEN);
+
+const SESSION_COOKIE_LEN: usize =
+    SESSION_COOKIE_PREFIX_LEN + SESSION_ID_ENCODED_LEN + SESSION_SIGNATURE_ENCODED_LEN;
 
 pub struct SessionIdentity {
     id: SessionId,
@@ -57,6 +88,7 @@ where
     Database: FromRef<S>,
     Requestor: FromRequestParts<S, Rejection = ()>,
     ServiceVerificationKey: FromRef<S>,
+    SessionCookie: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = SessionIdentityError;
@@ -64,7 +96,9 @@ where
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let cookie_jar: CookieJar = CookieJar::from_headers(&parts.headers);
 
-        let session_cookie = match cookie_jar.get(SESSION_COOKIE_NAME) {
+        let cookie_name = SessionCookie::from_ref(state).name_for_request(&parts.headers);
+
+        let session_cookie = match cookie_jar.get(cookie_name) {
             Some(st) => st,
             None => {
                 let OriginalUri(uri) = OriginalUri::from_request_parts(parts, state)
@@ -76,42 +110,8 @@ where
 
         // todo: some sanity checks on the cookie (path, security, is web only)
 
-        let raw_cookie_val = session_cookie.value();
-        if raw_cookie_val.len() != 150 {
-            // 22 bytes digest, 128 bytes hmac
-            // invalid session length
-            return Err(SessionIdentityError::EncodingError)?;
-        }
-
-        let (session_id_b64, authentication_tag_b64) = raw_cookie_val.split_at(22);
-
-        let authentication_tag_bytes = B64
-            .decode(authentication_tag_b64)
-            .map_err(|_| SessionIdentityError::EncodingError)?;
-
-        let ecdsa_signature = ecdsa::Signature::try_from(authentication_tag_bytes.as_slice())
-            .map_err(SessionIdentityError::InvalidSignatureBytes)?;
-        let mut digest = hmac_sha512::sha384::Hash::new();
-        digest.update(session_id_b64);
-
         let verification_key = ServiceVerificationKey::from_ref(state);
-        verification_key
-            .public_key()
-            .as_ref()
-            .verify_digest(digest, &ecdsa_signature)
-            .map_err(SessionIdentityError::BadSignature)?;
-
-        // We now know these are good bytes, decode them, turn them into a valid session ID and
-        // check the DB for them...
-
-        let session_id_bytes = B64
-            .decode(session_id_b64)
-            .map_err(|_| SessionIdentityError::EncodingError)?;
-
-        let session_id_bytes: [u8; 16] = session_id_bytes
-            .try_into()
-            .expect("signed session ID to be valid byte slice");
-        let session_id = SessionId::from(Uuid::from_bytes_le(session_id_bytes));
+        let session_id = signed_session_id(session_cookie.value(), &verification_key)?;
 
         let database = Database::from_ref(state);
         let mut conn = database
@@ -151,6 +151,99 @@ where
     }
 }
 
+/// The session a cookie was issued for, provided its signature holds up. Nothing here says whether
+/// the session still exists, that needs a trip to the database.
+pub(crate) fn signed_session_id(
+    cookie_value: &str,
+    verification_key: &ServiceVerificationKey,
+) -> Result<SessionId, SessionIdentityError> {
+    let (signed_content, session_id_bytes, ecdsa_signature) = split_session_cookie(cookie_value)?;
+
+    let mut digest = hmac_sha512::sha384::Hash::new();
+    digest.update(signed_content);
+
+    verification_key
+        .public_key()
+        .as_ref()
+        .verify_digest(digest, &ecdsa_signature)
+        .map_err(SessionIdentityError::BadSignature)?;
+
+    Ok(SessionId::from(Uuid::from_bytes_le(session_id_bytes)))
+}
+
+const fn unpadded_b64_len(len: usize) -> usize {
+    (len * 4 + 2) / 3
+}
+
+/// Builds the value of the session cookie identifying a newly created session.
+pub(crate) fn session_cookie_value(
+    session_id: SessionId,
+    signing_key: &ServiceSigningKey,
+) -> String {
+    let signed_content = format!(
+        "{SESSION_COOKIE_VERSION}{SESSION_COOKIE_VERSION_SEPARATOR}{}",
+        B64.encode(session_id.to_bytes_le())
+    );
+
+    let mut digest = hmac_sha512::sha384::Hash::new();
+    digest.update(signed_content.as_bytes());
+
+    let signature: SessionSignature = signing_key
+        .key_pair()
+        .as_ref()
+        .sign_digest_with_rng(&mut rand::thread_rng(), digest);
+
+    format!("{signed_content}{}", B64.encode(signature.to_vec()))
+}
+
+/// Splits a session cookie into the portion covered by the signature, along with the decoded
+/// session ID and signature. Each part is checked to decode to exactly the size it should be,
+/// nothing here says whether the signature is actually valid.
+fn split_session_cookie(
+    cookie_value: &str,
+) -> Result<(&str, [u8; SESSION_ID_LEN], SessionSignature), SessionIdentityError> {
+    if cookie_value.len() > SESSION_COOKIE_LEN {
+        return Err(SessionIdentityError::CookieTooLarge);
+    }
+
+    // Cookies issued before versioning was introduced have no prefix at all
+    match cookie_value.split_once(SESSION_COOKIE_VERSION_SEPARATOR) {
+        Some((SESSION_COOKIE_VERSION, _)) => (),
+        _ => return Err(SessionIdentityError::UnsupportedVersion),
+    }
+
+    // Splitting in the middle of a multi-byte character isn't possible, that can only happen when
+    // the cookie isn't base64 anyway
+    let id_end = SESSION_COOKIE_PREFIX_LEN + SESSION_ID_ENCODED_LEN;
+    let signed_content = cookie_value
+        .get(..id_end)
+        .ok_or(SessionIdentityError::EncodingError)?;
+    let session_id_b64 = cookie_value
+        .get(SESSION_COOKIE_PREFIX_LEN..id_end)
+        .ok_or(SessionIdentityError::EncodingError)?;
+    let signature_b64 = cookie_value
+        .get(id_end..)
+        .ok_or(SessionIdentityError::EncodingError)?;
+
+    let session_id_bytes: [u8; SESSION_ID_LEN] = B64
+        .decode(session_id_b64)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(SessionIdentityError::EncodingError)?;
+
+    let signature_bytes = B64
+        .decode(signature_b64)
+        .map_err(|_| SessionIdentityError::EncodingError)?;
+    if signature_bytes.len() != SESSION_SIGNATURE_LEN {
+        return Err(SessionIdentityError::EncodingError);
+    }
+
+    let signature = SessionSignature::try_from(signature_bytes.as_slice())
+        .map_err(SessionIdentityError::InvalidSignatureBytes)?;
+
+    Ok((signed_content, session_id_bytes, signature))
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum SessionIdentityError {
     #[error("signature did not match digest, tampering likely: {0}")]
@@ -182,6 +275,9 @@ pub enum SessionIdentityError {
 
     #[error("session was expired")]
     SessionExpired,
+
+    #[error("session cookie was missing a version or used one that isn't supported")]
+    UnsupportedVersion,
 }
 
 impl IntoResponse for SessionIdentityError {
@@ -190,7 +286,7 @@ impl IntoResponse for SessionIdentityError {
 
         let mut cookie_jar = CookieJar::default();
 
-        cookie_jar = remove_cookie(SESSION_COOKIE_NAME, cookie_jar);
+        cookie_jar = remove_session_cookies(cookie_jar);
 
         match self {
             SIE::NoSession(_orig_uri) => {
@@ -202,3 +298,117 @@ impl IntoResponse for SessionIdentityError {
         (cookie_jar, Redirect::to(LOGIN_PATH)).into_response()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use http::header::{LOCATION, SET_COOKIE};
+
+    use super::*;
+
+    fn signing_key() -> ServiceSigningKey {
+        ServiceSigningKey::new(ES384KeyPair::generate())
+    }
+
+    fn signed_cookie() -> String {
+        session_cookie_value(SessionId::from(Uuid::new_v4()), &signing_key())
+    }
+
+    #[test]
+    fn test_cookie_lengths_match_encoding() {
+        assert_eq!(SESSION_ID_ENCODED_LEN, 22);
+        assert_eq!(SESSION_SIGNATURE_ENCODED_LEN, 128);
+
+        let cookie = signed_cookie();
+        assert_eq!(cookie.len(), SESSION_COOKIE_LEN);
+        assert!(cookie.starts_with("v1."));
+
+        let (signed_content, _, _) = split_session_cookie(&cookie).unwrap();
+        assert_eq!(
+            signed_content,
+            &cookie[..SESSION_COOKIE_PREFIX_LEN + SESSION_ID_ENCODED_LEN]
+        );
+    }
+
+    #[test]
+    fn test_issued_cookie_verifies() {
+        let signing_key = signing_key();
+        let session_id = SessionId::from(Uuid::new_v4());
+        let cookie = session_cookie_value(session_id, &signing_key);
+
+        let (signed_content, session_id_bytes, signature) = split_session_cookie(&cookie).unwrap();
+        assert_eq!(session_id_bytes, session_id.to_bytes_le());
+
+        let mut digest = hmac_sha512::sha384::Hash::new();
+        digest.update(signed_content);
+        signing_key
+            .verifier()
+            .public_key()
+            .as_ref()
+            .verify_digest(digest, &signature)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_malformed_cookies_rejected() {
+        let cookie = signed_cookie();
+        let id_end = SESSION_COOKIE_PREFIX_LEN + SESSION_ID_ENCODED_LEN;
+
+        let too_long = format!("{cookie}A");
+        assert!(matches!(
+            split_session_cookie(&too_long),
+            Err(SessionIdentityError::CookieTooLarge)
+        ));
+
+        for truncated in [
+            &cookie[..id_end - 1],
+            &cookie[..id_end],
+            &cookie[..SESSION_COOKIE_LEN - 1],
+        ] {
+            assert!(matches!(
+                split_session_cookie(truncated),
+                Err(SessionIdentityError::EncodingError)
+            ));
+        }
+
+        // A multi-byte character straddling the boundary between the session ID and signature
+        let straddling = format!("{}\u{e9}{}", &cookie[..id_end - 1], &cookie[id_end + 1..]);
+        assert_eq!(straddling.len(), SESSION_COOKIE_LEN);
+        assert!(matches!(
+            split_session_cookie(&straddling),
+            Err(SessionIdentityError::EncodingError)
+        ));
+
+        // Valid base64 that decodes to an impossible signature, both scalars have to be non-zero
+        let zero_signature = format!(
+            "{}{}",
+            &cookie[..id_end],
+            B64.encode([0u8; SESSION_SIGNATURE_LEN])
+        );
+        assert!(matches!(
+            split_session_cookie(&zero_signature),
+            Err(SessionIdentityError::InvalidSignatureBytes(_))
+        ));
+    }
+
+    #[test]
+    fn test_unversioned_cookies_rejected() {
+        let cookie = signed_cookie();
+
+        // The format used before versioning, the same cookie without the prefix
+        let unversioned = &cookie[SESSION_COOKIE_PREFIX_LEN..];
+        let future_version = format!("v2{}", &cookie[SESSION_COOKIE_PREFIX_LEN - 1..]);
+
+        for rejected in ["", unversioned, &future_version] {
+            assert!(matches!(
+                split_session_cookie(rejected),
+                Err(SessionIdentityError::UnsupportedVersion)
+            ));
+        }
+
+        // Rejected cookies are cleared and the client is sent to log in again
+        let response = SessionIdentityError::UnsupportedVersion.into_response();
+        assert!(response.status().is_redirection());
+        assert_eq!(response.headers().get(LOCATION).unwrap(), LOGIN_PATH);
+        assert!(response.headers().get(SET_COOKIE).is_some());
+    }
+}
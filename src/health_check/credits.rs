@@ -1,23 +1,57 @@
 use axum::response::{IntoResponse, Response};
 use axum::Json;
-use http::StatusCode;
+use http::{HeaderMap, StatusCode};
+use serde::Serialize;
 
-pub async fn handler() -> Response {
-    // todo: add in additional core projects into this list
-    let credits = serde_json::json!([
-        {
-            "name": "FontAwesome",
-            "description": "icon library typeface",
-            "site": "https://fontawesome.com/",
-            "version": "6.5.1",
-        },
-        {
-            "name": "Inter",
-            "description": "flexible typeface used in the web interface",
-            "site": "https://rsms.me/inter/",
-            "version": "4.0",
-        },
-    ]);
+use crate::health_check::wants_html;
+use crate::pages::StatusTableTemplate;
 
-    (StatusCode::OK, Json(credits)).into_response()
+// todo: add in additional core projects into this list
+const CREDITS: &[Credit] = &[
+    Credit {
+        description: "icon library typeface",
+        name: "FontAwesome",
+        site: "https://fontawesome.com/",
+        version: "6.5.1",
+    },
+    Credit {
+        description: "flexible typeface used in the web interface",
+        name: "Inter",
+        site: "https://rsms.me/inter/",
+        version: "4.0",
+    },
+];
+
+pub async fn handler(headers: HeaderMap) -> Response {
+    if !wants_html(&headers) {
+        return (StatusCode::OK, Json(CREDITS)).into_response();
+    }
+
+    let rows = CREDITS
+        .iter()
+        .map(|credit| {
+            vec![
+                credit.name.to_string(),
+                credit.description.to_string(),
+                credit.site.to_string(),
+                credit.version.to_string(),
+            ]
+        })
+        .collect();
+
+    let template = StatusTableTemplate {
+        title: "Credits",
+        columns: vec!["Name", "Description", "Site", "Version"],
+        rows,
+    };
+
+    (StatusCode::OK, template).into_response()
+}
+
+#[derive(Serialize)]
+struct Credit {
+    description: &'static str,
+    name: &'static str,
+    site: &'static str,
+    version: &'static str,
 }
@@ -7,7 +7,9 @@ use axum::async_trait;
 use axum::extract::{FromRef, FromRequestParts};
 use http::request::Parts;
 
-use crate::database::Database;
+use super::ServiceReadiness;
+use crate::database::{Database, DatabaseHealth, DatabaseStatus};
+use crate::llm::{Embedder, ModelStatus};
 
 #[async_trait]
 pub trait DataSource {
@@ -17,9 +19,15 @@ pub trait DataSource {
 
 #[derive(Debug, thiserror::Error)]
 pub enum DataSourceError {
+    #[error("database connection and migrations haven't completed")]
+    DatabaseNotReady,
+
     #[error("one or more dependent services aren't available")]
     DependencyFailure,
 
+    #[error("required models haven't finished loading")]
+    ModelNotReady,
+
     #[error("service has received signal indicating it should shutdown")]
     ShuttingDown,
 }
@@ -43,19 +51,51 @@ impl Deref for StateDataSource {
     }
 }
 
+/// Deployments that exist to serve inference shouldn't receive traffic until their model is
+/// loaded, others shouldn't be held up by it. This holds the model readiness depends on when
+/// that has been opted into.
+#[derive(Clone)]
+pub struct ModelReadiness(Option<Embedder>);
+
+impl ModelReadiness {
+    fn check(&self) -> Result<(), DataSourceError> {
+        match &self.0 {
+            Some(embedder) if embedder.status() != ModelStatus::Ready => {
+                Err(DataSourceError::ModelNotReady)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn new(embedder: Embedder, required: bool) -> Self {
+        Self(required.then_some(embedder))
+    }
+}
+
 struct DbSource {
     db: Database,
+    db_health: DatabaseHealth,
+    model_readiness: ModelReadiness,
+    service_readiness: ServiceReadiness,
 }
 
 #[async_trait]
 impl DataSource for DbSource {
     async fn is_ready(&self) -> Result<(), DataSourceError> {
+        self.service_readiness.check()?;
+
+        // Avoid piling more queries onto a database that is known to be struggling, the monitor
+        // will notice when it recovers.
+        if self.db_health.status() == DatabaseStatus::Unavailable {
+            return Err(DataSourceError::DependencyFailure);
+        }
+
         let _ = sqlx::query("SELECT 1 as id;")
             .fetch_one(self.db.deref())
             .await
             .map_err(|_| DataSourceError::DependencyFailure)?;
 
-        Ok(())
+        self.model_readiness.check()
     }
 }
 
@@ -63,6 +103,9 @@ impl DataSource for DbSource {
 impl<S> FromRequestParts<S> for StateDataSource
 where
     Database: FromRef<S>,
+    DatabaseHealth: FromRef<S>,
+    ModelReadiness: FromRef<S>,
+    ServiceReadiness: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = ();
@@ -70,6 +113,9 @@ where
     async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         Ok(StateDataSource(Arc::new(DbSource {
             db: Database::from_ref(state),
+            db_health: DatabaseHealth::from_ref(state),
+            model_readiness: ModelReadiness::from_ref(state),
+            service_readiness: ServiceReadiness::from_ref(state),
         })))
     }
 }
@@ -81,6 +127,7 @@ pub(crate) mod tests {
     #[derive(Clone)]
     pub(crate) enum MockReadiness {
         DependencyFailure,
+        ModelNotReady,
         Ready,
         ShuttingDown,
     }
@@ -92,9 +139,25 @@ pub(crate) mod tests {
 
             match self {
                 DependencyFailure => Err(DataSourceError::DependencyFailure),
+                ModelNotReady => Err(DataSourceError::ModelNotReady),
                 Ready => Ok(()),
                 ShuttingDown => Err(DataSourceError::ShuttingDown),
             }
         }
     }
+
+    #[test]
+    fn test_model_readiness_toggle() {
+        let embedder = Embedder::new(candle_core::Device::Cpu);
+        assert_eq!(embedder.status(), ModelStatus::NotLoaded);
+
+        let not_required = ModelReadiness::new(embedder.clone(), false);
+        assert!(not_required.check().is_ok());
+
+        let required = ModelReadiness::new(embedder, true);
+        assert!(matches!(
+            required.check(),
+            Err(DataSourceError::ModelNotReady)
+        ));
+    }
 }
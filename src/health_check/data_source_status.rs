@@ -0,0 +1,16 @@
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::StatusCode;
+
+use crate::database::{DatabaseHealth, DatabaseStatus};
+
+pub async fn handler(State(db_health): State<DatabaseHealth>) -> Response {
+    let report = db_health.report();
+    let msg = serde_json::json!({ "database": report });
+
+    match report.status {
+        DatabaseStatus::Unavailable => (StatusCode::SERVICE_UNAVAILABLE, Json(msg)).into_response(),
+        _ => (StatusCode::OK, Json(msg)).into_response(),
+    }
+}
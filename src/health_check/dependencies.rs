@@ -0,0 +1,331 @@
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::StatusCode;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use url::Url;
+
+use crate::app::{UploadLocation, UploadStore};
+use crate::database::Database;
+use crate::event_bus::EventBus;
+use crate::health_check::ModelSourceHealth;
+
+/// Each dependency gets this long to respond before it is reported as unhealthy.
+const DEPENDENCY_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Every check reaches outside of the process, requests within this long of the last round of
+/// checks are answered with its results instead of repeating them.
+const DEPENDENCY_CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// The results of the last round of checks along with when it finished.
+type CachedStatuses = Arc<Mutex<Option<(Instant, Vec<DependencyStatus>)>>>;
+
+/// Used when the mail server URL doesn't include a port.
+const DEFAULT_SMTP_PORT: u16 = 25;
+
+/// Used for `smtps` mail server URLs that don't include a port.
+const DEFAULT_SMTPS_PORT: u16 = 465;
+
+/// Everything outside of the process the service relies on. Unlike readiness, which only answers
+/// whether traffic should be sent here, this reports on each dependency individually to help
+/// track down which one is misbehaving. Details that would describe the deployment, such as
+/// addresses and paths, are left out of the reports.
+#[derive(Clone)]
+pub struct Dependencies {
+    cached: CachedStatuses,
+    database: Database,
+    event_bus: EventBus,
+    model_source: ModelSourceHealth,
+    smtp_url: Option<Url>,
+    upload_location: UploadLocation,
+}
+
+impl Dependencies {
+    /// The results of the most recent round of checks, running a new one when they're stale. The
+    /// lock is held while the checks run so concurrent requests wait on a single round.
+    async fn cached_statuses(&self) -> Vec<DependencyStatus> {
+        let mut cached = self.cached.lock().await;
+
+        if let Some((checked_at, statuses)) = cached.as_ref() {
+            if checked_at.elapsed() < DEPENDENCY_CACHE_TTL {
+                return statuses.clone();
+            }
+        }
+
+        let statuses = self.check_all().await;
+        *cached = Some((Instant::now(), statuses.clone()));
+
+        statuses
+    }
+
+    async fn check_all(&self) -> Vec<DependencyStatus> {
+        let (database, event_bus, model_source, uploads) = tokio::join!(
+            timed("database", check_database(&self.database)),
+            timed("event_bus", check_event_bus(&self.event_bus)),
+            timed("model_source", check_model_source(&self.model_source)),
+            timed("upload_store", check_upload_store(&self.upload_location)),
+        );
+
+        let mut statuses = vec![database, event_bus, model_source, uploads];
+        if let Some(smtp_url) = &self.smtp_url {
+            statuses.push(timed("smtp", check_smtp(smtp_url)).await);
+        }
+
+        statuses
+    }
+
+    pub fn new(
+        database: Database,
+        event_bus: EventBus,
+        model_source: ModelSourceHealth,
+        smtp_url: Option<Url>,
+        upload_location: UploadLocation,
+    ) -> Self {
+        Self {
+            cached: Arc::new(Mutex::new(None)),
+            database,
+            event_bus,
+            model_source,
+            smtp_url,
+            upload_location,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct DependencyStatus {
+    name: &'static str,
+    healthy: bool,
+    latency_ms: f64,
+    detail: Option<String>,
+}
+
+pub async fn handler(State(dependencies): State<Dependencies>) -> Response {
+    let statuses = dependencies.cached_statuses().await;
+    let all_healthy = statuses.iter().all(|status| status.healthy);
+
+    let msg = serde_json::json!({ "dependencies": statuses });
+
+    if all_healthy {
+        (StatusCode::OK, Json(msg)).into_response()
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(msg)).into_response()
+    }
+}
+
+async fn check_database(database: &Database) -> Result<Option<String>, String> {
+    sqlx::query("SELECT 1;")
+        .fetch_one(&**database)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(None)
+}
+
+/// The work that follows from events is done by subscribers started along with the service, a bus
+/// without any has nothing acting on what is sent to it.
+async fn check_event_bus(event_bus: &EventBus) -> Result<Option<String>, String> {
+    match event_bus.subscriber_count() {
+        0 => Err("no subscribers, events are being dropped".to_string()),
+        count => Ok(Some(format!("{count} subscribers"))),
+    }
+}
+
+/// HuggingFace is checked in the background, this only reports how the last check went. Until
+/// the first check finishes there is nothing to hold against it.
+async fn check_model_source(model_source: &ModelSourceHealth) -> Result<Option<String>, String> {
+    match model_source.last_result() {
+        Some(result) => result.map(Some),
+        None => Ok(Some("not checked yet".to_string())),
+    }
+}
+
+async fn check_smtp(smtp_url: &Url) -> Result<Option<String>, String> {
+    let host = smtp_url
+        .host_str()
+        .ok_or_else(|| "mail server URL has no host".to_string())?;
+    let port = smtp_url.port().unwrap_or(match smtp_url.scheme() {
+        "smtps" => DEFAULT_SMTPS_PORT,
+        _ => DEFAULT_SMTP_PORT,
+    });
+
+    tokio::net::TcpStream::connect((host, port))
+        .await
+        .map_err(|err| format!("unable to connect: {err}"))?;
+
+    Ok(None)
+}
+
+/// Local directories are inspected directly, remote stores have to answer a listing of their
+/// root.
+async fn check_upload_store(location: &UploadLocation) -> Result<Option<String>, String> {
+    if let Some(directory) = location.local_directory() {
+        return check_upload_directory(directory).await;
+    }
+
+    // Store errors name the objects and locations involved, those are only logged
+    let store = UploadStore::open(location).map_err(|err| {
+        tracing::warn!("upload store dependency check failed: {err}");
+        "unable to open the store".to_string()
+    })?;
+    store.list_with_delimiter(None).await.map_err(|err| {
+        tracing::warn!("upload store dependency check failed: {err}");
+        "unable to list the store".to_string()
+    })?;
+
+    Ok(None)
+}
+
+async fn check_upload_directory(upload_directory: &Path) -> Result<Option<String>, String> {
+    let metadata = tokio::fs::metadata(upload_directory)
+        .await
+        .map_err(|err| format!("unable to inspect the upload directory: {err}"))?;
+
+    if !metadata.is_dir() {
+        return Err("upload location is not a directory".to_string());
+    }
+
+    if metadata.permissions().readonly() {
+        return Err("upload directory is read only".to_string());
+    }
+
+    Ok(None)
+}
+
+/// Runs a single check under the shared timeout, recording how long it took.
+async fn timed<F>(name: &'static str, check: F) -> DependencyStatus
+where
+    F: Future<Output = Result<Option<String>, String>>,
+{
+    let started_at = Instant::now();
+    let result = tokio::time::timeout(DEPENDENCY_CHECK_TIMEOUT, check).await;
+    let latency_ms = started_at.elapsed().as_secs_f64() * 1_000.0;
+
+    let (healthy, detail) = match result {
+        Ok(Ok(detail)) => (true, detail),
+        Ok(Err(err)) => (false, Some(err)),
+        Err(_) => (false, Some("check timed out".to_string())),
+    };
+
+    DependencyStatus {
+        name,
+        healthy,
+        latency_ms,
+        detail,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tests::prelude::*;
+
+    async fn response_json(response: Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_dependencies_reported_unhealthy() {
+        let database = Database::new(test_database().await);
+        let upload_location = UploadLocation::Local(std::env::temp_dir());
+        let event_bus = EventBus::default();
+        let _subscriber = event_bus.subscribe();
+
+        let healthy = Dependencies::new(
+            database.clone(),
+            event_bus,
+            ModelSourceHealth::default(),
+            None,
+            upload_location,
+        );
+        let response = handler(State(healthy)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response_json(response).await;
+        let dependencies = body["dependencies"].as_array().unwrap();
+        let names: Vec<_> = dependencies.iter().map(|dep| &dep["name"]).collect();
+        assert_eq!(
+            names,
+            ["database", "event_bus", "model_source", "upload_store"]
+        );
+
+        for dependency in dependencies {
+            assert_eq!(dependency["healthy"], true);
+            assert!(dependency["latency_ms"].is_number());
+            assert!(dependency.get("detail").is_some());
+        }
+
+        // Nothing listens on port 1, the directory doesn't exist and nothing subscribes to the bus
+        let smtp_url = Url::parse("smtp://127.0.0.1:1").unwrap();
+        let missing_directory =
+            UploadLocation::Local(std::env::temp_dir().join("missing-upload-directory"));
+        let unhealthy = Dependencies::new(
+            database,
+            EventBus::default(),
+            ModelSourceHealth::default(),
+            Some(smtp_url),
+            missing_directory,
+        );
+        let response = handler(State(unhealthy)).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = response_json(response).await;
+        let details = body.to_string();
+        assert!(!details.contains("127.0.0.1"));
+        assert!(!details.contains("missing-upload-directory"));
+
+        let healthy_by_name: Vec<_> = body["dependencies"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|dep| {
+                (
+                    dep["name"].as_str().unwrap(),
+                    dep["healthy"].as_bool().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            healthy_by_name,
+            [
+                ("database", true),
+                ("event_bus", false),
+                ("model_source", true),
+                ("upload_store", false),
+                ("smtp", false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recent_results_reused() {
+        let database = Database::new(test_database().await);
+        let event_bus = EventBus::default();
+        let subscriber = event_bus.subscribe();
+
+        let dependencies = Dependencies::new(
+            database,
+            event_bus,
+            ModelSourceHealth::default(),
+            None,
+            UploadLocation::Local(std::env::temp_dir()),
+        );
+        let response = handler(State(dependencies.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Losing the only subscriber isn't noticed until the cached results go stale
+        drop(subscriber);
+        let response = handler(State(dependencies)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -1,35 +1,88 @@
 use axum::routing::get;
 use axum::Router;
 use http::header::{ACCEPT, ORIGIN};
-use http::Method;
+use http::{HeaderMap, Method};
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::limit::RequestBodyLimitLayer;
 
 mod credits;
 mod data_source;
+mod data_source_status;
+mod dependencies;
 mod liveness;
+mod model;
+mod model_source;
 mod readiness;
+mod service_readiness;
+mod signing_key;
+mod uptime;
 mod version;
 
+pub(crate) use data_source::ModelReadiness;
+pub(crate) use dependencies::Dependencies;
+pub(crate) use model_source::ModelSourceHealth;
+pub use service_readiness::ServiceReadiness;
+
 use crate::app::State;
 
 /// Healthcheck endpoints generally shouldn't contain anything other than headers which are counted
 /// among these bytes in the limit. Large requests here should always be rejected.
 const HEALTHCHECK_REQUEST_SIZE_LIMIT: usize = 1_024;
 
+/// Only the shallow liveness check, suitable for a public listener when the detailed status
+/// endpoints are being served on a separate internal one.
+pub fn public_router(state: State) -> Router<State> {
+    with_common_layers(
+        Router::new()
+            .route("/healthz", get(liveness::handler))
+            .with_state(state),
+    )
+}
+
+/// The detailed status endpoints along with the dependency report. The report describes the
+/// infrastructure behind the service and is only served on the internal listener.
+pub fn internal_router(state: State) -> Router<State> {
+    with_common_layers(
+        detailed_routes()
+            .route("/deps", get(dependencies::handler))
+            .with_state(state),
+    )
+}
+
 pub fn router(state: State) -> Router<State> {
-    let cors_layer = CorsLayer::new()
-        .allow_methods(vec![Method::GET])
-        .allow_headers(vec![ACCEPT, ORIGIN])
-        .allow_origin(Any)
-        .allow_credentials(false);
+    with_common_layers(detailed_routes().with_state(state))
+}
 
+fn detailed_routes() -> Router<State> {
     Router::new()
         .route("/credits", get(credits::handler))
+        .route("/data_source", get(data_source_status::handler))
         .route("/healthz", get(liveness::handler))
+        .route("/model", get(model::handler))
         .route("/readyz", get(readiness::handler))
+        .route("/signing_key", get(signing_key::handler))
+        .route("/uptime", get(uptime::handler))
         .route("/version", get(version::handler))
-        .with_state(state)
+}
+
+/// Whether the client would rather have a page it can read than JSON, such as someone checking the
+/// status endpoints in their browser.
+fn wants_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .map(|accept| accept.contains("text/html"))
+        .unwrap_or(false)
+}
+
+fn with_common_layers(router: Router<State>) -> Router<State> {
+    let cors_layer = CorsLayer::new()
+        .allow_methods(vec![Method::GET])
+        .allow_headers(vec![ACCEPT, ORIGIN])
+        .allow_origin(Any)
+        .allow_credentials(false);
+
+    router
         .layer(cors_layer)
         .layer(RequestBodyLimitLayer::new(HEALTHCHECK_REQUEST_SIZE_LIMIT))
 }
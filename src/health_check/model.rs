@@ -0,0 +1,24 @@
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::StatusCode;
+
+use crate::llm::hugging_face::EMBEDDING_MODEL;
+use crate::llm::{Embedder, ModelStatus};
+
+pub async fn handler(State(embedder): State<Embedder>) -> Response {
+    let status = embedder.status();
+
+    let msg = serde_json::json!({
+        "embedding": {
+            "model": EMBEDDING_MODEL,
+            "device": embedder.device(),
+            "status": status,
+        },
+    });
+
+    match status {
+        ModelStatus::Ready => (StatusCode::OK, Json(msg)).into_response(),
+        _ => (StatusCode::SERVICE_UNAVAILABLE, Json(msg)).into_response(),
+    }
+}
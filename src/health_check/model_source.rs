@@ -0,0 +1,93 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+
+use crate::llm::hugging_face::{self, EMBEDDING_MODEL};
+use crate::ShutdownSignal;
+
+/// How often HuggingFace is asked about the current version of the model. Nothing depends on this
+/// being fresh, it's only reported alongside the other dependencies.
+const CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// The outcome of the most recent check that the embedding model can be retrieved from
+/// HuggingFace. The check runs in the background so an outage there never holds up the service
+/// starting, it only shows up as an unhealthy dependency.
+#[derive(Clone, Default)]
+pub struct ModelSourceHealth {
+    last_result: Arc<Mutex<Option<Result<String, String>>>>,
+}
+
+impl ModelSourceHealth {
+    /// The detail of the last successful check or the error of the last failed one, `None` until
+    /// the first check has finished.
+    pub fn last_result(&self) -> Option<Result<String, String>> {
+        self.last_result
+            .lock()
+            .expect("lock to not be poisoned")
+            .clone()
+    }
+
+    /// Checks HuggingFace until the shutdown signal is received.
+    pub fn monitor(&self, shutdown_rx: ShutdownSignal) -> JoinHandle<()> {
+        self.monitor_with(shutdown_rx, || async {
+            hugging_face::check_safetensor_model_version(EMBEDDING_MODEL)
+                .await
+                .map(|version| format!("{EMBEDDING_MODEL} available at {}", version.version()))
+                .map_err(|err| err.to_string())
+        })
+    }
+
+    fn monitor_with<F, Fut>(&self, mut shutdown_rx: ShutdownSignal, check: F) -> JoinHandle<()>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<String, String>> + Send,
+    {
+        let health = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let result = tokio::select! {
+                    _ = shutdown_rx.changed() => break,
+                    result = check() => result,
+                };
+
+                if let Err(err) = &result {
+                    tracing::warn!("unable to check the embedding model on HuggingFace: {err}");
+                }
+                *health.last_result.lock().expect("lock to not be poisoned") = Some(result);
+
+                tokio::select! {
+                    _ = shutdown_rx.changed() => break,
+                    _ = tokio::time::sleep(CHECK_INTERVAL) => {},
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ShutdownReason;
+
+    #[tokio::test]
+    async fn test_failed_check_recorded_without_blocking() {
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(None);
+        let health = ModelSourceHealth::default();
+        assert!(health.last_result().is_none());
+
+        let handle = health.monitor_with(shutdown_rx, || async {
+            Err("huggingface.co responded with 503".to_string())
+        });
+
+        while health.last_result().is_none() {
+            tokio::task::yield_now().await;
+        }
+        assert!(matches!(health.last_result(), Some(Err(_))));
+
+        shutdown_tx.send(Some(ShutdownReason::Terminate)).unwrap();
+        handle.await.unwrap();
+    }
+}
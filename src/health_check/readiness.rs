@@ -10,10 +10,19 @@ pub async fn handler(data_src: StateDataSource) -> Response {
             let msg = serde_json::json!({"status": "ok"});
             (StatusCode::OK, Json(msg)).into_response()
         }
+        Err(DataSourceError::DatabaseNotReady) => {
+            let msg = serde_json::json!({"status": "failure", "message": "database is still being set up"});
+            (StatusCode::SERVICE_UNAVAILABLE, Json(msg)).into_response()
+        }
         Err(DataSourceError::DependencyFailure) => {
             let msg = serde_json::json!({"status": "failure", "message": "one or more dependencies aren't available"});
             (StatusCode::SERVICE_UNAVAILABLE, Json(msg)).into_response()
         }
+        Err(DataSourceError::ModelNotReady) => {
+            let msg =
+                serde_json::json!({"status": "failure", "message": "models are still loading"});
+            (StatusCode::SERVICE_UNAVAILABLE, Json(msg)).into_response()
+        }
         Err(DataSourceError::ShuttingDown) => {
             let msg =
                 serde_json::json!({"status": "failure", "message": "service is shutting down"});
@@ -26,9 +35,81 @@ pub async fn handler(data_src: StateDataSource) -> Response {
 mod tests {
     use std::sync::Arc;
 
+    use axum::body::Body;
+    use axum::extract::FromRef;
+    use axum::routing::get;
+    use axum::Router;
+    use http::Request;
+    use tower::ServiceExt;
+
     use super::*;
 
+    use crate::database::{Database, DatabaseHealth};
     use crate::health_check::data_source::tests::*;
+    use crate::health_check::{ModelReadiness, ServiceReadiness};
+    use crate::llm::Embedder;
+    use crate::tests::prelude::*;
+
+    #[derive(Clone)]
+    struct TestState {
+        database: Database,
+        service_readiness: ServiceReadiness,
+    }
+
+    impl FromRef<TestState> for Database {
+        fn from_ref(state: &TestState) -> Self {
+            state.database.clone()
+        }
+    }
+
+    impl FromRef<TestState> for DatabaseHealth {
+        fn from_ref(_state: &TestState) -> Self {
+            DatabaseHealth::new()
+        }
+    }
+
+    impl FromRef<TestState> for ModelReadiness {
+        fn from_ref(_state: &TestState) -> Self {
+            ModelReadiness::new(Embedder::new(candle_core::Device::Cpu), false)
+        }
+    }
+
+    impl FromRef<TestState> for ServiceReadiness {
+        fn from_ref(state: &TestState) -> Self {
+            state.service_readiness.clone()
+        }
+    }
+
+    async fn readyz_status(router: &Router) -> StatusCode {
+        let request = Request::get("/readyz").body(Body::empty()).unwrap();
+        router.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_readyz_follows_service_lifecycle() {
+        let service_readiness = ServiceReadiness::new();
+        let state = TestState {
+            database: Database::new(test_database().await),
+            service_readiness: service_readiness.clone(),
+        };
+        let router = Router::new()
+            .route("/readyz", get(handler))
+            .with_state(state);
+
+        assert_eq!(
+            readyz_status(&router).await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        service_readiness.mark_database_ready();
+        assert_eq!(readyz_status(&router).await, StatusCode::OK);
+
+        service_readiness.mark_shutting_down();
+        assert_eq!(
+            readyz_status(&router).await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
 
     #[tokio::test]
     async fn test_handler_direct() {
@@ -41,6 +122,9 @@ mod tests {
         .await;
         assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
 
+        let response = handler(StateDataSource::new(Arc::new(MockReadiness::ModelNotReady))).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
         let response = handler(StateDataSource::new(Arc::new(MockReadiness::ShuttingDown))).await;
         assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
     }
@@ -0,0 +1,42 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::data_source::DataSourceError;
+
+/// The parts of readiness decided by where the service is in its own lifecycle rather than by
+/// checking a dependency. The service isn't ready until the database has been connected to and
+/// migrated, and stops being ready as soon as it has been asked to shut down so orchestrators stop
+/// routing new requests to it during the grace period.
+#[derive(Clone, Default)]
+pub struct ServiceReadiness {
+    database_ready: Arc<AtomicBool>,
+    shutting_down: Arc<AtomicBool>,
+}
+
+impl ServiceReadiness {
+    pub(crate) fn check(&self) -> Result<(), DataSourceError> {
+        if self.shutting_down.load(Ordering::Acquire) {
+            return Err(DataSourceError::ShuttingDown);
+        }
+
+        if !self.database_ready.load(Ordering::Acquire) {
+            return Err(DataSourceError::DatabaseNotReady);
+        }
+
+        Ok(())
+    }
+
+    /// Called once the database is connected and all migrations have been applied.
+    pub fn mark_database_ready(&self) {
+        self.database_ready.store(true, Ordering::Release);
+    }
+
+    /// Called when the service receives a signal to shut down, this is never undone.
+    pub fn mark_shutting_down(&self) {
+        self.shutting_down.store(true, Ordering::Release);
+    }
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
@@ -0,0 +1,36 @@
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::{HeaderMap, StatusCode};
+
+use crate::app::ServiceVerificationKey;
+use crate::health_check::wants_html;
+use crate::pages::StatusTableTemplate;
+
+/// The only algorithm the service signs tokens with.
+const SIGNING_ALGORITHM: &str = "ES384";
+
+/// Reports which key the service is currently signing tokens with. The key ID matches the one in
+/// the header of those tokens, letting clients tell when the service key has been replaced.
+pub async fn handler(
+    State(verifier): State<ServiceVerificationKey>,
+    headers: HeaderMap,
+) -> Response {
+    let key_id = verifier.fingerprint().to_string();
+
+    if !wants_html(&headers) {
+        let msg = serde_json::json!({"algorithm": SIGNING_ALGORITHM, "key_id": key_id});
+        return (StatusCode::OK, Json(msg)).into_response();
+    }
+
+    let template = StatusTableTemplate {
+        title: "Signing Key",
+        columns: vec!["Property", "Value"],
+        rows: vec![
+            vec!["Algorithm".to_string(), SIGNING_ALGORITHM.to_string()],
+            vec!["Key ID".to_string(), key_id],
+        ],
+    };
+
+    (StatusCode::OK, template).into_response()
+}
@@ -0,0 +1,54 @@
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::StatusCode;
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::app::StartTime;
+
+// todo: there is no metrics exporter yet, once there is uptime should be reported there as well
+pub async fn handler(State(start_time): State<StartTime>) -> Response {
+    let uptime = Uptime {
+        started_at: start_time.started_at(),
+        uptime_seconds: start_time.uptime().as_secs_f64(),
+    };
+
+    (StatusCode::OK, Json(uptime)).into_response()
+}
+
+#[derive(Serialize)]
+struct Uptime {
+    #[serde(with = "time::serde::rfc3339")]
+    started_at: OffsetDateTime,
+    uptime_seconds: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn uptime_seconds(start_time: StartTime) -> f64 {
+        let response = handler(State(start_time)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["started_at"].is_string());
+
+        body["uptime_seconds"].as_f64().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_uptime_increases() {
+        let start_time = StartTime::now();
+
+        let first = uptime_seconds(start_time).await;
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        let second = uptime_seconds(start_time).await;
+
+        assert!(second > first);
+    }
+}
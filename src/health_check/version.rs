@@ -1,22 +1,66 @@
 use axum::response::{IntoResponse, Response};
 use axum::Json;
-use http::StatusCode;
+use http::{HeaderMap, StatusCode};
 
 use crate::app::Version;
+use crate::health_check::wants_html;
+use crate::pages::StatusTableTemplate;
 
-pub async fn handler() -> Response {
-    (StatusCode::OK, Json(Version::new())).into_response()
+pub async fn handler(headers: HeaderMap) -> Response {
+    let version = Version::new();
+
+    if !wants_html(&headers) {
+        return (StatusCode::OK, Json(version)).into_response();
+    }
+
+    let rows = vec![
+        vec!["Version".to_string(), version.version.to_string()],
+        vec![
+            "Build Profile".to_string(),
+            version.build_profile.to_string(),
+        ],
+        vec!["Features".to_string(), version.features.to_string()],
+    ];
+
+    let template = StatusTableTemplate {
+        title: "Version",
+        columns: vec!["Property", "Value"],
+        rows,
+    };
+
+    (StatusCode::OK, template).into_response()
 }
 
 #[cfg(test)]
 mod tests {
+    use http::header;
+
     use super::*;
 
     #[tokio::test]
     async fn test_handler_direct() {
-        let response = handler().await;
+        let response = handler(HeaderMap::new()).await;
         assert_eq!(response.status(), StatusCode::OK);
 
         // todo: test the contents at least a little bit...
     }
+
+    #[tokio::test]
+    async fn test_browsers_get_html() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT,
+            "text/html,application/xhtml+xml".parse().unwrap(),
+        );
+
+        let response = handler(headers).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let content_type = response.headers().get(header::CONTENT_TYPE).unwrap();
+        assert!(content_type.to_str().unwrap().starts_with("text/html"));
+
+        let response = handler(HeaderMap::new()).await;
+        let content_type = response.headers().get(header::CONTENT_TYPE).unwrap();
+        assert_eq!(content_type, "application/json");
+    }
 }
@@ -0,0 +1,79 @@
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use super::request_id::X_REQUEST_ID;
+use crate::api::ApiError;
+use crate::background_jobs::CatchPanicFuture;
+
+/// Turns a panic in any of the wrapped handlers into a generic 500 response instead of dropping
+/// the connection. The panic itself has already been reported by the panic logger by the time it
+/// reaches us, the request ID is included in both our log and the response so the two can be tied
+/// together without exposing any of the details to the client.
+pub async fn middleware(request: Request, next: Next) -> Response {
+    // Always present behind the request ID layers, only generated here when used without them
+    let request_id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    match CatchPanicFuture::wrap(next.run(request)).await {
+        Ok(response) => response,
+        Err(err) => {
+            tracing::error!(request_id, "request handler panicked: {err}");
+            ApiError::internal()
+                .with_request_id(request_id)
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::Router;
+    use http::StatusCode;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn broken_handler() -> &'static str {
+        panic!("database password is hunter2");
+    }
+
+    fn test_router() -> Router {
+        Router::new()
+            .route("/fine", get(|| async { "fine" }))
+            .route("/broken", get(broken_handler))
+            .layer(axum::middleware::from_fn(middleware))
+    }
+
+    #[tokio::test]
+    async fn test_handler_panic_becomes_server_error() {
+        let router = test_router();
+
+        let request = Request::get("/broken")
+            .header(&X_REQUEST_ID, "req-1234")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body["code"], "internal_error");
+        assert_eq!(body["request_id"], "req-1234");
+        assert!(!body.to_string().contains("hunter2"));
+
+        // The router is still usable afterwards
+        let request = Request::get("/fine").body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,116 @@
+use axum::body::HttpBody;
+use http::header::CONTENT_TYPE;
+use tower_http::compression::predicate::{And, SizeAbove};
+use tower_http::compression::{CompressionLayer, Predicate};
+
+/// Only responses with one of these content types are compressed. Images, archives, and the like
+/// are already compressed and would only cost CPU time to go through it again.
+const COMPRESSIBLE_CONTENT_TYPES: &[&str] = &[
+    "application/javascript",
+    "application/json",
+    "application/problem+json",
+    "application/xml",
+    "image/svg+xml",
+    "text/",
+];
+
+const EVENT_STREAM_CONTENT_TYPE: &str = "text/event-stream";
+
+/// Compresses responses with gzip or brotli, whichever the client prefers according to its
+/// `Accept-Encoding` header. Responses that already have a content encoding, such as the
+/// precompressed static assets, are passed through untouched.
+pub(super) fn layer(min_size: u16) -> CompressionLayer<And<SizeAbove, CompressibleContentType>> {
+    CompressionLayer::new().compress_when(SizeAbove::new(min_size).and(CompressibleContentType))
+}
+
+#[derive(Clone, Copy)]
+pub(super) struct CompressibleContentType;
+
+impl Predicate for CompressibleContentType {
+    fn should_compress<B>(&self, response: &http::Response<B>) -> bool
+    where
+        B: HttpBody,
+    {
+        let content_type = match response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+        {
+            Some(ct) => ct,
+            None => return false,
+        };
+
+        // Compressing event streams would hold events back until enough have been buffered
+        if content_type.starts_with(EVENT_STREAM_CONTENT_TYPE) {
+            return false;
+        }
+
+        COMPRESSIBLE_CONTENT_TYPES
+            .iter()
+            .any(|allowed| content_type.starts_with(allowed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use axum::body::Body;
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use http::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
+    use http::Request;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn test_router() -> Router {
+        let large_json = || async {
+            let entries: Vec<_> = (0..512).map(|i| format!("entry-{i}")).collect();
+            Json(serde_json::json!({ "entries": entries }))
+        };
+        let small_json = || async { Json(serde_json::json!({ "status": "ok" })) };
+        let large_image =
+            || async { ([(CONTENT_TYPE, "image/png")], vec![0u8; 8_192]).into_response() };
+
+        Router::new()
+            .route("/large", get(large_json))
+            .route("/small", get(small_json))
+            .route("/image", get(large_image))
+            .layer(layer(1_024))
+    }
+
+    async fn gzip_request(path: &str) -> http::Response<Body> {
+        let request = Request::builder()
+            .uri(path)
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+
+        test_router().oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_large_json_compressed() {
+        let response = gzip_request("/large").await;
+        assert_eq!(response.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+
+        let compressed = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(compressed.as_ref())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+
+        let body: serde_json::Value = serde_json::from_str(&decompressed).unwrap();
+        assert_eq!(body["entries"].as_array().unwrap().len(), 512);
+
+        let response = gzip_request("/small").await;
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+
+        let response = gzip_request("/image").await;
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+    }
+}
@@ -0,0 +1,396 @@
+use std::convert::Infallible;
+use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::body::Body;
+use axum::extract::{FromRef, FromRequestParts, Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum_extra::extract::CookieJar;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as B64;
+use base64::Engine;
+use http::request::Parts;
+use http::{header, HeaderMap, Method, StatusCode};
+use rand::RngCore;
+
+use crate::app::ServiceSigningKey;
+use crate::auth::SessionCookie;
+use crate::extractors::request_scheme;
+
+/// Holds the token issued to browsers so it can be checked against the one they submit.
+pub const CSRF_COOKIE_NAME: &str = "_csrf_token";
+
+/// Name of the hidden field forms need to include the token in.
+pub const CSRF_FORM_FIELD: &str = "_csrf_token";
+
+/// Scripts making requests can provide the token in this header instead of a form field.
+pub const CSRF_HEADER_NAME: &str = "x-csrf-token";
+
+/// Form bodies are buffered to find the token, anything larger than this is refused.
+const CSRF_FORM_BODY_LIMIT: usize = 1_024 * 1_024;
+
+const CSRF_NONCE_LENGTH: usize = 16;
+
+/// Signs the CSRF tokens handed out to browsers. A token is only accepted alongside the session
+/// cookie it was issued for, so a token obtained by an attacker for their own session is useless
+/// against anyone else's. The key is derived from the service key and remains valid across
+/// restarts.
+#[derive(Clone)]
+pub struct CsrfKey(Arc<[u8; 64]>);
+
+impl CsrfKey {
+    pub fn derive(service_key: &ServiceSigningKey) -> Self {
+        let key = hmac_sha512::HMAC::mac(b"csrf-token", service_key.to_bytes());
+        Self(Arc::new(key))
+    }
+
+    fn issue(&self, session_value: &str) -> String {
+        let mut nonce = [0u8; CSRF_NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let tag = self.tag(&nonce, session_value);
+        format!("{}.{}", B64.encode(nonce), B64.encode(tag))
+    }
+
+    fn tag(&self, nonce: &[u8], session_value: &str) -> [u8; 64] {
+        let input = [nonce, session_value.as_bytes()].concat();
+        hmac_sha512::HMAC::mac(input, self.0.as_slice())
+    }
+
+    fn verify(&self, token: &str, session_value: &str) -> bool {
+        let Some((nonce_b64, tag_b64)) = token.split_once('.') else {
+            return false;
+        };
+
+        let (Ok(nonce), Ok(tag)) = (B64.decode(nonce_b64), B64.decode(tag_b64)) else {
+            return false;
+        };
+
+        if nonce.len() != CSRF_NONCE_LENGTH || tag.len() != 64 {
+            return false;
+        }
+
+        // Compare every byte regardless of where the first difference is so the timing doesn't
+        // reveal how much of a forged tag was correct
+        let expected = self.tag(&nonce, session_value);
+        let difference = expected
+            .iter()
+            .zip(tag.iter())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+
+        difference == 0
+    }
+}
+
+/// The CSRF token for the current request, available to any handler behind the CSRF middleware.
+/// Templates rendering a form that changes state need to include it in a hidden
+/// [`CSRF_FORM_FIELD`] input.
+#[derive(Clone)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for CsrfToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CsrfToken
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .extensions
+            .get::<CsrfToken>()
+            .cloned()
+            .expect("CSRF middleware to be applied to routes using the token");
+
+        Ok(token)
+    }
+}
+
+/// Protects the browser facing routes from cross-site request forgery using signed double-submit
+/// tokens. Every response carries a token in a cookie, requests that change state need to echo it
+/// back in either the [`CSRF_HEADER_NAME`] header or a [`CSRF_FORM_FIELD`] form field. Requests
+/// authenticated with an API key don't rely on cookies and are exempt.
+pub async fn middleware(
+    State(csrf_key): State<CsrfKey>,
+    State(session_cookie): State<SessionCookie>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let mut cookie_jar = CookieJar::from_headers(request.headers());
+
+    let session_value = cookie_jar
+        .get(session_cookie.name_for_request(request.headers()))
+        .map(|cookie| cookie.value().to_string())
+        .unwrap_or_default();
+
+    let existing_token = cookie_jar
+        .get(CSRF_COOKIE_NAME)
+        .map(|cookie| cookie.value().to_string())
+        .filter(|token| csrf_key.verify(token, &session_value));
+
+    if requires_token(request.method(), request.headers()) {
+        let (buffered_request, submitted_token) = match submitted_token(request).await {
+            Ok(extracted) => extracted,
+            Err(response) => return response,
+        };
+        request = buffered_request;
+
+        let valid = match (&existing_token, submitted_token) {
+            (Some(expected), Some(submitted)) => *expected == submitted,
+            _ => false,
+        };
+
+        if !valid {
+            tracing::warn!("rejected state changing request without a valid CSRF token");
+
+            let msg = serde_json::json!({"msg": "invalid csrf token"});
+            return (StatusCode::FORBIDDEN, Json(msg)).into_response();
+        }
+    }
+
+    let secure = request_scheme(request.headers()) == "https";
+    let (token, newly_issued) = match existing_token {
+        Some(token) => (token, false),
+        None => (csrf_key.issue(&session_value), true),
+    };
+
+    request.extensions_mut().insert(CsrfToken(token.clone()));
+    let response = next.run(request).await;
+
+    if !newly_issued {
+        return response;
+    }
+
+    // Scripts need to be able to read the token to place it in a header so this can't be
+    // restricted to HTTP
+    let cookie = Cookie::build((CSRF_COOKIE_NAME, token))
+        .http_only(false)
+        .same_site(SameSite::Strict)
+        .path("/")
+        .secure(secure);
+    cookie_jar = cookie_jar.add(cookie);
+
+    (cookie_jar, response).into_response()
+}
+
+/// Applies the CSRF [`middleware`] to every route in the router. Any routes that accept the session
+/// cookie need this, including those that also accept API keys as the cookie is still sent along
+/// with requests made from other sites.
+pub(crate) fn with_csrf_protection<S>(router: Router<S>, state: S) -> Router<S>
+where
+    CsrfKey: FromRef<S>,
+    SessionCookie: FromRef<S>,
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(axum::middleware::from_fn_with_state(state, middleware))
+}
+
+/// Safe methods don't change anything and never need a token, neither do requests carrying an
+/// API key since browsers won't attach those on their own.
+fn requires_token(method: &Method, headers: &HeaderMap) -> bool {
+    let safe_method = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+
+    let bearer_auth = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.starts_with("Bearer "))
+        .unwrap_or(false);
+
+    !safe_method && !bearer_auth
+}
+
+/// Finds the token submitted with a request. Form bodies have to be read to check for the field
+/// so the request is rebuilt with the buffered body for the handler.
+async fn submitted_token(request: Request) -> Result<(Request, Option<String>), Response> {
+    if let Some(token) = request
+        .headers()
+        .get(CSRF_HEADER_NAME)
+        .and_then(|value| value.to_str().ok())
+    {
+        let token = token.to_string();
+        return Ok((request, Some(token)));
+    }
+
+    let is_form = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .map(|value| {
+            value
+                .as_bytes()
+                .starts_with(b"application/x-www-form-urlencoded")
+        })
+        .unwrap_or(false);
+
+    if !is_form {
+        return Ok((request, None));
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, CSRF_FORM_BODY_LIMIT).await {
+        Ok(body) => body,
+        Err(err) => {
+            tracing::warn!("unable to read form body while checking CSRF token: {err}");
+
+            let msg = serde_json::json!({"msg": "unable to read request body"});
+            return Err((StatusCode::BAD_REQUEST, Json(msg)).into_response());
+        }
+    };
+
+    let token = url::form_urlencoded::parse(&body)
+        .find(|(name, _)| name == CSRF_FORM_FIELD)
+        .map(|(_, value)| value.into_owned());
+
+    Ok((Request::from_parts(parts, Body::from(body)), token))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::get;
+    use jwt_simple::prelude::ES384KeyPair;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::auth::SESSION_COOKIE_NAME;
+
+    #[derive(Clone)]
+    struct TestState {
+        csrf_key: CsrfKey,
+        session_cookie: SessionCookie,
+    }
+
+    impl FromRef<TestState> for CsrfKey {
+        fn from_ref(state: &TestState) -> Self {
+            state.csrf_key.clone()
+        }
+    }
+
+    impl FromRef<TestState> for SessionCookie {
+        fn from_ref(state: &TestState) -> Self {
+            state.session_cookie
+        }
+    }
+
+    fn test_router() -> Router {
+        let signing_key = ServiceSigningKey::new(ES384KeyPair::generate());
+        let state = TestState {
+            csrf_key: CsrfKey::derive(&signing_key),
+            session_cookie: SessionCookie::default(),
+        };
+
+        let form_handler = |token: CsrfToken| async move { token.as_str().to_string() };
+
+        let router = Router::new().route("/", get(form_handler).post(|| async { "changed" }));
+        with_csrf_protection(router, state.clone()).with_state(state)
+    }
+
+    /// Loads the form as the session would, returning the token and the cookie it came in.
+    async fn issued_token(router: &Router, session: &str) -> (String, String) {
+        let request = Request::get("/")
+            .header(header::COOKIE, format!("{SESSION_COOKIE_NAME}={session}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+
+        let set_cookie = response.headers()[header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let cookie = set_cookie.split(';').next().unwrap().to_string();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let token = String::from_utf8(body.to_vec()).unwrap();
+
+        assert_eq!(cookie, format!("{CSRF_COOKIE_NAME}={token}"));
+        (token, cookie)
+    }
+
+    fn post(session: &str, csrf_cookie: &str) -> http::request::Builder {
+        Request::post("/").header(
+            header::COOKIE,
+            format!("{SESSION_COOKIE_NAME}={session}; {csrf_cookie}"),
+        )
+    }
+
+    async fn status_for(router: &Router, request: Request) -> StatusCode {
+        router.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_valid_tokens_accepted() {
+        let router = test_router();
+        let (token, cookie) = issued_token(&router, "session-one").await;
+
+        let request = post("session-one", &cookie)
+            .header(CSRF_HEADER_NAME, &token)
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(status_for(&router, request).await, StatusCode::OK);
+
+        let request = post("session-one", &cookie)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(format!("name=value&{CSRF_FORM_FIELD}={token}")))
+            .unwrap();
+        assert_eq!(status_for(&router, request).await, StatusCode::OK);
+
+        // API key requests don't carry ambient credentials and are exempt
+        let request = Request::post("/")
+            .header(header::AUTHORIZATION, "Bearer api-key-token")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(status_for(&router, request).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_missing_and_forged_tokens_rejected() {
+        let router = test_router();
+        let (token, cookie) = issued_token(&router, "session-one").await;
+
+        let request = post("session-one", &cookie).body(Body::empty()).unwrap();
+        assert_eq!(status_for(&router, request).await, StatusCode::FORBIDDEN);
+
+        let request = post("session-one", &cookie)
+            .header(CSRF_HEADER_NAME, "forged.token")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(status_for(&router, request).await, StatusCode::FORBIDDEN);
+
+        // A token issued to one session can't be replayed against another
+        let request = post("session-two", &cookie)
+            .header(CSRF_HEADER_NAME, &token)
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(status_for(&router, request).await, StatusCode::FORBIDDEN);
+
+        // The session cookie alone is exactly what a forged request would carry
+        let request = Request::post("/")
+            .header(header::COOKIE, format!("{SESSION_COOKIE_NAME}=session-one"))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(status_for(&router, request).await, StatusCode::FORBIDDEN);
+
+        // Without the cookie there's nothing to compare the submitted token against
+        let request = Request::post("/")
+            .header(header::COOKIE, format!("{SESSION_COOKIE_NAME}=session-one"))
+            .header(CSRF_HEADER_NAME, &token)
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(status_for(&router, request).await, StatusCode::FORBIDDEN);
+    }
+}
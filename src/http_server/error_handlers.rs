@@ -1,10 +1,54 @@
+use axum::extract::Request;
 use axum::http::StatusCode;
+use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use axum_extra::TypedHeader;
 use headers::ContentType;
+use http::header;
 
-use crate::pages::NotFoundTemplate;
+use crate::api::ApiError;
+use crate::pages::{MethodNotAllowedTemplate, NotFoundTemplate};
+
+/// Axum answers requests for a known path made with the wrong method with an empty 405 response
+/// and an `Allow` header listing the permitted methods. This fills in a body matching the kind of
+/// client that made the request while keeping that header intact. Depending on whether the route
+/// was nested the header is either already present here or gets added by the router afterwards.
+pub async fn method_not_allowed_handler(request: Request, next: Next) -> Response {
+    let is_api = request.uri().path().starts_with("/api/");
+    let wants_html = request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .map(|accept| accept.contains("text/html"))
+        .unwrap_or(false);
+
+    let response = next.run(request).await;
+
+    // Handlers are free to return their own 405 responses, only the bodyless ones generated by the
+    // router are replaced.
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED
+        || response.headers().contains_key(header::CONTENT_TYPE)
+    {
+        return response;
+    }
+    let allow = response.headers().get(header::ALLOW).cloned();
+
+    let mut response = if is_api {
+        ApiError::method_not_allowed().into_response()
+    } else if wants_html {
+        (StatusCode::METHOD_NOT_ALLOWED, MethodNotAllowedTemplate).into_response()
+    } else {
+        let err_msg = serde_json::json!({"msg": "method not allowed"});
+        (StatusCode::METHOD_NOT_ALLOWED, Json(err_msg)).into_response()
+    };
+
+    if let Some(allow) = allow {
+        response.headers_mut().insert(header::ALLOW, allow);
+    }
+
+    response
+}
 
 pub async fn server_error_handler(error: tower::BoxError) -> Response {
     let mut errors = vec![error.to_string()];
@@ -44,3 +88,73 @@ pub async fn not_found_handler(TypedHeader(content_type): TypedHeader<ContentTyp
         _ => (StatusCode::NOT_FOUND, "not found").into_response(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn test_router() -> Router {
+        Router::new()
+            .route("/api/v1/thing", get(|| async { "thing" }))
+            .route("/page", get(|| async { "page" }))
+            .nest(
+                "/api/v1/nested",
+                Router::new().route("/thing", get(|| async { "nested" })),
+            )
+            .layer(axum::middleware::from_fn(method_not_allowed_handler))
+    }
+
+    #[tokio::test]
+    async fn test_api_method_not_allowed() {
+        let request = Request::post("/api/v1/thing").body(Body::empty()).unwrap();
+        let response = test_router().oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let allow = response.headers().get(header::ALLOW).unwrap();
+        assert!(allow.to_str().unwrap().contains("GET"));
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "method_not_allowed");
+    }
+
+    #[tokio::test]
+    async fn test_nested_method_not_allowed() {
+        let request = Request::put("/api/v1/nested/thing")
+            .body(Body::empty())
+            .unwrap();
+        let response = test_router().oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let allow = response.headers().get(header::ALLOW).unwrap();
+        assert!(allow.to_str().unwrap().contains("GET"));
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "method_not_allowed");
+    }
+
+    #[tokio::test]
+    async fn test_browser_method_not_allowed() {
+        let request = Request::delete("/page")
+            .header(header::ACCEPT, "text/html,application/xhtml+xml")
+            .body(Body::empty())
+            .unwrap();
+        let response = test_router().oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert!(response.headers().contains_key(header::ALLOW));
+
+        let content_type = response.headers().get(header::CONTENT_TYPE).unwrap();
+        assert!(content_type.to_str().unwrap().starts_with("text/html"));
+    }
+}
@@ -0,0 +1,76 @@
+use axum::extract::{Host, Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::StatusCode;
+
+use crate::app::AllowedHosts;
+
+/// Rejects any request addressed to a host the service hasn't been configured to answer for.
+/// Without this an attacker controlling the `Host` (or `X-Forwarded-Host`) header could get us to
+/// generate links and redirects pointing at their own server.
+pub async fn middleware(
+    State(allowed_hosts): State<AllowedHosts>,
+    Host(host): Host,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !allowed_hosts.is_allowed(&host) {
+        tracing::warn!(host, "rejected request for a host that isn't allowed");
+
+        let msg = serde_json::json!({"msg": "invalid host"});
+        return (StatusCode::BAD_REQUEST, Json(msg)).into_response();
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::Router;
+    use http::header;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn test_router() -> Router {
+        let allowed_hosts = AllowedHosts::new(&["app.example.com"]);
+
+        Router::new().route("/", get(|| async { "hello" })).layer(
+            axum::middleware::from_fn_with_state(allowed_hosts, middleware),
+        )
+    }
+
+    async fn status_for(headers: &[(&str, &str)]) -> StatusCode {
+        let mut request = Request::get("/");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+
+        let request = request.body(Body::empty()).unwrap();
+        test_router().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_allowed_host_accepted() {
+        let status = status_for(&[(header::HOST.as_str(), "app.example.com:443")]).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_spoofed_hosts_rejected() {
+        let status = status_for(&[(header::HOST.as_str(), "attacker.net")]).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // The forwarded host takes priority when working out where a request was sent
+        let forwarded = [
+            (header::HOST.as_str(), "app.example.com"),
+            ("x-forwarded-host", "attacker.net"),
+        ];
+        assert_eq!(status_for(&forwarded).await, StatusCode::BAD_REQUEST);
+
+        assert_eq!(status_for(&[]).await, StatusCode::BAD_REQUEST);
+    }
+}
@@ -1,35 +1,58 @@
+use std::future::IntoFuture;
 use std::net::SocketAddr;
 use std::time::Duration;
 
 use axum::error_handling::HandleErrorLayer;
 use axum::extract::DefaultBodyLimit;
-use axum::handler::HandlerWithoutStateExt;
+use axum::middleware;
 use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::Router;
 use axum::ServiceExt;
-use bincode::Options;
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine;
 use http::uri::PathAndQuery;
 use http::{header, Request};
 use time::OffsetDateTime;
-use tokio::sync::watch;
+use tower::limit::GlobalConcurrencyLimitLayer;
 use tower::ServiceBuilder;
-use tower_http::request_id::MakeRequestUuid;
 use tower_http::sensitive_headers::{
     SetSensitiveRequestHeadersLayer, SetSensitiveResponseHeadersLayer,
 };
-use tower_http::services::ServeDir;
 use tower_http::trace::{DefaultOnFailure, DefaultOnResponse, MakeSpan, TraceLayer};
 use tower_http::validate_request::ValidateRequestHeaderLayer;
-use tower_http::{LatencyUnit, ServiceBuilderExt};
+use tower_http::LatencyUnit;
 use tracing::{Level, Span};
 
-use crate::app::{State, StateSetupError};
+use crate::app::{Config, State, StateSetupError};
 use crate::background_jobs::impls::TickMessage;
 use crate::extractors::SessionIdentity;
-use crate::{auth, health_check, pages};
+use crate::{admin, api, auth, health_check, pages, shutdown_reason, ShutdownSignal};
 
+mod catch_panic;
+mod compression;
+mod csrf;
 mod error_handlers;
+mod host_validation;
+mod rate_limit;
+mod request_id;
+mod request_timeout;
+mod server_timing;
+mod static_assets;
+mod user_concurrency;
+
+use csrf::with_csrf_protection;
+use rate_limit::with_rate_limit;
+pub(crate) use request_timeout::with_request_timeout;
+
+pub use csrf::{CsrfKey, CsrfToken, CSRF_FORM_FIELD, CSRF_HEADER_NAME};
+pub use rate_limit::{RateLimit, RateLimiter, RateLimits, RateLimitsError, RouteGroup};
+pub use request_timeout::{RequestDeadline, RequestTimeouts};
+pub use server_timing::ServerTimings;
+pub use user_concurrency::UserConcurrencyLimiter;
+
+/// Where the built frontend assets are served from, relative to the working directory.
+pub(crate) const ASSET_DIRECTORY: &str = "dist";
 
 static FILTERED_VALUE: &str = "<filtered>";
 
@@ -37,11 +60,7 @@ static MISSING_VALUE: &str = "<not_provided>";
 
 /// The largest size content that any client can send us before we reject it. This is a pretty
 /// heavily restricted default but most JSON responses are relatively tiny.
-const REQUEST_MAX_SIZE: usize = 256 * 1_024;
-
-/// The maximum number of seconds that any individual request can take before it is dropped with an
-/// error.
-const REQUEST_TIMEOUT_SECS: u64 = 5;
+pub(crate) const REQUEST_MAX_SIZE: usize = 256 * 1_024;
 
 const SENSITIVE_HEADERS: &[http::HeaderName] = &[
     header::AUTHORIZATION,
@@ -62,9 +81,15 @@ impl<B> MakeSpan<B> for SensitiveRequestMakeSpan {
             .path_and_query
             .expect("http requests to have a path");
 
+        let request_id = request
+            .headers()
+            .get(&request_id::X_REQUEST_ID)
+            .and_then(|id| id.to_str().ok());
+
         tracing::span!(
             Level::INFO,
             "http_request",
+            request_id,
             method = %request.method(),
             uri = %filter_path_and_query(&path_and_query),
             version = ?request.version(),
@@ -109,72 +134,191 @@ fn filter_path_and_query(path_and_query: &PathAndQuery) -> String {
 }
 
 pub async fn run(
-    listen_addr: SocketAddr,
-    log_level: Level,
+    config: Config,
     state: State,
-    mut shutdown_rx: watch::Receiver<()>,
+    shutdown_rx: ShutdownSignal,
 ) -> Result<(), HttpServerError> {
-    let trace_layer = TraceLayer::new_for_http()
-        .make_span_with(SensitiveRequestMakeSpan)
-        .on_response(
-            DefaultOnResponse::new()
-                .include_headers(false)
-                .level(log_level)
-                .latency_unit(LatencyUnit::Micros),
+    let listen_addr = *config.listen_addr();
+    let internal_listen_addr = config.internal_listen_addr().copied();
+    let request_timeouts = config.request_timeouts();
+
+    // When there is a separate internal listener the administrative and detailed status endpoints
+    // are only served there, the public listener keeps just enough to report it's alive.
+    let serve_internal_publicly = internal_listen_addr.is_none();
+
+    // Health checks and static assets are exempt from the per-user limits, everything else needs
+    // to share fairly. They're also exempt from host validation, orchestrators probe the health
+    // checks by address and nothing served by either depends on the host.
+    let mut user_limited_router = Router::new();
+    if serve_internal_publicly {
+        let admin_router = with_csrf_protection(admin::router(state.clone()), state.clone()).layer(
+            middleware::from_fn_with_state(state.clone(), user_concurrency::middleware),
+        );
+        user_limited_router = user_limited_router.nest("/admin", admin_router);
+    }
+
+    // Each group of routes gets its own request rate allowance per client
+    let auth_router = with_csrf_protection(auth::router(state.clone()), state.clone());
+    let events_router = Router::new()
+        .route(
+            "/events",
+            get(event_bus_handler).layer(Extension(EventSocketConfig {
+                compression: config.event_compression(),
+            })),
         )
-        .on_failure(DefaultOnFailure::new().latency_unit(LatencyUnit::Micros));
+        .route("/events/test", get(test_event_handler));
+    let pages_router = with_csrf_protection(pages::router(state.clone()), state.clone());
+
+    let user_limited_router = user_limited_router
+        .nest(
+            "/auth",
+            with_client_limits(auth_router, state.clone(), RouteGroup::Auth),
+        )
+        .merge(with_client_limits(
+            events_router,
+            state.clone(),
+            RouteGroup::Events,
+        ))
+        .nest(
+            "/",
+            with_client_limits(pages_router, state.clone(), RouteGroup::Pages),
+        );
 
-    // todo: need to turn not_found_handler into its own service...
-    let static_assets = ServeDir::new("dist")
-        .precompressed_br()
-        .precompressed_gzip()
-        .not_found_service(error_handlers::not_found_handler.into_service());
+    // The API sets the timeouts of its own routes as some of them are long running, it has to be
+    // added after the standard timeout is applied to everything else. Browsers can call it with
+    // the session cookie so it needs the same CSRF protection as the pages.
+    let api_router =
+        with_csrf_protection(api::router(state.clone(), request_timeouts), state.clone());
+    let user_limited_router =
+        with_request_timeout(user_limited_router, request_timeouts.standard())
+            .nest(
+                "/api/v1",
+                with_client_limits(api_router, state.clone(), RouteGroup::Api),
+            )
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                host_validation::middleware,
+            ));
+
+    let status_router = if serve_internal_publicly {
+        health_check::router(state.clone())
+    } else {
+        health_check::public_router(state.clone())
+    };
 
     // todo: I think I can switch my sub-routers with different states using nest_service while
     // still having a global set of layers applied now...
     let root_router = Router::new()
         // order matters here, we inject a single dynamic asset mixed in with our static ones
         .route("/assets/css/metrics.css", get(pages::css_metrics_handler))
-        .nest_service("/assets", static_assets)
-        .nest("/auth", auth::router(state.clone()))
-        //.nest("/api/v1", api::router(app_state.clone()))
-        .nest("/_status", health_check::router(state.clone()))
-        .route("/events", get(event_bus_handler))
-        .route("/events/test", get(test_event_handler))
-        .nest("/", pages::router(state.clone()))
+        .nest_service("/assets", static_assets::service(ASSET_DIRECTORY))
+        .nest("/_status", status_router);
+    let root_router = with_request_timeout(root_router, request_timeouts.standard())
+        .merge(user_limited_router)
+        .with_state(state.clone())
+        .fallback(error_handlers::not_found_handler)
+        .layer(middleware::from_fn(
+            error_handlers::method_not_allowed_handler,
+        ))
+        .layer(compression::layer(config.compression_min_size()));
+    let root_router = with_common_layers(root_router, &config);
+
+    let public_server = serve(listen_addr, root_router, shutdown_rx.clone());
+
+    let internal_listen_addr = match internal_listen_addr {
+        Some(addr) => addr,
+        None => return public_server.await,
+    };
+
+    let internal_router = Router::new()
+        .nest("/_status", health_check::internal_router(state.clone()))
+        .nest(
+            "/admin",
+            with_csrf_protection(admin::router(state.clone()), state.clone()),
+        );
+    let internal_router = with_request_timeout(internal_router, request_timeouts.standard())
         .with_state(state)
         .fallback(error_handlers::not_found_handler)
+        .layer(middleware::from_fn(
+            error_handlers::method_not_allowed_handler,
+        ));
+    let internal_router = with_common_layers(internal_router, &config);
+
+    let internal_server = serve(internal_listen_addr, internal_router, shutdown_rx);
+
+    tokio::try_join!(public_server, internal_server)?;
+
+    Ok(())
+}
+
+async fn serve(
+    listen_addr: SocketAddr,
+    router: Router,
+    mut shutdown_rx: ShutdownSignal,
+) -> Result<(), HttpServerError> {
+    tracing::info!(addr = ?listen_addr, "server listening");
+    let listener = tokio::net::TcpListener::bind(listen_addr).await?;
+
+    let service = router.into_make_service_with_connect_info::<SocketAddr>();
+    let abandoned = shutdown_reason::connections_abandoned(shutdown_rx.clone());
+
+    let server = axum::serve(listener, service).with_graceful_shutdown(async move {
+        let _ = shutdown_rx.changed().await;
+    });
+
+    // Graceful shutdown waits on every open connection to finish, long lived ones like the event
+    // websocket could hold that up indefinitely. Dropping the server future closes them instead.
+    tokio::select! {
+        result = server.into_future() => result?,
+        _ = abandoned => tracing::info!(addr = ?listen_addr, "abandoning open connections"),
+    }
+
+    Ok(())
+}
+
+/// The middleware shared by every listener, covering request tracing, log sanitization, load
+/// limits, and request size limits.
+fn with_common_layers(mut router: Router, config: &Config) -> Router {
+    let trace_layer = TraceLayer::new_for_http()
+        .make_span_with(SensitiveRequestMakeSpan)
+        .on_response(
+            DefaultOnResponse::new()
+                .include_headers(false)
+                .level(config.log_level())
+                .latency_unit(LatencyUnit::Micros),
+        )
+        .on_failure(DefaultOnFailure::new().latency_unit(LatencyUnit::Micros));
+
+    // A panicking handler would otherwise take its connection down with it. Each layer added to a
+    // router wraps the ones before it, adding this first keeps it closest to the handlers so the
+    // 500 it produces still passes through tracing and everything else on the way out.
+    router = router.layer(middleware::from_fn(catch_panic::middleware));
+
+    // Exposes a breakdown of where time was spent handling each request to the client. Useful for
+    // debugging from a browser but not something that should be public in production.
+    if config.server_timing() {
+        router = router.layer(middleware::from_fn(server_timing::middleware));
+    }
+
+    router = router
         // The order of these layers and configuration extensions was carefully chosen as they will see
         // the requests to responses effectively in the order they're defined.
         //
         // Tracing and log handling get setup before anything else
         .layer(trace_layer)
-        //.layer(HandleErrorLayer::new(error_handlers::server_error_handler))
         // From here on out our requests might be logged, ensure any sensitive headers are stripped
         // before we do any logging
         .layer(SetSensitiveRequestHeadersLayer::from_shared(
             SENSITIVE_HEADERS.into(),
-        ))
-        // If requests are queued or take longer than this duration we want the cut them off
-        // regardless of any other protections that are inplace
-        // todo
-        //.timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
-        // If any future services or middleware indicate they're not available, reject them with a
-        // service too busy error
-        // todo
-        //.load_shed()
-        // Restrict the number of concurrent in flight requests, desired value for this is going to
-        // vary from service to service, make sure it reflects the number of concurrent requests
-        // your service can handle.
-        // todo
-        //.concurrency_limit(1024)
-        // Make sure our request has a unique identifier if we don't already have one. This does
-        // allow our upstream to arbitrarily set headers so this service should have protection
-        // against arbitrary untrusted injections of this header.
-        // todo
-        //.set_x_request_id(MakeRequestUuid)
-        // todo
-        //.propagate_x_request_id()
+        ));
+
+    router = with_load_limits(router, config.concurrency_limit());
+
+    // Make sure our request has a unique identifier if we don't already have one. Our upstream
+    // can set this header to anything, IDs that don't look like ours are replaced with new ones.
+    router = request_id::with_request_ids(router);
+
+    router
         // By default limit any request to this size. Individual handlers can opt-out of this limit
         // if they so choose (such as an upload handler).
         .layer(DefaultBodyLimit::max(REQUEST_MAX_SIZE))
@@ -187,18 +331,38 @@ pub async fn run(
         // filtering out any sensitive headers from our logs.
         .layer(SetSensitiveResponseHeadersLayer::from_shared(
             SENSITIVE_HEADERS.into(),
-        ));
+        ))
+}
 
-    tracing::info!(addr = ?listen_addr, "server listening");
-    let listener = tokio::net::TcpListener::bind(listen_addr).await?;
+/// Applies the per-client limits of the group to every route in the router. The rate limit is
+/// checked first, it doesn't touch the database while the concurrency limit looks up the session.
+fn with_client_limits(router: Router<State>, state: State, group: RouteGroup) -> Router<State> {
+    let router = router.layer(middleware::from_fn_with_state(
+        state.clone(),
+        user_concurrency::middleware,
+    ));
 
-    axum::serve(listener, root_router)
-        .with_graceful_shutdown(async move {
-            let _ = shutdown_rx.changed().await;
-        })
-        .await?;
+    with_rate_limit(router, state, group)
+}
 
-    Ok(())
+/// Rejects requests the service doesn't have room for, turning them into the 503 responses
+/// produced by [`error_handlers::server_error_handler`]. How long requests may run is limited
+/// separately for each group of routes by [`with_request_timeout`].
+///
+/// Layers added to a router wrap each of its routes individually, the limit is shared across all
+/// of them so it covers the whole listener rather than each route getting its own allowance.
+fn with_load_limits(router: Router, concurrency_limit: usize) -> Router {
+    router.layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(error_handlers::server_error_handler))
+            // If any future services or middleware indicate they're not available, reject them
+            // with a service too busy error
+            .load_shed()
+            // Restrict the number of concurrent in flight requests, desired value for this is
+            // going to vary from service to service, make sure it reflects the number of
+            // concurrent requests your service can handle.
+            .layer(GlobalConcurrencyLimitLayer::new(concurrency_limit)),
+    )
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -217,102 +381,181 @@ async fn test_event_handler(
     session: SessionIdentity,
     axum::extract::State(state): axum::extract::State<State>,
 ) -> Response {
-    let _ = state.event_bus().send(
-        SystemEvent::TestEvent,
-        &TestEvent {
-            session_id: session.id(),
-        },
-    );
+    let _ = state.event_bus().send_typed(&TestEvent {
+        session_id: session.id(),
+    });
     (StatusCode::NO_CONTENT, ()).into_response()
 }
 
-use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use std::io::Write;
+
+use axum::extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use axum::Extension;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use futures::{SinkExt, StreamExt};
 use serde::Serialize;
 
+/// The version of the messages sent over the event websocket. This needs to be incremented
+/// whenever a change is made to the shape of [`BusToClientMessage`] that existing clients won't
+/// understand.
+const EVENT_PROTOCOL_VERSION: u32 = 1;
+
+/// Clients can request a specific version of the protocol using the `Sec-WebSocket-Protocol`
+/// header with this value.
+const EVENT_SUBPROTOCOL: &str = "events.v1";
+
+/// Requesting this subprotocol instead of [`EVENT_SUBPROTOCOL`] indicates the client is able to
+/// handle gzip compressed messages. The messages are identical other than large messages being
+/// sent as compressed binary frames instead of text frames. Only offered when compression has
+/// been enabled.
+const EVENT_GZIP_SUBPROTOCOL: &str = "events.v1+gzip";
+
+/// Messages smaller than this are always sent uncompressed, the overhead of compressing them
+/// isn't worth the few bytes saved.
+const EVENT_COMPRESSION_THRESHOLD: usize = 1_024;
+
+/// Sent as the close code when a client requested only protocol versions we don't support. This
+/// is within the range reserved for application use.
+const UNSUPPORTED_PROTOCOL_CLOSE_CODE: u16 = 4001;
+
+/// Sent as the close code when the session the socket was opened with has been revoked.
+const SESSION_REVOKED_CLOSE_CODE: u16 = 4002;
+
+/// Sent as the close code when the session the socket was opened with has expired.
+const SESSION_EXPIRED_CLOSE_CODE: u16 = 4003;
+
+/// How often a connected socket checks that the session it was opened with is still valid.
+/// Revocations are announced on the event bus and handled immediately, this catches sessions that
+/// have expired or were removed without an announcement.
+const SESSION_REVALIDATION_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone)]
+struct EventSocketConfig {
+    compression: bool,
+}
+
 async fn event_bus_handler(
-    _session: SessionIdentity,
+    session: SessionIdentity,
+    headers: http::HeaderMap,
+    Extension(socket_config): Extension<EventSocketConfig>,
     upgrade_request: WebSocketUpgrade,
     axum::extract::State(state): axum::extract::State<State>,
 ) -> Response {
-    upgrade_request.on_upgrade(|sock| event_bus_stream_handler(sock, state))
-}
+    let requested_protocols = requested_subprotocols(&headers);
 
-use crate::event_bus::UserRegistration;
+    let compress = socket_config.compression
+        && requested_protocols
+            .iter()
+            .any(|p| p == EVENT_GZIP_SUBPROTOCOL);
+    let supported = compress
+        || requested_protocols.is_empty()
+        || requested_protocols.iter().any(|p| p == EVENT_SUBPROTOCOL);
 
-async fn event_bus_stream_handler(stream: WebSocket, state: State) {
-    let (mut client_tx, mut client_rx) = stream.split();
+    let offered_protocols = if socket_config.compression {
+        vec![EVENT_GZIP_SUBPROTOCOL, EVENT_SUBPROTOCOL]
+    } else {
+        vec![EVENT_SUBPROTOCOL]
+    };
 
-    let event_bus = state.event_bus();
-    let mut bus_rx = event_bus.subscribe();
+    let session_id = session.id();
+    let user_id = session.user_id();
 
-    // todo: need to force disconnects if a session is invalidated
-    // todo: need to force disconnect is a session expires
+    upgrade_request
+        .protocols(offered_protocols)
+        .on_upgrade(move |mut sock| async move {
+            if !supported {
+                tracing::debug!(requested = ?requested_protocols, "rejecting unsupported event protocol");
 
-    let mut bus_to_client_task = tokio::spawn(async move {
-        loop {
-            let (event_type, payload) = match bus_rx.recv().await {
-                Ok(msg) => msg,
-                Err(err) => {
-                    tracing::error!("encountered bus error in websocket handling: {err}");
-                    break;
-                }
-            };
-
-            let bin_code_config = bincode::DefaultOptions::new();
-
-            let decoded = match &event_type {
-                SystemEvent::UserRegistration => {
-                    match bin_code_config.deserialize::<UserRegistration>(&payload) {
-                        Ok(event) => serde_json::to_value(&event).ok(),
-                        Err(err) => {
-                            tracing::warn!(
-                                "failed to decode user registration on event bus: {err}"
-                            );
-                            None
-                        }
-                    }
-                }
-                SystemEvent::TestEvent => {
-                    match bin_code_config.deserialize::<TestEvent>(&payload) {
-                        Ok(event) => serde_json::to_value(&event).ok(),
-                        Err(err) => {
-                            tracing::warn!(
-                                "failed to decode user registration on event bus: {err}"
-                            );
-                            None
-                        }
-                    }
-                }
-                SystemEvent::Tick => match bin_code_config.deserialize::<TickMessage>(&payload) {
-                    Ok(event) => serde_json::to_value(&ClientTick::from(event)).ok(),
-                    Err(err) => {
-                        tracing::warn!("failed to decode tick on event bus: {err}");
-                        None
-                    }
-                },
-            };
+                let close_frame = CloseFrame {
+                    code: UNSUPPORTED_PROTOCOL_CLOSE_CODE,
+                    reason: format!("unsupported protocol, expected {EVENT_SUBPROTOCOL}").into(),
+                };
+                let _ = sock.send(Message::Close(Some(close_frame))).await;
 
-            let response = BusToClientMessage {
-                event_type,
-                payload,
-                decoded,
-            };
+                return;
+            }
 
-            let response_msg = match serde_json::to_string(&response) {
-                Ok(rm) => rm,
-                Err(err) => {
-                    tracing::error!("failed to serialize message to websocket client: {err}");
-                    break;
-                }
-            };
+            event_bus_stream_handler(sock, state, session_id, user_id, compress).await
+        })
+}
 
-            if let Err(err) = client_tx.send(Message::Text(response_msg)).await {
-                tracing::error!("failed to send message to websocket client: {err}");
-                break;
-            }
+/// Builds the websocket frame for a message to a client. When compression was negotiated, large
+/// messages are gzipped and sent as a binary frame.
+fn client_frame(message: String, compress: bool) -> Message {
+    if !compress || message.len() < EVENT_COMPRESSION_THRESHOLD {
+        return Message::Text(message);
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    let compressed = encoder
+        .write_all(message.as_bytes())
+        .and_then(|_| encoder.finish());
+
+    match compressed {
+        Ok(bytes) => Message::Binary(bytes),
+        Err(err) => {
+            tracing::warn!("failed to compress websocket message, sending uncompressed: {err}");
+            Message::Text(message)
         }
-    });
+    }
+}
+
+fn requested_subprotocols(headers: &http::HeaderMap) -> Vec<String> {
+    headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|val| val.to_str().ok())
+        .flat_map(|val| val.split(','))
+        .map(|proto| proto.trim().to_string())
+        .filter(|proto| !proto.is_empty())
+        .collect()
+}
+
+use std::sync::OnceLock;
+
+use futures::Sink;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::MissedTickBehavior;
+
+use crate::database::custom_types::{SessionId, UserId};
+use crate::database::models::Session;
+use crate::database::Database;
+use crate::event_bus::{
+    BusEvent, BusEventRegistry, BusStream, ReauthenticationRequired, SessionRevoked,
+    UserRegistration,
+};
+
+async fn event_bus_stream_handler(
+    stream: WebSocket,
+    state: State,
+    session_id: SessionId,
+    user_id: UserId,
+    compress: bool,
+) {
+    let (mut client_tx, mut client_rx) = stream.split();
+
+    // Let the client know what they're talking to before anything else so they can detect when
+    // they need to upgrade.
+    let hello = serde_json::json!({"protocol_version": EVENT_PROTOCOL_VERSION});
+    if let Err(err) = client_tx.send(Message::Text(hello.to_string())).await {
+        tracing::error!("failed to send protocol version to websocket client: {err}");
+        return;
+    }
+
+    let event_bus = state.event_bus();
+    let bus_rx = event_bus.subscribe_filtered(CLIENT_BUS_EVENTS);
+
+    let database = state.database();
+    let mut bus_to_client_task = tokio::spawn(forward_bus_events(
+        client_tx,
+        bus_rx,
+        database,
+        session_id,
+        user_id,
+        compress,
+        SESSION_REVALIDATION_INTERVAL,
+    ));
 
     let mut client_to_bus_task = tokio::spawn(async move {
         while let Some(maybe_client_msg) = client_rx.next().await {
@@ -343,25 +586,472 @@ async fn event_bus_stream_handler(stream: WebSocket, state: State) {
     };
 }
 
+/// Everything a websocket connection acts on. Security events include details about other users
+/// and are never subscribed to.
+const CLIENT_BUS_EVENTS: &[SystemEvent] = &[
+    SystemEvent::ReauthenticationRequired,
+    SystemEvent::SessionRevoked,
+    SystemEvent::TestEvent,
+    SystemEvent::Tick,
+    SystemEvent::UserRegistration,
+];
+
+/// The events websocket clients receive the decoded contents of. Everything else is forwarded with
+/// only its raw payload.
+fn client_events() -> &'static BusEventRegistry {
+    static CLIENT_EVENTS: OnceLock<BusEventRegistry> = OnceLock::new();
+
+    CLIENT_EVENTS.get_or_init(|| {
+        BusEventRegistry::new()
+            .register::<ReauthenticationRequired>()
+            .register::<TestEvent>()
+            .register::<TickMessage>()
+            .register::<UserRegistration>()
+    })
+}
+
+/// Relays events from the bus to a connected client until either side goes away or the session the
+/// client connected with is no longer valid.
+async fn forward_bus_events<S>(
+    mut client_tx: S,
+    mut bus_rx: BusStream,
+    database: Database,
+    session_id: SessionId,
+    user_id: UserId,
+    compress: bool,
+    revalidation_interval: Duration,
+) where
+    S: Sink<Message> + Unpin,
+    S::Error: std::fmt::Display,
+{
+    let mut revalidation = tokio::time::interval(revalidation_interval);
+    revalidation.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    // The session was just checked when the socket was opened
+    revalidation.tick().await;
+
+    loop {
+        let bus_msg = tokio::select! {
+            bus_msg = bus_rx.next() => bus_msg,
+            _ = revalidation.tick() => {
+                if let Some(close_frame) = ended_session_close_frame(&database, session_id).await {
+                    let _ = client_tx.send(Message::Close(Some(close_frame))).await;
+                    break;
+                }
+
+                continue;
+            }
+        };
+
+        let (event_type, payload) = match bus_msg {
+            Some(Ok(msg)) => msg,
+            // The client is reading slower than events arrive, the oldest ones it hadn't seen yet
+            // were dropped. Letting it know is enough for it to decide whether to resync.
+            Some(Err(RecvError::Lagged(skipped))) => {
+                tracing::warn!(skipped, "websocket client fell behind the event bus");
+
+                let notice = serde_json::json!({ "type": "lag", "skipped": skipped });
+                if let Err(err) = client_tx
+                    .send(client_frame(notice.to_string(), compress))
+                    .await
+                {
+                    tracing::error!("failed to send message to websocket client: {err}");
+                    break;
+                }
+
+                continue;
+            }
+            Some(Err(RecvError::Closed)) | None => break,
+        };
+
+        // Some events are only meant for particular clients, or for none at all
+        match event_type {
+            SystemEvent::ReauthenticationRequired => {
+                match ReauthenticationRequired::decode(&payload) {
+                    // Only the affected user needs to be prompted to log in again
+                    Ok(event) if event.user_id != user_id => continue,
+                    Ok(_) => (),
+                    // Without knowing who it was meant for it can't be sent to anyone
+                    Err(err) => {
+                        tracing::warn!(
+                            "failed to decode reauthentication request on event bus: {err}"
+                        );
+                        continue;
+                    }
+                }
+            }
+            SystemEvent::SessionRevoked => {
+                match SessionRevoked::decode(&payload) {
+                    Ok(event) if event.session_id == session_id => {
+                        let close_frame = CloseFrame {
+                            code: SESSION_REVOKED_CLOSE_CODE,
+                            reason: "session revoked".into(),
+                        };
+                        let _ = client_tx.send(Message::Close(Some(close_frame))).await;
+
+                        break;
+                    }
+                    // Revocations of other sessions are none of this client's business
+                    Ok(_) => continue,
+                    Err(err) => {
+                        tracing::warn!("failed to decode session revocation on event bus: {err}");
+                        continue;
+                    }
+                }
+            }
+            // Security events include details about other users and are never forwarded
+            SystemEvent::SuspiciousLogin => continue,
+            _ => (),
+        }
+
+        let decoded = match client_events().decode(event_type, &payload) {
+            Some(Ok(decoded)) => Some(decoded),
+            Some(Err(err)) => {
+                tracing::warn!(?event_type, "failed to decode event on bus: {err}");
+                None
+            }
+            None => None,
+        };
+
+        let response = BusToClientMessage::new(event_type, &payload, decoded);
+
+        let response_msg = match serde_json::to_string(&response) {
+            Ok(rm) => rm,
+            Err(err) => {
+                tracing::error!("failed to serialize message to websocket client: {err}");
+                break;
+            }
+        };
+
+        if let Err(err) = client_tx.send(client_frame(response_msg, compress)).await {
+            tracing::error!("failed to send message to websocket client: {err}");
+            break;
+        }
+    }
+}
+
+/// Checks whether the session a socket was opened with has ended, producing the frame the socket
+/// should be closed with when it has. Problems reaching the database aren't the client's fault so
+/// the session is given the benefit of the doubt until the next check.
+async fn ended_session_close_frame(
+    database: &Database,
+    session_id: SessionId,
+) -> Option<CloseFrame<'static>> {
+    let mut conn = match database.acquire().await {
+        Ok(conn) => conn,
+        Err(err) => {
+            tracing::warn!("unable to revalidate websocket session: {err}");
+            return None;
+        }
+    };
+
+    match Session::locate(&mut conn, session_id).await {
+        Ok(Some(session)) if session.expires_at() > OffsetDateTime::now_utc() => None,
+        Ok(Some(_)) => Some(CloseFrame {
+            code: SESSION_EXPIRED_CLOSE_CODE,
+            reason: "session expired".into(),
+        }),
+        Ok(None) => Some(CloseFrame {
+            code: SESSION_REVOKED_CLOSE_CODE,
+            reason: "session revoked".into(),
+        }),
+        Err(err) => {
+            tracing::warn!("unable to revalidate websocket session: {err}");
+            None
+        }
+    }
+}
+
 #[derive(Serialize)]
 struct BusToClientMessage {
     event_type: SystemEvent,
-    payload: Vec<u8>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     decoded: Option<serde_json::Value>,
+
+    /// The raw bincode encoded event encoded as base64. Clients have no use for this when the event
+    /// could be decoded so it is only included for debugging when decoding failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<String>,
 }
 
-/// We use a different encoding of time when communicating outside of our applications as the byte
-/// serialized version is more efficient but not a standard others want to interact with.
-#[derive(Serialize)]
-struct ClientTick {
-    #[serde(with = "time::serde::rfc3339")]
-    time: OffsetDateTime,
+impl BusToClientMessage {
+    fn new(event_type: SystemEvent, payload: &[u8], decoded: Option<serde_json::Value>) -> Self {
+        let payload = match decoded {
+            Some(_) => None,
+            None => Some(B64.encode(payload)),
+        };
+
+        Self {
+            event_type,
+            decoded,
+            payload,
+        }
+    }
 }
 
-impl From<TickMessage> for ClientTick {
-    fn from(value: TickMessage) -> Self {
-        Self { time: value.time() }
+#[cfg(test)]
+mod tests {
+    use futures::channel::mpsc;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::database::custom_types::{LoginProvider, ProviderId};
+    use crate::database::models::{CreateOAuthProviderAccount, CreateSession, CreateUser};
+    use crate::event_bus::EventBus;
+    use crate::tests::prelude::*;
+
+    /// Runs the event forwarding for a session until it closes the socket, returning the close
+    /// code that was sent.
+    async fn close_code_for(database: Database, session_id: SessionId, user_id: UserId) -> u16 {
+        let (client_tx, mut client_rx) = mpsc::unbounded();
+        let event_bus = EventBus::default();
+
+        let forwarding = forward_bus_events(
+            client_tx,
+            event_bus.subscribe_filtered(CLIENT_BUS_EVENTS),
+            database,
+            session_id,
+            user_id,
+            false,
+            Duration::from_millis(10),
+        );
+        tokio::time::timeout(Duration::from_secs(5), forwarding)
+            .await
+            .expect("socket to be closed");
+
+        match client_rx.next().await {
+            Some(Message::Close(Some(frame))) => frame.code,
+            other => panic!("expected a close frame, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lagging_clients_notified_and_kept() {
+        let database = Database::new(migrated_test_database().await);
+        let session_id = SessionId::from(Uuid::new_v4());
+        let user_id = UserId::from(Uuid::new_v4());
+
+        let (client_tx, client_rx) = mpsc::unbounded();
+        let event_bus = EventBus::new(2);
+        let bus_rx = event_bus.subscribe_filtered(CLIENT_BUS_EVENTS);
+
+        // Overflow the buffer before the client reads anything
+        for _ in 0..5 {
+            let event = crate::event_bus::TestEvent { session_id };
+            event_bus.send_typed(&event).unwrap();
+        }
+        drop(event_bus);
+
+        let forwarding = forward_bus_events(
+            client_tx,
+            bus_rx,
+            database,
+            session_id,
+            user_id,
+            false,
+            Duration::from_secs(60),
+        );
+        tokio::time::timeout(Duration::from_secs(5), forwarding)
+            .await
+            .expect("forwarding to stop once the bus closes");
+
+        let messages: Vec<serde_json::Value> = client_rx
+            .map(|msg| match msg {
+                Message::Text(text) => serde_json::from_str(&text).unwrap(),
+                other => panic!("expected a text frame, got {other:?}"),
+            })
+            .collect()
+            .await;
+
+        // The client hears about what it missed then receives the events that were still held
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[0],
+            serde_json::json!({"type": "lag", "skipped": 3})
+        );
+        for message in &messages[1..] {
+            assert_eq!(message["event_type"], "test_event");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_undecodable_reauthentication_not_forwarded() {
+        let database = Database::new(migrated_test_database().await);
+        let session_id = SessionId::from(Uuid::new_v4());
+        let user_id = UserId::from(Uuid::new_v4());
+
+        let (client_tx, client_rx) = mpsc::unbounded();
+        let bus_rx =
+            futures::stream::iter([Ok((SystemEvent::ReauthenticationRequired, vec![0xff; 4]))])
+                .boxed();
+
+        let forwarding = forward_bus_events(
+            client_tx,
+            bus_rx,
+            database,
+            session_id,
+            user_id,
+            false,
+            Duration::from_secs(60),
+        );
+        tokio::time::timeout(Duration::from_secs(5), forwarding)
+            .await
+            .expect("forwarding to stop once the bus closes");
+
+        let messages: Vec<Message> = client_rx.collect().await;
+        assert!(messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sockets_closed_when_session_ends() {
+        let database = Database::new(migrated_test_database().await);
+        let mut conn = database.acquire().await.unwrap();
+
+        let user_id = CreateUser::new("socket@example.com", "Socket User")
+            .save(&mut conn)
+            .await
+            .unwrap();
+        let provider_account_id = CreateOAuthProviderAccount::new(
+            user_id,
+            LoginProvider::Google,
+            ProviderId::from("socket-user".to_string()),
+            "socket@example.com".to_string(),
+        )
+        .save(&database)
+        .await
+        .unwrap();
+
+        let mut expiring_session = CreateSession::new(user_id, provider_account_id);
+        expiring_session.limit_duration_to(Duration::ZERO);
+        let session_id = expiring_session.create(&mut conn).await.unwrap();
+
+        let close_code = close_code_for(database.clone(), session_id, user_id).await;
+        assert_eq!(close_code, SESSION_EXPIRED_CLOSE_CODE);
+
+        let missing_session_id = SessionId::from(Uuid::new_v4());
+        let close_code = close_code_for(database.clone(), missing_session_id, user_id).await;
+        assert_eq!(close_code, SESSION_REVOKED_CLOSE_CODE);
+
+        // Sessions that are still valid keep receiving events
+        let session_id = CreateSession::new(user_id, provider_account_id)
+            .create(&mut conn)
+            .await
+            .unwrap();
+        assert!(ended_session_close_frame(&database, session_id)
+            .await
+            .is_none());
+    }
+
+    #[test]
+    fn test_large_frames_roundtrip_compressed() {
+        use std::io::Read;
+
+        let large_message = serde_json::json!({"data": "event ".repeat(1_000)}).to_string();
+
+        let compressed_bytes = match client_frame(large_message.clone(), true) {
+            Message::Binary(bytes) => bytes,
+            other => panic!("expected a compressed binary frame, got {other:?}"),
+        };
+        assert!(compressed_bytes.len() < large_message.len());
+
+        let mut decoder = flate2::read::GzDecoder::new(compressed_bytes.as_slice());
+        let mut decompressed = String::new();
+        decoder.read_to_string(&mut decompressed).unwrap();
+        assert_eq!(decompressed, large_message);
+
+        // Compression wasn't negotiated
+        assert!(matches!(
+            client_frame(large_message, false),
+            Message::Text(_)
+        ));
+
+        // Too small to be worth compressing
+        assert!(matches!(
+            client_frame("{}".to_string(), true),
+            Message::Text(_)
+        ));
+    }
+
+    #[test]
+    fn test_requested_subprotocol_parsing() {
+        let mut headers = http::HeaderMap::new();
+        assert!(requested_subprotocols(&headers).is_empty());
+
+        headers.insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            http::HeaderValue::from_static("events.v2, events.v1"),
+        );
+        assert_eq!(
+            requested_subprotocols(&headers),
+            vec!["events.v2".to_string(), "events.v1".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_bus_message_omits_decoded_payload() {
+        let decoded = serde_json::json!({"time": "2024-04-12T00:00:00Z"});
+        let message = BusToClientMessage::new(SystemEvent::Tick, &[1, 2, 3], Some(decoded));
+
+        let encoded = serde_json::to_value(&message).unwrap();
+        assert!(encoded.get("payload").is_none());
+        assert!(encoded.get("decoded").is_some());
+    }
+
+    #[test]
+    fn test_bus_message_keeps_undecodable_payload() {
+        let message = BusToClientMessage::new(SystemEvent::Tick, &[1, 2, 3], None);
+
+        let encoded = serde_json::to_value(&message).unwrap();
+        assert_eq!(encoded["payload"], "AQID");
+        assert!(encoded.get("decoded").is_none());
+    }
+
+    fn empty_request() -> Request<axum::body::Body> {
+        Request::builder()
+            .uri("/")
+            .body(axum::body::Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_requests_beyond_concurrency_limit_shed() {
+        let entered = std::sync::Arc::new(tokio::sync::Notify::new());
+        let handler_entered = entered.clone();
+        let blocking = move || async move {
+            handler_entered.notify_one();
+            std::future::pending::<()>().await;
+        };
+
+        // The limit is shared between routes, the second request hits a different one
+        let router = with_load_limits(
+            Router::new()
+                .route("/", get(blocking))
+                .route("/other", get(|| async { "ok" })),
+            1,
+        );
+
+        let in_flight = tokio::spawn(tower::ServiceExt::oneshot(router.clone(), empty_request()));
+        entered.notified().await;
+
+        let request = Request::builder()
+            .uri("/other")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = tower::ServiceExt::oneshot(router.clone(), request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Once the blocking request goes away there is room again
+        in_flight.abort();
+        let _ = in_flight.await;
+
+        let request = Request::builder()
+            .uri("/other")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = tower::ServiceExt::oneshot(router, request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
     }
 }
@@ -0,0 +1,469 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{Request, State};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use axum_extra::extract::CookieJar;
+use http::header::RETRY_AFTER;
+use http::{HeaderMap, StatusCode};
+
+use crate::app::State as AppState;
+use crate::database::custom_types::SessionId;
+use crate::extractors::{signed_session_id, Requestor};
+use crate::utils::client_network;
+
+/// The most buckets tracked at once, the least recently used one is dropped to make room for
+/// another. Losing a bucket only hands its client a fresh allowance.
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+/// The sets of routes that are limited independently of each other. A client using up its
+/// allowance for one group can keep using the others.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum RouteGroup {
+    Api,
+    Auth,
+    Events,
+    Pages,
+}
+
+impl RouteGroup {
+    pub const ALL: [RouteGroup; 4] = [Self::Api, Self::Auth, Self::Events, Self::Pages];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Api => "api",
+            Self::Auth => "auth",
+            Self::Events => "events",
+            Self::Pages => "pages",
+        }
+    }
+
+    /// Logins hit external providers and event sockets are long lived, neither should need to be
+    /// opened nearly as often as pages are loaded.
+    fn default_limit(&self) -> RateLimit {
+        let per_minute = |requests| RateLimit::new(requests, Duration::from_secs(60));
+
+        match self {
+            Self::Api => per_minute(120),
+            Self::Auth => per_minute(20),
+            Self::Events => per_minute(30),
+            Self::Pages => per_minute(300),
+        }
+    }
+}
+
+impl Display for RouteGroup {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RouteGroup {
+    type Err = RateLimitsError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|group| group.as_str() == val)
+            .ok_or_else(|| RateLimitsError::UnknownGroup(val.to_string()))
+    }
+}
+
+/// A client may make up to `requests` requests in a burst, regaining the ability to make them
+/// evenly over `period`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    requests: u32,
+    period: Duration,
+}
+
+impl RateLimit {
+    pub fn new(requests: u32, period: Duration) -> Self {
+        Self { requests, period }
+    }
+
+    fn capacity(&self) -> f64 {
+        f64::from(self.requests)
+    }
+
+    /// The number of requests regained each second.
+    fn refill_rate(&self) -> f64 {
+        self.capacity() / self.period.as_secs_f64()
+    }
+}
+
+/// The rate limit for each route group, parsed from declarations of the form
+/// `name=requests/seconds` separated by commas (such as `auth=20/60,pages=300/60`). Groups that
+/// aren't declared keep their default limit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimits(BTreeMap<RouteGroup, RateLimit>);
+
+impl RateLimits {
+    pub fn get(&self, group: RouteGroup) -> RateLimit {
+        self.0
+            .get(&group)
+            .copied()
+            .unwrap_or_else(|| group.default_limit())
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        let limits = RouteGroup::ALL
+            .into_iter()
+            .map(|group| (group, group.default_limit()))
+            .collect();
+
+        Self(limits)
+    }
+}
+
+impl FromStr for RateLimits {
+    type Err = RateLimitsError;
+
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        let mut limits = Self::default();
+
+        for declaration in val.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let malformed = || RateLimitsError::MalformedDeclaration(declaration.to_string());
+
+            let (group, limit) = declaration.split_once('=').ok_or_else(malformed)?;
+            let group: RouteGroup = group.trim().parse()?;
+
+            let (requests, seconds) = limit.split_once('/').ok_or_else(malformed)?;
+            let requests: u32 = requests.trim().parse().map_err(|_| malformed())?;
+            let seconds: u64 = seconds.trim().parse().map_err(|_| malformed())?;
+
+            if requests == 0 || seconds == 0 {
+                return Err(RateLimitsError::EmptyLimit(group));
+            }
+
+            limits.0.insert(
+                group,
+                RateLimit::new(requests, Duration::from_secs(seconds)),
+            );
+        }
+
+        Ok(limits)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RateLimitsError {
+    #[error("rate limit for '{0}' needs to allow at least one request over at least one second")]
+    EmptyLimit(RouteGroup),
+
+    #[error("rate limit declaration '{0}' should look like 'name=requests/seconds'")]
+    MalformedDeclaration(String),
+
+    #[error("unknown route group '{0}', expected one of api, auth, events, or pages")]
+    UnknownGroup(String),
+}
+
+/// Limits how quickly each client can make requests to each group of routes using a token bucket
+/// per client. The limit is checked before anything about the request is looked up in the
+/// database, requests are tracked by a session when they carry a validly signed session cookie
+/// and by the network of the client otherwise. API clients are tracked by their network as an API
+/// key can only be checked against the database.
+#[derive(Clone)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: Arc<Mutex<RecentBuckets>>,
+}
+
+impl RateLimiter {
+    /// Takes a token from the client's bucket for the group, returning how long until one is
+    /// available when the bucket is empty.
+    fn check(&self, group: RouteGroup, key: RateLimitKey, now: Instant) -> Result<(), Duration> {
+        let limit = self.limits.get(group);
+        let mut buckets = self.buckets.lock().expect("lock to not be poisoned");
+
+        buckets
+            .get_or_insert((group, key), || TokenBucket::full(limit, now))
+            .take(limit, now)
+    }
+
+    pub fn new(limits: RateLimits) -> Self {
+        Self::with_capacity(limits, MAX_TRACKED_BUCKETS)
+    }
+
+    fn with_capacity(limits: RateLimits, capacity: usize) -> Self {
+        Self {
+            limits,
+            buckets: Arc::new(Mutex::new(RecentBuckets::new(capacity))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum RateLimitKey {
+    Network(IpAddr),
+    Session(SessionId),
+}
+
+type BucketKey = (RouteGroup, RateLimitKey);
+
+/// The token buckets of the most recently seen clients. Each use of a bucket moves it to the back
+/// of the line, once full the bucket at the front is dropped to make room for a new one.
+struct RecentBuckets {
+    capacity: usize,
+    buckets: HashMap<BucketKey, (TokenBucket, u64)>,
+    last_used: BTreeMap<u64, BucketKey>,
+    next_use: u64,
+}
+
+impl RecentBuckets {
+    fn get_or_insert(
+        &mut self,
+        key: BucketKey,
+        new_bucket: impl FnOnce() -> TokenBucket,
+    ) -> &mut TokenBucket {
+        let use_id = self.next_use;
+        self.next_use += 1;
+
+        match self.buckets.get_mut(&key) {
+            Some((_, last_use)) => {
+                self.last_used.remove(last_use);
+                *last_use = use_id;
+            }
+            None => {
+                if self.buckets.len() >= self.capacity {
+                    if let Some((_, oldest)) = self.last_used.pop_first() {
+                        self.buckets.remove(&oldest);
+                    }
+                }
+
+                self.buckets.insert(key.clone(), (new_bucket(), use_id));
+            }
+        }
+
+        self.last_used.insert(use_id, key.clone());
+        &mut self
+            .buckets
+            .get_mut(&key)
+            .expect("bucket was just inserted")
+            .0
+    }
+
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            buckets: HashMap::new(),
+            last_used: BTreeMap::new(),
+            next_use: 0,
+        }
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn available(&self, limit: RateLimit, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        (self.tokens + elapsed.as_secs_f64() * limit.refill_rate()).min(limit.capacity())
+    }
+
+    fn full(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: limit.capacity(),
+            updated_at: now,
+        }
+    }
+
+    fn take(&mut self, limit: RateLimit, now: Instant) -> Result<(), Duration> {
+        self.tokens = self.available(limit, now);
+        self.updated_at = now;
+
+        if self.tokens < 1.0 {
+            let missing = 1.0 - self.tokens;
+            return Err(Duration::from_secs_f64(missing / limit.refill_rate()));
+        }
+
+        self.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+/// Applies the rate limit of the group to every route in the router.
+pub(super) fn with_rate_limit(
+    router: Router<AppState>,
+    state: AppState,
+    group: RouteGroup,
+) -> Router<AppState> {
+    router.layer(middleware::from_fn_with_state(
+        state,
+        move |state: State<AppState>, requestor: Requestor, request: Request, next: Next| {
+            rate_limit(group, state, requestor, request, next)
+        },
+    ))
+}
+
+async fn rate_limit(
+    group: RouteGroup,
+    State(state): State<AppState>,
+    requestor: Requestor,
+    request: Request,
+    next: Next,
+) -> Response {
+    let key = match (
+        signed_session(&state, request.headers()),
+        requestor.client_ip(),
+    ) {
+        (Some(session_id), _) => RateLimitKey::Session(session_id),
+        (None, Some(client_ip)) => RateLimitKey::Network(client_network(client_ip)),
+        // Without any way to identify the client there is nothing to key the limit on
+        (None, None) => return next.run(request).await,
+    };
+
+    match state.rate_limiter().check(group, key, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => too_many_requests(group, retry_after),
+    }
+}
+
+/// The session the request's cookie was signed for. Whether the session is still valid isn't
+/// checked, a forged cookie fails the signature check and can't be used to pick a fresh bucket.
+fn signed_session(state: &AppState, headers: &HeaderMap) -> Option<SessionId> {
+    let cookie_name = state.session_cookie().name_for_request(headers);
+    let cookie_jar = CookieJar::from_headers(headers);
+    let session_cookie = cookie_jar.get(cookie_name)?;
+
+    signed_session_id(session_cookie.value(), &state.service_verifier()).ok()
+}
+
+fn too_many_requests(group: RouteGroup, retry_after: Duration) -> Response {
+    tracing::debug!(%group, ?retry_after, "client exceeded rate limit");
+
+    // Retry-After only has whole second precision, rounding down would invite a retry that is
+    // still going to be rejected
+    let retry_secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+    let msg = serde_json::json!({"msg": "too many requests"});
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(RETRY_AFTER, retry_secs.max(1).to_string())],
+        Json(msg),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_declared_rate_limits() {
+        let limits: RateLimits = "auth=5/10, events=1/1".parse().unwrap();
+        assert_eq!(
+            limits.get(RouteGroup::Auth),
+            RateLimit::new(5, Duration::from_secs(10))
+        );
+        assert_eq!(
+            limits.get(RouteGroup::Events),
+            RateLimit::new(1, Duration::from_secs(1))
+        );
+        assert_eq!(
+            limits.get(RouteGroup::Pages),
+            RouteGroup::Pages.default_limit()
+        );
+
+        assert!(matches!(
+            "admin=5/10".parse::<RateLimits>(),
+            Err(RateLimitsError::UnknownGroup(_))
+        ));
+        assert!(matches!(
+            "auth=5".parse::<RateLimits>(),
+            Err(RateLimitsError::MalformedDeclaration(_))
+        ));
+        assert!(matches!(
+            "auth=0/10".parse::<RateLimits>(),
+            Err(RateLimitsError::EmptyLimit(RouteGroup::Auth))
+        ));
+    }
+
+    #[test]
+    fn test_burst_limited_and_recovers() {
+        let limits: RateLimits = "auth=3/3".parse().unwrap();
+        let limiter = RateLimiter::new(limits);
+
+        let client = RateLimitKey::Network(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        let other_client = RateLimitKey::Network(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)));
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter
+                .check(RouteGroup::Auth, client.clone(), start)
+                .is_ok());
+        }
+
+        let retry_after = limiter
+            .check(RouteGroup::Auth, client.clone(), start)
+            .unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(1));
+
+        // Neither other clients nor other groups are affected
+        assert!(limiter.check(RouteGroup::Auth, other_client, start).is_ok());
+        assert!(limiter
+            .check(RouteGroup::Pages, client.clone(), start)
+            .is_ok());
+
+        // Tokens come back gradually, a full window restores the whole burst
+        let later = start + Duration::from_secs(1);
+        assert!(limiter
+            .check(RouteGroup::Auth, client.clone(), later)
+            .is_ok());
+        assert!(limiter
+            .check(RouteGroup::Auth, client.clone(), later)
+            .is_err());
+
+        let recovered = later + Duration::from_secs(3);
+        for _ in 0..3 {
+            assert!(limiter
+                .check(RouteGroup::Auth, client.clone(), recovered)
+                .is_ok());
+        }
+        assert!(limiter.check(RouteGroup::Auth, client, recovered).is_err());
+    }
+
+    #[test]
+    fn test_least_recently_used_bucket_dropped() {
+        let limits: RateLimits = "auth=1/60".parse().unwrap();
+        let limiter = RateLimiter::with_capacity(limits, 2);
+
+        let client =
+            |last_octet| RateLimitKey::Network(IpAddr::V4(Ipv4Addr::new(10, 0, 0, last_octet)));
+        let now = Instant::now();
+
+        assert!(limiter.check(RouteGroup::Auth, client(1), now).is_ok());
+        assert!(limiter.check(RouteGroup::Auth, client(2), now).is_ok());
+
+        // Using the first bucket again leaves the second as the oldest
+        assert!(limiter.check(RouteGroup::Auth, client(1), now).is_err());
+        assert!(limiter.check(RouteGroup::Auth, client(3), now).is_ok());
+
+        assert_eq!(limiter.buckets.lock().unwrap().buckets.len(), 2);
+        assert!(limiter.check(RouteGroup::Auth, client(1), now).is_err());
+        assert!(limiter.check(RouteGroup::Auth, client(2), now).is_ok());
+    }
+
+    #[test]
+    fn test_rejection_sets_retry_after() {
+        let response = too_many_requests(RouteGroup::Auth, Duration::from_millis(1_200));
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "2");
+
+        let response = too_many_requests(RouteGroup::Auth, Duration::ZERO);
+        assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "1");
+    }
+}
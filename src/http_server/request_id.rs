@@ -0,0 +1,85 @@
+use axum::extract::Request;
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::Router;
+use http::HeaderName;
+use tower::ServiceBuilder;
+use tower_http::request_id::MakeRequestUuid;
+use tower_http::ServiceBuilderExt;
+
+pub(crate) static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Makes sure every request carries an ID and that the same ID is returned on the response. The
+/// ID is used to correlate logs with a request, this has to wrap the tracing layer so the ID is
+/// already present when the request span is created.
+pub(super) fn with_request_ids(router: Router) -> Router {
+    router.layer(
+        ServiceBuilder::new()
+            .layer(middleware::from_fn(discard_untrusted))
+            .set_x_request_id(MakeRequestUuid)
+            .propagate_x_request_id(),
+    )
+}
+
+/// Anything in front of us can set this header to whatever it likes. IDs we didn't generate are
+/// kept only when they look like one of ours, anything else is removed so a fresh ID is assigned
+/// in its place rather than letting arbitrary values into our logs.
+async fn discard_untrusted(mut request: Request, next: Next) -> Response {
+    let is_uuid = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .map(|id| uuid::Uuid::try_parse(id).is_ok());
+
+    if is_uuid == Some(false) {
+        tracing::debug!("replacing invalid request ID provided by client");
+        request.headers_mut().remove(&X_REQUEST_ID);
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::routing::get;
+    use http::StatusCode;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn response_request_id(request_id: Option<&str>) -> String {
+        let router = with_request_ids(Router::new().route("/", get(|| async { "ok" })));
+
+        let mut request = Request::builder().uri("/");
+        if let Some(id) = request_id {
+            request = request.header(&X_REQUEST_ID, id);
+        }
+
+        let response = router
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        response
+            .headers()
+            .get(&X_REQUEST_ID)
+            .expect("response to carry a request ID")
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_responses_carry_request_id() {
+        let generated = response_request_id(None).await;
+        assert!(uuid::Uuid::try_parse(&generated).is_ok());
+
+        let provided = uuid::Uuid::new_v4().to_string();
+        assert_eq!(response_request_id(Some(&provided)).await, provided);
+
+        let replaced = response_request_id(Some("<script>alert(1)</script>")).await;
+        assert!(uuid::Uuid::try_parse(&replaced).is_ok());
+    }
+}
@@ -0,0 +1,235 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::async_trait;
+use axum::error_handling::HandleErrorLayer;
+use axum::extract::{FromRequestParts, Request};
+use axum::Router;
+use http::request::Parts;
+use time::OffsetDateTime;
+use tower::ServiceBuilder;
+
+use crate::http_server::error_handlers;
+
+/// The number of seconds most requests can take before they are dropped with an error.
+const DEFAULT_STANDARD_SECS: u64 = 5;
+
+/// Uploads and embedding are expected to take much longer than anything else, they get this many
+/// seconds instead.
+const DEFAULT_LONG_RUNNING_SECS: u64 = 300;
+
+/// When the request being handled will be cut off by its timeout, requests to routes that aren't
+/// covered by a timeout have no deadline. Nothing is left waiting on work done on behalf of the
+/// request, such as a job the handler enqueues and waits on, once the deadline has passed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RequestDeadline(Option<OffsetDateTime>);
+
+impl RequestDeadline {
+    pub fn at(&self) -> Option<OffsetDateTime> {
+        self.0
+    }
+
+    pub fn new(at: OffsetDateTime) -> Self {
+        Self(Some(at))
+    }
+
+    /// Records the deadline of a request that is about to be given `timeout` to finish. A route
+    /// covered by more than one timeout keeps whichever deadline comes first.
+    fn start(request: &mut Request, timeout: Duration) {
+        let deadline = OffsetDateTime::now_utc() + timeout;
+
+        let extensions = request.extensions_mut();
+        let deadline = match extensions.get::<RequestDeadline>().and_then(|d| d.0) {
+            Some(existing) if existing < deadline => existing,
+            _ => deadline,
+        };
+
+        extensions.insert(RequestDeadline(Some(deadline)));
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequestDeadline
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<RequestDeadline>()
+            .copied()
+            .unwrap_or_default())
+    }
+}
+
+/// How long requests may take before they are cut off with a 408. Routes that legitimately need
+/// more time than the rest, such as uploads and embedding, use the long running timeout instead of
+/// the standard one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestTimeouts {
+    standard: Duration,
+    long_running: Duration,
+}
+
+impl RequestTimeouts {
+    pub fn long_running(&self) -> Duration {
+        self.long_running
+    }
+
+    pub fn new(standard: Duration, long_running: Duration) -> Self {
+        Self {
+            standard,
+            long_running,
+        }
+    }
+
+    pub fn standard(&self) -> Duration {
+        self.standard
+    }
+}
+
+impl Default for RequestTimeouts {
+    fn default() -> Self {
+        Self {
+            standard: Duration::from_secs(DEFAULT_STANDARD_SECS),
+            long_running: Duration::from_secs(DEFAULT_LONG_RUNNING_SECS),
+        }
+    }
+}
+
+/// Cuts off requests to any of the routes added to the router so far that take longer than
+/// `timeout`. Layers wrap each route individually so routes added afterwards aren't covered,
+/// which is how the long running routes avoid the standard timeout. Handlers can find out when
+/// they'll be cut off with the [`RequestDeadline`] extractor.
+pub(crate) fn with_request_timeout<S>(router: Router<S>, timeout: Duration) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(error_handlers::server_error_handler))
+            .timeout(timeout)
+            .layer(axum::middleware::map_request(
+                move |mut request: Request| async move {
+                    RequestDeadline::start(&mut request, timeout);
+                    request
+                },
+            )),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::extract::State;
+    use axum::routing::{get, post};
+    use http::{Request, StatusCode};
+    use sqlx::SqlitePool;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    use crate::background_jobs::impls::TestJob;
+    use crate::background_jobs::BasicTaskStore;
+    use crate::database::models::BackgroundJob;
+    use crate::tests::prelude::*;
+
+    /// Enqueues a job the way a handler that waits on its result would.
+    async fn enqueue_waited_on_job(
+        State(pool): State<SqlitePool>,
+        deadline: RequestDeadline,
+    ) -> StatusCode {
+        BasicTaskStore::enqueue_for_request(&pool, TestJob::<()>::new(1), deadline)
+            .await
+            .expect("enqueue");
+
+        StatusCode::ACCEPTED
+    }
+
+    async fn remaining(deadline: RequestDeadline) -> String {
+        match deadline.at() {
+            Some(at) => (at - OffsetDateTime::now_utc()).whole_seconds().to_string(),
+            None => "none".to_string(),
+        }
+    }
+
+    async fn slow() -> &'static str {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        "done"
+    }
+
+    async fn status(router: Router, uri: &str) -> StatusCode {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        router.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_enqueued_jobs_carry_the_request_deadline() {
+        let pool = migrated_test_database().await;
+        let router = Router::new()
+            .route("/", post(enqueue_waited_on_job))
+            .with_state(pool.clone());
+        let router = with_request_timeout(router, Duration::from_secs(30));
+
+        let before = OffsetDateTime::now_utc();
+        let request = Request::post("/").body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let job: BackgroundJob = sqlx::query_as("SELECT * FROM background_jobs;")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+        let deadline = job.deadline().expect("job to carry the request's deadline");
+        assert!(deadline >= before + Duration::from_secs(30));
+        assert!(deadline <= OffsetDateTime::now_utc() + Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn test_handlers_see_the_earliest_deadline() {
+        let router = Router::new().route("/", get(remaining));
+        let router = with_request_timeout(router, Duration::from_secs(30));
+        let router = with_request_timeout(router, Duration::from_secs(300));
+
+        let request = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let remaining: i64 = std::str::from_utf8(&body).unwrap().parse().unwrap();
+        assert!((25..=30).contains(&remaining));
+    }
+
+    #[tokio::test]
+    async fn test_slow_requests_time_out() {
+        let router = with_request_timeout(
+            Router::new().route("/", get(slow)),
+            Duration::from_millis(10),
+        );
+
+        assert_eq!(status(router, "/").await, StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_routes_added_later_keep_their_own_timeout() {
+        let long_running = with_request_timeout(
+            Router::new().route("/upload", get(slow)),
+            Duration::from_secs(5),
+        );
+        let router = with_request_timeout(
+            Router::new().route("/", get(slow)),
+            Duration::from_millis(10),
+        )
+        .merge(long_running);
+
+        assert_eq!(
+            status(router.clone(), "/").await,
+            StatusCode::REQUEST_TIMEOUT
+        );
+        assert_eq!(status(router, "/upload").await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,109 @@
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use futures::Future;
+use http::request::Parts;
+use http::{HeaderName, HeaderValue};
+
+static SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+/// Any paths under these prefixes never have timing information exposed. Timing differences in
+/// authentication flows can leak information about accounts and credentials.
+const EXCLUDED_PATH_PREFIXES: &[&str] = &["/auth"];
+
+/// Collects the duration of individual phases of handling a request so they can be reported back
+/// to the client in the `Server-Timing` header. When the header is disabled any recorded timings
+/// are quietly discarded.
+#[derive(Clone, Default)]
+pub struct ServerTimings(Arc<Mutex<Vec<(&'static str, Duration)>>>);
+
+impl ServerTimings {
+    fn header_value(&self, total: Duration) -> Option<HeaderValue> {
+        let timings = self.0.lock().ok()?;
+
+        let mut value = String::new();
+        for (name, duration) in timings.iter().chain([("total", total)].iter()) {
+            if !value.is_empty() {
+                value.push_str(", ");
+            }
+
+            let _ = write!(value, "{name};dur={:.1}", duration.as_secs_f64() * 1_000.0);
+        }
+
+        HeaderValue::from_str(&value).ok()
+    }
+
+    /// Runs the provided future recording how long it took to complete under the provided name.
+    pub async fn measure<F: Future>(&self, name: &'static str, future: F) -> F::Output {
+        let start = Instant::now();
+        let output = future.await;
+        self.record(name, start.elapsed());
+        output
+    }
+
+    pub fn record(&self, name: &'static str, duration: Duration) {
+        if let Ok(mut timings) = self.0.lock() {
+            timings.push((name, duration));
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ServerTimings
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<ServerTimings>()
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+pub async fn middleware(mut request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    if EXCLUDED_PATH_PREFIXES
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+    {
+        return next.run(request).await;
+    }
+
+    let timings = ServerTimings::default();
+    request.extensions_mut().insert(timings.clone());
+
+    let start = Instant::now();
+    let mut response = next.run(request).await;
+
+    if let Some(header_value) = timings.header_value(start.elapsed()) {
+        response
+            .headers_mut()
+            .insert(SERVER_TIMING.clone(), header_value);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_formatting() {
+        let timings = ServerTimings::default();
+        timings.record("db", Duration::from_micros(12_340));
+
+        let header = timings.header_value(Duration::from_millis(34)).unwrap();
+        assert_eq!(header, "db;dur=12.3, total;dur=34.0");
+    }
+}
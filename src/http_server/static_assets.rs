@@ -0,0 +1,131 @@
+use std::path::Path;
+
+use axum::handler::HandlerWithoutStateExt;
+use axum::Router;
+use http::{header, HeaderValue};
+use tower_http::services::ServeDir;
+use tower_http::set_header::SetResponseHeaderLayer;
+
+use crate::http_server::error_handlers;
+
+/// Serves the built frontend assets from the provided directory. Brotli and gzip compressed
+/// variants of a file (`app.js.br` and `app.js.gz`) are served in place of the original to
+/// clients that accept them, falling back to the uncompressed file when there is no usable
+/// variant. Assets that only exist in an encoding the client doesn't accept are reported as
+/// missing.
+///
+/// The response depends on the request's `Accept-Encoding` header so it is always included in
+/// `Vary`, without it a cache in front of us could hand a brotli encoded asset to a client that
+/// can't decode it.
+pub(crate) fn service(root: impl AsRef<Path>) -> Router {
+    // todo: need to turn not_found_handler into its own service...
+    let serve_dir = ServeDir::new(root)
+        .precompressed_br()
+        .precompressed_gzip()
+        .not_found_service(error_handlers::not_found_handler.into_service());
+
+    Router::new()
+        .fallback_service(serve_dir)
+        .layer(SetResponseHeaderLayer::appending(
+            header::VARY,
+            HeaderValue::from_static("accept-encoding"),
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use axum::body::Body;
+    use http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// A directory of assets covering each combination of precompressed variants, removed when
+    /// dropped. Each file contains the name of its encoding so the served variant is obvious.
+    struct AssetDir(PathBuf);
+
+    impl AssetDir {
+        fn new() -> Self {
+            let root = std::env::temp_dir().join(format!("assets-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&root).unwrap();
+
+            let files = [
+                ("all.js", "identity"),
+                ("all.js.br", "br"),
+                ("all.js.gz", "gzip"),
+                ("plain_only.js", "identity"),
+                ("gzip_and_plain.js", "identity"),
+                ("gzip_and_plain.js.gz", "gzip"),
+                ("br_only.js.br", "br"),
+            ];
+
+            for (name, contents) in files {
+                std::fs::write(root.join(name), contents).unwrap();
+            }
+
+            Self(root)
+        }
+    }
+
+    impl Drop for AssetDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_precompressed_variant_matrix() {
+        let assets = AssetDir::new();
+
+        // (asset, accept-encoding, expected encoding served, or None when it should be missing)
+        let cases = [
+            ("all.js", "br", Some("br")),
+            ("all.js", "gzip", Some("gzip")),
+            ("all.js", "identity", Some("identity")),
+            ("all.js", "br;q=0.5, gzip", Some("gzip")),
+            ("plain_only.js", "br", Some("identity")),
+            ("plain_only.js", "gzip", Some("identity")),
+            ("plain_only.js", "identity", Some("identity")),
+            ("gzip_and_plain.js", "br", Some("identity")),
+            ("gzip_and_plain.js", "gzip", Some("gzip")),
+            ("gzip_and_plain.js", "br, gzip", Some("gzip")),
+            ("br_only.js", "br", Some("br")),
+            ("br_only.js", "gzip", None),
+            ("br_only.js", "identity", None),
+        ];
+
+        for (asset, accept_encoding, expected) in cases {
+            let request = Request::get(format!("/{asset}"))
+                .header(header::ACCEPT_ENCODING, accept_encoding)
+                .body(Body::empty())
+                .unwrap();
+            let response = service(&assets.0).oneshot(request).await.unwrap();
+
+            let case = format!("{asset} with accept-encoding '{accept_encoding}'");
+
+            let Some(expected) = expected else {
+                assert_eq!(response.status(), StatusCode::NOT_FOUND, "{case}");
+                continue;
+            };
+
+            assert_eq!(response.status(), StatusCode::OK, "{case}");
+
+            let vary = response.headers().get(header::VARY).map(|v| v.as_bytes());
+            assert_eq!(vary, Some(&b"accept-encoding"[..]), "{case}");
+
+            let content_encoding = response
+                .headers()
+                .get(header::CONTENT_ENCODING)
+                .map(|v| v.to_str().unwrap().to_string());
+            let expected_header = (expected != "identity").then(|| expected.to_string());
+            assert_eq!(content_encoding, expected_header, "{case}");
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body, expected.as_bytes(), "{case}");
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::StatusCode;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::database::custom_types::UserId;
+use crate::extractors::{ApiKeyIdentity, Requestor, SessionIdentity};
+
+/// Limits the number of requests any individual user can have in flight at a time to prevent a
+/// single user from monopolizing the service. Authenticated requests are tracked by their user,
+/// whether they come from a session or one of the user's API keys, anything else falls back to the
+/// address of the client.
+#[derive(Clone)]
+pub struct UserConcurrencyLimiter {
+    limit: usize,
+    in_flight: Arc<Mutex<HashMap<LimitKey, Arc<Semaphore>>>>,
+}
+
+impl UserConcurrencyLimiter {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn try_acquire(&self, key: LimitKey) -> Option<UserConcurrencyPermit> {
+        let mut in_flight = self.in_flight.lock().expect("lock to not be poisoned");
+
+        let semaphore = in_flight
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.limit)))
+            .clone();
+
+        let permit = semaphore.try_acquire_owned().ok()?;
+
+        Some(UserConcurrencyPermit {
+            key,
+            limiter: self.clone(),
+            permit: Some(permit),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum LimitKey {
+    Anonymous(IpAddr),
+    User(UserId),
+}
+
+/// Holds one of the in-flight slots for a user until dropped. When the last outstanding permit
+/// for a user is released their tracking entry is removed so idle users don't accumulate.
+struct UserConcurrencyPermit {
+    key: LimitKey,
+    limiter: UserConcurrencyLimiter,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for UserConcurrencyPermit {
+    fn drop(&mut self) {
+        let mut in_flight = match self.limiter.in_flight.lock() {
+            Ok(in_flight) => in_flight,
+            Err(_) => return,
+        };
+
+        drop(self.permit.take());
+
+        let idle = in_flight
+            .get(&self.key)
+            .map(|sem| sem.available_permits() == self.limiter.limit)
+            .unwrap_or(false);
+
+        if idle {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+pub async fn middleware(
+    State(limiter): State<UserConcurrencyLimiter>,
+    session: Option<SessionIdentity>,
+    api_key: Option<ApiKeyIdentity>,
+    requestor: Requestor,
+    request: Request,
+    next: Next,
+) -> Response {
+    let key = match (session, api_key, requestor.client_ip()) {
+        (Some(session), _, _) => LimitKey::User(session.user_id()),
+        (None, Some(api_key), _) => LimitKey::User(UserId::from(*api_key.user_id())),
+        (None, None, Some(client_ip)) => LimitKey::Anonymous(client_ip),
+        // Without any way to identify the client there is nothing to key the limit on
+        (None, None, None) => return next.run(request).await,
+    };
+
+    let _permit = match limiter.try_acquire(key) {
+        Some(permit) => permit,
+        None => {
+            let msg = serde_json::json!({"msg": "too many concurrent requests"});
+            return (StatusCode::TOO_MANY_REQUESTS, Json(msg)).into_response();
+        }
+    };
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_limit_enforced_per_key() {
+        let limiter = UserConcurrencyLimiter::new(2);
+
+        let first_key = LimitKey::Anonymous(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        let second_key = LimitKey::Anonymous(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)));
+
+        let first = limiter.try_acquire(first_key.clone());
+        let second = limiter.try_acquire(first_key.clone());
+        assert!(first.is_some());
+        assert!(second.is_some());
+        assert!(limiter.try_acquire(first_key.clone()).is_none());
+
+        // Other clients aren't affected by the first one reaching its limit
+        assert!(limiter.try_acquire(second_key).is_some());
+
+        drop(first);
+        assert!(limiter.try_acquire(first_key).is_some());
+    }
+
+    #[test]
+    fn test_idle_keys_are_removed() {
+        let limiter = UserConcurrencyLimiter::new(2);
+        let key = LimitKey::Anonymous(IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+        let first = limiter.try_acquire(key.clone());
+        let second = limiter.try_acquire(key.clone());
+
+        drop(first);
+        assert!(limiter.in_flight.lock().unwrap().contains_key(&key));
+
+        drop(second);
+        assert!(limiter.in_flight.lock().unwrap().is_empty());
+    }
+}
@@ -1,16 +1,18 @@
-use std::net::SocketAddr;
 use std::time::Duration;
 
 use tokio::signal::unix::{signal, SignalKind};
-use tokio::sync::watch;
 use tokio::task::JoinHandle;
 
+mod admin;
+mod api;
 mod auth;
 mod database;
 mod event_bus;
 mod extractors;
 mod health_check;
 mod pages;
+mod shutdown_reason;
+mod shutdown_summary;
 
 pub mod app;
 pub mod background_jobs;
@@ -18,39 +20,97 @@ pub mod http_server;
 pub mod llm;
 pub mod utils;
 
-const REQUEST_GRACE_PERIOD: Duration = Duration::from_secs(10);
+pub use database::custom_types::{Did, DidError};
+pub use shutdown_reason::{ShutdownReason, ShutdownSignal, ShutdownTimeouts};
+pub use shutdown_summary::{ShutdownSummary, ShutdownTracker, SubsystemOutcome};
 
+/// How often the tick event is sent out over the event bus.
+const TICK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often old background runs are pruned down to the configured retention.
+const PRUNE_RUNS_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// How often provider accounts are checked for access tokens that need to be refreshed.
+const TOKEN_REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Starts every worker pool along with the event subscriber, tracking each of them so their
+/// shutdown shows up in the summary.
 pub async fn background_workers(
+    config: &app::Config,
     state: app::State,
-    shutdown_rx: watch::Receiver<()>,
-) -> Vec<JoinHandle<()>> {
-    let basic_store = state.basic_task_store();
-    let basic_context = basic_store.context();
-    let mut basic_shutdown_rx = shutdown_rx.clone();
-    let basic_handle = background_jobs::WorkerPool::new(basic_store, move || basic_context.clone())
-        .add_workers(background_jobs::QueueConfig::new("basic"))
-        .start(async move {
-            let _ = basic_shutdown_rx.changed().await;
-        })
-        .await
-        .expect("basic background workers to start up");
-
-    let event_store = state.event_task_store();
-    let event_context = event_store.context();
-    let mut event_shutdown_rx = shutdown_rx;
-    let event_handle = background_jobs::WorkerPool::new(event_store, move || event_context.clone())
-        .add_workers(background_jobs::QueueConfig::new("evented"))
-        .register_job_type::<background_jobs::impls::TickTask>()
-        .start(async move {
-            let _ = event_shutdown_rx.changed().await;
-        })
-        .await
-        .expect("evented background workers to start up");
-
-    // todo: need to figure out a way to ensure all reoccuring jobs are actually scheduled
-    // todo: need to implement recurring tasks and set the tick task to run every minute or so
-
-    vec![basic_handle, event_handle]
+    shutdown_rx: ShutdownSignal,
+    subsystems: &mut ShutdownTracker,
+) {
+    let queue_configs = state.queue_configs();
+    let shutdown_timeouts = config.shutdown_timeouts();
+    let shutdown_timeout = shutdown_timeouts.worker_shutdown_timeout();
+
+    // Each queue is serviced by a single pool running every job type that uses it, so the number
+    // of workers declared for a queue is the number that actually run. Jobs derive their own
+    // contexts from the application state.
+    let default_state = state.clone();
+    let default_shutdown_rx = shutdown_rx.clone();
+    let default_handle =
+        background_jobs::WorkerPool::new(state.basic_task_store(), move || default_state.clone())
+            .register_job_type::<background_jobs::impls::RecordRegistrationJob>()
+            .register_recurring_job_type::<background_jobs::impls::PruneRunsJob>(
+                background_jobs::RecurringSchedule::every(PRUNE_RUNS_INTERVAL),
+            )
+            .register_recurring_job_type::<background_jobs::impls::RefreshOAuthTokensJob>(
+                background_jobs::RecurringSchedule::every(TOKEN_REFRESH_INTERVAL),
+            )
+            .add_declared_workers(&queue_configs)
+            .with_shutdown_timeout(shutdown_timeout)
+            .start(pool_shutdown(default_shutdown_rx, shutdown_timeouts))
+            .await
+            .expect("default queue workers to start up");
+
+    let embedding_state = state.clone();
+    let embedding_shutdown_rx = shutdown_rx.clone();
+    let embedding_handle =
+        background_jobs::WorkerPool::new(state.basic_task_store(), move || embedding_state.clone())
+            .register_job_type::<background_jobs::impls::EmbedJob>()
+            .with_execution_timeout::<background_jobs::impls::EmbedJob>(config.embed_job_timeout())
+            .add_declared_workers(&queue_configs)
+            .with_shutdown_timeout(shutdown_timeout)
+            .start(pool_shutdown(embedding_shutdown_rx, shutdown_timeouts))
+            .await
+            .expect("embedding queue workers to start up");
+
+    // Jobs that follow from something happening elsewhere in the service are routed from their
+    // events rather than being enqueued by whatever sent the event
+    let subscriber_handle = background_jobs::EventSubscriber::declared(state.database())
+        .start(&state.event_bus(), shutdown_rx.clone());
+
+    let evented_state = state.clone();
+    let evented_shutdown_rx = shutdown_rx;
+    let evented_handle =
+        background_jobs::WorkerPool::new(state.event_task_store(), move || evented_state.clone())
+            .register_recurring_job_type::<background_jobs::impls::TickTask>(
+                background_jobs::RecurringSchedule::every(TICK_INTERVAL),
+            )
+            .add_declared_workers(&queue_configs)
+            .with_shutdown_timeout(shutdown_timeout)
+            .start(pool_shutdown(evented_shutdown_rx, shutdown_timeouts))
+            .await
+            .expect("evented queue workers to start up");
+
+    subsystems.track("default-workers", default_handle);
+    subsystems.track("embedding-workers", embedding_handle);
+    subsystems.track("evented-workers", evented_handle);
+    subsystems.track("event-subscriber", subscriber_handle);
+}
+
+/// Resolves once shutdown begins with the time the service will wait on a worker pool to stop,
+/// keeping the pool from outliving the drain timeout `serve` waits for.
+async fn pool_shutdown(
+    mut shutdown_rx: ShutdownSignal,
+    timeouts: ShutdownTimeouts,
+) -> Option<Duration> {
+    let _ = shutdown_rx.changed().await;
+    let reason = *shutdown_rx.borrow();
+
+    Some(timeouts.drain_timeout(reason.unwrap_or(ShutdownReason::Terminate)))
 }
 
 /// Follow k8s signal handling rules for these different signals. The order of shutdown events are:
@@ -81,47 +141,86 @@ pub async fn background_workers(
 ///
 /// This also handles SIGINT which K8s doesn't issue, those will be coming from users running the
 /// server locally and should shut the server down immediately.
-pub fn graceful_shutdown_blocker() -> (JoinHandle<()>, watch::Receiver<()>) {
+///
+/// Readiness starts failing as soon as either signal is received, before the grace period.
+///
+/// The returned handle resolves with the reason once shutdown has begun, which also determines how
+/// long remaining work should be given to finish up.
+pub fn graceful_shutdown_blocker(
+    readiness: health_check::ServiceReadiness,
+    timeouts: ShutdownTimeouts,
+) -> (JoinHandle<ShutdownReason>, ShutdownSignal) {
     let mut sigint = signal(SignalKind::interrupt()).unwrap();
     let mut sigterm = signal(SignalKind::terminate()).unwrap();
 
-    let (tx, rx) = tokio::sync::watch::channel(());
+    // Longer timeouts are allowed for deployments that have raised the kill deadline to match,
+    // anywhere else the process would be killed part way through draining
+    if !timeouts.fits_termination_budget() {
+        tracing::warn!(
+            max_termination = ?timeouts.max_termination(),
+            "the shutdown grace period and timeout exceed the default 30 second termination \
+             window, make sure the orchestrator waits at least this long before killing us"
+        );
+    }
+
+    let (tx, rx) = tokio::sync::watch::channel(None);
 
     let handle = tokio::spawn(async move {
-        tokio::select! {
+        let reason = tokio::select! {
             _ = sigint.recv() => {
-                tracing::debug!("gracefully exiting immediately on SIGINT");
+                tracing::debug!("exiting immediately on SIGINT");
+                ShutdownReason::Interrupt
             }
             _ = sigterm.recv() => {
                 // todo: this is the desired k8s behavior... but for our current usage, we don't have
                 // layers of proxies that require information progagation. This just increases the errors
                 // visible during deploys
-                tokio::time::sleep(REQUEST_GRACE_PERIOD).await;
                 tracing::debug!("initiaing graceful shutdown with delay on SIGTERM");
+                ShutdownReason::Terminate
             }
-        }
+        };
 
         // Time to start signaling any services that care about gracefully shutting down that the
         // time is at hand.
-        let _ = tx.send(());
+        shutdown_reason::begin_shutdown(reason, &timeouts, &readiness, &tx).await;
 
-        // todo: fail the readiness checks (should probably be handled by something with a copy of
-        // the receiver...
+        reason
     });
 
     (handle, rx)
 }
 
+/// Watches the database for failures while the service is running so readiness reflects whether
+/// it is actually usable.
+pub fn database_monitor(state: &app::State, shutdown_rx: ShutdownSignal) -> JoinHandle<()> {
+    state
+        .database_health()
+        .monitor(state.database(), shutdown_rx)
+}
+
+/// Periodically checks that the embedding model can still be retrieved from HuggingFace, reporting
+/// the result as one of the service's dependencies.
+pub fn model_source_monitor(state: &app::State, shutdown_rx: ShutdownSignal) -> JoinHandle<()> {
+    state.model_source_health().monitor(shutdown_rx)
+}
+
 pub async fn http_server(
-    listen_addr: SocketAddr,
-    log_level: tracing::Level,
+    config: &app::Config,
     state: app::State,
-    shutdown_rx: watch::Receiver<()>,
-) -> JoinHandle<()> {
+    shutdown_rx: ShutdownSignal,
+) -> JoinHandle<SubsystemOutcome> {
+    let config = config.clone();
+
     tokio::spawn(async move {
-        match http_server::run(listen_addr, log_level, state, shutdown_rx).await {
-            Ok(_) => tracing::info!("shutting down normally"),
-            Err(err) => tracing::error!("http server exited with an error: {err}"),
+        match http_server::run(config, state, shutdown_rx).await {
+            Ok(_) => {
+                tracing::info!("shutting down normally");
+                SubsystemOutcome::Clean
+            }
+            Err(err) => {
+                tracing::error!("http server exited with an error: {err}");
+                SubsystemOutcome::Failed(err.to_string())
+            }
         }
     })
 }
@@ -148,7 +247,7 @@ pub fn report_version() {
 
     tracing::info!(
         build_profile = ?version.build_profile,
-        features = ?version.features,
+        features = %version.features,
         version = ?version.version,
         "service starting up"
     );
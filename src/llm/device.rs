@@ -0,0 +1,153 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use candle_core::{Device, DeviceLocation};
+use serde::Serialize;
+
+/// The compute device models should be loaded on. This is parsed from configuration values such as
+/// `cpu`, `cuda:0`, or `metal`, when no ordinal is provided for a GPU the first one is used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(into = "String")]
+pub enum ModelDevice {
+    #[default]
+    Cpu,
+    Cuda(usize),
+    Metal(usize),
+}
+
+impl ModelDevice {
+    fn is_available(&self) -> bool {
+        match self {
+            ModelDevice::Cpu => true,
+            ModelDevice::Cuda(_) => candle_core::utils::cuda_is_available(),
+            ModelDevice::Metal(_) => candle_core::utils::metal_is_available(),
+        }
+    }
+
+    /// Initializes the requested device. When the device isn't available this will either fail
+    /// or, if `allow_fallback` is set, log a warning and fall back to running on the CPU.
+    pub fn select(self, allow_fallback: bool) -> Result<Device, ModelDeviceError> {
+        let device = if self.is_available() {
+            match self {
+                ModelDevice::Cpu => Ok(Device::Cpu),
+                ModelDevice::Cuda(ordinal) => Device::new_cuda(ordinal),
+                ModelDevice::Metal(ordinal) => Device::new_metal(ordinal),
+            }
+            .map_err(|err| ModelDeviceError::InitializationFailed(self, err))
+        } else {
+            Err(ModelDeviceError::Unavailable(self))
+        };
+
+        match device {
+            Ok(device) => Ok(device),
+            Err(err) if allow_fallback => {
+                tracing::warn!("{err}, falling back to running models on the CPU");
+                Ok(Device::Cpu)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Display for ModelDevice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelDevice::Cpu => write!(f, "cpu"),
+            ModelDevice::Cuda(ordinal) => write!(f, "cuda:{ordinal}"),
+            ModelDevice::Metal(ordinal) => write!(f, "metal:{ordinal}"),
+        }
+    }
+}
+
+impl From<&Device> for ModelDevice {
+    fn from(device: &Device) -> Self {
+        match device.location() {
+            DeviceLocation::Cpu => ModelDevice::Cpu,
+            DeviceLocation::Cuda { gpu_id } => ModelDevice::Cuda(gpu_id),
+            DeviceLocation::Metal { gpu_id } => ModelDevice::Metal(gpu_id),
+        }
+    }
+}
+
+impl From<ModelDevice> for String {
+    fn from(device: ModelDevice) -> Self {
+        device.to_string()
+    }
+}
+
+impl FromStr for ModelDevice {
+    type Err = ModelDeviceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lowered = s.trim().to_lowercase();
+
+        let (kind, ordinal) = match lowered.split_once(':') {
+            Some((kind, ordinal)) => {
+                let ordinal = ordinal
+                    .parse()
+                    .map_err(|_| ModelDeviceError::InvalidDevice(s.to_string()))?;
+                (kind, ordinal)
+            }
+            None => (lowered.as_str(), 0),
+        };
+
+        match kind {
+            "cpu" if ordinal == 0 => Ok(ModelDevice::Cpu),
+            "cuda" => Ok(ModelDevice::Cuda(ordinal)),
+            "metal" => Ok(ModelDevice::Metal(ordinal)),
+            _ => Err(ModelDeviceError::InvalidDevice(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ModelDeviceError {
+    #[error("failed to initialize model device {0}: {1}")]
+    InitializationFailed(ModelDevice, candle_core::Error),
+
+    #[error("unknown model device '{0}', expected one of cpu, cuda[:N], or metal[:N]")]
+    InvalidDevice(String),
+
+    #[error("model device {0} is not available in this build or on this host")]
+    Unavailable(ModelDevice),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing() {
+        assert_eq!("cpu".parse::<ModelDevice>().unwrap(), ModelDevice::Cpu);
+        assert_eq!("CUDA".parse::<ModelDevice>().unwrap(), ModelDevice::Cuda(0));
+        assert_eq!(
+            "cuda:1".parse::<ModelDevice>().unwrap(),
+            ModelDevice::Cuda(1)
+        );
+        assert_eq!(
+            "metal".parse::<ModelDevice>().unwrap(),
+            ModelDevice::Metal(0)
+        );
+
+        assert!("cpu:1".parse::<ModelDevice>().is_err());
+        assert!("cuda:first".parse::<ModelDevice>().is_err());
+        assert!("tpu".parse::<ModelDevice>().is_err());
+    }
+
+    #[test]
+    fn test_display_roundtripping() {
+        for device in [
+            ModelDevice::Cpu,
+            ModelDevice::Cuda(2),
+            ModelDevice::Metal(0),
+        ] {
+            assert_eq!(device.to_string().parse::<ModelDevice>().unwrap(), device);
+        }
+    }
+
+    #[test]
+    fn test_cpu_selection() {
+        let device = ModelDevice::Cpu.select(false).unwrap();
+        assert_eq!(ModelDevice::from(&device), ModelDevice::Cpu);
+    }
+}
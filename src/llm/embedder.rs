@@ -0,0 +1,321 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use candle_core::{Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config as BertConfig, DTYPE};
+use futures::{StreamExt, TryStreamExt};
+use hf_hub::api::sync::{Api, ApiError};
+use serde::Serialize;
+use tokenizers::{PaddingParams, PaddingStrategy, Tokenizer, TruncationParams};
+use tokio::sync::{watch, OnceCell, Semaphore};
+
+use crate::llm::hugging_face::EMBEDDING_MODEL;
+use crate::llm::ModelDevice;
+
+/// The largest number of texts that will be run through the model in a single forward pass.
+/// Larger requests are split up into chunks of at most this size.
+pub const MAX_BATCH_SIZE: usize = 32;
+
+/// Inference is CPU (or GPU) bound and runs on the blocking thread pool. This limits how many
+/// batches can be running through the model at once across all users of an [`Embedder`],
+/// anything beyond this waits its turn rather than starving the rest of the blocking pool.
+const MAX_CONCURRENT_BATCHES: usize = 2;
+
+/// BERT style models have a fixed size position embedding table, anything longer than this gets
+/// truncated by the tokenizer.
+const MAX_SEQUENCE_LENGTH: usize = 512;
+
+/// A cheaply cloneable handle to a sentence embedding model. The model itself is only downloaded
+/// and loaded the first time it is used or when [`Embedder::warm_up`] is called. Until that has
+/// completed [`Embedder::status`] can be used to avoid blocking on the load.
+#[derive(Clone)]
+pub struct Embedder {
+    inner: Arc<EmbedderInner>,
+}
+
+struct EmbedderInner {
+    device: Device,
+    model: OnceCell<Arc<LoadedModel>>,
+    permits: Semaphore,
+    status: watch::Sender<ModelStatus>,
+}
+
+struct LoadedModel {
+    model: BertModel,
+    tokenizer: Tokenizer,
+}
+
+impl Embedder {
+    pub fn new(device: Device) -> Self {
+        Self {
+            inner: Arc::new(EmbedderInner {
+                device,
+                model: OnceCell::new(),
+                permits: Semaphore::new(MAX_CONCURRENT_BATCHES),
+                status: watch::Sender::new(ModelStatus::NotLoaded),
+            }),
+        }
+    }
+
+    /// The compute device the model runs on.
+    pub fn device(&self) -> ModelDevice {
+        ModelDevice::from(&self.inner.device)
+    }
+
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+        let mut embeddings = self.embed_batch(&[text.to_string()]).await?;
+        embeddings.pop().ok_or(EmbedderError::MissingEmbedding)
+    }
+
+    /// Produces a normalized embedding for each of the provided texts, returned in the same order
+    /// they were provided. Inputs are processed in chunks of at most [`MAX_BATCH_SIZE`] with each
+    /// chunk padded to its longest member.
+    pub async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let loaded_model = self.loaded_model().await?;
+
+        let pending_batches: Vec<_> = texts
+            .chunks(MAX_BATCH_SIZE)
+            .map(|chunk| self.run_batch(loaded_model.clone(), chunk.to_vec()))
+            .collect();
+
+        let batches: Vec<Vec<Vec<f32>>> = futures::stream::iter(pending_batches)
+            .buffered(MAX_CONCURRENT_BATCHES)
+            .try_collect()
+            .await?;
+
+        Ok(batches.into_iter().flatten().collect())
+    }
+
+    async fn loaded_model(&self) -> Result<Arc<LoadedModel>, EmbedderError> {
+        self.inner
+            .model
+            .get_or_try_init(|| async {
+                let device = self.inner.device.clone();
+                self.inner.status.send_replace(ModelStatus::Loading);
+
+                let load_result =
+                    tokio::task::spawn_blocking(move || LoadedModel::load(EMBEDDING_MODEL, device))
+                        .await
+                        .map_err(EmbedderError::BlockingTaskFailed)
+                        .and_then(|res| res);
+
+                match &load_result {
+                    Ok(_) => self.inner.status.send_replace(ModelStatus::Ready),
+                    Err(_) => self.inner.status.send_replace(ModelStatus::Failed),
+                };
+
+                load_result.map(Arc::new)
+            })
+            .await
+            .cloned()
+    }
+
+    /// Reports whether the model is available for use without waiting on it to load.
+    pub fn status(&self) -> ModelStatus {
+        *self.inner.status.borrow()
+    }
+
+    /// Starts loading the model in the background if it hasn't already been loaded. Loading
+    /// involves downloading the model the first time which can take quite a while, this allows
+    /// that to happen without holding up anything else.
+    pub fn warm_up(&self) {
+        if matches!(self.status(), ModelStatus::Loading | ModelStatus::Ready) {
+            return;
+        }
+
+        let embedder = self.clone();
+        tokio::spawn(async move {
+            match embedder.loaded_model().await {
+                Ok(_) => tracing::info!(model = EMBEDDING_MODEL, "embedding model loaded"),
+                Err(err) => tracing::error!(
+                    model = EMBEDDING_MODEL,
+                    "failed to load embedding model: {err}"
+                ),
+            }
+        });
+    }
+
+    async fn run_batch(
+        &self,
+        loaded_model: Arc<LoadedModel>,
+        texts: Vec<String>,
+    ) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let _permit = self
+            .inner
+            .permits
+            .acquire()
+            .await
+            .expect("embedder semaphore is never closed");
+
+        tokio::task::spawn_blocking(move || loaded_model.embed_batch(texts))
+            .await
+            .map_err(EmbedderError::BlockingTaskFailed)?
+    }
+}
+
+impl LoadedModel {
+    fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let encodings = self
+            .tokenizer
+            .encode_batch(texts, true)
+            .map_err(EmbedderError::TokenizationFailed)?;
+
+        let device = &self.model.device;
+
+        let token_ids = encodings
+            .iter()
+            .map(|enc| Tensor::new(enc.get_ids(), device))
+            .collect::<Result<Vec<_>, _>>()?;
+        let token_ids = Tensor::stack(&token_ids, 0)?;
+
+        let attention_mask = encodings
+            .iter()
+            .map(|enc| Tensor::new(enc.get_attention_mask(), device))
+            .collect::<Result<Vec<_>, _>>()?;
+        let attention_mask = Tensor::stack(&attention_mask, 0)?;
+
+        let token_type_ids = token_ids.zeros_like()?;
+        let hidden_states = self.model.forward(&token_ids, &token_type_ids)?;
+
+        let embeddings = mean_pool_and_normalize(&hidden_states, &attention_mask)?;
+
+        Ok(embeddings.to_vec2::<f32>()?)
+    }
+
+    fn load(model: &str, device: Device) -> Result<Self, EmbedderError> {
+        let api = Api::new().map_err(EmbedderError::ModelUnavailable)?;
+        let repo = api.model(model.to_string());
+
+        let config_path = repo
+            .get("config.json")
+            .map_err(EmbedderError::ModelUnavailable)?;
+        let tokenizer_path = repo
+            .get("tokenizer.json")
+            .map_err(EmbedderError::ModelUnavailable)?;
+        let weights_path = repo
+            .get("model.safetensors")
+            .map_err(EmbedderError::ModelUnavailable)?;
+
+        let config_bytes = std::fs::read(&config_path)
+            .map_err(|err| EmbedderError::UnreadableFile(config_path, err))?;
+        let config: BertConfig =
+            serde_json::from_slice(&config_bytes).map_err(EmbedderError::InvalidConfig)?;
+
+        let mut tokenizer =
+            Tokenizer::from_file(tokenizer_path).map_err(EmbedderError::InvalidTokenizer)?;
+        tokenizer.with_padding(Some(PaddingParams {
+            strategy: PaddingStrategy::BatchLongest,
+            ..Default::default()
+        }));
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: MAX_SEQUENCE_LENGTH,
+                ..Default::default()
+            }))
+            .map_err(EmbedderError::InvalidTokenizer)?;
+
+        // Safety: the weights are memory mapped from the local HuggingFace cache. The file must
+        // not be modified while the model is loaded which holds as long as nothing else is
+        // messing around with the contents of the cache.
+        let var_builder =
+            unsafe { VarBuilder::from_mmaped_safetensors(&[weights_path], DTYPE, &device)? };
+        let model = BertModel::load(var_builder, &config)?;
+
+        Ok(Self { model, tokenizer })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelStatus {
+    /// Nothing has attempted to use the model yet
+    NotLoaded,
+
+    /// The model is being downloaded and loaded onto the device
+    Loading,
+
+    /// The model is available for inference
+    Ready,
+
+    /// The last attempt to load the model failed, it will be attempted again on the next use
+    Failed,
+}
+
+/// Averages the hidden states of each sequence while ignoring any padding tokens, then scales
+/// each of the resulting vectors to unit length so they can be compared using a dot product.
+fn mean_pool_and_normalize(
+    hidden_states: &Tensor,
+    attention_mask: &Tensor,
+) -> candle_core::Result<Tensor> {
+    let mask = attention_mask
+        .to_dtype(hidden_states.dtype())?
+        .unsqueeze(2)?;
+
+    let summed = hidden_states.broadcast_mul(&mask)?.sum(1)?;
+    let token_counts = mask.sum(1)?;
+    let pooled = summed.broadcast_div(&token_counts)?;
+
+    let norms = pooled.sqr()?.sum_keepdim(1)?.sqrt()?;
+    pooled.broadcast_div(&norms)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmbedderError {
+    #[error("background inference task failed to complete: {0}")]
+    BlockingTaskFailed(tokio::task::JoinError),
+
+    #[error("model execution failed: {0}")]
+    InferenceFailed(#[from] candle_core::Error),
+
+    #[error("model config was not valid: {0}")]
+    InvalidConfig(serde_json::Error),
+
+    #[error("tokenizer could not be loaded: {0}")]
+    InvalidTokenizer(tokenizers::Error),
+
+    #[error("model produced no embedding for the provided input")]
+    MissingEmbedding,
+
+    #[error("unable to retrieve model files: {0}")]
+    ModelUnavailable(ApiError),
+
+    #[error("failed to tokenize input: {0}")]
+    TokenizationFailed(tokenizers::Error),
+
+    #[error("unable to read model file {0:?}: {1}")]
+    UnreadableFile(PathBuf, std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mean_pooling_ignores_padding() {
+        let device = Device::Cpu;
+
+        // Two sequences of three tokens with a hidden size of two. The second sequence only has
+        // two real tokens, its last token is padding with values that would skew the mean.
+        let hidden_states = Tensor::new(
+            &[
+                [[3.0f32, 0.0], [3.0, 0.0], [3.0, 0.0]],
+                [[0.0f32, 2.0], [0.0, 4.0], [100.0, 100.0]],
+            ],
+            &device,
+        )
+        .unwrap();
+        let attention_mask = Tensor::new(&[[1u32, 1, 1], [1, 1, 0]], &device).unwrap();
+
+        let embeddings = mean_pool_and_normalize(&hidden_states, &attention_mask)
+            .unwrap()
+            .to_vec2::<f32>()
+            .unwrap();
+
+        assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+    }
+}
@@ -1,27 +1,46 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
 use reqwest::header::{
-    HeaderMap, HeaderName, HeaderValue, ToStrError, CONTENT_RANGE, LOCATION, RANGE,
+    HeaderMap, HeaderName, HeaderValue, ToStrError, CONTENT_LENGTH, CONTENT_RANGE, LOCATION, RANGE,
 };
-use reqwest::redirect::Policy;
+use reqwest::StatusCode;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use url::Url;
+
+use crate::background_jobs::BackoffStrategy;
+use crate::utils::{http_client, HttpClient, HttpClientOptions};
+
+pub const EMBEDDING_MODEL: &str = "thenlper/gte-base";
 
-const EMBEDDING_MODEL: &str = "thenlper/gte-base";
+pub const RERANKING_MODEL: &str = "BAAI/bge-reranker-base";
 
-const RERANKING_MODEL: &str = "BAAI/bge-reranker-base";
+const HUGGING_FACE_URL: &str = "https://huggingface.co";
 
-const SAFE_TENSOR_REPO_FMT: &str = "https://huggingface.co/{}/resolve/main/model.safetensors";
+/// Version checks only fetch headers, anything slower than this means HuggingFace is struggling.
+const VERSION_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
 
-const HTTP_CLIENT_CONTACT: &str = "https://github.com/sstelfox/web-app-template";
+/// The longest a single download request may take. Model weights can be several gigabytes, a
+/// download that runs past this is picked back up where it stopped by the next attempt.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60 * 60);
 
-/// The available version information retrieved from HuggingFace.
+/// A download is abandoned when no data has arrived for this long.
+const DOWNLOAD_STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The available version information retrieved from HuggingFace. At least one of the commit or
+/// etag is always present.
 #[derive(Debug)]
 pub struct ModelVersion {
-    commit: String,
+    commit: Option<String>,
     etag: Option<String>,
     size: usize,
 }
 
 impl ModelVersion {
-    pub fn commit(&self) -> &str {
-        &self.commit
+    pub fn commit(&self) -> Option<&str> {
+        self.commit.as_deref()
     }
 
     pub fn etag(&self) -> Option<&str> {
@@ -31,15 +50,45 @@ impl ModelVersion {
     pub fn size(&self) -> usize {
         self.size
     }
+
+    /// Identifies the version of the model, this is the commit when HuggingFace reported one and
+    /// the etag otherwise.
+    pub fn version(&self) -> &str {
+        self.commit
+            .as_deref()
+            .or(self.etag.as_deref())
+            .expect("one of the commit or etag to be present")
+    }
 }
 
-/// Performs an online check against HuggingFace to determien what the current version of the
-/// remote model is.
-///
+/// Performs an online check against HuggingFace to determine what the current version of a file
+/// in a model repository is.
 ///
 /// # Arguments
 ///
 /// * `model` - The path of the HuggingFace repo including the user namespace.
+/// * `filename` - The path of the file within the repo, such as `config.json` or
+///   `pytorch_model.bin`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// #   use web_app_template::llm::hugging_face::check_model_file_version;
+///     let config_version = check_model_file_version("thenlper/gte-base", "config.json").await?;
+/// #   Ok(())
+/// # }
+/// ```
+pub async fn check_model_file_version(
+    model: &str,
+    filename: &str,
+) -> Result<ModelVersion, HuggingFaceError> {
+    check_file_version(HUGGING_FACE_URL, model, filename).await
+}
+
+/// Checks the version of the `model.safetensors` weights in a HuggingFace repo, see
+/// [`check_model_file_version`] for other files.
 ///
 /// # Examples
 ///
@@ -51,21 +100,176 @@ impl ModelVersion {
 /// #   Ok(())
 /// # }
 /// ```
+pub async fn check_safetensor_model_version(model: &str) -> Result<ModelVersion, HuggingFaceError> {
+    check_model_file_version(model, "model.safetensors").await
+}
+
+/// Downloads a file from a HuggingFace model repo to `dest`, returning the version that was
+/// downloaded. Data is written to a `.partial` file next to the destination first, if a download
+/// is interrupted the next call picks it back up where it stopped as long as the file hasn't
+/// changed remotely. The version of the file is recorded in a `.version` file next to the
+/// destination and nothing is downloaded when it is already current.
 ///
-/// # Note
+/// # Arguments
 ///
-/// Currently this is limited to the safetensor models but we'll need a variety of
-/// model support in the future, at which point this function will likely be renamed and
-/// deprecated.
-pub async fn check_safetensor_model_version(model: &str) -> Result<ModelVersion, HuggingFaceError> {
+/// * `model` - The path of the HuggingFace repo including the user namespace.
+/// * `filename` - The path of the file within the repo.
+/// * `dest` - Where the completed file is written, its parent directory must already exist.
+pub async fn download_model(
+    model: &str,
+    filename: &str,
+    dest: &Path,
+) -> Result<ModelVersion, HuggingFaceError> {
+    download_file(HUGGING_FACE_URL, model, filename, dest).await
+}
+
+async fn download_file(
+    base_url: &str,
+    model: &str,
+    filename: &str,
+    dest: &Path,
+) -> Result<ModelVersion, HuggingFaceError> {
+    let (version, file_url) = file_metadata(base_url, model, filename).await?;
+
+    let version_path = sibling_path(dest, "version");
+    let partial_path = sibling_path(dest, "partial");
+
+    let recorded = match tokio::fs::read_to_string(&version_path).await {
+        Ok(recorded) => Some(recorded),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => return Err(HuggingFaceError::StorageFailed(err)),
+    };
+
+    let mut offset = 0;
+    if recorded.as_deref() == Some(content_key(&version)) {
+        if file_len(dest).await? == Some(version.size as u64) {
+            return Ok(version);
+        }
+
+        offset = file_len(&partial_path).await?.unwrap_or(0);
+    } else {
+        // Whatever is on disk belongs to another version of the file, the version is recorded
+        // before downloading so an interrupted download can tell what it was downloading
+        remove_if_present(dest).await?;
+        remove_if_present(&partial_path).await?;
+
+        tokio::fs::write(&version_path, content_key(&version))
+            .await
+            .map_err(HuggingFaceError::StorageFailed)?;
+    }
+
+    if offset > version.size as u64 {
+        remove_if_present(&partial_path).await?;
+        offset = 0;
+    }
+
+    if offset < version.size as u64 {
+        download_range(&file_url, &partial_path, offset).await?;
+    }
+
+    let downloaded = file_len(&partial_path).await?.unwrap_or(0);
+    if downloaded != version.size as u64 {
+        return Err(HuggingFaceError::SizeMismatch {
+            expected: version.size,
+            actual: downloaded,
+        });
+    }
+
+    tokio::fs::rename(&partial_path, dest)
+        .await
+        .map_err(HuggingFaceError::StorageFailed)?;
+
+    Ok(version)
+}
+
+/// Appends everything from `offset` onwards to the partial file. Servers that ignore the range
+/// send the whole file, which replaces what was there.
+async fn download_range(
+    file_url: &Url,
+    partial_path: &Path,
+    offset: u64,
+) -> Result<(), HuggingFaceError> {
+    let client = download_client();
+
+    let request = client
+        .get(file_url.clone())
+        .header(RANGE, format!("bytes={offset}-"));
+    let mut response = client
+        .send(request)
+        .await
+        .map_err(HuggingFaceError::DownloadFailed)?;
+
+    let mut file_options = OpenOptions::new();
+    file_options.create(true);
+
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT => {
+            let content_range = retrieve_header(CONTENT_RANGE, response.headers())?;
+            let start = content_range
+                .trim_start_matches("bytes ")
+                .split('-')
+                .next()
+                .and_then(|start| start.parse::<u64>().ok())
+                .ok_or(HuggingFaceError::BadContentRange)?;
+
+            if start != offset {
+                return Err(HuggingFaceError::BadContentRange);
+            }
+
+            file_options.append(true);
+        }
+        StatusCode::OK => {
+            file_options.write(true).truncate(true);
+        }
+        status => return Err(HuggingFaceError::UnexpectedStatus(status)),
+    }
+
+    let mut file = file_options
+        .open(partial_path)
+        .await
+        .map_err(HuggingFaceError::StorageFailed)?;
+
+    loop {
+        let chunk = tokio::time::timeout(DOWNLOAD_STALL_TIMEOUT, response.chunk())
+            .await
+            .map_err(|_| HuggingFaceError::DownloadStalled)?
+            .map_err(HuggingFaceError::DownloadFailed)?;
+
+        let Some(chunk) = chunk else {
+            break;
+        };
+
+        file.write_all(&chunk)
+            .await
+            .map_err(HuggingFaceError::StorageFailed)?;
+    }
+
+    file.flush().await.map_err(HuggingFaceError::StorageFailed)
+}
+
+async fn check_file_version(
+    base_url: &str,
+    model: &str,
+    filename: &str,
+) -> Result<ModelVersion, HuggingFaceError> {
+    let (version, _) = file_metadata(base_url, model, filename).await?;
+    Ok(version)
+}
+
+/// Retrieves the version of a file along with the URL its contents are actually served from.
+async fn file_metadata(
+    base_url: &str,
+    model: &str,
+    filename: &str,
+) -> Result<(ModelVersion, Url), HuggingFaceError> {
     let client = no_redirect_light_client();
 
-    // todo: This really needs to be more generic than just looking at safetensor model versions,
-    // bt for now this should be sufficient.
-    let model_url = SAFE_TENSOR_REPO_FMT.replace("{}", model);
+    // Files small enough to be served directly rather than redirected to a CDN are returned by
+    // this request, only ask for the first byte of them.
+    let model_url = format!("{base_url}/{model}/resolve/main/{filename}");
+    let mut file_url = Url::parse(&model_url).map_err(HuggingFaceError::InvalidRedirect)?;
     let mut response = client
-        .get(&model_url)
-        .send()
+        .send(client.get(file_url.clone()).header(RANGE, "bytes=0-0"))
         .await
         .map_err(HuggingFaceError::NoMetadata)?;
 
@@ -79,46 +283,68 @@ pub async fn check_safetensor_model_version(model: &str) -> Result<ModelVersion,
         .map(clean_etag)
         .transpose()?;
 
-    // The commit level is also in a custom header
+    // The commit level is also in a custom header, not every mirror sets it though so the etag is
+    // used to identify the version when its missing
     let current_commit =
-        retrieve_header(HeaderName::from_static("x-repo-commit"), metadata_headers)?;
+        optional_header(HeaderName::from_static("x-repo-commit"), metadata_headers)?;
+
+    if current_commit.is_none() {
+        if etag.is_none() {
+            return Err(HuggingFaceError::MissingHeader);
+        }
+
+        tracing::warn!(
+            model,
+            filename,
+            "no commit reported for model file, using its etag as the version"
+        );
+    }
 
     if response.status().is_redirection() {
-        let next_location = retrieve_header(LOCATION, metadata_headers)?;
+        // Redirects between repository paths (such as for renamed repos) are relative
+        let location = retrieve_header(LOCATION, metadata_headers)?;
+        file_url = file_url
+            .join(&location)
+            .map_err(HuggingFaceError::InvalidRedirect)?;
 
+        // This request only checks the current version of the repository, it doesn't download
+        // anything. Specifically request that no data is returned. This matches the requested
+        // behavior HuggingFace has requested for cacheing download clients.
+        let range_request = client.get(file_url.clone()).header(RANGE, "bytes=0-0");
         response = client
-            .get(&next_location)
-            // This request only checks the current version of the repository, it doesn't download
-            // anything. Specifically request that no data is returned. This matches the requested
-            // behavior HuggingFace has requested for cacheing download clients.
-            .header(RANGE, "bytes=0-0")
-            .send()
+            .send(range_request)
             .await
             .map_err(HuggingFaceError::RedirectFailed)?;
     }
 
     // HuggingFace lets us know how big the file is going to be so we can make a determination
-    // before attempting an actual download.
-    let content_range = retrieve_header(CONTENT_RANGE, response.headers())?;
+    // before attempting an actual download. Servers ignoring the range request return the whole
+    // file instead and its length is the size.
+    let size = match optional_header(CONTENT_RANGE, response.headers())? {
+        Some(content_range) => content_range
+            .split('/')
+            .last()
+            .ok_or(HuggingFaceError::BadContentRange)?
+            .parse()
+            .map_err(HuggingFaceError::InvalidSize)?,
+        None => retrieve_header(CONTENT_LENGTH, response.headers())?
+            .parse()
+            .map_err(HuggingFaceError::InvalidSize)?,
+    };
 
-    let size = content_range
-        .split('/')
-        .last()
-        .ok_or(HuggingFaceError::BadContentRange)?
-        .parse()
-        .map_err(HuggingFaceError::InvalidSize)?;
-
-    Ok(ModelVersion {
+    let version = ModelVersion {
         commit: current_commit,
         etag,
         size,
-    })
+    };
+
+    Ok((version, file_url))
 }
 
 /// Converts a response header into the unquoted string. In general Etag headers
 /// shouldn't be used to identify a specific version only whether it has changed
 /// or not. The [`ModelVersion::commit`] attribute should be used for version
-/// identification.
+/// identification when it is available.
 ///
 /// # Arguments
 ///
@@ -135,36 +361,79 @@ fn clean_etag(etag: &HeaderValue) -> Result<String, HuggingFaceError> {
         .map(|v| v.to_string().replace('"', ""))
 }
 
-/// Returns a configured HTTP client that allows us to handle redirects in a custom way. We are a
-/// good netizen and set a custom user agent to allow remote hosts to identify us.
-fn no_redirect_light_client() -> reqwest::Client {
-    let mut default_headers = HeaderMap::new();
-    default_headers.insert("Content-Type", HeaderValue::from_static("application/json"));
+/// Identifies the contents of the file itself. The commit changes whenever anything in the repo
+/// does, the etag only when this file does.
+fn content_key(version: &ModelVersion) -> &str {
+    version.etag().unwrap_or_else(|| version.version())
+}
 
-    let user_agent = format!(
-        "{}/{}; +{HTTP_CLIENT_CONTACT}",
-        env!("CARGO_PKG_NAME"),
-        env!("CARGO_PKG_VERSION")
-    );
+fn download_client() -> HttpClient {
+    let backoff = BackoffStrategy::Exponential {
+        base: Duration::from_millis(500),
+        cap: Duration::from_secs(5),
+    };
 
-    // todo: add a timeout to these request
-    let client = reqwest::Client::builder()
-        .default_headers(default_headers)
-        .redirect(Policy::none())
-        .user_agent(user_agent)
-        .build()
-        .expect("static client build should always succeed");
+    http_client(
+        HttpClientOptions::default()
+            .with_request_timeout(DOWNLOAD_TIMEOUT)
+            .with_retries(2, backoff),
+    )
+}
 
-    client
+async fn file_len(path: &Path) -> Result<Option<u64>, HuggingFaceError> {
+    match tokio::fs::metadata(path).await {
+        Ok(metadata) => Ok(Some(metadata.len())),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(HuggingFaceError::StorageFailed(err)),
+    }
 }
 
-fn retrieve_header(name: HeaderName, headers: &HeaderMap) -> Result<String, HuggingFaceError> {
+/// Returns a configured HTTP client that allows us to handle redirects in a custom way.
+fn no_redirect_light_client() -> HttpClient {
+    let backoff = BackoffStrategy::Exponential {
+        base: Duration::from_millis(500),
+        cap: Duration::from_secs(5),
+    };
+
+    http_client(
+        HttpClientOptions::default()
+            .without_redirects()
+            .with_request_timeout(VERSION_CHECK_TIMEOUT)
+            .with_retries(2, backoff),
+    )
+}
+
+fn optional_header(
+    name: HeaderName,
+    headers: &HeaderMap,
+) -> Result<Option<String>, HuggingFaceError> {
     headers
         .get(name)
-        .ok_or(HuggingFaceError::MissingHeader)?
-        .to_str()
-        .map_err(HuggingFaceError::InvalidHeaderValue)
-        .map(|v| v.to_string())
+        .map(|v| {
+            v.to_str()
+                .map_err(HuggingFaceError::InvalidHeaderValue)
+                .map(|v| v.to_string())
+        })
+        .transpose()
+}
+
+async fn remove_if_present(path: &Path) -> Result<(), HuggingFaceError> {
+    match tokio::fs::remove_file(path).await {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(HuggingFaceError::StorageFailed(err)),
+        _ => Ok(()),
+    }
+}
+
+fn retrieve_header(name: HeaderName, headers: &HeaderMap) -> Result<String, HuggingFaceError> {
+    optional_header(name, headers)?.ok_or(HuggingFaceError::MissingHeader)
+}
+
+/// The path of a file kept next to `path`, named after it with an extra extension.
+fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(".");
+    sibling.push(extension);
+    PathBuf::from(sibling)
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -175,9 +444,18 @@ pub enum HuggingFaceError {
     #[error("error occurred building a client: {0}")]
     BuildError(reqwest::Error),
 
+    #[error("downloading the file failed: {0}")]
+    DownloadFailed(reqwest::Error),
+
+    #[error("no data was received for the download in {}s", DOWNLOAD_STALL_TIMEOUT.as_secs())]
+    DownloadStalled,
+
     #[error("expected a header to be a valid string")]
     InvalidHeaderValue(ToStrError),
 
+    #[error("the redirect location wasn't a valid URL: {0}")]
+    InvalidRedirect(url::ParseError),
+
     #[error("the provided content size wasn't a number")]
     InvalidSize(std::num::ParseIntError),
 
@@ -189,4 +467,208 @@ pub enum HuggingFaceError {
 
     #[error("attempting to follow the provided redirect failed: {0}")]
     RedirectFailed(reqwest::Error),
+
+    #[error("downloaded {actual} bytes but expected {expected}")]
+    SizeMismatch { expected: usize, actual: u64 },
+
+    #[error("unable to write the downloaded file: {0}")]
+    StorageFailed(std::io::Error),
+
+    #[error("download request responded with unexpected status {0}")]
+    UnexpectedStatus(StatusCode),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::http::{header, StatusCode};
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use axum::Router;
+
+    use super::*;
+
+    const COMMIT: &str = "5e6b4b3d2c0b8f7f9d3c7d1e8f0b5a1e2c3d4f5a";
+
+    /// Serves the headers HuggingFace responded with for a few files in a model repo.
+    async fn fixture_server() -> String {
+        let router = Router::new()
+            .route(
+                "/thenlper/gte-base/resolve/main/model.safetensors",
+                get(|| async {
+                    (
+                        StatusCode::FOUND,
+                        [
+                            (header::LOCATION, "/cdn/gte-base/model.safetensors"),
+                            (header::ETAG, "\"7d3f0e4b\""),
+                            (
+                                HeaderName::from_static("x-linked-etag"),
+                                "\"a6f8d5f2c1b9e4d7\"",
+                            ),
+                            (HeaderName::from_static("x-repo-commit"), COMMIT),
+                        ],
+                    )
+                }),
+            )
+            .route(
+                "/cdn/gte-base/model.safetensors",
+                get(|headers: http::HeaderMap| async move {
+                    assert_eq!(headers.get(header::RANGE).unwrap(), "bytes=0-0");
+                    (
+                        StatusCode::PARTIAL_CONTENT,
+                        [(header::CONTENT_RANGE, "bytes 0-0/438235074")],
+                        "\0",
+                    )
+                }),
+            )
+            .route(
+                "/thenlper/gte-base/resolve/main/config.json",
+                get(|| async {
+                    (
+                        StatusCode::PARTIAL_CONTENT,
+                        [
+                            (header::CONTENT_RANGE, "bytes 0-0/618"),
+                            (header::ETAG, "\"cd0ab7e1\""),
+                            (HeaderName::from_static("x-repo-commit"), COMMIT),
+                        ],
+                        "{",
+                    )
+                }),
+            )
+            .route(
+                "/thenlper/gte-base/resolve/main/tokenizer.json",
+                get(|| async { ([(header::ETAG, "W/\"b1946ac9\"")], "{}") }),
+            );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        base_url
+    }
+
+    /// Serves a redirected file that honors range requests, recording every range asked for
+    /// other than the single byte used for version checks.
+    async fn blob_server(contents: &'static [u8]) -> (String, Arc<Mutex<Vec<String>>>) {
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let recorded = ranges.clone();
+
+        let router = Router::new()
+            .route(
+                "/org/model/resolve/main/model.bin",
+                get(|| async {
+                    (
+                        StatusCode::FOUND,
+                        [
+                            (header::LOCATION, "/cdn/model.bin"),
+                            (HeaderName::from_static("x-linked-etag"), "\"blob-etag\""),
+                            (HeaderName::from_static("x-repo-commit"), COMMIT),
+                        ],
+                    )
+                }),
+            )
+            .route(
+                "/cdn/model.bin",
+                get(move |headers: http::HeaderMap| async move {
+                    let range = headers.get(header::RANGE).unwrap().to_str().unwrap();
+                    if range != "bytes=0-0" {
+                        recorded.lock().unwrap().push(range.to_string());
+                    }
+
+                    let (start, end) = range.trim_start_matches("bytes=").split_once('-').unwrap();
+                    let start: usize = start.parse().unwrap();
+                    let end = end.parse().map(|e: usize| e + 1).unwrap_or(contents.len());
+
+                    let content_range = format!("bytes {start}-{}/{}", end - 1, contents.len());
+                    (
+                        StatusCode::PARTIAL_CONTENT,
+                        [(header::CONTENT_RANGE, content_range)],
+                        &contents[start..end],
+                    )
+                        .into_response()
+                }),
+            );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        (base_url, ranges)
+    }
+
+    #[tokio::test]
+    async fn test_download_resumes_and_skips_current_files() {
+        const CONTENTS: &[u8] = b"not actually the weights of a model";
+
+        let (base_url, ranges) = blob_server(CONTENTS).await;
+        let directory = std::env::temp_dir().join(format!("model-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let dest = directory.join("model.bin");
+
+        // An earlier download of the same version was interrupted part way through
+        std::fs::write(sibling_path(&dest, "version"), "blob-etag").unwrap();
+        std::fs::write(sibling_path(&dest, "partial"), &CONTENTS[..10]).unwrap();
+
+        let version = download_file(&base_url, "org/model", "model.bin", &dest)
+            .await
+            .unwrap();
+        assert_eq!(version.size(), CONTENTS.len());
+        assert_eq!(std::fs::read(&dest).unwrap(), CONTENTS);
+        assert!(!sibling_path(&dest, "partial").exists());
+        assert_eq!(*ranges.lock().unwrap(), ["bytes=10-"]);
+
+        // Nothing is downloaded when the file is already current
+        download_file(&base_url, "org/model", "model.bin", &dest)
+            .await
+            .unwrap();
+        assert_eq!(ranges.lock().unwrap().len(), 1);
+
+        // A partial file from another version is thrown away
+        std::fs::remove_file(&dest).unwrap();
+        std::fs::write(sibling_path(&dest, "version"), "old-etag").unwrap();
+        std::fs::write(sibling_path(&dest, "partial"), b"stale").unwrap();
+
+        download_file(&base_url, "org/model", "model.bin", &dest)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), CONTENTS);
+        assert_eq!(ranges.lock().unwrap().last().unwrap(), "bytes=0-");
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_redirected_file_version() {
+        let base_url = fixture_server().await;
+
+        let version = check_file_version(&base_url, "thenlper/gte-base", "model.safetensors")
+            .await
+            .unwrap();
+        assert_eq!(version.commit(), Some(COMMIT));
+        assert_eq!(version.etag(), Some("a6f8d5f2c1b9e4d7"));
+        assert_eq!(version.size(), 438_235_074);
+    }
+
+    #[tokio::test]
+    async fn test_direct_file_versions() {
+        let base_url = fixture_server().await;
+
+        let version = check_file_version(&base_url, "thenlper/gte-base", "config.json")
+            .await
+            .unwrap();
+        assert_eq!(version.version(), COMMIT);
+        assert_eq!(version.etag(), Some("cd0ab7e1"));
+        assert_eq!(version.size(), 618);
+
+        // Without a commit or range support the etag and full length are used
+        let version = check_file_version(&base_url, "thenlper/gte-base", "tokenizer.json")
+            .await
+            .unwrap();
+        assert_eq!(version.version(), "W/b1946ac9");
+        assert_eq!(version.size(), 2);
+
+        let missing = check_file_version(&base_url, "thenlper/gte-base", "vocab.txt").await;
+        assert!(matches!(missing, Err(HuggingFaceError::MissingHeader)));
+    }
 }
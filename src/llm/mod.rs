@@ -1,2 +1,7 @@
+mod device;
+mod embedder;
 pub mod hugging_face;
 pub mod models;
+
+pub use device::{ModelDevice, ModelDeviceError};
+pub use embedder::{Embedder, EmbedderError, ModelStatus, MAX_BATCH_SIZE};
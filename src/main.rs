@@ -1,85 +1,176 @@
-use std::time::Duration;
-
-use futures::future::join_all;
-use tokio::time::timeout;
+use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{EnvFilter, Layer};
 
-use web_app_template::app::Config;
-
-const FINAL_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
-
-#[tokio::main]
-async fn main() {
-    //use web_app_template::llm::hugging_face;
+use web_app_template::app::{set_secret_access_level, Command, Config};
+use web_app_template::{ShutdownReason, ShutdownTracker, SubsystemOutcome};
 
-    //let vers = hugging_face::check_safetensor_model_version(hugging_face::EMBEDDING_MODEL)
-    //    .await
-    //    .expect("valid");
-    //println!("{:?}", vers);
+fn main() {
+    let mut log_guard = None;
 
-    let config = match Config::from_env_and_args() {
-        Ok(c) => c,
+    let exit_code = match run(&mut log_guard) {
+        Ok(()) => 0,
         Err(err) => {
-            println!("failed to load config: {err}");
-            std::process::exit(2);
+            // Config errors happen before logging is available and need to go straight out
+            match log_guard {
+                Some(_) => tracing::error!("{err}"),
+                None => println!("{err}"),
+            }
+
+            err.exit_code()
         }
     };
 
-    let (non_blocking_writer, _guard) = tracing_appender::non_blocking(std::io::stdout());
+    exit(log_guard, exit_code);
+}
+
+/// The only place the process should exit from. `std::process::exit` doesn't run destructors, so
+/// the log writer's guard has to be dropped explicitly first to flush any buffered lines that
+/// would otherwise be lost, which are usually the ones explaining why we're exiting.
+fn exit(log_guard: Option<WorkerGuard>, code: i32) -> ! {
+    drop(log_guard);
+    std::process::exit(code);
+}
+
+fn run(log_guard: &mut Option<WorkerGuard>) -> Result<(), ServiceError> {
+    let config = Config::from_env_and_args()?;
+
+    let (non_blocking_writer, guard) = tracing_appender::non_blocking(std::io::stdout());
+    *log_guard = Some(guard);
+
     let env_filter = EnvFilter::builder()
         .with_default_directive(config.log_level().into())
         .from_env_lossy();
 
-    let stderr_layer = tracing_subscriber::fmt::layer()
-        .compact()
-        .with_writer(non_blocking_writer)
+    let stderr_layer = config
+        .log_format()
+        .layer(non_blocking_writer)
         .with_filter(env_filter);
 
     tracing_subscriber::registry().with(stderr_layer).init();
+    set_secret_access_level(config.secret_access_log_level());
+
+    // Both the database and model code lean on `spawn_blocking` so operators need to be able to
+    // size the blocking pool to their hardware, which isn't possible through `#[tokio::main]`
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .worker_threads(config.worker_threads())
+        .max_blocking_threads(config.max_blocking_threads())
+        .build()
+        .map_err(ServiceError::RuntimeSetupFailed)?;
+
+    tracing::info!(
+        worker_threads = config.worker_threads(),
+        max_blocking_threads = config.max_blocking_threads(),
+        "async runtime configured"
+    );
+
+    let result = runtime.block_on(serve(config));
+
+    // Anything still running has either outlived the drain timeout or been abandoned, waiting on
+    // it would only delay the exit
+    runtime.shutdown_background();
+
+    result
+}
 
+async fn serve(config: Config) -> Result<(), ServiceError> {
     web_app_template::register_panic_logger();
     web_app_template::report_version();
 
-    let state = match web_app_template::app::State::from_config(&config).await {
-        Ok(s) => s,
-        Err(err) => {
-            tracing::error!("failed to initialize state: {err}");
-            std::process::exit(3);
+    if config.command() == Command::Check {
+        let report = web_app_template::app::self_check(&config).await;
+        print!("{report}");
+
+        if !report.is_healthy() {
+            return Err(ServiceError::SelfCheckFailed);
         }
-    };
 
-    let (graceful_waiter, shutdown_rx) = web_app_template::graceful_shutdown_blocker();
+        return Ok(());
+    }
+
+    let state = web_app_template::app::State::from_config(&config).await?;
+
+    // Models can take a while to download and load, get that started in the background so it's not
+    // holding up the rest of the service from starting.
+    state.embedder().warm_up();
 
-    let mut all_handles = Vec::new();
+    let shutdown_timeouts = config.shutdown_timeouts();
+    let (graceful_waiter, shutdown_rx) =
+        web_app_template::graceful_shutdown_blocker(state.service_readiness(), shutdown_timeouts);
 
-    //let worker_handles =
-    //    web_app_template::background_workers(state.clone(), shutdown_rx.clone()).await;
-    //all_handles.extend(worker_handles);
+    let mut subsystems = ShutdownTracker::new();
 
-    let http_handle = web_app_template::http_server(
-        *config.listen_addr(),
-        config.log_level(),
-        state,
+    let monitor_handle = web_app_template::database_monitor(&state, shutdown_rx.clone());
+    subsystems.track("database-monitor", monitor_handle);
+
+    // HuggingFace being unreachable shouldn't keep the service from starting, it's only reported
+    let model_source_handle = web_app_template::model_source_monitor(&state, shutdown_rx.clone());
+    subsystems.track("model-source-monitor", model_source_handle);
+
+    web_app_template::background_workers(
+        &config,
+        state.clone(),
         shutdown_rx.clone(),
+        &mut subsystems,
     )
     .await;
-    all_handles.push(http_handle);
 
-    let _ = graceful_waiter.await;
+    let http_handle = web_app_template::http_server(&config, state, shutdown_rx.clone()).await;
+    subsystems.track("http", http_handle);
+
+    let drain_timeout = match graceful_waiter.await {
+        Ok(reason) => shutdown_timeouts.drain_timeout(reason),
+        Err(_) => shutdown_timeouts.drain_timeout(ShutdownReason::Terminate),
+    };
+
+    let summary = subsystems.wait(drain_timeout).await;
+    for (subsystem, outcome) in summary.outcomes() {
+        if *outcome != SubsystemOutcome::Clean {
+            tracing::warn!(subsystem, %outcome, "subsystem didn't shut down cleanly");
+        }
+    }
+
+    if summary.is_clean() {
+        tracing::info!(%summary, "all subsystems shut down");
+    } else {
+        tracing::warn!(%summary, "shutdown finished with problems");
+    }
 
-    if (timeout(FINAL_SHUTDOWN_TIMEOUT, join_all(all_handles)).await).is_err() {
-        tracing::error!("hit final shutdown timeout. exiting with remaining work in progress");
-        std::process::exit(4);
+    if summary.timed_out() {
+        return Err(ServiceError::ShutdownTimeout);
     }
+
+    Ok(())
 }
 
 #[derive(Debug, thiserror::Error)]
 enum ServiceError {
-    #[error("service couldn't initialize the config: {0}")]
+    #[error("failed to load config: {0}")]
     ConfigSetupFailed(#[from] web_app_template::app::ConfigError),
 
-    #[error("service encountered an issue: {0}")]
-    RunFailed(#[from] web_app_template::http_server::HttpServerError),
+    #[error("one or more self checks failed")]
+    SelfCheckFailed,
+
+    #[error("hit final shutdown timeout. exiting with remaining work in progress")]
+    ShutdownTimeout,
+
+    #[error("failed to start the async runtime: {0}")]
+    RuntimeSetupFailed(std::io::Error),
+
+    #[error("failed to initialize state: {0}")]
+    StateSetupFailed(#[from] web_app_template::app::StateSetupError),
+}
+
+impl ServiceError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            ServiceError::ConfigSetupFailed(_) => 2,
+            ServiceError::StateSetupFailed(_) => 3,
+            ServiceError::ShutdownTimeout => 4,
+            ServiceError::SelfCheckFailed => 5,
+            ServiceError::RuntimeSetupFailed(_) => 6,
+        }
+    }
 }
@@ -18,7 +18,8 @@ pub async fn home_handler(session: SessionIdentity) -> Response {
 }
 
 pub async fn css_metrics_handler(requestor: Requestor) -> Response {
-    if requestor.is_private() {
+    // Bots and users that have asked not to be tracked don't get counted
+    if requestor.is_private() || requestor.is_bot() {
         return (StatusCode::NO_CONTENT, ()).into_response();
     }
 
@@ -46,6 +47,19 @@ pub struct HomeTemplate {
     pub session: SessionIdentity,
 }
 
+#[derive(Template)]
+#[template(path = "method_not_allowed.html")]
+pub struct MethodNotAllowedTemplate;
+
 #[derive(Template)]
 #[template(path = "not_found.html")]
 pub struct NotFoundTemplate;
+
+/// A plain table for showing the status endpoints to someone looking at them in a browser.
+#[derive(Template)]
+#[template(path = "status_table.html")]
+pub struct StatusTableTemplate {
+    pub title: &'static str,
+    pub columns: Vec<&'static str>,
+    pub rows: Vec<Vec<String>>,
+}
@@ -0,0 +1,255 @@
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use crate::health_check::ServiceReadiness;
+
+/// How long orchestrators may keep routing new requests to us after asking us to stop, unless
+/// configured otherwise. See [`crate::graceful_shutdown_blocker`] for the details.
+const REQUEST_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// The longest in-progress work is allowed to take to finish up once shutdown has begun, unless
+/// configured otherwise. The drain only starts once the grace period is over, together they have
+/// to fit within [`TERMINATION_BUDGET`]. The defaults take 25 seconds, leaving a few for the
+/// process to exit.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Extra time worker pools get beyond their drain timeout to stop, covering the bookkeeping for
+/// any jobs that had to be abandoned, unless configured otherwise. This comes out of the drain
+/// timeout rather than adding to it, pools are never waited on for longer than the drain.
+const WORKER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long after SIGTERM the process is killed outright, the k8s default for
+/// `terminationGracePeriodSeconds`. Deployments configuring a longer grace period or drain timeout
+/// need to raise that setting to match.
+pub(crate) const TERMINATION_BUDGET: Duration = Duration::from_secs(30);
+
+/// Someone interrupting a locally running server wants it gone, not a tidy exit. This gives work
+/// that is about to finish anyway a moment to do so without making them wait on anything else.
+const INTERRUPT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Receives the reason once the service has been asked to shut down, the value is `None` until
+/// then.
+pub type ShutdownSignal = watch::Receiver<Option<ShutdownReason>>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShutdownReason {
+    /// SIGINT, generally from a developer pressing Ctrl-C. Shutdown happens as soon as possible
+    /// and open connections are abandoned rather than drained.
+    Interrupt,
+
+    /// SIGTERM, from an orchestrator asking for a clean exit. Requests continue to be served
+    /// through the grace period and in-progress work is drained before exiting.
+    Terminate,
+}
+
+impl ShutdownReason {
+    /// Whether open connections should be dropped instead of waiting for them to close.
+    pub fn abandons_connections(&self) -> bool {
+        matches!(self, ShutdownReason::Interrupt)
+    }
+}
+
+/// How long each stage of shutting down is allowed to take. Orchestrators differ in how long they
+/// keep routing traffic after asking for an exit and how long they wait before killing the
+/// process, so these are configurable per deployment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShutdownTimeouts {
+    grace_period: Duration,
+    drain_timeout: Duration,
+    worker_shutdown_timeout: Duration,
+}
+
+impl ShutdownTimeouts {
+    /// How long in-progress work gets to finish before the process exits regardless. Interrupts
+    /// never wait longer than a moment, even if the configured timeout is longer.
+    pub fn drain_timeout(&self, reason: ShutdownReason) -> Duration {
+        match reason {
+            ShutdownReason::Interrupt => INTERRUPT_DRAIN_TIMEOUT.min(self.drain_timeout),
+            ShutdownReason::Terminate => self.drain_timeout,
+        }
+    }
+
+    /// How long to keep operating normally after the signal before shutdown begins.
+    pub fn grace_period(&self, reason: ShutdownReason) -> Duration {
+        match reason {
+            ShutdownReason::Interrupt => Duration::ZERO,
+            ShutdownReason::Terminate => self.grace_period,
+        }
+    }
+
+    /// Whether a SIGTERM turns into an exit before the default [`TERMINATION_BUDGET`] runs out.
+    pub fn fits_termination_budget(&self) -> bool {
+        self.max_termination() <= TERMINATION_BUDGET
+    }
+
+    /// The longest a SIGTERM can take to turn into an exit, the grace period followed by the
+    /// drain.
+    pub fn max_termination(&self) -> Duration {
+        self.grace_period + self.drain_timeout
+    }
+
+    pub fn new(
+        grace_period: Duration,
+        drain_timeout: Duration,
+        worker_shutdown_timeout: Duration,
+    ) -> Self {
+        Self {
+            grace_period,
+            drain_timeout,
+            worker_shutdown_timeout,
+        }
+    }
+
+    /// Extra time worker pools get beyond their own drain timeout for their workers to stop.
+    pub fn worker_shutdown_timeout(&self) -> Duration {
+        self.worker_shutdown_timeout
+    }
+}
+
+impl Default for ShutdownTimeouts {
+    fn default() -> Self {
+        Self::new(REQUEST_GRACE_PERIOD, DRAIN_TIMEOUT, WORKER_SHUTDOWN_TIMEOUT)
+    }
+}
+
+/// Fails readiness, waits out the grace period for the reason, then notifies everything holding a
+/// [`ShutdownSignal`] that it is time to stop. Readiness has to fail first as orchestrators only
+/// stop routing new traffic to us once it does, which is what the grace period is waiting on.
+pub(crate) async fn begin_shutdown(
+    reason: ShutdownReason,
+    timeouts: &ShutdownTimeouts,
+    readiness: &ServiceReadiness,
+    tx: &watch::Sender<Option<ShutdownReason>>,
+) {
+    readiness.mark_shutting_down();
+    tokio::time::sleep(timeouts.grace_period(reason)).await;
+    let _ = tx.send(Some(reason));
+}
+
+/// Resolves once shutdown has been requested for a reason that abandons open connections, never
+/// resolving otherwise.
+pub async fn connections_abandoned(mut shutdown_rx: ShutdownSignal) {
+    let abandoned = shutdown_rx
+        .wait_for(|reason| reason.is_some_and(|r| r.abandons_connections()))
+        .await
+        .is_ok();
+
+    // The sender going away without an abandoning reason means nothing is ever going to ask us to
+    // drop connections.
+    if !abandoned {
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::*;
+
+    async fn shutdown_timing(reason: ShutdownReason) -> (Duration, bool) {
+        let (tx, mut rx) = watch::channel(None);
+
+        let start = Instant::now();
+        let readiness = ServiceReadiness::new();
+        tokio::spawn(async move {
+            begin_shutdown(reason, &ShutdownTimeouts::default(), &readiness, &tx).await
+        });
+        rx.changed().await.unwrap();
+        let waited = start.elapsed();
+
+        let abandoned =
+            tokio::time::timeout(Duration::from_secs(60), connections_abandoned(rx)).await;
+
+        (waited, abandoned.is_ok())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interrupt_is_immediate() {
+        let (waited, abandoned) = shutdown_timing(ShutdownReason::Interrupt).await;
+
+        assert_eq!(waited, Duration::ZERO);
+        assert!(abandoned);
+        let drain_timeout = ShutdownTimeouts::default().drain_timeout(ShutdownReason::Interrupt);
+        assert!(drain_timeout < Duration::from_secs(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_terminate_fails_readiness_before_signalling() {
+        let (tx, mut rx) = watch::channel(None);
+        let readiness = ServiceReadiness::new();
+        readiness.mark_database_ready();
+
+        let shutdown_readiness = readiness.clone();
+        tokio::spawn(async move {
+            let timeouts = ShutdownTimeouts::default();
+            begin_shutdown(
+                ShutdownReason::Terminate,
+                &timeouts,
+                &shutdown_readiness,
+                &tx,
+            )
+            .await
+        });
+
+        tokio::time::sleep(REQUEST_GRACE_PERIOD / 2).await;
+        assert!(readiness.check().is_err());
+        assert!(!rx.has_changed().unwrap());
+
+        rx.changed().await.unwrap();
+        assert_eq!(*rx.borrow(), Some(ShutdownReason::Terminate));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_terminate_honors_grace_period() {
+        let (waited, abandoned) = shutdown_timing(ShutdownReason::Terminate).await;
+
+        assert_eq!(waited, REQUEST_GRACE_PERIOD);
+        assert!(!abandoned);
+        assert_eq!(
+            ShutdownTimeouts::default().drain_timeout(ShutdownReason::Terminate),
+            DRAIN_TIMEOUT
+        );
+    }
+
+    #[test]
+    fn test_defaults_fit_termination_budget() {
+        let timeouts = ShutdownTimeouts::default();
+
+        assert!(timeouts.max_termination() < TERMINATION_BUDGET);
+        assert!(timeouts.worker_shutdown_timeout() < timeouts.drain_timeout);
+    }
+
+    #[test]
+    fn test_long_drain_exceeds_termination_budget() {
+        let timeouts = ShutdownTimeouts::new(
+            Duration::from_secs(10),
+            Duration::from_secs(30),
+            Duration::from_secs(5),
+        );
+
+        assert!(!timeouts.fits_termination_budget());
+        assert!(ShutdownTimeouts::default().fits_termination_budget());
+    }
+
+    #[test]
+    fn test_interrupt_drain_never_exceeds_configured_timeout() {
+        let timeouts = ShutdownTimeouts::new(
+            Duration::from_secs(2),
+            Duration::from_millis(200),
+            Duration::from_secs(1),
+        );
+
+        assert_eq!(
+            timeouts.drain_timeout(ShutdownReason::Interrupt),
+            Duration::from_millis(200)
+        );
+        assert_eq!(
+            timeouts.grace_period(ShutdownReason::Terminate),
+            Duration::from_secs(2)
+        );
+    }
+}
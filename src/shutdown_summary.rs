@@ -0,0 +1,189 @@
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// How a single subsystem finished up once shutdown began.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubsystemOutcome {
+    Clean,
+
+    /// The subsystem stopped but reported an error or panicked on the way out
+    Failed(String),
+
+    /// The subsystem was still running when we stopped waiting on it. Subsystems that can tell
+    /// how much of their work was left unfinished include it, otherwise it is zero.
+    TimedOut {
+        in_flight: usize,
+    },
+}
+
+impl Display for SubsystemOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SubsystemOutcome::Clean => f.write_str("clean"),
+            SubsystemOutcome::Failed(err) => write!(f, "failed ({err})"),
+            SubsystemOutcome::TimedOut { in_flight: 0 } => f.write_str("timed out"),
+            SubsystemOutcome::TimedOut { in_flight } => {
+                write!(f, "timed out with {in_flight} in-flight")
+            }
+        }
+    }
+}
+
+impl From<()> for SubsystemOutcome {
+    fn from(_: ()) -> Self {
+        SubsystemOutcome::Clean
+    }
+}
+
+/// What each subsystem reported during shutdown, in the order they were tracked.
+#[derive(Debug)]
+pub struct ShutdownSummary {
+    outcomes: Vec<(&'static str, SubsystemOutcome)>,
+}
+
+impl ShutdownSummary {
+    pub fn is_clean(&self) -> bool {
+        self.outcomes
+            .iter()
+            .all(|(_, outcome)| *outcome == SubsystemOutcome::Clean)
+    }
+
+    pub fn outcomes(&self) -> &[(&'static str, SubsystemOutcome)] {
+        &self.outcomes
+    }
+
+    /// Whether any subsystem was abandoned while it was still running.
+    pub fn timed_out(&self) -> bool {
+        self.outcomes
+            .iter()
+            .any(|(_, outcome)| matches!(outcome, SubsystemOutcome::TimedOut { .. }))
+    }
+}
+
+impl Display for ShutdownSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (idx, (name, outcome)) in self.outcomes.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(", ")?;
+            }
+
+            write!(f, "{name}: {outcome}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Collects the outcome of every long running part of the service as it stops. Each tracked
+/// handle reports back over a channel as soon as it finishes so the ones still running when the
+/// drain timeout passes can be named instead of just reporting that something was left over.
+pub struct ShutdownTracker {
+    names: Vec<&'static str>,
+    rx: mpsc::UnboundedReceiver<(&'static str, SubsystemOutcome)>,
+    tx: mpsc::UnboundedSender<(&'static str, SubsystemOutcome)>,
+}
+
+impl ShutdownTracker {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        Self {
+            names: Vec::new(),
+            rx,
+            tx,
+        }
+    }
+
+    /// Reports the outcome of the handle under the provided name once it finishes. A panic in the
+    /// subsystem is reported as a failure.
+    pub fn track<T>(&mut self, name: &'static str, handle: JoinHandle<T>)
+    where
+        T: Into<SubsystemOutcome> + Send + 'static,
+    {
+        self.names.push(name);
+        let tx = self.tx.clone();
+
+        tokio::spawn(async move {
+            let outcome = match handle.await {
+                Ok(outcome) => outcome.into(),
+                Err(err) => SubsystemOutcome::Failed(err.to_string()),
+            };
+
+            let _ = tx.send((name, outcome));
+        });
+    }
+
+    /// Waits up to `drain_timeout` for every tracked subsystem to report in. Anything that hasn't
+    /// by then is reported as having timed out.
+    pub async fn wait(mut self, drain_timeout: Duration) -> ShutdownSummary {
+        drop(self.tx);
+
+        let mut reported = Vec::with_capacity(self.names.len());
+        let _ = tokio::time::timeout(drain_timeout, async {
+            while let Some(report) = self.rx.recv().await {
+                reported.push(report);
+            }
+        })
+        .await;
+
+        let outcomes = self
+            .names
+            .into_iter()
+            .map(|name| {
+                let outcome = reported
+                    .iter()
+                    .position(|(reported_name, _)| *reported_name == name)
+                    .map(|idx| reported.swap_remove(idx).1)
+                    .unwrap_or(SubsystemOutcome::TimedOut { in_flight: 0 });
+
+                (name, outcome)
+            })
+            .collect();
+
+        ShutdownSummary { outcomes }
+    }
+}
+
+impl Default for ShutdownTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_summary_reports_each_subsystem() {
+        let mut tracker = ShutdownTracker::new();
+
+        tracker.track("http", tokio::spawn(async {}));
+        tracker.track(
+            "basic-workers",
+            tokio::spawn(async { SubsystemOutcome::TimedOut { in_flight: 2 } }),
+        );
+        tracker.track(
+            "event-subscriber",
+            tokio::spawn(std::future::pending::<()>()),
+        );
+        let panicking: JoinHandle<()> = tokio::spawn(async { panic!("monitor exploded") });
+        tracker.track("model-source", panicking);
+
+        let summary = tracker.wait(Duration::from_secs(5)).await;
+
+        assert!(!summary.is_clean());
+        assert!(summary.timed_out());
+        assert_eq!(summary.outcomes()[0], ("http", SubsystemOutcome::Clean));
+        assert!(matches!(
+            summary.outcomes()[3].1,
+            SubsystemOutcome::Failed(_)
+        ));
+        assert!(summary.to_string().starts_with(
+            "http: clean, basic-workers: timed out with 2 in-flight, event-subscriber: timed out, model-source: failed"
+        ));
+    }
+}
@@ -5,3 +5,14 @@ pub(crate) async fn test_database() -> SqlitePool {
         .await
         .expect("db setup")
 }
+
+/// A fresh in-memory database with the full application schema applied.
+pub(crate) async fn migrated_test_database() -> SqlitePool {
+    let pool = test_database().await;
+
+    crate::database::sqlite::migrate_sqlite(&pool)
+        .await
+        .expect("migrations to apply");
+
+    pool
+}
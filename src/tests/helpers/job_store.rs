@@ -0,0 +1,200 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use time::OffsetDateTime;
+
+use crate::background_jobs::{
+    BackoffFn, EnqueueOutcome, JobLike, JobStore, JobStoreError, QueueName,
+};
+use crate::database::custom_types::{BackgroundJobId, BackgroundJobState};
+use crate::database::models::{BackgroundJob, CreateBackgroundJob};
+use crate::tests::helpers::migrated_test_database;
+
+/// How long jobs claimed in tests are leased for, long enough that no test sees it expire.
+pub(crate) const CLAIM_LEASE: Duration = Duration::from_secs(3_600);
+
+/// A [`JobStore`] for exercising workers without running a real store. Jobs handed to
+/// [`TestJobStore::with_jobs`] are given out in order by `next`, and every request to move a job
+/// along is recorded for the test to inspect. Jobs have to be built up front with [`stored_job`],
+/// enqueueing and the operator facing lookups aren't supported.
+#[derive(Clone, Default)]
+pub(crate) struct TestJobStore {
+    queued: Arc<Mutex<VecDeque<BackgroundJob>>>,
+
+    released: Arc<Mutex<Vec<BackgroundJobId>>>,
+    rescheduled: Arc<Mutex<Vec<(BackgroundJobId, OffsetDateTime)>>>,
+    retried: Arc<Mutex<Vec<(BackgroundJobId, BackoffFn)>>>,
+    updated: Arc<Mutex<Vec<(BackgroundJobId, BackgroundJobState)>>>,
+}
+
+impl TestJobStore {
+    pub(crate) fn released(&self) -> Vec<BackgroundJobId> {
+        self.released.lock().unwrap().clone()
+    }
+
+    pub(crate) fn rescheduled(&self) -> Vec<(BackgroundJobId, OffsetDateTime)> {
+        self.rescheduled.lock().unwrap().clone()
+    }
+
+    pub(crate) fn retried(&self) -> Vec<(BackgroundJobId, BackoffFn)> {
+        self.retried.lock().unwrap().clone()
+    }
+
+    pub(crate) fn updated(&self) -> Vec<(BackgroundJobId, BackgroundJobState)> {
+        self.updated.lock().unwrap().clone()
+    }
+
+    pub(crate) fn with_jobs(jobs: impl IntoIterator<Item = BackgroundJob>) -> Self {
+        Self {
+            queued: Arc::new(Mutex::new(jobs.into_iter().collect())),
+            ..Self::default()
+        }
+    }
+}
+
+#[async_trait]
+impl JobStore for TestJobStore {
+    type Connection = ();
+
+    async fn acquire_lock(
+        &self,
+        _name: &str,
+        _holder: &str,
+        _lease: Duration,
+    ) -> Result<bool, JobStoreError> {
+        unimplemented!("singleton jobs need a real store")
+    }
+
+    async fn dead_letter(
+        &self,
+        _queue_name: QueueName,
+        _limit: usize,
+    ) -> Result<Vec<BackgroundJob>, JobStoreError> {
+        unimplemented!("the test store doesn't track job states")
+    }
+
+    async fn enqueue<T: JobLike>(
+        _conn: &mut Self::Connection,
+        _task: T,
+    ) -> Result<EnqueueOutcome, JobStoreError> {
+        unimplemented!("jobs are handed to the test store when it is created")
+    }
+
+    async fn enqueue_recurring<T: JobLike>(
+        &self,
+        _task: T,
+    ) -> Result<EnqueueOutcome, JobStoreError> {
+        unimplemented!("jobs are handed to the test store when it is created")
+    }
+
+    async fn find(&self, _id: BackgroundJobId) -> Result<Option<BackgroundJob>, JobStoreError> {
+        unimplemented!("the test store doesn't track job states")
+    }
+
+    async fn last_scheduled(
+        &self,
+        _job_name: &str,
+    ) -> Result<Option<OffsetDateTime>, JobStoreError> {
+        unimplemented!("jobs are handed to the test store when it is created")
+    }
+
+    async fn list_by_state(
+        &self,
+        _state: BackgroundJobState,
+        _limit: usize,
+    ) -> Result<Vec<BackgroundJob>, JobStoreError> {
+        unimplemented!("the test store doesn't track job states")
+    }
+
+    async fn next(
+        &self,
+        _queue_name: QueueName,
+        _task_names: &[&str],
+        _lease: Duration,
+    ) -> Result<Option<BackgroundJob>, JobStoreError> {
+        Ok(self.queued.lock().unwrap().pop_front())
+    }
+
+    async fn release(
+        &self,
+        id: BackgroundJobId,
+        _attempt_run_at: OffsetDateTime,
+    ) -> Result<(), JobStoreError> {
+        self.released.lock().unwrap().push(id);
+        Ok(())
+    }
+
+    async fn release_lock(&self, _name: &str, _holder: &str) -> Result<(), JobStoreError> {
+        unimplemented!("singleton jobs need a real store")
+    }
+
+    async fn requeue(&self, _id: BackgroundJobId) -> Result<(), JobStoreError> {
+        unimplemented!("the test store doesn't track job states")
+    }
+
+    async fn requeue_dead_by_name(
+        &self,
+        _job_name: &str,
+        _limit: usize,
+    ) -> Result<u64, JobStoreError> {
+        unimplemented!("the test store doesn't track job states")
+    }
+
+    async fn reschedule(
+        &self,
+        id: BackgroundJobId,
+        attempt_run_at: OffsetDateTime,
+    ) -> Result<(), JobStoreError> {
+        self.rescheduled.lock().unwrap().push((id, attempt_run_at));
+        Ok(())
+    }
+
+    async fn retry(
+        &self,
+        id: BackgroundJobId,
+        backoff_fn: BackoffFn,
+    ) -> Result<Option<OffsetDateTime>, JobStoreError> {
+        // Retried jobs are always reported as having used up their attempts
+        self.retried.lock().unwrap().push((id, backoff_fn));
+        Ok(None)
+    }
+
+    async fn update_state(
+        &self,
+        id: BackgroundJobId,
+        new_state: BackgroundJobState,
+    ) -> Result<(), JobStoreError> {
+        self.updated.lock().unwrap().push((id, new_state));
+        Ok(())
+    }
+}
+
+/// A freshly scheduled instance of the job as it would be handed to a worker.
+pub(crate) async fn stored_job<JL: JobLike>(job: JL) -> BackgroundJob {
+    let new_job = CreateBackgroundJob::now(JL::JOB_NAME, JL::QUEUE_NAME.as_str(), None, &job);
+    save_and_load(new_job).await
+}
+
+/// Like [`stored_job`] but enqueued on behalf of a request that gives up on it at `deadline`.
+pub(crate) async fn stored_job_with_deadline<JL: JobLike>(
+    job: JL,
+    deadline: OffsetDateTime,
+) -> BackgroundJob {
+    let new_job = CreateBackgroundJob::now(JL::JOB_NAME, JL::QUEUE_NAME.as_str(), None, &job)
+        .with_deadline(deadline);
+    save_and_load(new_job).await
+}
+
+async fn save_and_load<JL: JobLike>(new_job: CreateBackgroundJob<'_, JL>) -> BackgroundJob {
+    let pool = migrated_test_database().await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    new_job.save(&mut conn).await.unwrap();
+
+    sqlx::query_as("SELECT * FROM background_jobs;")
+        .fetch_one(&mut *conn)
+        .await
+        .unwrap()
+}
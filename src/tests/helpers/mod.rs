@@ -1,3 +1,5 @@
 mod database;
+mod job_store;
 
-pub(crate) use database::test_database;
+pub(crate) use database::{migrated_test_database, test_database};
+pub(crate) use job_store::{stored_job, stored_job_with_deadline, TestJobStore, CLAIM_LEASE};
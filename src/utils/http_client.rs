@@ -0,0 +1,209 @@
+use std::ops::Deref;
+use std::time::Duration;
+
+use reqwest::redirect::Policy;
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+use crate::background_jobs::BackoffStrategy;
+
+/// Included in our user agent so the operators of remote hosts know who to get in touch with.
+const HTTP_CLIENT_CONTACT: &str = "https://github.com/sstelfox/web-app-template";
+
+/// How long to wait for a connection to a remote host to be established.
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long an entire request, including reading the response body, is allowed to take.
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The policy used for requests the service makes to other hosts. Every client gets the same user
+/// agent and never waits on a remote host indefinitely, the rest can be adjusted to suit the
+/// host being talked to.
+#[derive(Clone, Debug)]
+pub struct HttpClientOptions {
+    connect_timeout: Duration,
+    follow_redirects: bool,
+    request_timeout: Duration,
+    retries: u8,
+    retry_backoff: BackoffStrategy,
+}
+
+impl HttpClientOptions {
+    /// Redirects are returned to the caller instead of being followed.
+    pub fn without_redirects(mut self) -> Self {
+        self.follow_redirects = false;
+        self
+    }
+
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Idempotent requests sent through [`HttpClient::send`] are tried again this many times when
+    /// the remote host can't be reached or reports a temporary failure.
+    pub fn with_retries(mut self, retries: u8, backoff: BackoffStrategy) -> Self {
+        self.retries = retries;
+        self.retry_backoff = backoff;
+        self
+    }
+}
+
+impl Default for HttpClientOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            follow_redirects: true,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            retries: 0,
+            retry_backoff: BackoffStrategy::Exponential {
+                base: Duration::from_millis(250),
+                cap: Duration::from_secs(5),
+            },
+        }
+    }
+}
+
+/// A [`reqwest::Client`] configured by [`http_client`]. Requests built from it can be sent
+/// directly as normal, going through [`HttpClient::send`] adds the configured retries.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    retries: u8,
+    retry_backoff: BackoffStrategy,
+}
+
+impl HttpClient {
+    /// Sends the request, retrying it when it failed in a way that might succeed on another
+    /// attempt. Only idempotent requests are ever retried. The response of the final attempt is
+    /// returned as is, including any error status.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, reqwest::Error> {
+        let request = request.build()?;
+        let retries = if request.method().is_idempotent() {
+            self.retries
+        } else {
+            0
+        };
+
+        let mut attempt = 1;
+        loop {
+            let last_attempt = attempt > retries;
+            let current = match request.try_clone() {
+                Some(current) if !last_attempt => current,
+                // Streaming bodies can't be replayed, those only ever get the one attempt
+                _ => return self.client.execute(request).await,
+            };
+
+            let retry_reason = match self.client.execute(current).await {
+                Ok(response) if is_temporary_failure(response.status()) => {
+                    format!("remote host responded with {}", response.status())
+                }
+                Ok(response) => return Ok(response),
+                Err(err) if err.is_connect() || err.is_timeout() => err.to_string(),
+                Err(err) => return Err(err),
+            };
+
+            let delay = self.retry_backoff.delay(attempt);
+            tracing::debug!(attempt, ?delay, "retrying outbound request: {retry_reason}");
+            tokio::time::sleep(delay).await;
+
+            attempt += 1;
+        }
+    }
+}
+
+impl Deref for HttpClient {
+    type Target = reqwest::Client;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+/// Builds a client for talking to other hosts according to the provided options. We are a good
+/// netizen and set a custom user agent to allow remote hosts to identify us.
+pub fn http_client(options: HttpClientOptions) -> HttpClient {
+    let user_agent = format!(
+        "{}/{}; +{HTTP_CLIENT_CONTACT}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let redirect_policy = if options.follow_redirects {
+        Policy::default()
+    } else {
+        Policy::none()
+    };
+
+    let client = reqwest::Client::builder()
+        .connect_timeout(options.connect_timeout)
+        .redirect(redirect_policy)
+        .timeout(options.request_timeout)
+        .user_agent(user_agent)
+        .build()
+        .expect("static client build should always succeed");
+
+    HttpClient {
+        client,
+        retries: options.retries,
+        retry_backoff: options.retry_backoff,
+    }
+}
+
+fn is_temporary_failure(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use axum::routing::get;
+    use axum::Router;
+
+    use super::*;
+
+    /// Serves a single route that fails with a 503 the first `failures` times it is requested.
+    async fn flaky_server(failures: usize) -> (String, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let seen = requests.clone();
+
+        let handler = move || async move {
+            if seen.fetch_add(1, Ordering::SeqCst) < failures {
+                StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                StatusCode::OK
+            }
+        };
+        let router = Router::new().route("/", get(handler.clone()).post(handler));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        (format!("http://{addr}/"), requests)
+    }
+
+    #[tokio::test]
+    async fn test_temporary_failures_retried() {
+        let backoff = BackoffStrategy::Fixed(Duration::from_millis(1));
+        let client = http_client(HttpClientOptions::default().with_retries(2, backoff));
+
+        let (url, requests) = flaky_server(2).await;
+        let response = client.send(client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        // Running out of retries hands back the last failure
+        let (url, requests) = flaky_server(5).await;
+        let response = client.send(client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        // A request that may have had an effect is never repeated
+        let (url, requests) = flaky_server(1).await;
+        let response = client.send(client.post(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,14 +1,65 @@
+use std::net::{IpAddr, Ipv6Addr};
+
 use axum_extra::extract::cookie::Cookie;
 use axum_extra::extract::CookieJar;
 use time::OffsetDateTime;
 
+mod http_client;
+
+pub use http_client::{http_client, HttpClient, HttpClientOptions};
+
+/// The block of addresses a single client can be expected to control. IPv6 clients are commonly
+/// handed a whole /64 and are free to use any address within it, limits tracked per client need
+/// to be tracked per network to mean anything. IPv4 addresses, including those mapped into IPv6,
+/// are used as they are.
+pub fn client_network(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(addr) => match addr.to_ipv4_mapped() {
+            Some(mapped) => IpAddr::V4(mapped),
+            None => IpAddr::V6(Ipv6Addr::from(u128::from(addr) & (u128::MAX << 64))),
+        },
+        addr => addr,
+    }
+}
+
 pub fn remove_cookie(name: &'static str, mut cookie_jar: CookieJar) -> CookieJar {
     cookie_jar = cookie_jar.remove(Cookie::new(name, ""));
+
+    // Browsers ignore prefixed cookies without the secure flag, even when expiring them
+    let secure = name.starts_with("__Host-") || name.starts_with("__Secure-");
+
     cookie_jar.add(
         Cookie::build(name)
             .path("/")
             .http_only(false)
+            .secure(secure)
             .expires(OffsetDateTime::UNIX_EPOCH)
             .finish(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_client_network() {
+        let first: IpAddr = "2001:db8:1:2:aaaa::1".parse().unwrap();
+        let second: IpAddr = "2001:db8:1:2:ffff::9".parse().unwrap();
+        let elsewhere: IpAddr = "2001:db8:1:3::1".parse().unwrap();
+
+        assert_eq!(
+            client_network(first),
+            "2001:db8:1:2::".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(client_network(first), client_network(second));
+        assert_ne!(client_network(first), client_network(elsewhere));
+
+        let v4 = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 7));
+        let mapped: IpAddr = "::ffff:192.0.2.7".parse().unwrap();
+        assert_eq!(client_network(v4), v4);
+        assert_eq!(client_network(mapped), v4);
+    }
+}